@@ -7,64 +7,216 @@
 ///
 
 use backend::storage::*;
-use json::JsonValue;
-use chrono::NaiveDate;
+use backend::error::Error;
+use backend::references::*;
+use backend::money::Money;
+use backend::transactions::{ Transaction, StatusFilter, T_CURRENT_BALANCE };
+use i18n::*;
+use std::str::FromStr;
+use json::{ parse, JsonValue };
+use json::number::Number;
+use chrono::{ NaiveDate, Datelike, Duration };
+
+// Kinds of account, each one with his own labels and totals
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AccountType {
+    Checking,
+    CreditCard,
+    Cash,
+    Savings,
+    Loan,
+    Asset
+}
 
-#[derive(Default, Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct Account {
    pub uuid: String,
    pub bank: String,
    pub name: String,
-   pub open_balance: f32,
+   pub open_balance: Money,
    pub open_balance_date: Option<NaiveDate>,
-   pub currency: String
+   pub currency: String,
+   pub kind: AccountType,
+   // Percent per year earned by the savings accounts
+   pub interest_rate: Option<Number>,
+   // Days of month when the statement of credit card closes and is due
+   pub closing_day: Option<u32>,
+   pub due_day: Option<u32>,
+   // When the account was closed, after it no transactions are accepted
+   pub closed_in: Option<NaiveDate>,
+   // The balances start on the opening balance date, the transactions
+   // before it are already on the opening balance. Only turned off for
+   // keep all transactions on the balances
+   pub starts_on_opening: bool
 }
 
-impl Model for Account {
-
-    fn new(row: JsonValue, uuid: String, _storage: &mut Storage, _can_recursive: bool) -> Account {
+impl Default for Account {
 
-        if row["bank"].is_null() {
-            panic!("Bank name not found into a row(id {}) account", uuid);
+    fn default() -> Account {
+        Account {
+            uuid: "".to_string(),
+            bank: "".to_string(),
+            name: "".to_string(),
+            open_balance: Money::zero(),
+            open_balance_date: None,
+            currency: "".to_string(),
+            kind: AccountType::Checking,
+            interest_rate: None,
+            closing_day: None,
+            due_day: None,
+            closed_in: None,
+            starts_on_opening: true
         }
+    }
+}
 
-        if row["name"].is_null() {
-            panic!("Name not found into a row(id {}) account", uuid);
-        }
+impl Default for AccountType {
+    fn default() -> AccountType {
+        AccountType::Checking
+    }
+}
 
-        if row["open_balance"].is_null() {
-            panic!("Open balance not found into a row(id {}) account", uuid);
-        }
+impl FromStr for AccountType {
+    type Err = Error;
 
-        if row["open_balance_date"].is_null() {
-            panic!("Open balance date not found into a row(id {}) account", uuid);
+    // Parse the names like checking or credit_card
+    fn from_str(value: &str) -> Result<AccountType, Error> {
+        AccountType::all().into_iter()
+            .find(|kind| kind.as_str() == value.trim().to_lowercase())
+            .ok_or(Error::Invalid("error_invalid_account_type"))
+    }
+}
+
+impl AccountType {
+
+    pub fn all() -> Vec<AccountType> {
+        vec![AccountType::Checking, AccountType::CreditCard, AccountType::Cash, AccountType::Savings, AccountType::Loan, AccountType::Asset]
+    }
+
+    // Name of type on storage and params
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountType::Checking => "checking",
+            AccountType::CreditCard => "credit_card",
+            AccountType::Cash => "cash",
+            AccountType::Savings => "savings",
+            AccountType::Loan => "loan",
+            AccountType::Asset => "asset"
         }
+    }
 
-        if row["currency"].is_null() {
-            panic!("Currency not found into a row(id {}) account", uuid);
+    // Name of type on the language in use
+    pub fn label(&self) -> String {
+        match self {
+            AccountType::Checking => I18n::text("accounts_type_checking"),
+            AccountType::CreditCard => I18n::text("accounts_type_credit_card"),
+            AccountType::Cash => I18n::text("accounts_type_cash"),
+            AccountType::Savings => I18n::text("accounts_type_savings"),
+            AccountType::Loan => I18n::text("accounts_type_loan"),
+            AccountType::Asset => I18n::text("accounts_type_asset")
         }
+    }
 
-        let open_balance_date = Some(NaiveDate::parse_from_str(&row["open_balance_date"].to_string(), "%Y-%m-%d").unwrap());
+    // The balance is money owed by the user, like the credit cards
+    pub fn is_liability(&self) -> bool {
+        *self == AccountType::CreditCard || *self == AccountType::Loan
+    }
+}
 
-        Account {
-            uuid: uuid,
+impl Model for Account {
+
+    fn new(row: JsonValue, uuid: String, _storage: &mut Storage, _can_recursive: bool) -> Result<Account, Error> {
+
+        Data::require(&row, "accounts", &uuid, vec!["bank", "name", "open_balance", "open_balance_date", "currency"])?;
+
+        let invalid = |field: &str| Error::InvalidField("accounts".to_string(), uuid.clone(), field.to_string());
+
+        let open_balance_date = NaiveDate::parse_from_str(&row["open_balance_date"].to_string(), "%Y-%m-%d")
+            .map_err(|_| invalid("open_balance_date"))?;
+
+        let open_balance = Money::from_json(&row["open_balance"])
+            .ok_or(invalid("open_balance"))?;
+
+        // The accounts created before the types are checking accounts
+        let kind = match row["type"].as_str() {
+            Some(kind) => kind.parse::<AccountType>().map_err(|_| invalid("type"))?,
+            None => AccountType::Checking
+        };
+
+        let interest_rate = match row["interest_rate"] {
+            JsonValue::Number(value) if value.is_sign_positive() => Some(value),
+            JsonValue::Null => None,
+            _ => return Err(invalid("interest_rate"))
+        };
+
+        let day = |field: &str| match row[field] {
+            JsonValue::Null => Ok(None),
+            _ => match row[field].as_u32() {
+                Some(day) if Account::is_valid_day(day) => Ok(Some(day)),
+                _ => Err(invalid(field))
+            }
+        };
+
+        let closing_day = day("closing_day")?;
+        let due_day = day("due_day")?;
+
+        let closed_in = match row["closed_in"].as_str() {
+            Some(date) => Some(NaiveDate::parse_from_str(date, "%Y-%m-%d").map_err(|_| invalid("closed_in"))?),
+            None => None
+        };
+
+        // The accounts of old files also start on the opening balance date
+        let starts_on_opening = match row["starts_on_opening"] {
+            JsonValue::Boolean(value) => value,
+            JsonValue::Null => true,
+            _ => return Err(invalid("starts_on_opening"))
+        };
+
+        Ok(Account {
+            uuid: uuid.clone(),
             bank: row["bank"].to_string(),
             name: row["name"].to_string(),
-            open_balance: row["open_balance"].as_f32().unwrap(),
-            open_balance_date: open_balance_date,
-            currency: row["currency"].to_string()
-        }
+            open_balance: open_balance,
+            open_balance_date: Some(open_balance_date),
+            currency: row["currency"].to_string(),
+            kind: kind,
+            interest_rate: interest_rate,
+            closing_day: closing_day,
+            due_day: due_day,
+            closed_in: closed_in,
+            starts_on_opening: starts_on_opening
+        })
     }
 
     fn to_save(self) -> (String, bool, JsonValue) {
 
-        (self.uuid.clone(), self.uuid.is_empty(), object!{
+        let mut json = object!{
             "bank" => self.bank,
             "name" => self.name,
             "open_balance" => self.open_balance,
             "open_balance_date" => self.open_balance_date.unwrap().format("%Y-%m-%d").to_string(),
             "currency" => self.currency,
-        })
+            "type" => self.kind.as_str()
+        };
+
+        if let Some(rate) = self.interest_rate {
+            json["interest_rate"] = JsonValue::Number(rate);
+        }
+
+        if let (Some(closing_day), Some(due_day)) = (self.closing_day, self.due_day) {
+            json["closing_day"] = closing_day.into();
+            json["due_day"] = due_day.into();
+        }
+
+        if let Some(closed_in) = self.closed_in {
+            json["closed_in"] = closed_in.format("%Y-%m-%d").to_string().into();
+        }
+
+        if !self.starts_on_opening {
+            json["starts_on_opening"] = false.into();
+        }
+
+        (self.uuid.clone(), self.uuid.is_empty(), json)
     }
 }
 
@@ -76,63 +228,251 @@ impl Account {
     }
 
     // Return the value formatted with the currency of account
-    pub fn format_value(&self, value: f32) -> String {
-        format!("{} {:.2}", self.currency, value)
+    pub fn format_value(&self, value: Money) -> String {
+        format!("{} {}", self.currency, I18n::locale().format_money(value))
     }
 
-    // Short version of the uuid
-    pub fn id(self) -> String {
-        Data::uuid_to_id(self.uuid)
+    // The currencies are the same without case and spaces, like usd and USD
+    pub fn same_currency(&self, other: &Account) -> bool {
+        self.currency.trim().to_uppercase() == other.currency.trim().to_uppercase()
     }
 
-    // Return a list with all accounts
-    pub fn get_accounts(storage: &mut Storage) -> Vec<Account> {
+    // Name of type, with the interest rate of the savings
+    pub fn kind_formmated(&self) -> String {
+        match self.interest_rate {
+            Some(rate) if self.kind == AccountType::Savings => format!("{} {}%", self.kind.label(), I18n::locale().format_decimal(rate)),
+            _ => self.kind.label()
+        }
+    }
 
-        storage.start_section("accounts".to_string());
+    // Parse the interest rate, like 6.5 or 6,5 percent per year
+    pub fn parse_interest_rate(value: &str) -> Result<Option<Number>, Error> {
 
-        let mut data = storage.get_section_data("accounts".to_string());
+        if value.trim().is_empty() {
+            return Ok(None);
+        }
+
+        match parse(&value.trim().replace(",", ".")) {
+            Ok(JsonValue::Number(rate)) if rate.is_sign_positive() && !rate.is_nan() => Ok(Some(rate)),
+            _ => Err(Error::Invalid("error_invalid_interest_rate"))
+        }
+    }
+
+    // Interest earned by the balance between the dates, by the rate of
+    // savings, without compound
+    pub fn interest(&self, balance: Money, from: NaiveDate, to: NaiveDate) -> Option<Money> {
 
-        let mut list: Vec<Account> = vec![];
+        let rate = match self.interest_rate {
+            Some(rate) if self.kind == AccountType::Savings => rate,
+            _ => return None
+        };
 
-        while let Ok(line) = data.next::<Account>() {
-            list.push(line);
+        let days = (to - from).num_days() + 1;
+
+        if days <= 0 || balance.is_negative() {
+            return Some(Money::zero());
         }
 
-        return list;
+        balance.times(&Number::from(days as u64))?
+            .times(&rate)?
+            .divided_by(&Number::from(36500u64))
+    }
+
+    // The opening balance is on the balances of the date, always for the
+    // accounts that keep the transactions before it
+    pub fn is_opened_on(&self, date: NaiveDate) -> bool {
+        match self.open_balance_date {
+            Some(open_balance_date) if self.starts_on_opening => open_balance_date <= date,
+            _ => true
+        }
+    }
+
+    fn is_valid_day(day: u32) -> bool {
+        day >= 1 && day <= 31
+    }
+
+    // Parse the day of month of the billing cycle
+    pub fn parse_day(value: &str) -> Result<u32, Error> {
+        match value.trim().parse::<u32>() {
+            Ok(day) if Account::is_valid_day(day) => Ok(day),
+            _ => Err(Error::Invalid("error_invalid_billing_day"))
+        }
+    }
+
+    // The day on the month, or the last day when the month is shorter
+    fn day_of_month(month: NaiveDate, day: u32) -> NaiveDate {
+
+        let first = month.with_day(1).unwrap();
+        let last = (first + Duration::days(32)).with_day(1).unwrap() - Duration::days(1);
+
+        first.with_day(day.min(last.day())).unwrap()
+    }
+
+    // First day of the next month
+    fn next_month(month: NaiveDate) -> NaiveDate {
+        (month.with_day(1).unwrap() + Duration::days(32)).with_day(1).unwrap()
+    }
+
+    // First day of the previous month
+    fn previous_month(month: NaiveDate) -> NaiveDate {
+        (month.with_day(1).unwrap() - Duration::days(1)).with_day(1).unwrap()
+    }
+
+    // First, closing and due dates of the statement that closes on the
+    // month. When the due day is before the closing day it is on the next month
+    pub fn billing_cycle(&self, month: NaiveDate) -> Option<(NaiveDate, NaiveDate, NaiveDate)> {
+
+        let (closing_day, due_day) = match (self.closing_day, self.due_day) {
+            (Some(closing_day), Some(due_day)) if self.kind == AccountType::CreditCard => (closing_day, due_day),
+            _ => return None
+        };
+
+        let from = Account::day_of_month(Account::previous_month(month), closing_day) + Duration::days(1);
+        let closing = Account::day_of_month(month, closing_day);
+
+        let due = match due_day > closing_day {
+            true => Account::day_of_month(month, due_day),
+            false => Account::day_of_month(Account::next_month(month), due_day)
+        };
+
+        Some((from, closing, due))
+    }
+
+    // Month of the statement that the purchase of date belongs to, only
+    // for the credit cards
+    pub fn statement_month(&self, date: NaiveDate) -> Option<NaiveDate> {
+
+        let closing_day = match self.closing_day {
+            Some(closing_day) if self.kind == AccountType::CreditCard => closing_day,
+            _ => return None
+        };
+
+        match date <= Account::day_of_month(date, closing_day) {
+            true => Some(date.with_day(1).unwrap()),
+            false => Some(Account::next_month(date))
+        }
+    }
+
+    // Closed accounts are hidden on the lists and options
+    pub fn is_archived(&self) -> bool {
+        self.closed_in.is_some()
+    }
+
+    // The transactions of date can be created on the account
+    pub fn check_open(&self, date: Option<NaiveDate>) -> Result<(), Error> {
+        match (self.closed_in, date) {
+            (Some(closed_in), Some(date)) if date > closed_in => Err(Error::Invalid("error_account_closed")),
+            _ => Ok(())
+        }
+    }
+
+    // Shortest unique prefix of the uuid
+    pub fn id(self, storage: &mut Storage) -> String {
+        storage.short_id("accounts", &self.uuid)
+    }
+
+    // Return a list with all accounts
+    pub fn get_accounts(storage: &mut Storage) -> Result<Vec<Account>, Error> {
+
+        storage.start_section("accounts".to_string())?;
+
+        let mut data = storage.get_section_data("accounts".to_string());
+
+        data.all::<Account>()
+    }
+
+    // Return the accounts not closed, or all with the archived ones
+    pub fn get_active_accounts(storage: &mut Storage, include_archived: bool) -> Result<Vec<Account>, Error> {
+        Ok(Account::get_accounts(storage)?
+            .into_iter()
+            .filter(|account| include_archived || !account.is_archived())
+            .collect())
     }
 
     // Return the account of id
-    pub fn get_account(storage: &mut Storage, uuid: String) -> Result<Account, &'static str> {
+    pub fn get_account(storage: &mut Storage, uuid: String) -> Result<Account, Error> {
 
-        storage.start_section("accounts".to_string());
+        storage.start_section("accounts".to_string())?;
 
         let mut data = storage.get_section_data("accounts".to_string());
 
-        if data.find_by_id(uuid) {
+        if data.find_by_id(uuid.clone())? {
             return data.next::<Account>();
         }
 
-        Err("Account not found")
+        Err(Error::NotFound("accounts".to_string(), uuid))
     }
 
     // Save updates, or create new, account on storage
-    pub fn store_account(storage: &mut Storage, account: Account) {
+    pub fn store_account(storage: &mut Storage, account: Account) -> Result<String, Error> {
+
+        // The billing cycle needs both days
+        if account.closing_day.is_some() != account.due_day.is_some() {
+            return Err(Error::Invalid("error_billing_days"));
+        }
 
-        storage.start_section("accounts".to_string());
+        storage.start_section("accounts".to_string())?;
 
         let mut data = storage.get_section_data("accounts".to_string());
 
-        data.save(account);
+        data.save(account)
     }
 
-    // Remvoe account of storage
-    pub fn remove_account(storage: &mut Storage, uuid: String) {
+    // Close the account on the date, or open it again without date. Return
+    // the balance of account on the closing date, it should be zero
+    pub fn close_account(storage: &mut Storage, uuid: String, date: Option<NaiveDate>) -> Result<Money, Error> {
+
+        let mut account = Account::get_account(storage, uuid)?;
+
+        account.closed_in = date;
+
+        let balance = match date {
+            Some(date) => {
+                let (_, totals) = Transaction::get_transactions(storage, account.clone(), date, date, StatusFilter::ALL, None, false)?;
+                totals[T_CURRENT_BALANCE].value
+            },
+            None => Money::zero()
+        };
+
+        Account::store_account(storage, account)?;
+
+        Ok(balance)
+    }
+
+    // Recompute the opening balance for the current balance on the date
+    // match the balance of a bank statement. Return the new opening balance
+    pub fn rebalance(storage: &mut Storage, uuid: String, date: NaiveDate, balance: Money) -> Result<Money, Error> {
+
+        let mut account = Account::get_account(storage, uuid)?;
+
+        if !account.is_opened_on(date) {
+            return Err(Error::Invalid("error_rebalance_before_opening"));
+        }
+
+        let (_, totals) = Transaction::get_transactions(storage, account.clone(), date, date, StatusFilter::ALL, None, false)?;
+
+        // What the paid transactions moved since the opening
+        let moved = totals[T_CURRENT_BALANCE].value - account.open_balance;
+
+        account.open_balance = balance - moved;
 
-        storage.start_section("accounts".to_string());
+        let open_balance = account.open_balance;
+
+        Account::store_account(storage, account)?;
+
+        Ok(open_balance)
+    }
+
+    // Remvoe account of storage, after handle the rows that point to it
+    pub fn remove_account(storage: &mut Storage, uuid: String, on_remove: OnRemove) -> Result<(), Error> {
+
+        let account = Account::get_account(storage, uuid)?;
+
+        References::release(storage, "accounts", &account.uuid, on_remove)?;
 
         let mut data = storage.get_section_data("accounts".to_string());
 
-        data.remove_by_id(uuid);
+        data.remove_by_id(account.uuid)
     }
 }
 
@@ -143,23 +483,23 @@ mod tests {
     use super::*;
     use uuid::Uuid;
     use chrono::Local;
-    use std::collections::HashMap;
-    use std::cell::RefCell;
+    use backend::contacts::Contact;
+    use backend::transactions::{ T_PREVIOUS_BALANCE, T_EXPECTED_BALANCE };
 
     fn populate() -> String {
 
         let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
 
-        let mut st = Storage { path_str: path.clone(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(path.clone());
 
-        assert!(st.start_section("accounts".to_string()));
+        assert!(st.start_section("accounts".to_string()).is_ok());
 
         let mut data = st.get_section_data("accounts".to_string());
 
-        data.save(Account { uuid: "".to_string(), name: "account 1".to_string(), bank: "bank A".to_string(), currency: "R$".to_string(), open_balance: 0.0, open_balance_date: Some(Local::today().naive_local()) });
-        data.save(Account { uuid: "".to_string(), name: "account 2".to_string(), bank: "bank B".to_string(), currency: "$".to_string(), open_balance: 100.0, open_balance_date: Some(Local::today().naive_local()) });
-        data.save(Account { uuid: "".to_string(), name: "account 3".to_string(), bank: "bank D".to_string(), currency: "$".to_string(), open_balance: -10.0, open_balance_date: Some(Local::today().naive_local()) });
-        data.save(Account { uuid: "".to_string(), name: "account 4".to_string(), bank: "bank B".to_string(), currency: "R$".to_string(), open_balance: 35.0, open_balance_date: Some(Local::today().naive_local()) });
+        data.save(Account { uuid: "".to_string(), name: "account 1".to_string(), bank: "bank A".to_string(), currency: "R$".to_string(), open_balance: Money::zero(), open_balance_date: Some(NaiveDate::from_ymd(2018, 1, 1)), kind: AccountType::Checking, interest_rate: None, closing_day: None, due_day: None, closed_in: None, starts_on_opening: true }).unwrap();
+        data.save(Account { uuid: "".to_string(), name: "account 2".to_string(), bank: "bank B".to_string(), currency: "$".to_string(), open_balance: Money::from_cents(10000), open_balance_date: Some(NaiveDate::from_ymd(2018, 1, 1)), kind: AccountType::Checking, interest_rate: None, closing_day: None, due_day: None, closed_in: None, starts_on_opening: true }).unwrap();
+        data.save(Account { uuid: "".to_string(), name: "account 3".to_string(), bank: "bank D".to_string(), currency: "$".to_string(), open_balance: Money::from_cents(-1000), open_balance_date: Some(NaiveDate::from_ymd(2018, 1, 1)), kind: AccountType::Checking, interest_rate: None, closing_day: None, due_day: None, closed_in: None, starts_on_opening: true }).unwrap();
+        data.save(Account { uuid: "".to_string(), name: "account 4".to_string(), bank: "bank B".to_string(), currency: "R$".to_string(), open_balance: Money::from_cents(3500), open_balance_date: Some(NaiveDate::from_ymd(2018, 1, 1)), kind: AccountType::Checking, interest_rate: None, closing_day: None, due_day: None, closed_in: None, starts_on_opening: true }).unwrap();
 
         path
     }
@@ -167,41 +507,41 @@ mod tests {
     #[test]
     fn get_accounts() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
-        let accounts = Account::get_accounts(&mut st);
+        let accounts = Account::get_accounts(&mut st).unwrap();
 
         assert_eq!(accounts[0].name, "account 4".to_string());
         assert_eq!(accounts[0].bank, "bank B".to_string());
         assert_eq!(accounts[0].currency, "R$".to_string());
-        assert_eq!(accounts[0].open_balance, 35.0);
-        assert_eq!(accounts[0].open_balance_date, Some(Local::today().naive_local()));
+        assert_eq!(accounts[0].open_balance, Money::from_cents(3500));
+        assert_eq!(accounts[0].open_balance_date, Some(NaiveDate::from_ymd(2018, 1, 1)));
 
         assert_eq!(accounts[1].name, "account 3".to_string());
         assert_eq!(accounts[1].bank, "bank D".to_string());
         assert_eq!(accounts[1].currency, "$".to_string());
-        assert_eq!(accounts[1].open_balance, -10.0);
-        assert_eq!(accounts[1].open_balance_date, Some(Local::today().naive_local()));
+        assert_eq!(accounts[1].open_balance, Money::from_cents(-1000));
+        assert_eq!(accounts[1].open_balance_date, Some(NaiveDate::from_ymd(2018, 1, 1)));
 
         assert_eq!(accounts[2].name, "account 2".to_string());
         assert_eq!(accounts[2].bank, "bank B".to_string());
         assert_eq!(accounts[2].currency, "$".to_string());
-        assert_eq!(accounts[2].open_balance, 100.0);
-        assert_eq!(accounts[2].open_balance_date, Some(Local::today().naive_local()));
+        assert_eq!(accounts[2].open_balance, Money::from_cents(10000));
+        assert_eq!(accounts[2].open_balance_date, Some(NaiveDate::from_ymd(2018, 1, 1)));
 
         assert_eq!(accounts[3].name, "account 1".to_string());
         assert_eq!(accounts[3].bank, "bank A".to_string());
         assert_eq!(accounts[3].currency, "R$".to_string());
-        assert_eq!(accounts[3].open_balance, 0.0);
-        assert_eq!(accounts[3].open_balance_date, Some(Local::today().naive_local()));
+        assert_eq!(accounts[3].open_balance, Money::zero());
+        assert_eq!(accounts[3].open_balance_date, Some(NaiveDate::from_ymd(2018, 1, 1)));
     }
 
     #[test]
     fn get_account() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
-        let accounts = Account::get_accounts(&mut st);
+        let accounts = Account::get_accounts(&mut st).unwrap();
 
         let uuid = accounts[0].uuid.clone();
 
@@ -211,8 +551,8 @@ mod tests {
         assert_eq!(account.clone().unwrap().name, "account 4".to_string());
         assert_eq!(account.clone().unwrap().bank, "bank B".to_string());
         assert_eq!(account.clone().unwrap().currency, "R$".to_string());
-        assert_eq!(account.clone().unwrap().open_balance, 35.0);
-        assert_eq!(account.unwrap().open_balance_date, Some(Local::today().naive_local()));
+        assert_eq!(account.clone().unwrap().open_balance, Money::from_cents(3500));
+        assert_eq!(account.unwrap().open_balance_date, Some(NaiveDate::from_ymd(2018, 1, 1)));
         let accounte = Account::get_account(&mut st, "NOOOO".to_string());
 
         assert!(accounte.is_err());
@@ -222,31 +562,186 @@ mod tests {
     #[test]
     fn store_account() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
-        Account::store_account(&mut st, Account { uuid: "".to_string(), name: "account 5".to_string(), bank: "bank A".to_string(), currency: "R$".to_string(), open_balance: 0.0, open_balance_date: Some(Local::today().naive_local())  });
+        Account::store_account(&mut st, Account { uuid: "".to_string(), name: "account 5".to_string(), bank: "bank A".to_string(), currency: "R$".to_string(), open_balance: Money::zero(), open_balance_date: Some(Local::today().naive_local()), kind: AccountType::Checking, interest_rate: None, closing_day: None, due_day: None, closed_in: None, starts_on_opening: true }).unwrap();
 
-        let accounts = Account::get_accounts(&mut st);
+        let accounts = Account::get_accounts(&mut st).unwrap();
 
         assert_eq!(accounts[0].name, "account 5".to_string());
         assert_eq!(accounts[0].bank, "bank A".to_string());
         assert_eq!(accounts[0].currency, "R$".to_string());
-        assert_eq!(accounts[0].open_balance, 0.0);
+        assert_eq!(accounts[0].open_balance, Money::zero());
         assert_eq!(accounts[0].open_balance_date, Some(Local::today().naive_local()));
 
         assert_eq!(accounts[1].name, "account 4".to_string());
         assert_eq!(accounts[1].bank, "bank B".to_string());
         assert_eq!(accounts[1].currency, "R$".to_string());
-        assert_eq!(accounts[1].open_balance, 35.0);
-        assert_eq!(accounts[1].open_balance_date, Some(Local::today().naive_local()));
+        assert_eq!(accounts[1].open_balance, Money::from_cents(3500));
+        assert_eq!(accounts[1].open_balance_date, Some(NaiveDate::from_ymd(2018, 1, 1)));
+    }
+
+    #[test]
+    fn account_types() {
+
+        let mut st = Storage::new(populate());
+
+        let mut account = Account::get_accounts(&mut st).unwrap()[0].clone();
+
+        // The default type
+        assert_eq!(account.kind, AccountType::Checking);
+
+        account.kind = "Savings".parse::<AccountType>().unwrap();
+        account.interest_rate = Account::parse_interest_rate("6,5").unwrap();
+
+        Account::store_account(&mut st, account.clone()).unwrap();
+
+        let account = Account::get_account(&mut st, account.uuid).unwrap();
+
+        assert_eq!(account.kind, AccountType::Savings);
+        assert_eq!(JsonValue::Number(account.interest_rate.unwrap()).dump(), "6.5");
+
+        assert_eq!("credit_card".parse::<AccountType>(), Ok(AccountType::CreditCard));
+        assert_eq!("bitcoin".parse::<AccountType>(), Err(Error::Invalid("error_invalid_account_type")));
+        assert_eq!(Account::parse_interest_rate("-1"), Err(Error::Invalid("error_invalid_interest_rate")));
+        assert_eq!(Account::parse_interest_rate(""), Ok(None));
+    }
+
+    #[test]
+    fn billing_cycle() {
+
+        let date = |value: &str| NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap();
+
+        let mut card = Account { kind: AccountType::CreditCard, closing_day: Some(31), due_day: Some(10), ..Default::default() };
+
+        // Closes on the last day of the shorter months
+        assert_eq!(card.billing_cycle(date("2019-02-01")), Some((date("2019-02-01"), date("2019-02-28"), date("2019-03-10"))));
+        assert_eq!(card.billing_cycle(date("2019-03-01")), Some((date("2019-03-01"), date("2019-03-31"), date("2019-04-10"))));
+
+        card.closing_day = Some(3);
+        card.due_day = Some(10);
+
+        assert_eq!(card.billing_cycle(date("2018-10-01")), Some((date("2018-09-04"), date("2018-10-03"), date("2018-10-10"))));
+        assert_eq!(card.statement_month(date("2018-10-03")), Some(date("2018-10-01")));
+        assert_eq!(card.statement_month(date("2018-12-04")), Some(date("2019-01-01")));
+
+        card.kind = AccountType::Checking;
+
+        assert_eq!(card.billing_cycle(date("2018-10-01")), None);
+        assert_eq!(card.statement_month(date("2018-10-03")), None);
+        assert_eq!(Account::parse_day("32"), Err(Error::Invalid("error_invalid_billing_day")));
+    }
+
+    #[test]
+    fn close_account() {
+
+        I18n::config("en_US".to_string());
+
+        let mut st = Storage::new(populate());
+
+        let accounts = Account::get_accounts(&mut st).unwrap();
+        let date = NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap();
+
+        // The opening balance is still on the account
+        assert_eq!(Account::close_account(&mut st, accounts[0].uuid.clone(), Some(date)), Ok(Money::from_cents(3500)));
+        assert_eq!(Account::close_account(&mut st, accounts[3].uuid.clone(), Some(date)), Ok(Money::zero()));
+
+        let active = Account::get_active_accounts(&mut st, false).unwrap();
+
+        assert_eq!(active.len(), 2);
+        assert!(active.iter().all(|account| account.closed_in.is_none()));
+        assert_eq!(Account::get_active_accounts(&mut st, true).unwrap().len(), 4);
+
+        let closed = Account::get_account(&mut st, accounts[0].uuid.clone()).unwrap();
+
+        assert!(closed.check_open(Some(date)).is_ok());
+        assert_eq!(closed.check_open(Some(date.succ())), Err(Error::Invalid("error_account_closed")));
+
+        // Open again
+        Account::close_account(&mut st, accounts[0].uuid.clone(), None).unwrap();
+
+        assert_eq!(Account::get_active_accounts(&mut st, false).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn starts_on_opening() {
+
+        I18n::config("en_US".to_string());
+
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
+
+        let date = |value: &str| NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap();
+
+        let account = Account::store_account(&mut st, Account { name: "checking".to_string(), currency: "R$".to_string(), open_balance: Money::from_cents(10000), open_balance_date: Some(date("2018-03-01")), ..Default::default() }).unwrap();
+        let contact = Contact::store_contact(&mut st, Contact { uuid: "".to_string(), name: "contact".to_string(), city_location: "city".to_string() }).unwrap();
+
+        let account = Account::get_account(&mut st, account).unwrap();
+        let contact = Contact::get_contact(&mut st, contact).unwrap();
+
+        // The first one is already on the opening balance
+        for (day, value, paid) in vec![("2018-02-01", -2000, true), ("2018-03-05", 5000, true), ("2018-03-10", -1000, false)] {
+            Transaction::store_transaction(&mut st, Transaction {
+                description: "transaction".to_string(),
+                value: Money::from_cents(value),
+                account: Some(account.clone()),
+                contact: Some(contact.clone()),
+                deadline: Some(date(day)),
+                paid_in: if paid { Some(date(day)) } else { None },
+                ..Default::default()
+            }).unwrap();
+        }
+
+        // By default the accounts start on the opening
+        assert!(account.starts_on_opening);
+
+        let (transactions, totals) = Transaction::get_transactions(&mut st, account.clone(), date("2018-01-01"), date("2018-12-31"), StatusFilter::ALL, None, false).unwrap();
+
+        assert_eq!(transactions.len(), 3);
+        assert!(transactions.iter().find(|transaction| transaction.value == Money::from_cents(-2000)).unwrap().is_before_opening());
+        assert_eq!(totals[T_PREVIOUS_BALANCE].value, Money::zero());
+        assert_eq!(totals[T_CURRENT_BALANCE].value, Money::from_cents(15000));
+        assert_eq!(totals[T_EXPECTED_BALANCE].value, Money::from_cents(14000));
+
+        // Before the opening the account has no balance
+        let (_, totals) = Transaction::get_transactions(&mut st, account.clone(), date("2018-02-01"), date("2018-02-28"), StatusFilter::ALL, None, false).unwrap();
+
+        assert_eq!(totals[T_CURRENT_BALANCE].value, Money::zero());
+
+        let (_, totals) = Transaction::get_transactions(&mut st, account.clone(), date("2018-04-01"), date("2018-04-30"), StatusFilter::ALL, None, false).unwrap();
+
+        assert_eq!(totals[T_PREVIOUS_BALANCE].value, Money::from_cents(15000));
+
+        assert_eq!(Account::rebalance(&mut st, account.uuid.clone(), date("2018-02-15"), Money::zero()), Err(Error::Invalid("error_rebalance_before_opening")));
+        assert_eq!(Account::rebalance(&mut st, account.uuid.clone(), date("2018-03-31"), Money::from_cents(20000)), Ok(Money::from_cents(15000)));
+
+        let account = Account::get_account(&mut st, account.uuid).unwrap();
+        let (_, totals) = Transaction::get_transactions(&mut st, account.clone(), date("2018-03-31"), date("2018-03-31"), StatusFilter::ALL, None, false).unwrap();
+
+        assert_eq!(totals[T_CURRENT_BALANCE].value, Money::from_cents(20000));
+
+        // Without starting on the opening all transactions are on the balances
+        let mut account = account;
+
+        account.starts_on_opening = false;
+        Account::store_account(&mut st, account.clone()).unwrap();
+
+        let account = Account::get_account(&mut st, account.uuid).unwrap();
+
+        assert!(!account.starts_on_opening);
+
+        let (transactions, totals) = Transaction::get_transactions(&mut st, account, date("2018-01-01"), date("2018-12-31"), StatusFilter::ALL, None, false).unwrap();
+
+        assert!(!transactions.iter().any(|transaction| transaction.is_before_opening()));
+        assert_eq!(totals[T_PREVIOUS_BALANCE].value, Money::from_cents(15000));
+        assert_eq!(totals[T_CURRENT_BALANCE].value, Money::from_cents(18000));
     }
 
     #[test]
     fn remove_account() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
-        let accounts = Account::get_accounts(&mut st);
+        let accounts = Account::get_accounts(&mut st).unwrap();
 
         let uuid = accounts[0].uuid.clone();
 
@@ -254,7 +749,7 @@ mod tests {
 
         assert!(account.is_ok());
 
-        Account::remove_account(&mut st, uuid.clone());
+        Account::remove_account(&mut st, uuid.clone(), OnRemove::Refuse).unwrap();
 
         let accounte = Account::get_account(&mut st, uuid.clone());
 
@@ -0,0 +1,359 @@
+///
+/// Blitz Money
+///
+/// Backend of module for the rotating backups of storage file
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::collections::HashSet;
+use chrono::{ Local, NaiveDateTime };
+use backend::storage::Storage;
+use backend::crypto::Crypto;
+use backend::error::Error;
+
+// Format of the date on the name of backups
+const DATE_FORMAT: &str = "%Y-%m-%d_%H-%M-%S%.6f";
+
+// Format for read the date of the names, the older backups have less digits
+const PARSE_FORMAT: &str = "%Y-%m-%d_%H-%M-%S%.f";
+
+// Representation of a copy of the storage file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Backup {
+    pub name: String,
+    pub path: String,
+    pub created_at: NaiveDateTime,
+    pub size: u64
+}
+
+// How many backups are kept. Besides the last ones, the newest
+// backup of each day and of each month are kept
+#[derive(Clone, Debug, PartialEq)]
+pub struct Policy {
+    pub last: usize,
+    pub daily: usize,
+    pub monthly: usize
+}
+
+pub struct Backups {}
+
+impl Policy {
+
+    // Policy from the BMONEY_BACKUP_LAST, BMONEY_BACKUP_DAILY and
+    // BMONEY_BACKUP_MONTHLY env vars
+    pub fn from_env() -> Policy {
+        Policy {
+            last: Policy::env_or("BMONEY_BACKUP_LAST", 10),
+            daily: Policy::env_or("BMONEY_BACKUP_DAILY", 7),
+            monthly: Policy::env_or("BMONEY_BACKUP_MONTHLY", 12)
+        }
+    }
+
+    fn env_or(var: &str, default: usize) -> usize {
+        match env::var(var) {
+            Ok(value) => value.trim().parse::<usize>()
+                .expect(&format!("Invalid number on {}", var)),
+            Err(_) => default
+        }
+    }
+}
+
+impl Backups {
+
+    // Directory of the backups, from the BMONEY_BACKUP_DIR env var or
+    // a sibling of the storage file
+    pub fn dir(storage: &Storage) -> String {
+        match env::var("BMONEY_BACKUP_DIR") {
+            Ok(dir) => dir,
+            Err(_) => format!("{}.backups", storage.path_str)
+        }
+    }
+
+    // Start of the name of all backups of the storage file
+    fn prefix(storage: &Storage) -> String {
+
+        let file_name = Path::new(&storage.path_str)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("bmoney.bms");
+
+        format!("{}.", file_name.trim_start_matches('.'))
+    }
+
+    // Copy the current storage file to the backups and apply the policy. Empty
+    // files, like a new storage, are not copied
+    pub fn snapshot(storage: &Storage) -> Option<Backup> {
+
+        let size = match fs::metadata(&storage.path_str) {
+            Ok(metadata) => metadata.len(),
+            Err(_) => return None
+        };
+
+        if size == 0 {
+            return None;
+        }
+
+        let dir = Backups::dir(storage);
+
+        fs::create_dir_all(&dir)
+            .expect("Couldn't create the directory of backups");
+
+        let created_at = Local::now().naive_local();
+        let name = format!("{}{}", Backups::prefix(storage), created_at.format(DATE_FORMAT));
+        let path = format!("{}/{}", dir, name);
+
+        fs::copy(&storage.path_str, &path)
+            .expect("Couldn't copy the storage file to the backups");
+
+        Backups::rotate(storage, &Policy::from_env());
+
+        Some(Backup { name: name, path: path, created_at: created_at, size: size })
+    }
+
+    // All backups of the storage file, the newest first
+    pub fn list(storage: &Storage) -> Vec<Backup> {
+
+        let dir = Backups::dir(storage);
+        let prefix = Backups::prefix(storage);
+
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(_) => return vec![]
+        };
+
+        let mut backups: Vec<Backup> = entries
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| {
+
+                let name = entry.file_name().into_string().ok()?;
+
+                if !name.starts_with(&prefix) {
+                    return None;
+                }
+
+                let created_at = NaiveDateTime::parse_from_str(&name[prefix.len()..], PARSE_FORMAT).ok()?;
+
+                Some(Backup {
+                    path: format!("{}/{}", dir, name),
+                    name: name,
+                    created_at: created_at,
+                    size: entry.metadata().map(|metadata| metadata.len()).unwrap_or(0)
+                })
+            })
+            .collect();
+
+        backups.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+        backups
+    }
+
+    // Remove the backups out of the policy. Return the removed ones
+    pub fn rotate(storage: &Storage, policy: &Policy) -> Vec<Backup> {
+
+        let backups = Backups::list(storage);
+
+        let mut keep: HashSet<String> = HashSet::new();
+        let mut days: Vec<String> = vec![];
+        let mut months: Vec<String> = vec![];
+
+        // The list is sorted by newest, so the first of each day or month is kept
+        for (i, backup) in backups.iter().enumerate() {
+
+            if i < policy.last {
+                keep.insert(backup.name.clone());
+            }
+
+            let day = backup.created_at.format("%Y-%m-%d").to_string();
+            if !days.contains(&day) {
+                if days.len() < policy.daily {
+                    keep.insert(backup.name.clone());
+                }
+                days.push(day);
+            }
+
+            let month = backup.created_at.format("%Y-%m").to_string();
+            if !months.contains(&month) {
+                if months.len() < policy.monthly {
+                    keep.insert(backup.name.clone());
+                }
+                months.push(month);
+            }
+        }
+
+        let removed: Vec<Backup> = backups.into_iter()
+            .filter(|backup| !keep.contains(&backup.name))
+            .collect();
+
+        for backup in removed.iter() {
+            fs::remove_file(&backup.path)
+                .expect("Couldn't remove a old backup of storage file");
+        }
+
+        removed
+    }
+
+    // Replace the storage file with the backup. The current file is kept
+    // on the backups too, so the restore can be reverted
+    pub fn restore(storage: &mut Storage, name: String) -> Result<Backup, Error> {
+
+        let backup = match Backups::list(storage).into_iter().find(|backup| backup.name == name.trim()) {
+            Some(backup) => backup,
+            None => return Err(Error::NotFound("backups".to_string(), name))
+        };
+
+        let contents = fs::read(&backup.path)
+            .expect("Couldn't read the backup of storage file");
+
+        storage.replace_contents(contents)?;
+
+        Ok(backup)
+    }
+
+    // Remove the backups that are not encrypted, used when the
+    // storage is encrypted for don't leave a plain copy behind
+    pub fn remove_unencrypted(storage: &Storage) {
+
+        for backup in Backups::list(storage) {
+
+            let contents = fs::read(&backup.path).unwrap_or_default();
+
+            if !Crypto::is_encrypted(&String::from_utf8_lossy(&contents)) {
+                fs::remove_file(&backup.path)
+                    .expect("Couldn't remove a backup of storage file");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use uuid::Uuid;
+    use backend::tags::Tag;
+
+    // Storage with a fake backup for each date
+    fn populate(dates: Vec<&str>) -> Storage {
+
+        let st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
+
+        fs::create_dir_all(Backups::dir(&st)).unwrap();
+
+        for date in dates {
+            let created_at = NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap();
+            let path = format!("{}/{}{}", Backups::dir(&st), Backups::prefix(&st), created_at.format(DATE_FORMAT));
+
+            fs::write(path, "::section::tags\n").unwrap();
+        }
+
+        st
+    }
+
+    fn names(backups: &Vec<Backup>) -> Vec<String> {
+        backups.iter()
+            .map(|backup| backup.created_at.format("%Y-%m-%d %H:%M").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn snapshot() {
+
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
+
+        // Nothing to copy on a new storage
+        assert_eq!(Backups::snapshot(&st), None);
+
+        Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 1".to_string() }).unwrap();
+        Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 2".to_string() }).unwrap();
+
+        // Only the first write of the storage takes a copy
+        assert_eq!(Backups::list(&st).len(), 0);
+
+        let path = st.path_str.clone();
+        drop(st);
+
+        let mut st = Storage::new(path);
+
+        Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 3".to_string() }).unwrap();
+
+        let backups = Backups::list(&st);
+
+        assert_eq!(backups.len(), 1);
+
+        let contents = fs::read_to_string(&backups[0].path).unwrap();
+
+        assert!(contents.contains("tag 2"));
+        assert!(!contents.contains("tag 3"));
+    }
+
+    #[test]
+    fn rotate() {
+
+        let st = populate(vec![
+            "2018-01-10 10:00",
+            "2018-01-20 10:00",
+            "2018-02-05 09:00",
+            "2018-02-05 18:00",
+            "2018-02-06 10:00",
+            "2018-02-07 10:00",
+            "2018-02-07 11:00",
+            "2018-02-07 12:00"
+        ]);
+
+        let removed = Backups::rotate(&st, &Policy { last: 2, daily: 3, monthly: 2 });
+
+        assert_eq!(names(&removed), vec!["2018-02-07 10:00", "2018-02-05 09:00", "2018-01-10 10:00"]);
+
+        assert_eq!(names(&Backups::list(&st)), vec![
+            // Last ones
+            "2018-02-07 12:00",
+            "2018-02-07 11:00",
+            // Daily
+            "2018-02-06 10:00",
+            "2018-02-05 18:00",
+            // Monthly
+            "2018-01-20 10:00"
+        ]);
+    }
+
+    #[test]
+    fn restore() {
+
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
+
+        Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 1".to_string() }).unwrap();
+
+        let path = st.path_str.clone();
+        drop(st);
+
+        let mut st = Storage::new(path.clone());
+
+        Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 2".to_string() }).unwrap();
+
+        drop(st);
+
+        let mut st = Storage::new(path.clone());
+
+        let name = Backups::list(&st)[0].name.clone();
+
+        assert_eq!(Backups::restore(&mut st, "nope".to_string()), Err(Error::NotFound("backups".to_string(), "nope".to_string())));
+        assert!(Backups::restore(&mut st, name).is_ok());
+
+        let tags: Vec<String> = Tag::get_tags(&mut st).unwrap()
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+
+        assert_eq!(tags, vec!["tag 1"]);
+
+        // The version before the restore was kept
+        let backups = Backups::list(&st);
+
+        assert_eq!(backups.len(), 2);
+        assert!(fs::read_to_string(&backups[0].path).unwrap().contains("tag 2"));
+    }
+}
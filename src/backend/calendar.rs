@@ -10,6 +10,7 @@
 use icalendar::*;
 use chrono::*;
 use backend::transactions::Transaction;
+use backend::storage::Storage;
 use i18n::*;
 
 pub struct Calendar {
@@ -18,7 +19,7 @@ pub struct Calendar {
 impl Calendar {
 
     // Build the calendar objects for print or storage in a file
-    pub fn export(transactions: Vec<Transaction>) -> icalendar::Calendar {
+    pub fn export(storage: &mut Storage, transactions: Vec<Transaction>) -> icalendar::Calendar {
 
         let mut calendar = icalendar::Calendar::new();
 
@@ -42,7 +43,7 @@ impl Calendar {
                 account = tr.account.clone().unwrap().name,
                 lobs = I18n::text("transactions_observations"),
                 obs = tr.observations.clone(),
-                id = tr.clone().id()));
+                id = tr.clone().id(storage)));
 
             event.uid(&tr.uuid.clone());
 
@@ -0,0 +1,574 @@
+///
+/// Blitz Money
+///
+/// Backend of module for check, and repair, the integrity of storage
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use std::collections::{ HashMap, HashSet };
+use json::{ parse, JsonValue };
+use uuid::Uuid;
+use i18n::*;
+use backend::storage::{ Storage, TRASH_PREFIX };
+use backend::error::Error;
+use backend::references::{ FIELDS, LISTS, References };
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum IssueKind {
+    // Line without a valid uuid, json or required fields
+    InvalidRow,
+    DuplicatedUuid,
+    BrokenReference,
+    // Transfer whose partner does not point back to it
+    AsymmetricTransfer
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Repair {
+    RemoveRow,
+    ReplaceRow(String)
+}
+
+// Representation of a problem found on a line of storage
+#[derive(Clone, Debug)]
+pub struct Issue {
+    pub kind: IssueKind,
+    pub section: String,
+    // Line that has the problem and will be changed by the repair
+    pub line: String,
+    pub detail: String,
+    pub repair: Option<Repair>
+}
+
+// A valid row of storage
+#[derive(Clone, Debug)]
+struct Row {
+    section: String,
+    uuid: String,
+    line: String,
+    json: JsonValue
+}
+
+pub struct Check {}
+
+impl Row {
+
+    // Line with the changed json
+    fn line_with(&self, json: JsonValue) -> String {
+        format!("{} {}", self.uuid, json.dump())
+    }
+
+    // Repair that drops the reference field, or remove the row
+    // if the field is required
+    fn drop_field(&self, field: &'static str) -> Repair {
+
+        let mut json = self.json.clone();
+        json.remove(field);
+
+        match Check::missing_field(&self.section, &json) {
+            Some(_) => Repair::RemoveRow,
+            None => Repair::ReplaceRow(self.line_with(json))
+        }
+    }
+
+    fn issue(&self, kind: IssueKind, detail: String, repair: Option<Repair>) -> Issue {
+        Issue { kind: kind, section: self.section.clone(), line: self.line.clone(), detail: detail, repair: repair }
+    }
+}
+
+impl Check {
+
+    // Required fields of the rows by section
+    fn required_fields(section: &str) -> Vec<&'static str> {
+
+        // The removed rows keep all fields, plus the time of removal
+        if section.starts_with(TRASH_PREFIX) {
+            let mut fields = Check::required_fields(&section[TRASH_PREFIX.len()..]);
+            fields.push("deleted_at");
+            return fields;
+        }
+
+        match section {
+            "accounts" => vec!["bank", "name", "open_balance", "open_balance_date", "currency"],
+            "contacts" => vec!["name", "city_location"],
+            "tags" => vec!["name"],
+            "rules" => vec!["term", "description"],
+            "forecasts" => vec!["tag", "account", "value"],
+            "transactions" => vec!["description", "value", "deadline", "account", "created_at"],
+            "rates" => vec!["currency", "target", "date", "value"],
+            _ => vec![]
+        }
+    }
+
+    // Return the first required field not present on json
+    fn missing_field(section: &str, json: &JsonValue) -> Option<String> {
+
+        for field in Check::required_fields(section) {
+            if json[field].is_null() {
+                return Some(field.to_string());
+            }
+        }
+
+        // We dont need contact if is a transfer
+        if section.trim_start_matches(TRASH_PREFIX) == "transactions" && json["contact"].is_null() && json["transfer"].is_null() {
+            return Some("contact".to_string());
+        }
+
+        None
+    }
+
+    // Parse all lines of storage. Return the valid rows and the
+    // issues of the invalid lines
+    fn parse_rows(storage: &mut Storage) -> (Vec<Row>, Vec<Issue>) {
+
+        let mut rows: Vec<Row> = vec![];
+        let mut issues: Vec<Issue> = vec![];
+        let mut section: Option<String> = None;
+
+        // A file that can't be read fails on the start of command
+        let _ = storage.load();
+
+        for line in storage.lines() {
+
+            if line.starts_with("::section::") {
+                section = Some(line["::section::".len()..].to_string());
+                continue;
+            }
+
+            if line.starts_with("::") {
+                continue;
+            }
+
+            if line.trim().is_empty() {
+                // The rows of section ends on the blank line
+                section = None;
+                continue;
+            }
+
+            let invalid = |detail: String| Issue {
+                kind: IssueKind::InvalidRow,
+                section: section.clone().unwrap_or_default(),
+                line: line.clone(),
+                detail: detail,
+                repair: Some(Repair::RemoveRow)
+            };
+
+            let current = match section.clone() {
+                Some(current) => current,
+                None => {
+                    issues.push(invalid(I18n::text("storage_check_outside_section")));
+                    continue;
+                }
+            };
+
+            let uuid = line.chars().take(36).collect::<String>();
+
+            if Uuid::parse_str(&uuid).is_err() {
+                issues.push(invalid(I18n::text("storage_check_invalid_uuid")));
+                continue;
+            }
+
+            let json = match parse(&line.chars().skip(37).collect::<String>()) {
+                Ok(ref json) if json.is_object() => json.clone(),
+                _ => {
+                    issues.push(invalid(I18n::text("storage_check_invalid_json")));
+                    continue;
+                }
+            };
+
+            if let Some(field) = Check::missing_field(&current, &json) {
+                issues.push(invalid(format!("{}: {}", I18n::text("storage_check_field_not_found"), field)));
+                continue;
+            }
+
+            rows.push(Row { section: current, uuid: uuid, line: line.clone(), json: json });
+        }
+
+        (rows, issues)
+    }
+
+    // Check the rows with the same uuid on a section. The first one is
+    // the row found by id, the others are removed when they are copies
+    // or receive a new uuid
+    fn check_duplicates(rows: &Vec<Row>, issues: &mut Vec<Issue>) {
+
+        let mut seen: HashMap<(String, String), String> = HashMap::new();
+
+        for row in rows {
+
+            let key = (row.section.clone(), row.uuid.clone());
+
+            if let Some(first) = seen.get(&key) {
+
+                let repair = match first == &row.line {
+                    true => Repair::RemoveRow,
+                    false => Repair::ReplaceRow(format!("{} {}", Uuid::new_v4(), row.json.dump()))
+                };
+
+                issues.push(row.issue(IssueKind::DuplicatedUuid, row.uuid.clone(), Some(repair)));
+                continue;
+            }
+
+            seen.insert(key, row.line.clone());
+        }
+    }
+
+    // Check the uuids, on fields of rows, that point to other sections
+    fn check_references(rows: &Vec<Row>, issues: &mut Vec<Issue>) {
+
+        let uuids: HashSet<(String, String)> = rows.iter()
+            .map(|row| (row.section.clone(), row.uuid.clone()))
+            .collect();
+
+        let exists = |section: &str, uuid: &String| uuids.contains(&(section.to_string(), uuid.clone()));
+
+        for row in rows {
+
+            for &(section, field, target) in FIELDS.iter() {
+
+                if row.section != section || row.json[field].is_empty() {
+                    continue;
+                }
+
+                let uuid = row.json[field].to_string();
+
+                if !exists(target, &uuid) {
+                    let detail = format!("{} {}", field, uuid);
+                    issues.push(row.issue(IssueKind::BrokenReference, detail, Some(row.drop_field(field))));
+                }
+            }
+
+            // Like the tags, that are a list of references
+            for &(section, field, target) in LISTS.iter() {
+
+                if row.section != section {
+                    continue;
+                }
+
+                let missing: Vec<String> = References::list_members(&row.json, field)
+                    .into_iter()
+                    .filter(|uuid| !exists(target, uuid))
+                    .collect();
+
+                if missing.len() > 0 {
+
+                    let mut json = row.json.clone();
+                    References::map_list(&mut json, field, |uuid| match missing.contains(&uuid) {
+                        true => None,
+                        false => Some(uuid)
+                    });
+
+                    let detail = format!("{} {}", field, missing.join(", "));
+                    issues.push(row.issue(IssueKind::BrokenReference, detail, Some(Repair::ReplaceRow(row.line_with(json)))));
+                }
+            }
+        }
+    }
+
+    // Check if the both sides of transfers point to each other. A partner
+    // without transfer is linked back, otherwise the transfer is dropped
+    fn check_transfers(rows: &Vec<Row>, issues: &mut Vec<Issue>) {
+
+        let transactions: HashMap<String, &Row> = rows.iter()
+            .filter(|row| row.section == "transactions")
+            .map(|row| (row.uuid.clone(), row))
+            .collect();
+
+        for row in transactions.values() {
+
+            if row.json["transfer"].is_empty() {
+                continue;
+            }
+
+            let partner = match transactions.get(&row.json["transfer"].to_string()) {
+                Some(partner) => partner,
+                // Broken reference
+                None => continue
+            };
+
+            let partner_transfer = partner.json["transfer"].to_string();
+
+            if partner_transfer == row.uuid {
+                continue;
+            }
+
+            let detail = format!("transfer {}", partner.uuid);
+
+            if partner.json["transfer"].is_empty() {
+
+                let mut json = partner.json.clone();
+                json["transfer"] = row.uuid.clone().into();
+
+                let repair = Repair::ReplaceRow(partner.line_with(json));
+
+                issues.push(partner.issue(IssueKind::AsymmetricTransfer, format!("transfer {}", row.uuid), Some(repair)));
+            } else if transactions.contains_key(&partner_transfer) {
+                issues.push(row.issue(IssueKind::AsymmetricTransfer, detail, Some(row.drop_field("transfer"))));
+            }
+        }
+    }
+
+    // Return all problems found on storage
+    pub fn run(storage: &mut Storage) -> Vec<Issue> {
+
+        let (rows, mut issues) = Check::parse_rows(storage);
+
+        Check::check_duplicates(&rows, &mut issues);
+        Check::check_references(&rows, &mut issues);
+        Check::check_transfers(&rows, &mut issues);
+
+        issues
+    }
+
+    // Apply the repair of issue
+    pub fn repair(storage: &mut Storage, issue: &Issue) -> Result<(), Error> {
+
+        match issue.repair.clone() {
+            Some(Repair::RemoveRow) => storage.replace_lines(vec![(issue.line.clone(), None)]),
+            Some(Repair::ReplaceRow(line)) => storage.replace_lines(vec![(issue.line.clone(), Some(line))]),
+            None => Err(Error::Invalid("storage_check_no_repair"))
+        }
+    }
+
+    // Apply all repairs, until nothing more can be repaired. A repair can
+    // uncover other issues, like the transfer of a removed transaction.
+    // Return the number of repairs
+    pub fn repair_all(storage: &mut Storage) -> usize {
+
+        let mut repaired = 0;
+
+        loop {
+
+            let mut changed = false;
+
+            for issue in Check::run(storage) {
+                // Other repair may have changed the same line
+                if issue.repair.is_some() && Check::repair(storage, &issue).is_ok() {
+                    repaired += 1;
+                    changed = true;
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        repaired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::fs::File;
+    use std::io::Write;
+    use backend::storage::RawRow;
+    use backend::journal::Journal;
+
+    // Storage with the lines written direct on file
+    fn populate(lines: Vec<&str>) -> Storage {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        let mut file = File::create(&path).unwrap();
+
+        for line in lines {
+            file.write_fmt(format_args!("{}\n", line)).unwrap();
+        }
+
+        Storage::new(path)
+    }
+
+    fn kinds(issues: &Vec<Issue>) -> Vec<IssueKind> {
+        issues.iter().map(|issue| issue.kind.clone()).collect()
+    }
+
+    #[test]
+    fn valid_storage() {
+
+        let mut st = populate(vec![
+            "",
+            "::section::accounts",
+            "a0000000-0000-4000-8000-000000000001 {\"bank\":\"b\",\"name\":\"n\",\"open_balance\":0,\"open_balance_date\":\"2018-01-01\",\"currency\":\"$\"}",
+            "",
+            "::section::contacts",
+            "c0000000-0000-4000-8000-000000000001 {\"name\":\"n\",\"city_location\":\"c\"}",
+            "",
+            "::section::transactions",
+            "10000000-0000-4000-8000-000000000001 {\"account\":\"a0000000-0000-4000-8000-000000000001\",\"description\":\"d\",\"value\":1,\"deadline\":\"2018-01-01\",\"created_at\":\"2018-01-01T00:00:00-02:00\",\"contact\":\"c0000000-0000-4000-8000-000000000001\"}"
+        ]);
+
+        assert_eq!(Check::run(&mut st).len(), 0);
+    }
+
+    #[test]
+    fn invalid_rows_and_duplicates() {
+
+        let mut st = populate(vec![
+            "",
+            "::section::tags",
+            "b0000000-0000-4000-8000-000000000001 {\"name\":\"tag 1\"}",
+            "not a uuid {\"name\":\"tag 2\"}",
+            "b0000000-0000-4000-8000-000000000002 {\"name\":",
+            "b0000000-0000-4000-8000-000000000003 {}",
+            "b0000000-0000-4000-8000-000000000001 {\"name\":\"tag 1\"}",
+            "b0000000-0000-4000-8000-000000000001 {\"name\":\"other tag\"}"
+        ]);
+
+        let issues = Check::run(&mut st);
+
+        assert_eq!(kinds(&issues), vec![
+            IssueKind::InvalidRow,
+            IssueKind::InvalidRow,
+            IssueKind::InvalidRow,
+            IssueKind::DuplicatedUuid,
+            IssueKind::DuplicatedUuid
+        ]);
+
+        assert_eq!(issues[3].repair, Some(Repair::RemoveRow));
+
+        assert_eq!(Check::repair_all(&mut st), 5);
+        assert_eq!(Check::run(&mut st).len(), 0);
+
+        // The different copy was kept with a new uuid
+        let tags: Vec<String> = st.lines().into_iter().filter(|line| line.contains("\"name\"")).collect();
+
+        assert_eq!(tags.len(), 2);
+        assert!(tags[1].ends_with("{\"name\":\"other tag\"}"));
+        assert!(!tags[1].starts_with("b0000000-0000-4000-8000-000000000001"));
+    }
+
+    #[test]
+    fn trash_rows() {
+
+        I18n::config("en_US".to_string());
+
+        let mut st = populate(vec![
+            "",
+            "::section::trash_tags",
+            "b0000000-0000-4000-8000-000000000001 {\"name\":\"tag 1\",\"deleted_at\":\"2018-01-01T00:00:00-02:00\"}",
+            "b0000000-0000-4000-8000-000000000002 {\"name\":\"tag 2\"}",
+            "b0000000-0000-4000-8000-000000000003 {\"deleted_at\":\"2018-01-01T00:00:00-02:00\"}",
+            "",
+            "::section::trash_transactions",
+            "10000000-0000-4000-8000-000000000001 {\"account\":\"a0000000-0000-4000-8000-000000000001\",\"description\":\"d\",\"value\":1,\"deadline\":\"2018-01-01\",\"created_at\":\"2018-01-01T00:00:00-02:00\",\"deleted_at\":\"2018-01-01T00:00:00-02:00\"}"
+        ]);
+
+        let issues = Check::run(&mut st);
+
+        assert_eq!(kinds(&issues), vec![
+            IssueKind::InvalidRow,
+            IssueKind::InvalidRow,
+            IssueKind::InvalidRow
+        ]);
+
+        assert_eq!(issues[0].section, "trash_tags".to_string());
+        assert_eq!(issues[0].detail, "Field not found: deleted_at".to_string());
+        assert_eq!(issues[1].detail, "Field not found: name".to_string());
+        assert_eq!(issues[2].detail, "Field not found: contact".to_string());
+    }
+
+    #[test]
+    fn references_and_transfers() {
+
+        let account = "a0000000-0000-4000-8000-000000000001";
+
+        let transaction = |uuid: &str, extra: &str| format!(
+            "{} {{\"account\":\"{}\",\"description\":\"d\",\"value\":1,\"deadline\":\"2018-01-01\",\"created_at\":\"2018-01-01T00:00:00-02:00\"{}}}",
+            uuid, account, extra
+        );
+
+        let lines = vec![
+            "".to_string(),
+            "::section::accounts".to_string(),
+            format!("{} {{\"bank\":\"b\",\"name\":\"n\",\"open_balance\":0,\"open_balance_date\":\"2018-01-01\",\"currency\":\"$\"}}", account),
+            "".to_string(),
+            "::section::contacts".to_string(),
+            "c0000000-0000-4000-8000-000000000001 {\"name\":\"n\",\"city_location\":\"c\"}".to_string(),
+            "".to_string(),
+            "::section::transactions".to_string(),
+            // Contact removed
+            transaction("10000000-0000-4000-8000-000000000001", ",\"contact\":\"c0000000-0000-4000-8000-000000000002\""),
+            // Merged in a removed transaction and a removed tag
+            transaction("10000000-0000-4000-8000-000000000002", ",\"contact\":\"c0000000-0000-4000-8000-000000000001\",\"merged_in\":\"10000000-0000-4000-8000-000000000009\",\"tags\":[\"b0000000-0000-4000-8000-000000000001\"]"),
+            // Partner don't point back
+            transaction("10000000-0000-4000-8000-000000000003", ",\"transfer\":\"10000000-0000-4000-8000-000000000004\""),
+            transaction("10000000-0000-4000-8000-000000000004", ",\"contact\":\"c0000000-0000-4000-8000-000000000001\""),
+            // Partner removed
+            transaction("10000000-0000-4000-8000-000000000005", ",\"transfer\":\"10000000-0000-4000-8000-000000000008\"")
+        ];
+
+        let mut st = populate(lines.iter().map(|line| line.as_str()).collect());
+
+        let issues = Check::run(&mut st);
+
+        assert_eq!(kinds(&issues), vec![
+            IssueKind::BrokenReference,
+            IssueKind::BrokenReference,
+            IssueKind::BrokenReference,
+            IssueKind::BrokenReference,
+            IssueKind::AsymmetricTransfer
+        ]);
+
+        // Transactions without contact and transfer can't exist
+        assert_eq!(issues[0].repair, Some(Repair::RemoveRow));
+        assert_eq!(issues[3].repair, Some(Repair::RemoveRow));
+
+        Check::repair_all(&mut st);
+
+        assert_eq!(Check::run(&mut st).len(), 0);
+
+        let rows: Vec<String> = st.lines().iter()
+            .filter(|line| line.starts_with("10000000"))
+            .cloned()
+            .collect();
+
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], transaction("10000000-0000-4000-8000-000000000002", ",\"contact\":\"c0000000-0000-4000-8000-000000000001\",\"tags\":[]"));
+        assert!(rows[2].contains("\"transfer\":\"10000000-0000-4000-8000-000000000003\""));
+    }
+
+    #[test]
+    fn repair_and_undo() {
+
+        let transaction = "10000000-0000-4000-8000-000000000001";
+
+        let mut st = populate(vec![
+            "",
+            "::section::accounts",
+            "a0000000-0000-4000-8000-000000000001 {\"bank\":\"b\",\"name\":\"n\",\"open_balance\":0,\"open_balance_date\":\"2018-01-01\",\"currency\":\"$\"}",
+            "",
+            "::section::contacts",
+            "c0000000-0000-4000-8000-000000000001 {\"name\":\"n\",\"city_location\":\"c\"}",
+            "",
+            "::section::transactions",
+            "10000000-0000-4000-8000-000000000001 {\"account\":\"a0000000-0000-4000-8000-000000000001\",\"description\":\"d\",\"value\":1,\"deadline\":\"2018-01-01\",\"created_at\":\"2018-01-01T00:00:00-02:00\",\"contact\":\"c0000000-0000-4000-8000-000000000001\",\"tags\":[\"b0000000-0000-4000-8000-000000000001\"]}"
+        ]);
+
+        let mut row = parse(&st.row("transactions", transaction).unwrap()[37..]).unwrap();
+        row["description"] = "changed".into();
+
+        st.get_section_data("transactions".to_string())
+            .save(RawRow { uuid: transaction.to_string(), json: row }).unwrap();
+
+        // The tag removed is taken out of the row without record
+        assert_eq!(Check::repair_all(&mut st), 1);
+
+        // But the change made before the repair can still be undone
+        assert!(Journal::undo(&mut st).unwrap().is_some());
+        assert!(st.row("transactions", transaction).unwrap().contains("\"description\":\"d\""));
+
+        assert!(Journal::redo(&mut st).unwrap().is_some());
+
+        // A removed row has no image to follow, so nothing before it can be undone
+        let line = st.row("transactions", transaction).unwrap();
+
+        st.replace_lines(vec![(line, None)]).unwrap();
+
+        assert_eq!(Journal::undo(&mut st), Ok(None));
+        assert_eq!(Journal::applied_changes(&st).unwrap().len(), 1);
+    }
+}
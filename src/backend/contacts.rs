@@ -7,6 +7,8 @@
 ///
 
 use backend::storage::*;
+use backend::error::Error;
+use backend::references::*;
 use json::JsonValue;
 
 #[derive(Default, Clone, Debug)]
@@ -18,21 +20,15 @@ pub struct Contact {
 
 impl Model for Contact {
 
-    fn new(row: JsonValue, uuid: String, _storage: &mut Storage, _can_recursive: bool) -> Contact {
+    fn new(row: JsonValue, uuid: String, _storage: &mut Storage, _can_recursive: bool) -> Result<Contact, Error> {
 
-        if row["name"].is_null() {
-            panic!("Name not found into a row(id {}) contact", uuid);
-        }
-
-        if row["city_location"].is_null() {
-            panic!("City location not found into a row(id {}) contact", uuid);
-        }
+        Data::require(&row, "contacts", &uuid, vec!["name", "city_location"])?;
 
-        Contact {
+        Ok(Contact {
             uuid: uuid,
             name: row["name"].to_string(),
             city_location: row["city_location"].to_string(),
-        }
+        })
     }
 
     fn to_save(self) -> (String, bool, JsonValue) {
@@ -46,59 +42,82 @@ impl Model for Contact {
 
 impl Contact {
 
-    // Short version of the uuid
-    pub fn id(self) -> String {
-        Data::uuid_to_id(self.uuid)
+    // Shortest unique prefix of the uuid
+    pub fn id(self, storage: &mut Storage) -> String {
+        storage.short_id("contacts", &self.uuid)
     }
 
     // Return a list with all contacts
-    pub fn get_contacts(storage: &mut Storage) -> Vec<Contact> {
+    pub fn get_contacts(storage: &mut Storage) -> Result<Vec<Contact>, Error> {
 
-        storage.start_section("contacts".to_string());
+        storage.start_section("contacts".to_string())?;
 
         let mut data = storage.get_section_data("contacts".to_string());
 
-        let mut list: Vec<Contact> = vec![];
-
-        while let Ok(line) = data.next::<Contact>() {
-            list.push(line);
-        }
-
-        return list;
+        data.all::<Contact>()
     }
 
     // Return the contact of id
-    pub fn get_contact(storage: &mut Storage, uuid: String) -> Result<Contact, &'static str> {
+    pub fn get_contact(storage: &mut Storage, uuid: String) -> Result<Contact, Error> {
 
-        storage.start_section("contacts".to_string());
+        storage.start_section("contacts".to_string())?;
 
         let mut data = storage.get_section_data("contacts".to_string());
 
-        if data.find_by_id(uuid) {
+        if data.find_by_id(uuid.clone())? {
             return data.next::<Contact>();
         }
 
-        Err("Contact not found")
+        Err(Error::NotFound("contacts".to_string(), uuid))
     }
 
     // Save updates, or create new, contact on storage
-    pub fn store_contact(storage: &mut Storage, contact: Contact) {
+    pub fn store_contact(storage: &mut Storage, contact: Contact) -> Result<String, Error> {
 
-        storage.start_section("contacts".to_string());
+        storage.start_section("contacts".to_string())?;
 
         let mut data = storage.get_section_data("contacts".to_string());
 
-        data.save(contact);
+        data.save(contact)
     }
 
-    // Remvoe contact of storage
-    pub fn remove_contact(storage: &mut Storage, uuid: String) {
+    // Remvoe contact of storage, after handle the rows that point to it
+    pub fn remove_contact(storage: &mut Storage, uuid: String, on_remove: OnRemove) -> Result<(), Error> {
 
-        storage.start_section("contacts".to_string());
+        let contact = Contact::get_contact(storage, uuid)?;
+
+        References::release(storage, "contacts", &contact.uuid, on_remove)?;
 
         let mut data = storage.get_section_data("contacts".to_string());
 
-        data.remove_by_id(uuid);
+        data.remove_by_id(contact.uuid)
+    }
+
+    // Point all rows of the dropped contacts to the kept one and remove them
+    pub fn merge_contacts(storage: &mut Storage, keep: String, drop: Vec<String>) -> Result<(), Error> {
+
+        let keep = Contact::get_contact(storage, keep)?;
+        let mut dropped: Vec<String> = vec![];
+
+        // All ids are checked before the first change
+        for id in drop {
+
+            let contact = Contact::get_contact(storage, id)?;
+
+            if contact.uuid == keep.uuid {
+                return Err(Error::Invalid("error_merge_into_itself"));
+            }
+
+            if !dropped.contains(&contact.uuid) {
+                dropped.push(contact.uuid);
+            }
+        }
+
+        for uuid in dropped {
+            Contact::remove_contact(storage, uuid, OnRemove::ReassignTo(keep.uuid.clone()))?;
+        }
+
+        Ok(())
     }
 }
 
@@ -107,23 +126,21 @@ mod tests {
 
     use super::*;
     use uuid::Uuid;
-    use std::collections::HashMap;
-    use std::cell::RefCell;
 
     fn populate() -> String {
 
         let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
 
-        let mut st = Storage { path_str: path.clone(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(path.clone());
 
-        assert!(st.start_section("contacts".to_string()));
+        assert!(st.start_section("contacts".to_string()).is_ok());
 
         let mut data = st.get_section_data("contacts".to_string());
 
-        data.save(Contact { uuid: "".to_string(), name: "contact 1".to_string(), city_location: "city A".to_string() });
-        data.save(Contact { uuid: "".to_string(), name: "contact 2".to_string(), city_location: "city B".to_string() });
-        data.save(Contact { uuid: "".to_string(), name: "contact 3".to_string(), city_location: "city C".to_string() });
-        data.save(Contact { uuid: "".to_string(), name: "contact 4".to_string(), city_location: "city D".to_string() });
+        data.save(Contact { uuid: "".to_string(), name: "contact 1".to_string(), city_location: "city A".to_string() }).unwrap();
+        data.save(Contact { uuid: "".to_string(), name: "contact 2".to_string(), city_location: "city B".to_string() }).unwrap();
+        data.save(Contact { uuid: "".to_string(), name: "contact 3".to_string(), city_location: "city C".to_string() }).unwrap();
+        data.save(Contact { uuid: "".to_string(), name: "contact 4".to_string(), city_location: "city D".to_string() }).unwrap();
 
         path
     }
@@ -131,9 +148,9 @@ mod tests {
     #[test]
     fn get_contacts() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
-        let contacts = Contact::get_contacts(&mut st);
+        let contacts = Contact::get_contacts(&mut st).unwrap();
 
         assert_eq!(contacts[0].name, "contact 4".to_string());
         assert_eq!(contacts[0].city_location, "city D".to_string());
@@ -148,9 +165,9 @@ mod tests {
     #[test]
     fn get_contact() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
-        let contacts = Contact::get_contacts(&mut st);
+        let contacts = Contact::get_contacts(&mut st).unwrap();
 
         let uuid = contacts[0].uuid.clone();
 
@@ -169,11 +186,11 @@ mod tests {
     #[test]
     fn store_contact() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
-        Contact::store_contact(&mut st, Contact { uuid: "".to_string(), name: "contact 5".to_string(), city_location: "city E".to_string() });
+        Contact::store_contact(&mut st, Contact { uuid: "".to_string(), name: "contact 5".to_string(), city_location: "city E".to_string() }).unwrap();
 
-        let contacts = Contact::get_contacts(&mut st);
+        let contacts = Contact::get_contacts(&mut st).unwrap();
 
         assert_eq!(contacts[0].name, "contact 5".to_string());
         assert_eq!(contacts[0].city_location, "city E".to_string());
@@ -184,9 +201,9 @@ mod tests {
     #[test]
     fn remove_contact() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
-        let contacts = Contact::get_contacts(&mut st);
+        let contacts = Contact::get_contacts(&mut st).unwrap();
 
         let uuid = contacts[0].uuid.clone();
 
@@ -194,7 +211,7 @@ mod tests {
 
         assert!(contact.is_ok());
 
-        Contact::remove_contact(&mut st, uuid.clone());
+        Contact::remove_contact(&mut st, uuid.clone(), OnRemove::Refuse).unwrap();
 
         let contacte = Contact::get_contact(&mut st, uuid.clone());
 
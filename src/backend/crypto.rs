@@ -0,0 +1,175 @@
+///
+/// Blitz Money
+///
+/// Backend of module for encrypt the storage contents
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use std::env;
+use argon2::Argon2;
+use chacha20poly1305::{ XChaCha20Poly1305, XNonce, Key, KeyInit, AeadCore };
+use chacha20poly1305::aead::{ Aead, OsRng };
+use chacha20poly1305::aead::rand_core::RngCore;
+use hex;
+use rpassword;
+use i18n::*;
+use backend::error::Error;
+
+// Header of the encrypted contents
+pub const ENCRYPTED_HEADER: &'static str = "::encrypted::v1";
+
+// Representation of the key, derived from the passphrase, used
+// for encrypt the storage and the journal
+#[derive(Clone)]
+pub struct Crypto {
+    pub salt: Vec<u8>,
+    key: Vec<u8>
+}
+
+impl Crypto {
+
+    // Derive a new key from the passphrase with a random salt
+    pub fn new(passphrase: String) -> Crypto {
+
+        let mut salt = vec![0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+
+        Crypto::with_salt(passphrase, salt)
+    }
+
+    // Derive the key from the passphrase and the salt
+    pub fn with_salt(passphrase: String, salt: Vec<u8>) -> Crypto {
+
+        let mut key = vec![0u8; 32];
+
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), &salt, &mut key)
+            .expect("Couldn't derive the key from passphrase");
+
+        Crypto { salt: salt, key: key }
+    }
+
+    // Return the passphrase from the BMONEY_PASSPHRASE env var or ask it on terminal
+    pub fn passphrase(confirm: bool) -> Result<String, Error> {
+
+        let read = |key: &'static str| rpassword::prompt_password(format!("{}: ", I18n::text(key)))
+            .map_err(|e| Error::Io("error_passphrase_read", e.to_string()));
+
+        let passphrase = match env::var_os("BMONEY_PASSPHRASE") {
+            Some(passphrase) => passphrase.into_string()
+                .map_err(|_| Error::Invalid("error_invalid_passphrase_env"))?,
+            None => {
+
+                let passphrase = read("storage_passphrase")?;
+
+                if confirm && read("storage_passphrase_confirm")? != passphrase {
+                    return Err(Error::Invalid("error_passphrases_dont_match"));
+                }
+
+                passphrase
+            }
+        };
+
+        if passphrase.is_empty() {
+            return Err(Error::Invalid("error_passphrase_empty"));
+        }
+
+        Ok(passphrase)
+    }
+
+    // Check if the contents of a file is encrypted
+    pub fn is_encrypted(content: &str) -> bool {
+        content.starts_with(ENCRYPTED_HEADER)
+    }
+
+    // Return the salt of the encrypted contents
+    pub fn salt_of(content: &str) -> Result<Vec<u8>, &'static str> {
+
+        let header = content.lines().next().unwrap_or("");
+
+        match header.split(' ').nth(1) {
+            Some(salt) => hex::decode(salt.trim()).map_err(|_| "Invalid salt on encrypted contents"),
+            None => Err("Salt not found on encrypted contents")
+        }
+    }
+
+    // Encrypt the text and return the nonce + encrypted text, both in hex
+    pub fn encrypt(&self, text: &str) -> String {
+
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&self.key));
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+
+        let encrypted = cipher.encrypt(&nonce, text.as_bytes())
+            .expect("Couldn't encrypt the contents");
+
+        format!("{} {}", hex::encode(nonce), hex::encode(encrypted))
+    }
+
+    // Decrypt the nonce + encrypted text made by #encrypt
+    pub fn decrypt(&self, text: &str) -> Result<String, &'static str> {
+
+        let parts: Vec<&str> = text.trim().split(' ').collect();
+
+        if parts.len() != 2 {
+            return Err("Invalid encrypted contents");
+        }
+
+        let nonce = hex::decode(parts[0]).map_err(|_| "Invalid nonce on encrypted contents")?;
+        let encrypted = hex::decode(parts[1]).map_err(|_| "Invalid encrypted contents")?;
+
+        if nonce.len() != 24 {
+            return Err("Invalid nonce on encrypted contents");
+        }
+
+        let cipher = XChaCha20Poly1305::new(Key::from_slice(&self.key));
+
+        let plain = cipher.decrypt(XNonce::from_slice(&nonce), encrypted.as_ref())
+            .map_err(|_| "Couldn't decrypt the contents, wrong passphrase?")?;
+
+        String::from_utf8(plain).map_err(|_| "Invalid encrypted contents")
+    }
+
+    // Encrypt the full contents of a file, with the header
+    pub fn encrypt_file(&self, content: &str) -> String {
+        format!("{} {}\n{}\n", ENCRYPTED_HEADER, hex::encode(&self.salt), self.encrypt(content))
+    }
+
+    // Decrypt the full contents of a file made by #encrypt_file
+    pub fn decrypt_file(&self, content: &str) -> Result<String, &'static str> {
+
+        match content.lines().nth(1) {
+            Some(body) => self.decrypt(body),
+            None => Err("Invalid encrypted contents")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn encrypt_decrypt() {
+
+        let crypto = Crypto::new("my secret".to_string());
+
+        let content = crypto.encrypt_file("::section::accounts\nline 1\n");
+
+        assert!(Crypto::is_encrypted(&content));
+        assert!(!content.contains("accounts"));
+
+        let salt = Crypto::salt_of(&content).unwrap();
+
+        assert_eq!(salt, crypto.salt);
+
+        // Same passphrase
+        let other = Crypto::with_salt("my secret".to_string(), salt.clone());
+        assert_eq!(other.decrypt_file(&content), Ok("::section::accounts\nline 1\n".to_string()));
+
+        // Wrong passphrase
+        let wrong = Crypto::with_salt("not my secret".to_string(), salt);
+        assert!(wrong.decrypt_file(&content).is_err());
+    }
+}
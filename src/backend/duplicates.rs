@@ -0,0 +1,183 @@
+///
+/// Blitz Money
+///
+/// Backend of module for find records that are likely the same, like
+/// the contacts created with small differences by the imports
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+// Minimum similarity for two names be suggested as duplicates
+const MIN_SIMILARITY: f32 = 0.8;
+
+// Words of company names that don't tell them apart
+const IGNORED_WORDS: [&str; 9] = ["ltda", "me", "epp", "eireli", "sa", "s/a", "inc", "llc", "ltd"];
+
+pub struct Duplicates {}
+
+impl Duplicates {
+
+    // Name without case, accents, punctuation and company suffixes
+    pub fn normalize(name: &str) -> String {
+
+        let plain: String = name.to_lowercase()
+            .chars()
+            .map(|c| match c {
+                'á' | 'à' | 'â' | 'ã' | 'ä' => 'a',
+                'é' | 'è' | 'ê' | 'ë' => 'e',
+                'í' | 'ì' | 'î' | 'ï' => 'i',
+                'ó' | 'ò' | 'ô' | 'õ' | 'ö' => 'o',
+                'ú' | 'ù' | 'û' | 'ü' => 'u',
+                'ç' => 'c',
+                'ñ' => 'n',
+                c if c.is_alphanumeric() || c == '/' => c,
+                _ => ' '
+            })
+            .collect();
+
+        plain.split_whitespace()
+            .filter(|word| !IGNORED_WORDS.contains(word))
+            .map(|word| word.replace("/", ""))
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    // Similarity of the normalized names, from 0 to 1, by the edit distance
+    pub fn similarity(a: &str, b: &str) -> f32 {
+
+        let a: Vec<char> = Duplicates::normalize(a).chars().collect();
+        let b: Vec<char> = Duplicates::normalize(b).chars().collect();
+
+        if a == b {
+            return 1.0;
+        }
+
+        if a.is_empty() || b.is_empty() {
+            return 0.0;
+        }
+
+        // Names with other numbers, like two branches of a store, are not the same
+        let numbers = |name: &Vec<char>| -> Vec<String> {
+            name.iter()
+                .map(|c| if c.is_numeric() { *c } else { ' ' })
+                .collect::<String>()
+                .split_whitespace()
+                .map(|number| number.to_string())
+                .collect()
+        };
+
+        if numbers(&a) != numbers(&b) {
+            return 0.0;
+        }
+
+        // Levenshtein distance, keeping only the previous row
+        let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+
+        for i in 0..a.len() {
+
+            let mut current: Vec<usize> = vec![i + 1];
+
+            for j in 0..b.len() {
+
+                let cost = match a[i] == b[j] {
+                    true => 0,
+                    false => 1
+                };
+
+                current.push((previous[j] + cost)
+                    .min(previous[j + 1] + 1)
+                    .min(current[j] + 1));
+            }
+
+            previous = current;
+        }
+
+        1.0 - previous[b.len()] as f32 / a.len().max(b.len()) as f32
+    }
+
+    // Groups of records, by uuid and name, that are likely duplicates. The
+    // records keep the order of the list, so the first is the suggestion to keep
+    pub fn suggest(records: Vec<(String, String)>) -> Vec<Vec<(String, String)>> {
+
+        // Group of each record, similar records are joined on one group
+        let mut groups: Vec<usize> = (0..records.len()).collect();
+
+        for i in 0..records.len() {
+            for j in 0..i {
+                if groups[i] != groups[j] && Duplicates::similarity(&records[i].1, &records[j].1) >= MIN_SIMILARITY {
+
+                    let (from, to) = (groups[i], groups[j]);
+
+                    for group in groups.iter_mut() {
+                        if *group == from {
+                            *group = to;
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut suggestions: Vec<Vec<(String, String)>> = vec![];
+        let mut found: Vec<usize> = vec![];
+
+        for (i, record) in records.iter().enumerate() {
+            match found.iter().position(|group| *group == groups[i]) {
+                Some(position) => suggestions[position].push(record.clone()),
+                None => {
+                    found.push(groups[i]);
+                    suggestions.push(vec![record.clone()]);
+                }
+            }
+        }
+
+        // A record alone on the group has no duplicates
+        suggestions.into_iter()
+            .filter(|suggestion| suggestion.len() > 1)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn record(uuid: &str, name: &str) -> (String, String) {
+        (uuid.to_string(), name.to_string())
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(Duplicates::normalize("Mercado X Ltda."), "mercado x");
+        assert_eq!(Duplicates::normalize("  MERCADO   X "), "mercado x");
+        assert_eq!(Duplicates::normalize("Padaria São João S/A"), "padaria sao joao");
+    }
+
+    #[test]
+    fn similarity() {
+        assert_eq!(Duplicates::similarity("MERCADO X", "Mercado X Ltda"), 1.0);
+        assert!(Duplicates::similarity("Posto Shell", "Posto Shel") >= MIN_SIMILARITY);
+        assert!(Duplicates::similarity("Posto Shell", "Posto Ipiranga") < MIN_SIMILARITY);
+        assert_eq!(Duplicates::similarity("Loja 1", "Loja 2"), 0.0);
+        assert_eq!(Duplicates::similarity("", "Posto"), 0.0);
+    }
+
+    #[test]
+    fn suggest() {
+
+        let suggestions = Duplicates::suggest(vec![
+            record("1", "MERCADO X"),
+            record("2", "Posto Shell"),
+            record("3", "Mercado X Ltda"),
+            record("4", "Padaria"),
+            record("5", "Posto Shel"),
+            record("6", "mercado x")
+        ]);
+
+        assert_eq!(suggestions, vec![
+            vec![record("1", "MERCADO X"), record("3", "Mercado X Ltda"), record("6", "mercado x")],
+            vec![record("2", "Posto Shell"), record("5", "Posto Shel")]
+        ]);
+    }
+}
@@ -0,0 +1,156 @@
+///
+/// Blitz Money
+///
+/// Backend of module for the engines where the rows of storage are kept
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use backend::crypto::Crypto;
+use backend::error::Error;
+use backend::text_engine::TextEngine;
+use backend::sqlite_engine::SqliteEngine;
+
+// Place where the rows of storage are kept. The rows are exchanged like
+// the lines of the text format: the uuid, one space and the json of fields.
+// All changes are persisted before the method returns, unless made
+// between #begin and #commit
+pub trait Engine {
+
+    // Read the rows, if not already read. Fails when the file can't be
+    // read or decrypted
+    fn load(&mut self) -> Result<(), Error>;
+
+    // Discard what was read, so the next use reads the file again
+    fn reset(&mut self);
+
+    // Key of the file, only when is encrypted
+    fn crypto(&self) -> Option<&Crypto>;
+
+    // Passphrase for read the encrypted file, without ask it
+    fn set_passphrase(&mut self, passphrase: String);
+
+    // Encrypt the file with a new key
+    fn encrypt(&mut self, crypto: Crypto) -> Result<(), Error>;
+
+    // Decrypt the file
+    fn decrypt(&mut self) -> Result<(), Error>;
+
+    // Names of the sections, in the order of creation
+    fn sections(&mut self) -> Vec<String>;
+
+    // Creates the section, if does not exists
+    fn start_section(&mut self, name: &str) -> Result<(), Error>;
+
+    // Lines of all rows of section, the newest first
+    fn rows(&mut self, section: &str) -> Vec<String>;
+
+    // Line of the row of section
+    fn row(&mut self, section: &str, uuid: &str) -> Option<String>;
+
+    // Uuids of the section that start with the prefix, sorted
+    fn with_prefix(&mut self, section: &str, prefix: &str) -> Vec<String>;
+
+    // The uuids of section right before and after the uuid, on the sorted order
+    fn neighbours(&mut self, section: &str, uuid: &str) -> Vec<String>;
+
+    // Add the row as the newest of section
+    fn insert(&mut self, section: &str, line: String) -> Result<(), Error>;
+
+    // Replace the row of section. Return the line before the change
+    fn update(&mut self, section: &str, uuid: &str, line: String) -> Result<Option<String>, Error>;
+
+    // Remove the row of section. Return the removed line
+    fn remove(&mut self, section: &str, uuid: &str) -> Result<Option<String>, Error>;
+
+    // Replace, or remove when None, the row with the exact line. Used for the
+    // rows that can't be found by uuid. Return false if the line was not found
+    fn replace_line(&mut self, expected: &str, line: Option<String>) -> Result<bool, Error>;
+
+    // Value of the metadata key
+    fn metadata(&self, key: &str) -> Option<String>;
+
+    // Create or update the value of the metadata key
+    fn set_metadata(&mut self, key: &str, value: String) -> Result<(), Error>;
+
+    // All metadata, sections and rows in the text format
+    fn lines(&mut self) -> Vec<String>;
+
+    // Replace all metadata, sections and rows with the lines of text format
+    fn replace_all(&mut self, lines: Vec<String>) -> Result<(), Error>;
+
+    // Start to group the changes, they are only persisted on the #commit
+    fn begin(&mut self);
+
+    // Persist the changes made after the #begin
+    fn commit(&mut self) -> Result<(), Error>;
+
+    // Discard the changes made after the #begin
+    fn rollback(&mut self);
+}
+
+// Kinds of engine
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EngineKind {
+    Text,
+    Sqlite
+}
+
+impl EngineKind {
+
+    // Kind by the name, like on the --storage-engine option
+    pub fn from_name(name: &str) -> Result<EngineKind, Error> {
+        match name.trim() {
+            "text" => Ok(EngineKind::Text),
+            "sqlite" => Ok(EngineKind::Sqlite),
+            _ => Err(Error::Invalid("error_invalid_engine"))
+        }
+    }
+
+    // Kind of the file. An existing file is checked by the contents,
+    // a new one by the extension
+    pub fn of(path_str: &str) -> EngineKind {
+
+        if let Ok(file) = File::open(path_str) {
+
+            let mut header: Vec<u8> = vec![];
+
+            if file.take(16).read_to_end(&mut header).is_ok() && !header.is_empty() {
+                if header == b"SQLite format 3\0" {
+                    return EngineKind::Sqlite;
+                }
+
+                return EngineKind::Text;
+            }
+        }
+
+        match Path::new(path_str).extension().and_then(|extension| extension.to_str()) {
+            Some("sqlite") | Some("sqlite3") | Some("db") => EngineKind::Sqlite,
+            _ => EngineKind::Text
+        }
+    }
+
+    // Name of the kind
+    pub fn name(&self) -> &'static str {
+        match self {
+            EngineKind::Text => "text",
+            EngineKind::Sqlite => "sqlite"
+        }
+    }
+
+    // Engine of this kind for the file
+    pub fn open(&self, path_str: String) -> Box<dyn Engine> {
+        match self {
+            EngineKind::Text => Box::new(TextEngine::new(path_str)),
+            EngineKind::Sqlite => Box::new(SqliteEngine::new(path_str))
+        }
+    }
+}
+
+// Split the line into the uuid and the json
+pub fn split_line(line: &str) -> (String, String) {
+    (line.chars().take(36).collect(), line.chars().skip(37).collect())
+}
@@ -0,0 +1,90 @@
+///
+/// Blitz Money
+///
+/// Errors of backend
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use std::fmt;
+use i18n::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    // Section and id of the row not found
+    NotFound(String, String),
+    // Section, id given and the uuids of all rows with the id as prefix
+    AmbiguousId(String, String, Vec<String>),
+    // Section, uuid and field not found on the row
+    FieldNotFound(String, String, String),
+    // Section, uuid and field of the row with a invalid value
+    InvalidField(String, String, String),
+    // Section and uuid of the row that isn't a valid json
+    InvalidRow(String, String),
+    // No more rows on section, returned by Data#next
+    EndOfSection,
+    // Pid of the process that holds the lock of storage, if known
+    Locked(Option<String>),
+    // Key of the message, on langs, of a operation that can't be done
+    Invalid(&'static str),
+    // Section and uuid of the row that can't be removed and the
+    // number of rows, by section, that point to it
+    HasDependents(String, String, Vec<(String, usize)>),
+    // Currency, target currency and date without a exchange rate
+    MissingRate(String, String, String),
+    // Key of the message, on langs, of a file that can't be read or
+    // written and the cause
+    Io(&'static str, String),
+    // Key of the message, on langs, of a value typed by the user that
+    // can't be parsed and the expected format
+    InvalidInput(&'static str, String)
+}
+
+impl Error {
+
+    // Localized message of error
+    pub fn message(&self) -> String {
+        match self {
+            Error::NotFound(section, id) => format!("{}: {} {}", I18n::text("error_not_found"), section, id),
+            Error::AmbiguousId(section, id, candidates) => format!("{}: {} {}\n{}", I18n::text("error_ambiguous_id"), section, id, candidates.join("\n")),
+            Error::FieldNotFound(section, uuid, field) => format!("{}: {} {} ({})", I18n::text("error_field_not_found"), section, uuid, field),
+            Error::InvalidField(section, uuid, field) => format!("{}: {} {} ({})", I18n::text("error_invalid_field"), section, uuid, field),
+            Error::InvalidRow(section, uuid) => format!("{}: {} {}", I18n::text("error_invalid_row"), section, uuid),
+            Error::EndOfSection => I18n::text("error_end_of_section"),
+            Error::Locked(Some(pid)) => format!("{}: {}", I18n::text("error_locked_by"), pid),
+            Error::Locked(None) => I18n::text("error_locked"),
+            Error::Invalid(key) => I18n::text(key),
+            Error::HasDependents(section, uuid, summary) => {
+
+                let dependents: Vec<String> = summary.iter()
+                    .map(|(section, count)| format!("{}: {}", section, count))
+                    .collect();
+
+                format!("{}: {} {}\n{}", I18n::text("error_has_dependents"), section, uuid, dependents.join("\n"))
+            },
+            Error::MissingRate(currency, target, date) => format!("{}: {} -> {} ({})", I18n::text("error_missing_rate"), currency, target, date),
+            Error::Io(key, cause) => format!("{}: {}", I18n::text(key), cause),
+            Error::InvalidInput(key, format) => format!("{} {}", I18n::text(key), format).trim().to_string()
+        }
+    }
+
+    // Status of exit for the command that failed with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NotFound(_, _) | Error::MissingRate(_, _, _) => 2,
+            Error::FieldNotFound(_, _, _) | Error::InvalidField(_, _, _) | Error::InvalidRow(_, _) => 3,
+            Error::Locked(_) => 4,
+            Error::Invalid(_) | Error::HasDependents(_, _, _) | Error::InvalidInput(_, _) => 5,
+            Error::AmbiguousId(_, _, _) => 6,
+            Error::EndOfSection => 1,
+            Error::Io(_, _) => 7
+        }
+    }
+}
+
+impl fmt::Display for Error {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
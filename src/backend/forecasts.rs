@@ -14,6 +14,8 @@ use backend::tags::*;
 use backend::accounts::*;
 use backend::transactions::*;
 use backend::contacts::*;
+use backend::money::Money;
+use backend::error::Error;
 use json::JsonValue;
 
 #[derive(Default, Clone, Debug)]
@@ -21,31 +23,24 @@ pub struct Forecast {
    pub uuid: String,
    pub account: Option<Account>,
    pub tag: Option<Tag>,
-   pub value: f32
+   pub value: Money
 }
 
 impl Model for Forecast {
 
-    fn new(row: JsonValue, uuid: String, storage: &mut Storage, _can_recursive: bool) -> Forecast {
+    fn new(row: JsonValue, uuid: String, storage: &mut Storage, _can_recursive: bool) -> Result<Forecast, Error> {
 
-        if row["tag"].is_null() {
-            panic!("Tag not found into a row(id {}) forecast", uuid);
-        }
-
-        if row["account"].is_null() {
-            panic!("Account not found into a row(id {}) forecast", uuid);
-        }
+        Data::require(&row, "forecasts", &uuid, vec!["tag", "account", "value"])?;
 
-        if row["value"].is_null() {
-            panic!("Value not found into a row(id {}) forecast", uuid);
-        }
+        let value = Money::from_json(&row["value"])
+            .ok_or(Error::InvalidField("forecasts".to_string(), uuid.clone(), "value".to_string()))?;
 
-        Forecast {
+        Ok(Forecast {
             uuid: uuid,
-            tag: Some(Tag::get_tag(storage, row["tag"].to_string()).unwrap()),
-            account: Some(Account::get_account(storage, row["account"].to_string()).unwrap()),
-            value: row["value"].as_f32().unwrap()
-        }
+            tag: Some(Tag::get_tag(storage, row["tag"].to_string())?),
+            account: Some(Account::get_account(storage, row["account"].to_string())?),
+            value: value
+        })
     }
 
     fn to_save(self) -> (String, bool, JsonValue) {
@@ -63,95 +58,89 @@ impl Model for Forecast {
 
 impl Forecast {
 
-    // Short version of the uuid
-    pub fn id(self) -> String {
-        Data::uuid_to_id(self.uuid)
+    // Shortest unique prefix of the uuid
+    pub fn id(self, storage: &mut Storage) -> String {
+        storage.short_id("forecasts", &self.uuid)
     }
 
     // Return a list with all forecasts
-    pub fn get_forecasts(storage: &mut Storage) -> Vec<Forecast> {
+    pub fn get_forecasts(storage: &mut Storage) -> Result<Vec<Forecast>, Error> {
 
-        storage.start_section("forecasts".to_string());
+        storage.start_section("forecasts".to_string())?;
 
         let mut data = storage.get_section_data("forecasts".to_string());
 
-        let mut list: Vec<Forecast> = vec![];
-
-        while let Ok(line) = data.next::<Forecast>() {
-            list.push(line);
-        }
-
-        return list;
+        data.all::<Forecast>()
     }
 
     // Return the forecast of id
-    pub fn get_forecast(storage: &mut Storage, uuid: String) -> Result<Forecast, &'static str> {
+    pub fn get_forecast(storage: &mut Storage, uuid: String) -> Result<Forecast, Error> {
 
-        storage.start_section("forecasts".to_string());
+        storage.start_section("forecasts".to_string())?;
 
         let mut data = storage.get_section_data("forecasts".to_string());
 
-        if data.find_by_id(uuid) {
+        if data.find_by_id(uuid.clone())? {
             return data.next::<Forecast>();
         }
 
-        Err("Forecast not found")
+        Err(Error::NotFound("forecasts".to_string(), uuid))
     }
 
     // Save updates, or create new, forecast on storage
-    pub fn store_forecast(storage: &mut Storage, forecast: Forecast) {
+    pub fn store_forecast(storage: &mut Storage, forecast: Forecast) -> Result<String, Error> {
 
-        storage.start_section("forecasts".to_string());
+        storage.start_section("forecasts".to_string())?;
 
         let mut data = storage.get_section_data("forecasts".to_string());
 
-        data.save(forecast);
+        data.save(forecast)
     }
 
     // Remvoe forecast of storage
-    pub fn remove_forecast(storage: &mut Storage, uuid: String) {
+    pub fn remove_forecast(storage: &mut Storage, uuid: String) -> Result<(), Error> {
 
-        storage.start_section("forecasts".to_string());
+        storage.start_section("forecasts".to_string())?;
 
         let mut data = storage.get_section_data("forecasts".to_string());
 
-        data.remove_by_id(uuid);
+        data.remove_by_id(uuid)
     }
 
     // Make transactions for remaining values of tags
-    pub fn remaining_transactions(storage: &mut Storage, transactions: Vec<Transaction>, end_date: NaiveDate) -> Vec<Transaction> {
+    pub fn remaining_transactions(storage: &mut Storage, transactions: Vec<Transaction>, end_date: NaiveDate) -> Result<Vec<Transaction>, Error> {
 
-        let mut tags: HashMap<String, f32> = HashMap::new();
+        let mut tags: HashMap<String, Money> = HashMap::new();
         let mut remaining: Vec<Transaction> = vec![];
 
 
         for tr in transactions {
-            for tag in tr.tags {
-                let old_value = tags.entry(tag.uuid).or_insert(0.0);
+            for (tag, value) in tr.values_by_tag() {
+                let old_value = tags.entry(tag.uuid).or_insert(Money::zero());
 
-                *old_value += tr.value;
+                *old_value += value;
             }
         }
 
-        for fore in Forecast::get_forecasts(storage) {
+        for fore in Forecast::get_forecasts(storage)? {
 
             let attain = match tags.get(&fore.clone().tag.unwrap().uuid) {
-                Some(at) => at,
-                _ => &0.0
+                Some(at) => *at,
+                _ => Money::zero()
             };
 
             let mut remain = fore.value - attain;
 
-            if (fore.value < 0.0 && remain > 0.0) || (fore.value >= 0.0 && remain < 0.0) {
-                remain = 0.0;
+            if (fore.value.is_negative() && remain > Money::zero()) || (!fore.value.is_negative() && remain.is_negative()) {
+                remain = Money::zero();
             }
 
-            let percent = (attain / fore.value) * 100.0;
+            let percent = (attain.cents() as f64 / fore.value.cents() as f64) * 100.0;
 
             let account = fore.account.clone().unwrap().clone();
             let contact = Contact { uuid: "".to_string(), name: "Remaining".to_string(), city_location: "Remaining".to_string() };
 
-            let description = format!("{}: {} of {} ({:.0}%)", fore.clone().tag.unwrap().name, account.format_value(*attain), account.format_value(fore.value), percent);
+            let description = format!("{}: {} of {} ({:.0}%)", fore.clone().tag.unwrap().name, account.format_value(attain), account.format_value(fore.value), percent);
 
             remaining.push(Transaction {
                 uuid: "00000000-0000-0000-0000-000000000000".to_string(),
@@ -164,7 +153,7 @@ impl Forecast {
             });
         }
 
-        remaining
+        Ok(remaining)
     }
 }
 
@@ -181,9 +170,9 @@ mod tests {
 
         let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
 
-        let mut st = Storage { path_str: path.clone(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(path.clone());
 
-        assert!(st.start_section("forecasts".to_string()));
+        assert!(st.start_section("forecasts".to_string()).is_ok());
 
         let mut data = st.get_section_data("forecasts".to_string());
 
@@ -191,22 +180,22 @@ mod tests {
             uuid: "".to_string(),
             name: "name A".to_string(),
             value: 1000,
-        });
+        }).unwrap();
         data.save(Forecast {
             uuid: "".to_string(),
             name: "name B".to_string(),
             value: 200,
-        });
+        }).unwrap();
         data.save(Forecast {
             uuid: "".to_string(),
             name: "name D".to_string(),
             value: 50,
-        });
+        }).unwrap();
         data.save(Forecast {
             uuid: "".to_string(),
             name: "name B".to_string(),
             value: 300,
-        });
+        }).unwrap();
 
         path
     }
@@ -214,9 +203,9 @@ mod tests {
     #[test]
     fn get_forecasts() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
-        let forecasts = Forecast::get_forecasts(&mut st);
+        let forecasts = Forecast::get_forecasts(&mut st).unwrap();
 
         assert_eq!(forecasts[0].description, "forecast 4".to_string());
         assert_eq!(forecasts[0].name, "name B".to_string());
@@ -232,11 +221,11 @@ mod tests {
     #[test]
     fn apply_forecasts() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
         let mut tr = Transaction {
             description: "more name b and other".to_string(),
-            value: 150.0,
+            value: Money::from_cents(15000),
             ..Default::default()
         };
 
@@ -246,7 +235,7 @@ mod tests {
 
         let mut tr2 = Transaction {
             description: "more name not found and other".to_string(),
-            value: 10.0,
+            value: Money::from_cents(1000),
             ..Default::default()
         };
 
@@ -0,0 +1,347 @@
+///
+/// Blitz Money
+///
+/// Backend of module for the versions of the rows, made from the
+/// changes recorded on the journal
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use chrono::{ DateTime, Local };
+use json::{ parse, JsonValue };
+use backend::storage::*;
+use backend::journal::Journal;
+use backend::references::References;
+use backend::transactions::Transaction;
+use backend::accounts::Account;
+use backend::contacts::Contact;
+use backend::tags::Tag;
+use backend::rules::Rule;
+use backend::forecasts::Forecast;
+use backend::error::Error;
+
+// Change of a field from the previous revision
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Option<String>,
+    pub after: Option<String>
+}
+
+// Representation of a version of the row
+#[derive(Clone, Debug)]
+pub struct Revision {
+    // Starts on 1, the oldest version
+    pub number: usize,
+    // Empty when the version was made before the journal
+    pub changed_at: Option<DateTime<Local>>,
+    // Command that made the version
+    pub command: String,
+    // Login of who ran the command
+    pub user: String,
+    // Empty when the row was removed
+    pub row: Option<JsonValue>,
+    pub changes: Vec<FieldChange>
+}
+
+pub struct History {}
+
+impl History {
+
+    // Uuid of the row of id, on the section or on his trash
+    fn resolve(storage: &mut Storage, section: &str, id: &String) -> Result<String, Error> {
+
+        for name in vec![section.to_string(), format!("{}{}", TRASH_PREFIX, section)] {
+            if let Some(uuid) = storage.resolve_id(&name, id)? {
+                return Ok(uuid);
+            }
+        }
+
+        Err(Error::NotFound(section.to_string(), id.clone()))
+    }
+
+    // Value of the field, like is shown to the user
+    fn value(row: &JsonValue, field: &str) -> Option<String> {
+        match &row[field] {
+            JsonValue::Null => None,
+            value if value.is_string() => Some(value.to_string()),
+            value => Some(value.dump())
+        }
+    }
+
+    // Fields changed between the versions of row. A removed
+    // row has no fields to compare
+    fn diff(before: &Option<JsonValue>, after: &Option<JsonValue>) -> Vec<FieldChange> {
+
+        let after = match after {
+            Some(after) => after,
+            None => return vec![]
+        };
+
+        let empty = JsonValue::new_object();
+        let before = before.as_ref().unwrap_or(&empty);
+
+        let mut fields: Vec<String> = after.entries()
+            .map(|(field, _)| field.to_string())
+            .collect();
+
+        for (field, _) in before.entries() {
+            if !fields.contains(&field.to_string()) {
+                fields.push(field.to_string());
+            }
+        }
+
+        fields.into_iter()
+            .map(|field| FieldChange {
+                before: History::value(before, &field),
+                after: History::value(after, &field),
+                field: field
+            })
+            .filter(|change| change.before != change.after)
+            .collect()
+    }
+
+    // All versions of the row of id, the oldest first
+    pub fn revisions(storage: &mut Storage, section: &str, id: String) -> Result<Vec<Revision>, Error> {
+
+        let uuid = History::resolve(storage, section, &id)?;
+
+        // The moves to the trash are already the removals on the section
+        let changes: Vec<_> = Journal::applied_changes(storage)?
+            .into_iter()
+            .filter(|change| change.section == section && change.uuid == uuid)
+            .collect();
+
+        let mut versions: Vec<(Option<DateTime<Local>>, String, String, Option<String>)> = vec![];
+
+        match changes.first() {
+            // The row was made before the journal
+            Some(first) if first.before.is_some() => versions.push((None, "".to_string(), "".to_string(), first.before.clone())),
+            Some(_) => {},
+            None => {
+                let mut data = storage.get_section_data(section.to_string());
+
+                if data.find_by_id(uuid.clone())? {
+                    let row = data.next::<RawRow>()?;
+                    versions.push((None, "".to_string(), "".to_string(), Some(format!("{} {}", row.uuid, row.json.dump()))));
+                }
+            }
+        }
+
+        for change in changes {
+            versions.push((change.changed_at, change.command, change.user, change.after));
+        }
+
+        let mut revisions: Vec<Revision> = vec![];
+        let mut previous: Option<JsonValue> = None;
+
+        for (changed_at, command, user, line) in versions {
+
+            let row = match line {
+                Some(line) => Some(parse(&line.chars().skip(37).collect::<String>())
+                    .map_err(|_| Error::InvalidRow(section.to_string(), uuid.clone()))?),
+                None => None
+            };
+
+            revisions.push(Revision {
+                number: revisions.len() + 1,
+                changed_at: changed_at,
+                command: command,
+                user: user,
+                changes: History::diff(&previous, &row),
+                row: row.clone()
+            });
+
+            previous = row;
+        }
+
+        Ok(revisions)
+    }
+
+    // Save the version of a transaction by the model, with the checks of
+    // reconciled and closed accounts. The other side of a transfer is updated too
+    fn revert_transaction(storage: &mut Storage, row: JsonValue, uuid: String) -> Result<(), Error> {
+
+        let mut transaction = Transaction::new(row, uuid, storage, true)?;
+
+        transaction.account.as_ref().unwrap().check_open(transaction.deadline)?;
+
+        match transaction.transfer.clone() {
+            Some(other) => {
+
+                let mut other = *other;
+
+                other.account.as_ref().unwrap().check_open(transaction.deadline)?;
+
+                Transaction::store_transfer(storage, &mut transaction, &mut other)?;
+            },
+            None => {
+                Transaction::store_transaction(storage, transaction)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Change the row of id back to the version of number. The version
+    // goes through the model, like any other change of the module
+    pub fn revert(storage: &mut Storage, section: &str, id: String, number: usize) -> Result<Revision, Error> {
+
+        let revisions = History::revisions(storage, section, id.clone())?;
+
+        let revision = match number {
+            0 => None,
+            number => revisions.get(number - 1)
+        };
+
+        let revision = match revision {
+            Some(revision) => revision.clone(),
+            None => return Err(Error::NotFound("revisions".to_string(), number.to_string()))
+        };
+
+        let row = match revision.row.clone() {
+            Some(row) => row,
+            None => return Err(Error::Invalid("error_revert_removed"))
+        };
+
+        let uuid = match storage.resolve_id(&section.to_string(), &id)? {
+            Some(uuid) => uuid,
+            None => return Err(Error::Invalid("error_revert_trashed"))
+        };
+
+        References::check(storage, section, &row)?;
+
+        match section {
+            "transactions" => History::revert_transaction(storage, row, uuid)?,
+            "accounts" => {
+                let account = Account::new(row, uuid, storage, true)?;
+                Account::store_account(storage, account)?;
+            },
+            "contacts" => {
+                let contact = Contact::new(row, uuid, storage, true)?;
+                Contact::store_contact(storage, contact)?;
+            },
+            "tags" => {
+                let tag = Tag::new(row, uuid, storage, true)?;
+                Tag::store_tag(storage, tag)?;
+            },
+            "rules" => {
+                let rule = Rule::new(row, uuid, storage, true)?;
+                Rule::store_rule(storage, rule)?;
+            },
+            "forecasts" => {
+                let forecast = Forecast::new(row, uuid, storage, true)?;
+                Forecast::store_forecast(storage, forecast)?;
+            },
+            _ => {
+                let mut data = storage.get_section_data(section.to_string());
+
+                data.save(RawRow { uuid: uuid, json: row })?;
+            }
+        }
+
+        Ok(revision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use uuid::Uuid;
+    use chrono::NaiveDate;
+    use backend::money::Money;
+    use backend::references::OnRemove;
+
+    fn change(field: &str, before: Option<&str>, after: Option<&str>) -> FieldChange {
+        FieldChange {
+            field: field.to_string(),
+            before: before.map(|value| value.to_string()),
+            after: after.map(|value| value.to_string())
+        }
+    }
+
+    #[test]
+    fn revisions() {
+
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
+
+        let uuid = Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 1".to_string() }).unwrap();
+
+        Tag::store_tag(&mut st, Tag { uuid: uuid.clone(), name: "tag 2".to_string() }).unwrap();
+        Tag::remove_tag(&mut st, uuid.clone(), OnRemove::Refuse).unwrap();
+
+        let revisions = History::revisions(&mut st, "tags", uuid.clone()).unwrap();
+
+        assert_eq!(revisions.len(), 3);
+        assert_eq!(revisions[0].changes, vec![change("name", None, Some("tag 1"))]);
+        assert_eq!(revisions[1].changes, vec![change("name", Some("tag 1"), Some("tag 2"))]);
+        assert!(revisions[2].row.is_none());
+
+        assert!(History::revisions(&mut st, "tags", "NOOOO".to_string()).is_err());
+    }
+
+    #[test]
+    fn revert() {
+
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
+
+        let uuid = Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 1".to_string() }).unwrap();
+
+        Tag::store_tag(&mut st, Tag { uuid: uuid.clone(), name: "tag 2".to_string() }).unwrap();
+
+        assert_eq!(History::revert(&mut st, "tags", uuid.clone(), 5).unwrap_err(), Error::NotFound("revisions".to_string(), "5".to_string()));
+
+        History::revert(&mut st, "tags", uuid.clone(), 1).unwrap();
+
+        assert_eq!(Tag::get_tag(&mut st, uuid.clone()).unwrap().name, "tag 1");
+
+        // The revert is a new revision
+        assert_eq!(History::revisions(&mut st, "tags", uuid.clone()).unwrap().len(), 3);
+
+        Tag::remove_tag(&mut st, uuid.clone(), OnRemove::Refuse).unwrap();
+
+        assert_eq!(History::revert(&mut st, "tags", uuid.clone(), 4).unwrap_err(), Error::Invalid("error_revert_removed"));
+        assert_eq!(History::revert(&mut st, "tags", uuid.clone(), 2).unwrap_err(), Error::Invalid("error_revert_trashed"));
+    }
+
+    #[test]
+    fn revert_reconciled() {
+
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
+
+        let date = NaiveDate::from_ymd(2018, 10, 1);
+
+        let account = Account::store_account(&mut st, Account { name: "checking".to_string(), currency: "R$".to_string(), open_balance_date: Some(date), ..Default::default() }).unwrap();
+        let contact = Contact::store_contact(&mut st, Contact { uuid: "".to_string(), name: "contact".to_string(), city_location: "city".to_string() }).unwrap();
+
+        let mut transaction = Transaction {
+            description: "transaction".to_string(),
+            value: Money::from_cents(-3000),
+            account: Some(Account::get_account(&mut st, account).unwrap()),
+            contact: Some(Contact::get_contact(&mut st, contact).unwrap()),
+            deadline: Some(date),
+            ..Default::default()
+        };
+
+        transaction.uuid = Transaction::store_transaction(&mut st, transaction.clone()).unwrap();
+
+        // Fixed and reconciled against the bank statement
+        transaction.value = Money::from_cents(-2500);
+        transaction.reconciled_in = Some(date);
+        transaction.paid_in = Some(date);
+
+        Transaction::store_transaction(&mut st, transaction.clone()).unwrap();
+
+        assert_eq!(History::revert(&mut st, "transactions", transaction.uuid.clone(), 1).unwrap_err(), Error::Invalid("error_transaction_reconciled"));
+        assert_eq!(Transaction::get_transaction(&mut st, transaction.uuid.clone()).unwrap().value, Money::from_cents(-2500));
+
+        // The change of description only is kept
+        transaction.description = "other".to_string();
+
+        Transaction::store_transaction(&mut st, transaction.clone()).unwrap();
+        History::revert(&mut st, "transactions", transaction.uuid.clone(), 2).unwrap();
+
+        assert_eq!(Transaction::get_transaction(&mut st, transaction.uuid.clone()).unwrap().description, "transaction");
+    }
+}
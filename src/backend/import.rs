@@ -10,6 +10,8 @@ use chrono::NaiveDate;
 use backend::transactions;
 use backend::storage::Storage;
 use backend::accounts::Account;
+use backend::money::Money;
+use backend::error::Error;
 
 pub struct Import {
 
@@ -18,7 +20,7 @@ pub struct Import {
 #[derive(Clone)]
 pub struct Transaction {
     pub posted_at: Option<NaiveDate>,
-    pub amount: f32,
+    pub amount: Money,
     pub fitid: String, // Financial instituion id
     pub memo: String,
 }
@@ -27,12 +29,12 @@ impl Import {
 
     // Populate de index of transactions for use on
     // import files
-    pub fn index(storage: &mut Storage) {
+    pub fn index(storage: &mut Storage) -> Result<(), Error> {
 
-        let accounts = Account::get_accounts(storage);
+        let accounts = Account::get_accounts(storage)?;
 
         for account in accounts {
-            let transactions = transactions::Transaction::get_transactions_simple(storage, account.clone());
+            let transactions = transactions::Transaction::get_transactions_simple(storage, account.clone())?;
 
             for transaction in transactions {
                 if !transaction.ofx_fitid.is_empty() {
@@ -41,6 +43,8 @@ impl Import {
                 }
             }
         }
+
+        Ok(())
     }
 }
 
@@ -49,16 +53,16 @@ impl Transaction {
     // Make a transaction for storage or return the old
     // transaction if the fitid exists on transactions of
     // the account
-    pub fn build_transaction(self, storage: &mut Storage, account: Account) -> transactions::Transaction {
+    pub fn build_transaction(self, storage: &mut Storage, account: Account) -> Result<transactions::Transaction, Error> {
 
-        if let Some(transaction) = self.clone().find_transaction_by_fitid(storage, account) {
-            return transaction;
+        if let Some(transaction) = self.clone().find_transaction_by_fitid(storage, account)? {
+            return Ok(transaction);
         }
 
         // This abstract file use the ofx prefix becase we
         // start this project with only support for ofx format
 
-        transactions::Transaction {
+        Ok(transactions::Transaction {
             description: self.memo.clone(),
             value: self.amount,
             deadline: self.posted_at,
@@ -66,25 +70,23 @@ impl Transaction {
             ofx_memo: self.memo.clone(),
             ofx_fitid: self.fitid.clone(),
             ..Default::default()
-        }
+        })
     }
 
     // Get, if already exists, the trasaction by fitid
-    fn find_transaction_by_fitid(self, storage: &mut Storage, account: Account) -> Option<transactions::Transaction> {
+    fn find_transaction_by_fitid(self, storage: &mut Storage, account: Account) -> Result<Option<transactions::Transaction>, Error> {
 
-        storage.start_section("transactions".to_string());
+        storage.start_section("transactions".to_string())?;
 
         let mut data = storage.get_section_data("transactions".to_string());
 
         let key = format!("fitid_{}", account.clone().uuid);
 
         if data.find_by_index(key, self.fitid) {
-            if let Ok(transaction) = data.next::<transactions::Transaction>() {
-                return Some(transaction);
-            }
+            return Ok(Some(data.next::<transactions::Transaction>()?));
         }
 
-        None
+        Ok(None)
     }
 
 }
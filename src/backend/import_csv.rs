@@ -10,6 +10,7 @@ use std::fs::File;
 use csv::{ReaderBuilder,Reader};
 use chrono::NaiveDate;
 use backend::import::*;
+use backend::money::Money;
 
 pub struct ImportCsv {
     pub file_csv: Box<Reader<File>>,
@@ -43,15 +44,11 @@ impl ImportCsv {
 
                 let samount = row.get(pos_amount)
                                 .expect("Can't find the amount column");
-                let mut amount = samount.replace(",", ".").parse::<f32>()
+                let mut amount = samount.replace(",", ".").parse::<Money>()
                                 .expect("Can't parse the amount value");
 
                 if invert_values {
-                    if amount >= 0.0 {
-                        amount = 0.0 - amount;
-                    } else {
-                        amount = amount.abs();
-                    }
+                    amount = -amount;
                 }
 
                 let memo = row.get(pos_memo)
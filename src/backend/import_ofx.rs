@@ -11,6 +11,7 @@ use std::io::prelude::*;
 use xmltree::*;
 use chrono::NaiveDate;
 use backend::import::*;
+use backend::money::Money;
 
 pub struct ImportOfx {
     pub file_doc: Box<Element>,
@@ -78,14 +79,10 @@ impl ImportOfx {
                     .expect("Can't find MEMO element")
                     .clone().text.unwrap();
 
-                let mut amount = trnamt.parse::<f32>().unwrap();
+                let mut amount = trnamt.parse::<Money>().unwrap();
 
                 if invert_values {
-                    if amount >= 0.0 {
-                        amount = 0.0 - amount;
-                    } else {
-                        amount = amount.abs();
-                    }
+                    amount = -amount;
                 }
 
                 transactions.push(Transaction {
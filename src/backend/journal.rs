@@ -0,0 +1,511 @@
+///
+/// Blitz Money
+///
+/// Backend of module for the journal of changes on storage
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use std::env;
+use std::fs::{ self, File, OpenOptions };
+use std::io::{ Read, Write };
+use chrono::{ Local, DateTime };
+use json::parse;
+use backend::storage::Storage;
+use backend::error::Error;
+
+// Representation of one change on a row of storage. The images
+// are the full lines, uuid + json, of the row
+#[derive(Clone, Debug)]
+pub struct Change {
+    // All changes of the same command share the group
+    pub group: String,
+    pub section: String,
+    pub uuid: String,
+    // Empty when the row was created
+    pub before: Option<String>,
+    // Empty when the row was removed
+    pub after: Option<String>,
+    pub changed_at: Option<DateTime<Local>>,
+    pub command: String,
+    // Login of who ran the command, empty on the older journals
+    pub user: String
+}
+
+pub struct Journal {}
+
+impl Change {
+
+    // Parse the line of journal
+    fn from_line(line: String) -> Option<Change> {
+
+        let group = line.chars().take(36).collect::<String>();
+        let json = line.chars().skip(37).collect::<String>();
+
+        let row = match parse(&json) {
+            Ok(row) => row,
+            Err(_) => return None
+        };
+
+        let mut change = Change {
+            group: group,
+            section: row["section"].to_string(),
+            uuid: row["uuid"].to_string(),
+            before: None,
+            after: None,
+            changed_at: None,
+            command: row["command"].to_string(),
+            user: row["user"].as_str().unwrap_or("").to_string()
+        };
+
+        if !row["before"].is_null() {
+            change.before = Some(row["before"].to_string());
+        }
+
+        if !row["after"].is_null() {
+            change.after = Some(row["after"].to_string());
+        }
+
+        if let Ok(changed_at) = row["changed_at"].to_string().parse::<DateTime<Local>>() {
+            change.changed_at = Some(changed_at);
+        }
+
+        Some(change)
+    }
+
+    // Line for the journal
+    fn to_line(&self) -> String {
+
+        let mut ob = object!{
+            "section" => self.section.clone(),
+            "uuid" => self.uuid.clone(),
+            "changed_at" => self.changed_at.unwrap().to_rfc3339(),
+            "command" => self.command.clone(),
+            "user" => self.user.clone()
+        };
+
+        if let Some(before) = self.before.clone() {
+            ob["before"] = before.into();
+        }
+
+        if let Some(after) = self.after.clone() {
+            ob["after"] = after.into();
+        }
+
+        format!("{} {}", self.group, ob.dump())
+    }
+}
+
+impl Journal {
+
+    // Path of the journal, sibling of the storage file
+    fn path(storage: &Storage) -> String {
+        format!("{}.journal", storage.path_str)
+    }
+
+    // Encrypt the line when the storage is encrypted
+    fn encode(storage: &Storage, line: String) -> String {
+
+        match storage.crypto() {
+            Some(crypto) => format!("::encrypted:: {}", crypto.encrypt(&line)),
+            None => line
+        }
+    }
+
+    // Decrypt the line, if was encrypted
+    fn decode(storage: &Storage, line: String) -> Result<String, Error> {
+
+        if !line.starts_with("::encrypted:: ") {
+            return Ok(line);
+        }
+
+        let crypto = match storage.crypto() {
+            Some(crypto) => crypto,
+            None => return Err(Error::Invalid("error_journal_encrypted"))
+        };
+
+        crypto.decrypt(&line["::encrypted:: ".len()..])
+            .map_err(|_| Error::Invalid("error_journal_decrypt"))
+    }
+
+    // Append the lines on journal
+    fn append(storage: &Storage, line: String) -> Result<(), Error> {
+
+        let line = Journal::encode(storage, line);
+
+        let failed = |e: ::std::io::Error| Error::Io("error_journal_write", e.to_string());
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(Journal::path(storage))
+            .map_err(failed)?;
+
+        file.write_fmt(format_args!("{}\n", line)).map_err(failed)?;
+        file.sync_all().map_err(failed)
+    }
+
+    // Size of the journal, in bytes, for #truncate
+    pub fn length(storage: &Storage) -> u64 {
+        fs::metadata(Journal::path(storage))
+            .map(|metadata| metadata.len())
+            .unwrap_or(0)
+    }
+
+    // Take out the lines appended after the journal had the length
+    pub fn truncate(storage: &Storage, length: u64) -> Result<(), Error> {
+
+        let failed = |e: ::std::io::Error| Error::Io("error_journal_write", e.to_string());
+
+        let file = match OpenOptions::new().write(true).open(Journal::path(storage)) {
+            Ok(file) => file,
+            Err(_) => return Ok(())
+        };
+
+        if file.metadata().map_err(failed)?.len() <= length {
+            return Ok(());
+        }
+
+        file.set_len(length).map_err(failed)?;
+        file.sync_all().map_err(failed)
+    }
+
+    // Return the lines of journal, decrypted
+    pub fn lines(storage: &Storage) -> Result<Vec<String>, Error> {
+
+        let mut buff = String::new();
+
+        if let Ok(mut file) = File::open(Journal::path(storage)) {
+            file.read_to_string(&mut buff)
+                .map_err(|e| Error::Io("error_journal_read", e.to_string()))?;
+        }
+
+        buff.lines()
+            .map(|line| line.to_string())
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| Journal::decode(storage, line))
+            .collect()
+    }
+
+    // Write again all lines of journal, used when the storage
+    // is encrypted or decrypted
+    pub fn rewrite(storage: &Storage, lines: Vec<String>) -> Result<(), Error> {
+
+        let path = Journal::path(storage);
+        let tmp_path = format!("{}.tmp", path);
+
+        let failed = |e: ::std::io::Error| Error::Io("error_journal_write", e.to_string());
+
+        {
+            let mut tmp = File::create(&tmp_path).map_err(failed)?;
+
+            for line in lines {
+                tmp.write_fmt(format_args!("{}\n", Journal::encode(storage, line))).map_err(failed)?;
+            }
+
+            tmp.sync_all().map_err(failed)?;
+        }
+
+        fs::rename(&tmp_path, &path).map_err(failed)
+    }
+
+    // Copy the journal to the other storage, like on the conversion to other engine
+    pub fn copy_to(storage: &Storage, target: &Storage) -> Result<(), Error> {
+
+        let lines = Journal::lines(storage)?;
+
+        if !lines.is_empty() {
+            Journal::rewrite(target, lines)?;
+        }
+
+        Ok(())
+    }
+
+    // Record the change of a row, made by the current command
+    pub fn record(storage: &Storage, section: String, uuid: String, before: Option<String>, after: Option<String>) -> Result<(), Error> {
+
+        // The first param is the binary
+        let command: Vec<String> = env::args()
+            .skip(1)
+            .filter(|arg| !arg.starts_with("--storage-file="))
+            .collect();
+
+        let change = Change {
+            group: storage.journal_group.clone(),
+            section: section,
+            uuid: uuid,
+            before: before,
+            after: after,
+            changed_at: Some(Local::now()),
+            command: command.join(" "),
+            user: env::var("USER").or_else(|_| env::var("USERNAME")).unwrap_or_default()
+        };
+
+        Journal::append(storage, change.to_line())
+    }
+
+    // Change the images of the rows on journal by f, with the section and the line
+    // of each image, or None for keep it. Used when the rows are changed without
+    // record, like by a migration, so the undo and redo still find them as they are
+    pub fn rewrite_images<F: Fn(&str, &str) -> Option<String>>(storage: &Storage, f: F) -> Result<(), Error> {
+
+        let mut lines: Vec<String> = vec![];
+        let mut changed = false;
+
+        for line in Journal::lines(storage)? {
+
+            let mut change = match line.starts_with("::") {
+                true => None,
+                false => Change::from_line(line.clone())
+            };
+
+            if let Some(change) = change.as_mut() {
+
+                let before = change.before.as_ref().and_then(|image| f(&change.section, image));
+                let after = change.after.as_ref().and_then(|image| f(&change.section, image));
+
+                if before.is_some() || after.is_some() {
+                    change.before = before.or(change.before.clone());
+                    change.after = after.or(change.after.clone());
+
+                    lines.push(change.to_line());
+                    changed = true;
+                    continue;
+                }
+            }
+
+            lines.push(line);
+        }
+
+        match changed {
+            true => Journal::rewrite(storage, lines),
+            false => Ok(())
+        }
+    }
+
+    // Mark the commands recorded until now as not undoable. Used when the rows
+    // are changed without record in a way that their images can't follow,
+    // like the removal of a broken row. They are still part of the history
+    pub fn barrier(storage: &Storage) -> Result<(), Error> {
+        Journal::append(storage, "::barrier::".to_string())
+    }
+
+    // Return all changes recorded on journal
+    pub fn get_changes(storage: &Storage) -> Result<Vec<Change>, Error> {
+
+        Ok(Journal::lines(storage)?
+            .into_iter()
+            .filter(|line| !line.starts_with("::"))
+            .filter_map(|line| Change::from_line(line))
+            .collect())
+    }
+
+    // Return the changes that are applied on the storage, without
+    // the ones of commands undone
+    pub fn applied_changes(storage: &Storage) -> Result<Vec<Change>, Error> {
+
+        let (done, _, kept) = Journal::state(storage)?;
+
+        Ok(Journal::get_changes(storage)?
+            .into_iter()
+            .filter(|change| done.contains(&change.group) || kept.contains(&change.group))
+            .collect())
+    }
+
+    // Return the groups that can be undone and the groups that can be
+    // redone, the last one of each list is the next to be used, and the
+    // groups applied before a barrier, that can't be undone anymore
+    fn state(storage: &Storage) -> Result<(Vec<String>, Vec<String>, Vec<String>), Error> {
+
+        let mut done: Vec<String> = vec![];
+        let mut undone: Vec<String> = vec![];
+        let mut kept: Vec<String> = vec![];
+
+        for line in Journal::lines(storage)? {
+
+            if line.starts_with("::barrier::") {
+                kept.extend(done.drain(..));
+                undone.clear();
+            } else if line.starts_with("::undo:: ") {
+                let group = line["::undo:: ".len()..].trim().to_string();
+                done.retain(|g| g != &group);
+                undone.push(group);
+            } else if line.starts_with("::redo:: ") {
+                let group = line["::redo:: ".len()..].trim().to_string();
+                undone.retain(|g| g != &group);
+                done.push(group);
+            } else {
+                let group = line.chars().take(36).collect::<String>();
+
+                if !done.contains(&group) {
+                    // A new command discards what was undone before him
+                    done.push(group);
+                    undone.clear();
+                }
+            }
+        }
+
+        Ok((done, undone, kept))
+    }
+
+    // Changes of the group, on the same order that was made
+    fn group_changes(storage: &Storage, group: String) -> Result<Vec<Change>, Error> {
+
+        Ok(Journal::get_changes(storage)?
+            .into_iter()
+            .filter(|change| change.group == group)
+            .collect())
+    }
+
+    // Revert all changes of the last command and return
+    // the command, if exists one for undo
+    pub fn undo(storage: &mut Storage) -> Result<Option<String>, Error> {
+
+        // The key of an encrypted journal comes from the storage file
+        storage.load()?;
+
+        let (done, _, _) = Journal::state(storage)?;
+
+        let group = match done.last() {
+            Some(group) => group.clone(),
+            None => return Ok(None)
+        };
+
+        let mut changes = Journal::group_changes(storage, group.clone())?;
+        let command = changes[0].command.clone();
+
+        changes.reverse();
+
+        let rows = changes.into_iter()
+            .map(|change| (change.section, change.uuid, change.after, change.before))
+            .collect();
+
+        storage.replace_rows(rows)?;
+
+        Journal::append(storage, format!("::undo:: {}", group))?;
+
+        Ok(Some(command))
+    }
+
+    // Apply again all changes of the last undone command and
+    // return the command, if exists one for redo
+    pub fn redo(storage: &mut Storage) -> Result<Option<String>, Error> {
+
+        storage.load()?;
+
+        let (_, undone, _) = Journal::state(storage)?;
+
+        let group = match undone.last() {
+            Some(group) => group.clone(),
+            None => return Ok(None)
+        };
+
+        let changes = Journal::group_changes(storage, group.clone())?;
+        let command = changes[0].command.clone();
+
+        let rows = changes.into_iter()
+            .map(|change| (change.section, change.uuid, change.before, change.after))
+            .collect();
+
+        storage.replace_rows(rows)?;
+
+        Journal::append(storage, format!("::redo:: {}", group))?;
+
+        Ok(Some(command))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use uuid::Uuid;
+    use backend::tags::Tag;
+
+    #[test]
+    fn undo_redo() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        // First command
+        {
+            let mut st = Storage::new(path.clone());
+
+            Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 1".to_string() }).unwrap();
+        }
+
+        // Second command
+        {
+            let mut st = Storage::new(path.clone());
+
+            let mut tag = Tag::get_tags(&mut st).unwrap()[0].clone();
+            tag.name = "tag 1 updated".to_string();
+            Tag::store_tag(&mut st, tag).unwrap();
+
+            Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 2".to_string() }).unwrap();
+        }
+
+        let mut st = Storage::new(path.clone());
+
+        assert_eq!(Journal::get_changes(&st).unwrap().len(), 3);
+
+        // Undo the whole second command
+        assert!(Journal::undo(&mut st).unwrap().is_some());
+
+        let tags = Tag::get_tags(&mut st).unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, "tag 1".to_string());
+
+        // And the first
+        assert!(Journal::undo(&mut st).unwrap().is_some());
+        assert_eq!(Tag::get_tags(&mut st).unwrap().len(), 0);
+
+        assert!(Journal::undo(&mut st).unwrap().is_none());
+
+        // Redo the first command
+        assert!(Journal::redo(&mut st).unwrap().is_some());
+
+        let tags = Tag::get_tags(&mut st).unwrap();
+        assert_eq!(tags.len(), 1);
+        assert_eq!(tags[0].name, "tag 1".to_string());
+
+        // And the second
+        assert!(Journal::redo(&mut st).unwrap().is_some());
+
+        let tags = Tag::get_tags(&mut st).unwrap();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[0].name, "tag 2".to_string());
+        assert_eq!(tags[1].name, "tag 1 updated".to_string());
+
+        assert!(Journal::redo(&mut st).unwrap().is_none());
+    }
+
+    #[test]
+    fn undo_discards_redo() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        {
+            let mut st = Storage::new(path.clone());
+
+            Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 1".to_string() }).unwrap();
+        }
+
+        {
+            let mut st = Storage::new(path.clone());
+
+            assert!(Journal::undo(&mut st).unwrap().is_some());
+        }
+
+        // A new command after the undo
+        {
+            let mut st = Storage::new(path.clone());
+
+            Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 2".to_string() }).unwrap();
+        }
+
+        let mut st = Storage::new(path.clone());
+
+        assert!(Journal::redo(&mut st).unwrap().is_none());
+    }
+}
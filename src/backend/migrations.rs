@@ -0,0 +1,390 @@
+///
+/// Blitz Money
+///
+/// Backend of module for upgrade the format of storage file
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use std::fs;
+use json::{ parse, JsonValue };
+use backend::storage::{ Storage, Metadata, RawRow, TRASH_PREFIX };
+use backend::journal::Journal;
+use backend::engine::split_line;
+use backend::money::{ self, Money };
+use backend::error::Error;
+
+// Representation of one step of upgrade. Every change on the
+// format of rows needs a new migration for upgrade the old files
+#[derive(Clone)]
+pub struct Migration {
+    // Version of the file after the migration
+    pub version: u32,
+    // The changes must be made with the rows of storage, eg.: #replace_rows,
+    // so they are not recorded on journal and can't be undone. The images of
+    // the changed rows on journal must follow, eg.: Journal#rewrite_images
+    pub up: fn(&mut Storage) -> Result<(), Error>
+}
+
+pub struct Migrations {}
+
+impl Migrations {
+
+    // All migrations, ordered by version
+    pub fn all() -> Vec<Migration> {
+        vec![
+            Migration { version: 1, up: Migrations::start_versioning },
+            Migration { version: 2, up: Migrations::money_to_cents }
+        ]
+    }
+
+    // Version of the file. Files without the metadata are from
+    // before the versioning
+    pub fn current_version(storage: &mut Storage) -> Result<u32, Error> {
+
+        match storage.get_metadata().get("schema_version") {
+            Some(version) => version.parse::<u32>().map_err(|_| Error::Invalid("error_invalid_schema_version")),
+            None => Ok(0)
+        }
+    }
+
+    // Upgrade the storage to the current version of format. Return
+    // the path of the backup when the file was changed
+    pub fn run(storage: &mut Storage) -> Result<Option<String>, Error> {
+        Migrations::run_migrations(storage, Migrations::all())
+    }
+
+    // Upgrade the storage, step by step, with the migrations. A copy of the
+    // file is taken before the first step
+    pub fn run_migrations(storage: &mut Storage, migrations: Vec<Migration>) -> Result<Option<String>, Error> {
+
+        let latest = migrations.iter()
+            .map(|migration| migration.version)
+            .max()
+            .unwrap_or(0);
+
+        if Migrations::is_latest(storage, latest)? {
+            return Ok(None);
+        }
+
+        // The file is read again with the lock, other process can have
+        // upgraded or changed it after our first read
+        storage.lock_for_write()?;
+
+        if Migrations::is_latest(storage, latest)? {
+            return Ok(None);
+        }
+
+        let version = Migrations::current_version(storage)?;
+
+        // A new file has nothing to upgrade
+        if storage.lines().iter().all(|line| line.trim().is_empty() || line.starts_with("::")) {
+            Metadata::set(storage, "schema_version", latest.to_string())?;
+            return Ok(None);
+        }
+
+        let backup = format!("{}.v{}.bak", storage.path_str, version);
+
+        if fs::copy(&storage.path_str, &backup).is_err() {
+            return Err(Error::Invalid("error_upgrade_backup"));
+        }
+
+        for migration in migrations.iter().filter(|migration| migration.version > version) {
+
+            (migration.up)(storage)?;
+
+            // Each step is persisted, so a fail only repeats the steps after him
+            Metadata::set(storage, "schema_version", migration.version.to_string())?;
+        }
+
+        Ok(Some(backup))
+    }
+
+    // The file is on the version, fails when it is of a newer build
+    fn is_latest(storage: &mut Storage, latest: u32) -> Result<bool, Error> {
+
+        let version = Migrations::current_version(storage)?;
+
+        if version > latest {
+            return Err(Error::Invalid("error_newer_schema_version"));
+        }
+
+        Ok(version == latest)
+    }
+
+    // The format of first version is the same of the files without versioning
+    fn start_versioning(_storage: &mut Storage) -> Result<(), Error> {
+        Ok(())
+    }
+
+    // Round the money fields of the row of section to cents, like 120.38.
+    // Return false when they were already rounded
+    fn round_money(section: &str, json: &mut JsonValue) -> bool {
+
+        let mut changed = false;
+
+        for &(_, field) in money::FIELDS.iter().filter(|&&(name, _)| name == section.trim_start_matches(TRASH_PREFIX)) {
+
+            let money = match Money::from_json(&json[field]) {
+                Some(money) => money,
+                None => continue
+            };
+
+            if JsonValue::from(money) != json[field] {
+                json[field] = money.into();
+                changed = true;
+            }
+        }
+
+        changed
+    }
+
+    // The money values were saved from f32, like 120.37999725341797, now
+    // they are saved rounded to cents, like 120.38. The images of journal
+    // are rounded too, so the changes before the upgrade can still be undone
+    fn money_to_cents(storage: &mut Storage) -> Result<(), Error> {
+
+        let mut rows = vec![];
+
+        for &(section, _) in money::FIELDS.iter() {
+            for section in vec![section.to_string(), format!("{}{}", TRASH_PREFIX, section)] {
+
+                if !storage.check_section(section.clone()) {
+                    continue;
+                }
+
+                let mut data = storage.get_section_data(section.clone());
+                let mut found: Vec<RawRow> = vec![];
+
+                loop {
+                    match data.next::<RawRow>() {
+                        Ok(row) => found.push(row),
+                        // The broken rows are left for the storage check
+                        Err(Error::InvalidRow(_, _)) => continue,
+                        Err(Error::EndOfSection) => break,
+                        Err(e) => return Err(e)
+                    }
+                }
+
+                for mut row in found {
+
+                    if !Migrations::round_money(&section, &mut row.json) {
+                        continue;
+                    }
+
+                    let before = storage.row(&section, &row.uuid);
+                    let after = format!("{} {}", row.uuid, row.json.dump());
+
+                    rows.push((section.clone(), row.uuid, before, Some(after)));
+                }
+            }
+        }
+
+        storage.replace_rows(rows)?;
+
+        Journal::rewrite_images(storage, |section, image| {
+
+            let (uuid, json) = split_line(image);
+
+            let mut json = match parse(&json) {
+                Ok(json) => json,
+                Err(_) => return None
+            };
+
+            match Migrations::round_money(section, &mut json) {
+                true => Some(format!("{} {}", uuid, json.dump())),
+                false => None
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::path::Path;
+    use uuid::Uuid;
+    use backend::tags::Tag;
+    use backend::accounts::Account;
+    use backend::transactions::Transaction;
+
+    // Rename all tags to upper case
+    fn upper_tags(storage: &mut Storage) -> Result<(), Error> {
+
+        let rows = Tag::get_tags(storage)?
+            .into_iter()
+            .map(|tag| {
+                let before = storage.lines().iter()
+                    .find(|line| line.starts_with(&tag.uuid))
+                    .cloned();
+                let after = before.clone().map(|line| line.replace(&tag.name, &tag.name.to_uppercase()));
+
+                ("tags".to_string(), tag.uuid.clone(), before, after)
+            })
+            .collect();
+
+        storage.replace_rows(rows)
+    }
+
+    // Fails if the tags are not upper case
+    fn check_upper_tags(storage: &mut Storage) -> Result<(), Error> {
+
+        if Tag::get_tags(storage)?.iter().any(|tag| tag.name != tag.name.to_uppercase()) {
+            return Err(Error::Invalid("error_not_upper_case"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn run() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        // New file
+        {
+            let mut st = Storage::new(path.clone());
+
+            assert_eq!(Migrations::current_version(&mut st), Ok(0));
+            assert_eq!(Migrations::run(&mut st), Ok(None));
+            assert_eq!(Migrations::current_version(&mut st), Ok(2));
+
+            Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 1".to_string() }).unwrap();
+        }
+
+        // Now the file is on the last version
+        {
+            let mut st = Storage::new(path.clone());
+
+            assert_eq!(Migrations::run(&mut st), Ok(None));
+        }
+
+        let migrations = vec![
+            Migration { version: 1, up: Migrations::start_versioning },
+            Migration { version: 2, up: Migrations::money_to_cents },
+            Migration { version: 3, up: upper_tags },
+            Migration { version: 4, up: check_upper_tags }
+        ];
+
+        {
+            // Loaded before the upgrade of other process
+            let mut stale = Storage::new(path.clone());
+
+            assert_eq!(Migrations::current_version(&mut stale), Ok(2));
+
+            {
+                let mut st = Storage::new(path.clone());
+
+                let backup = Migrations::run_migrations(&mut st, migrations.clone()).unwrap();
+
+                assert_eq!(backup, Some(format!("{}.v2.bak", path)));
+                assert!(Path::new(&backup.unwrap()).exists());
+            }
+
+            // The stale one sees the upgrade when takes the lock
+            assert_eq!(Migrations::run_migrations(&mut stale, migrations), Ok(None));
+            assert_eq!(Migrations::current_version(&mut stale), Ok(4));
+        }
+
+        let mut st = Storage::new(path.clone());
+
+        assert_eq!(Migrations::current_version(&mut st), Ok(4));
+        assert_eq!(Tag::get_tags(&mut st).unwrap()[0].name, "TAG 1".to_string());
+
+        // Nothing of the migrations can be undone
+        assert_eq!(Journal::get_changes(&st).unwrap().len(), 1);
+
+        // This build only knows the version 2
+        assert!(Migrations::run(&mut st).is_err());
+    }
+
+    #[test]
+    fn money_to_cents() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        // Values saved by the f32 of the old versions
+        fs::write(&path, vec![
+            "",
+            "::section::accounts",
+            "a0000000-0000-4000-8000-000000000001 {\"bank\":\"b\",\"name\":\"n\",\"open_balance\":100000.0078125,\"open_balance_date\":\"2018-01-01\",\"currency\":\"$\"}",
+            "",
+            "::section::contacts",
+            "c0000000-0000-4000-8000-000000000001 {\"name\":\"n\",\"city_location\":\"c\"}",
+            "",
+            "::section::transactions",
+            "10000000-0000-4000-8000-000000000001 {\"account\":\"a0000000-0000-4000-8000-000000000001\",\"description\":\"d\",\"value\":120.37999725341797,\"deadline\":\"2018-01-01\",\"created_at\":\"2018-01-01T00:00:00-02:00\",\"contact\":\"c0000000-0000-4000-8000-000000000001\"}",
+            "10000000-0000-4000-8000-000000000002 {\"account\":\"a0000000-0000-4000-8000-000000000001\",\"description\":\"d\",\"value\":-2,\"deadline\":\"2018-01-01\",\"created_at\":\"2018-01-01T00:00:00-02:00\",\"contact\":\"c0000000-0000-4000-8000-000000000001\"}",
+            "",
+            "::section::trash_transactions",
+            "10000000-0000-4000-8000-000000000003 {\"account\":\"a0000000-0000-4000-8000-000000000001\",\"description\":\"d\",\"value\":-125.52999877929688,\"deadline\":\"2018-01-01\",\"created_at\":\"2018-01-01T00:00:00-02:00\",\"contact\":\"c0000000-0000-4000-8000-000000000001\",\"deleted_at\":\"2018-01-02T00:00:00-02:00\"}",
+            ""
+        ].join("\n")).unwrap();
+
+        let mut st = Storage::new(path.clone());
+
+        assert!(Migrations::run(&mut st).unwrap().is_some());
+
+        let lines = st.lines();
+
+        assert!(lines.iter().any(|line| line.contains("\"open_balance\":100000.01,")));
+        assert!(lines.iter().any(|line| line.contains("\"value\":120.38,")));
+        assert!(lines.iter().any(|line| line.contains("\"value\":-2,")));
+        assert!(lines.iter().any(|line| line.contains("\"value\":-125.53,")));
+
+        let account = Account::get_account(&mut st, "a0000000-0000-4000-8000-000000000001".to_string()).unwrap();
+        let transaction = Transaction::get_transaction(&mut st, "10000000-0000-4000-8000-000000000001".to_string()).unwrap();
+
+        assert_eq!(account.open_balance, Money::from_cents(10000001));
+        assert_eq!(transaction.value, Money::from_cents(12038));
+    }
+
+    #[test]
+    fn undo_before_money_to_cents() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        let transaction = |description: &str| format!(
+            "10000000-0000-4000-8000-000000000001 {{\"account\":\"a0000000-0000-4000-8000-000000000001\",\"description\":\"{}\",\"value\":120.37999725341797,\"deadline\":\"2018-01-01\",\"created_at\":\"2018-01-01T00:00:00-02:00\",\"contact\":\"c0000000-0000-4000-8000-000000000001\"}}",
+            description
+        );
+
+        fs::write(&path, vec![
+            "".to_string(),
+            "::section::accounts".to_string(),
+            "a0000000-0000-4000-8000-000000000001 {\"bank\":\"b\",\"name\":\"n\",\"open_balance\":0,\"open_balance_date\":\"2018-01-01\",\"currency\":\"$\"}".to_string(),
+            "".to_string(),
+            "::section::contacts".to_string(),
+            "c0000000-0000-4000-8000-000000000001 {\"name\":\"n\",\"city_location\":\"c\"}".to_string(),
+            "".to_string(),
+            "::section::transactions".to_string(),
+            transaction("changed"),
+            "".to_string()
+        ].join("\n")).unwrap();
+
+        // The change of description, recorded by an old version
+        let change = object!{
+            "section" => "transactions",
+            "uuid" => "10000000-0000-4000-8000-000000000001",
+            "changed_at" => "2018-01-02T00:00:00-02:00",
+            "command" => "transactions update",
+            "before" => transaction("d"),
+            "after" => transaction("changed")
+        };
+
+        fs::write(format!("{}.journal", path), format!("{} {}\n", Uuid::new_v4(), change.dump())).unwrap();
+
+        let mut st = Storage::new(path.clone());
+
+        assert!(Migrations::run(&mut st).unwrap().is_some());
+
+        assert_eq!(Journal::undo(&mut st), Ok(Some("transactions update".to_string())));
+
+        let transaction = Transaction::get_transaction(&mut st, "10000000-0000-4000-8000-000000000001".to_string()).unwrap();
+
+        // The version before the change, on cents too
+        assert_eq!(transaction.description, "d".to_string());
+        assert_eq!(transaction.value, Money::from_cents(12038));
+    }
+}
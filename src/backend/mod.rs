@@ -9,3 +9,20 @@ pub mod calendar;
 pub mod rules;
 pub mod forecasts;
 pub mod storage;
+pub mod journal;
+pub mod crypto;
+pub mod migrations;
+pub mod check;
+pub mod error;
+pub mod backups;
+pub mod engine;
+pub mod text_engine;
+pub mod sqlite_engine;
+pub mod references;
+pub mod duplicates;
+pub mod trash;
+pub mod history;
+pub mod money;
+pub mod rates;
+pub mod statements;
+pub mod reconcile;
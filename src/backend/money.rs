@@ -0,0 +1,351 @@
+///
+/// Blitz Money
+///
+/// Backend of module for the money values, kept as integer cents so
+/// the sums and comparisons are exact
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{ Add, AddAssign, Neg, Sub, SubAssign };
+use std::str::FromStr;
+use json::JsonValue;
+use json::number::Number;
+use backend::error::Error;
+
+// Section and field of the money values on storage
+pub const FIELDS: [(&str, &str); 4] = [
+    ("accounts", "open_balance"),
+    ("transactions", "value"),
+    ("forecasts", "value"),
+    ("rules", "expected_value")
+];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money {
+    cents: i64
+}
+
+impl Money {
+
+    pub fn from_cents(cents: i64) -> Money {
+        Money { cents: cents }
+    }
+
+    pub fn zero() -> Money {
+        Money::from_cents(0)
+    }
+
+    pub fn cents(&self) -> i64 {
+        self.cents
+    }
+
+    pub fn abs(&self) -> Money {
+        Money::from_cents(self.cents.abs())
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.cents == 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.cents < 0
+    }
+
+    // Value of the number of storage, rounded to cents. The old files have
+    // the values of f32, like 120.37999725341797 for 120.38
+    pub fn from_json(value: &JsonValue) -> Option<Money> {
+
+        let (positive, mantissa, exponent) = match value {
+            JsonValue::Number(number) if !number.is_nan() => number.as_parts(),
+            _ => return None
+        };
+
+        let cents = Money::round(mantissa as u128, exponent as i32 + 2)?;
+
+        match positive {
+            true => Some(Money::from_cents(cents)),
+            false => Some(Money::from_cents(-cents))
+        }
+    }
+
+    // Value multiplied by the decimal factor, like a exchange rate, rounded to cents
+    pub fn times(&self, factor: &Number) -> Option<Money> {
+
+        let (positive, mantissa, exponent) = factor.as_parts();
+        let value = (self.cents as i128).checked_mul(mantissa as i128)?;
+
+        let cents = match exponent >= 0 {
+            true => value.checked_mul(10i128.checked_pow(exponent as u32)?)?,
+            false => Money::divide(value, 10i128.checked_pow(-(exponent as i32) as u32)?)
+        };
+
+        Money::with_sign(cents, positive)
+    }
+
+    // Value divided by the decimal divisor, like a exchange rate, rounded to cents
+    pub fn divided_by(&self, divisor: &Number) -> Option<Money> {
+
+        let (positive, mantissa, exponent) = divisor.as_parts();
+
+        if mantissa == 0 {
+            return None;
+        }
+
+        let cents = match exponent >= 0 {
+            true => Money::divide(self.cents as i128, (mantissa as i128).checked_mul(10i128.checked_pow(exponent as u32)?)?),
+            false => Money::divide((self.cents as i128).checked_mul(10i128.checked_pow(-(exponent as i32) as u32)?)?, mantissa as i128)
+        };
+
+        Money::with_sign(cents, positive)
+    }
+
+    // How many of the other value for one of this, like the exchange rate
+    // implied by the two values of a transfer. Kept with 6 decimals
+    pub fn ratio_to(&self, other: Money) -> Option<Number> {
+
+        if self.cents == 0 {
+            return None;
+        }
+
+        let mut mantissa = Money::divide((other.cents as i128).abs() * 1_000_000, (self.cents as i128).abs());
+        let mut exponent: i16 = -6;
+
+        while mantissa != 0 && mantissa % 10 == 0 && exponent < 0 {
+            mantissa /= 10;
+            exponent += 1;
+        }
+
+        match mantissa <= u64::max_value() as i128 {
+            true => Some(Number::from_parts(true, mantissa as u64, exponent)),
+            false => None
+        }
+    }
+
+    // Division of positive divisor rounding half away from zero
+    fn divide(value: i128, divisor: i128) -> i128 {
+        match value < 0 {
+            true => -((-value + divisor / 2) / divisor),
+            false => (value + divisor / 2) / divisor
+        }
+    }
+
+    fn with_sign(cents: i128, positive: bool) -> Option<Money> {
+
+        let cents = match positive {
+            true => cents,
+            false => -cents
+        };
+
+        match cents.abs() <= i64::max_value() as i128 {
+            true => Some(Money::from_cents(cents as i64)),
+            false => None
+        }
+    }
+
+    // The digits moved by the exponent, rounding half away from zero
+    fn round(digits: u128, exponent: i32) -> Option<i64> {
+
+        let rounded = if exponent >= 0 {
+            digits.checked_mul(10u128.checked_pow(exponent as u32)?)?
+        } else if exponent < -38 {
+            0
+        } else {
+            let divisor = 10u128.pow(-exponent as u32);
+
+            (digits + divisor / 2) / divisor
+        };
+
+        match rounded <= i64::max_value() as u128 {
+            true => Some(rounded as i64),
+            false => None
+        }
+    }
+}
+
+impl FromStr for Money {
+    type Err = Error;
+
+    // Parse values like 1234.56, -10 or +0.5
+    fn from_str(value: &str) -> Result<Money, Error> {
+
+        let invalid = Error::Invalid("error_invalid_money");
+        let value = value.trim();
+
+        let (positive, value) = match value.chars().next() {
+            Some('-') => (false, &value[1..]),
+            Some('+') => (true, &value[1..]),
+            _ => (true, value)
+        };
+
+        let mut parts = value.splitn(2, '.');
+        let units = parts.next().unwrap_or("");
+        let fraction = parts.next().unwrap_or("");
+
+        if units.is_empty() && fraction.is_empty() {
+            return Err(invalid);
+        }
+
+        if !units.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) || units.len() + fraction.len() > 38 {
+            return Err(invalid);
+        }
+
+        let digits = format!("{}{}", units, fraction).parse::<u128>().unwrap_or(0);
+        let cents = Money::round(digits, 2 - fraction.len() as i32).ok_or(invalid)?;
+
+        match positive {
+            true => Ok(Money::from_cents(cents)),
+            false => Ok(Money::from_cents(-cents))
+        }
+    }
+}
+
+impl fmt::Display for Money {
+
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+
+        let sign = match self.cents < 0 {
+            true => "-",
+            false => ""
+        };
+
+        let cents = (self.cents as i128).abs();
+
+        write!(f, "{}{}.{:02}", sign, cents / 100, cents % 100)
+    }
+}
+
+impl From<Money> for JsonValue {
+
+    fn from(money: Money) -> JsonValue {
+        JsonValue::Number(Number::from_parts(money.cents >= 0, (money.cents as i128).abs() as u64, -2))
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money::from_cents(self.cents + other.cents)
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        Money::from_cents(self.cents - other.cents)
+    }
+}
+
+impl Neg for Money {
+    type Output = Money;
+
+    fn neg(self) -> Money {
+        Money::from_cents(-self.cents)
+    }
+}
+
+impl AddAssign for Money {
+
+    fn add_assign(&mut self, other: Money) {
+        self.cents += other.cents;
+    }
+}
+
+impl SubAssign for Money {
+
+    fn sub_assign(&mut self, other: Money) {
+        self.cents -= other.cents;
+    }
+}
+
+impl Sum for Money {
+
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::zero(), |total, money| total + money)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use json::parse;
+
+    fn money(value: &str) -> Money {
+        value.parse::<Money>().unwrap()
+    }
+
+    #[test]
+    fn from_str() {
+        assert_eq!(money("1234.56"), Money::from_cents(123456));
+        assert_eq!(money("-10"), Money::from_cents(-1000));
+        assert_eq!(money("+0.5"), Money::from_cents(50));
+        assert_eq!(money(".5"), Money::from_cents(50));
+        assert_eq!(money("0.125"), Money::from_cents(13));
+        assert_eq!(money("-0.125"), Money::from_cents(-13));
+
+        assert!("".parse::<Money>().is_err());
+        assert!("1,50".parse::<Money>().is_err());
+        assert!("-".parse::<Money>().is_err());
+        assert!("abc".parse::<Money>().is_err());
+    }
+
+    #[test]
+    fn from_json() {
+        // Values saved by the f32 of the old versions
+        assert_eq!(Money::from_json(&parse("120.37999725341797").unwrap()), Some(Money::from_cents(12038)));
+        assert_eq!(Money::from_json(&parse("-125.52999877929688").unwrap()), Some(Money::from_cents(-12553)));
+        assert_eq!(Money::from_json(&parse("100000.0078125").unwrap()), Some(Money::from_cents(10000001)));
+
+        assert_eq!(Money::from_json(&parse("35").unwrap()), Some(Money::from_cents(3500)));
+        assert_eq!(Money::from_json(&parse("\"35\"").unwrap()), None);
+    }
+
+    #[test]
+    fn to_json_and_display() {
+        assert_eq!(JsonValue::from(Money::from_cents(12038)).dump(), "120.38");
+        assert_eq!(JsonValue::from(Money::from_cents(-3500)).dump(), "-35");
+        assert_eq!(JsonValue::from(Money::zero()).dump(), "0");
+
+        assert_eq!(Money::from_cents(-5).to_string(), "-0.05");
+        assert_eq!(Money::from_cents(123456).to_string(), "1234.56");
+    }
+
+    #[test]
+    fn times_and_divided_by() {
+
+        let rate = |value: &str| match parse(value).unwrap() {
+            JsonValue::Number(number) => number,
+            _ => panic!("Not a number")
+        };
+
+        assert_eq!(money("100").times(&rate("3.2654")), Some(money("326.54")));
+        assert_eq!(money("-10.01").times(&rate("0.5")), Some(money("-5.01")));
+        assert_eq!(money("10").times(&rate("100")), Some(money("1000")));
+
+        assert_eq!(money("326.54").divided_by(&rate("3.2654")), Some(money("100")));
+        assert_eq!(money("-1").divided_by(&rate("3")), Some(money("-0.33")));
+        assert_eq!(money("1").divided_by(&rate("0")), None);
+    }
+
+    #[test]
+    fn ratio_to() {
+        assert_eq!(JsonValue::Number(money("-100").ratio_to(money("325")).unwrap()).dump(), "3.25");
+        assert_eq!(JsonValue::Number(money("3").ratio_to(money("1")).unwrap()).dump(), "0.333333");
+        assert_eq!(JsonValue::Number(money("10").ratio_to(money("10")).unwrap()).dump(), "1");
+        assert_eq!(money("0").ratio_to(money("10")), None);
+    }
+
+    #[test]
+    fn sum() {
+
+        // The f32 sum of these drifts by cents
+        let values = vec![money("100000.01"); 1000];
+
+        assert_eq!(values.into_iter().sum::<Money>(), money("100000010"));
+    }
+}
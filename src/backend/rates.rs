@@ -0,0 +1,349 @@
+///
+/// Blitz Money
+///
+/// Backend of module for manange the exchange rates between the
+/// currencies of accounts
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use csv::ReaderBuilder;
+use chrono::NaiveDate;
+use json::{ parse, JsonValue };
+use json::number::Number;
+use backend::storage::*;
+use backend::money::Money;
+use backend::error::Error;
+use i18n::*;
+
+// Representation of the value of one unit of currency on the target
+// currency, valid from the date until the next rate
+#[derive(Clone, Debug)]
+pub struct Rate {
+   pub uuid: String,
+   pub currency: String,
+   pub target: String,
+   pub date: NaiveDate,
+   pub value: Number
+}
+
+// Rates loaded for convert the values to the base currency
+pub struct Exchange {
+    pub base: String,
+    // Newest first
+    rates: Vec<Rate>
+}
+
+impl Model for Rate {
+
+    fn new(row: JsonValue, uuid: String, _storage: &mut Storage, _can_recursive: bool) -> Result<Rate, Error> {
+
+        Data::require(&row, "rates", &uuid, vec!["currency", "target", "date", "value"])?;
+
+        let invalid = |field: &str| Error::InvalidField("rates".to_string(), uuid.clone(), field.to_string());
+
+        let date = NaiveDate::parse_from_str(&row["date"].to_string(), "%Y-%m-%d")
+            .map_err(|_| invalid("date"))?;
+
+        let value = match row["value"] {
+            JsonValue::Number(value) if Rate::is_valid(&value) => value,
+            _ => return Err(invalid("value"))
+        };
+
+        Ok(Rate {
+            uuid: uuid.clone(),
+            currency: row["currency"].to_string(),
+            target: row["target"].to_string(),
+            date: date,
+            value: value
+        })
+    }
+
+    fn to_save(self) -> (String, bool, JsonValue) {
+
+        (self.uuid.clone(), self.uuid.is_empty(), object!{
+            "currency" => self.currency,
+            "target" => self.target,
+            "date" => self.date.format("%Y-%m-%d").to_string(),
+            "value" => JsonValue::Number(self.value)
+        })
+    }
+}
+
+impl Rate {
+
+    // Only positive rates can convert the values
+    fn is_valid(value: &Number) -> bool {
+        value.is_sign_positive() && !value.is_empty()
+    }
+
+    // Currencies are the same without case and spaces, like usd and USD
+    fn normalize(currency: &str) -> String {
+        currency.trim().to_uppercase()
+    }
+
+    // Parse the value of rate, like 3.2654 or 3,2654
+    pub fn parse_value(value: &str) -> Result<Number, Error> {
+        match parse(&value.trim().replace(",", ".")) {
+            Ok(JsonValue::Number(value)) if Rate::is_valid(&value) => Ok(value),
+            _ => Err(Error::Invalid("error_invalid_rate"))
+        }
+    }
+
+    // Shortest unique prefix of the uuid
+    pub fn id(self, storage: &mut Storage) -> String {
+        storage.short_id("rates", &self.uuid)
+    }
+
+    // Return a list with all rates, the newest first
+    pub fn get_rates(storage: &mut Storage) -> Result<Vec<Rate>, Error> {
+
+        storage.start_section("rates".to_string())?;
+
+        let mut data = storage.get_section_data("rates".to_string());
+
+        let mut rates = data.all::<Rate>()?;
+
+        rates.sort_by(|a, b| b.date.cmp(&a.date)
+            .then(a.currency.cmp(&b.currency))
+            .then(a.target.cmp(&b.target)));
+
+        Ok(rates)
+    }
+
+    // Return the rate of id
+    pub fn get_rate(storage: &mut Storage, uuid: String) -> Result<Rate, Error> {
+
+        storage.start_section("rates".to_string())?;
+
+        let mut data = storage.get_section_data("rates".to_string());
+
+        if data.find_by_id(uuid.clone())? {
+            return data.next::<Rate>();
+        }
+
+        Err(Error::NotFound("rates".to_string(), uuid))
+    }
+
+    // Save updates, or create new, rate on storage. A new rate of the same
+    // currencies and date replaces the old one
+    pub fn store_rate(storage: &mut Storage, mut rate: Rate) -> Result<String, Error> {
+
+        rate.currency = Rate::normalize(&rate.currency);
+        rate.target = Rate::normalize(&rate.target);
+
+        if rate.currency == rate.target {
+            return Err(Error::Invalid("error_rate_same_currency"));
+        }
+
+        if rate.uuid.is_empty() {
+            if let Some(old) = Rate::get_rates(storage)?.into_iter().find(|old| old.currency == rate.currency && old.target == rate.target && old.date == rate.date) {
+                rate.uuid = old.uuid;
+            }
+        }
+
+        storage.start_section("rates".to_string())?;
+
+        let mut data = storage.get_section_data("rates".to_string());
+
+        data.save(rate)
+    }
+
+    // Remvoe rate of storage
+    pub fn remove_rate(storage: &mut Storage, uuid: String) -> Result<(), Error> {
+
+        let rate = Rate::get_rate(storage, uuid)?;
+
+        let mut data = storage.get_section_data("rates".to_string());
+
+        data.remove_by_id(rate.uuid)
+    }
+
+    // Store the rates of CSV file, with the columns date(YYYY-MM-DD), currency,
+    // target currency and value. A first line that is not a rate is the header.
+    // Return the number of rates stored
+    pub fn import_csv(storage: &mut Storage, file_path: String, delimiter: String) -> Result<usize, Error> {
+
+        let mut reader = ReaderBuilder::new()
+            .has_headers(false)
+            .delimiter(delimiter.as_bytes()[0])
+            .from_path(file_path)
+            .map_err(|_| Error::Invalid("error_invalid_rates_file"))?;
+
+        let mut rates: Vec<Rate> = vec![];
+
+        for (i, record) in reader.records().enumerate() {
+
+            let record = record.map_err(|_| Error::Invalid("error_invalid_rates_file"))?;
+
+            let date = NaiveDate::parse_from_str(record.get(0).unwrap_or("").trim(), "%Y-%m-%d");
+
+            if date.is_err() && i == 0 {
+                continue;
+            }
+
+            let rate = match (date, record.get(1), record.get(2), record.get(3)) {
+                (Ok(date), Some(currency), Some(target), Some(value)) => Rate {
+                    uuid: "".to_string(),
+                    currency: currency.to_string(),
+                    target: target.to_string(),
+                    date: date,
+                    value: Rate::parse_value(value)?
+                },
+                _ => return Err(Error::Invalid("error_invalid_rates_file"))
+            };
+
+            rates.push(rate);
+        }
+
+        // All lines are checked before the first change
+        for rate in rates.iter() {
+            Rate::store_rate(storage, rate.clone())?;
+        }
+
+        Ok(rates.len())
+    }
+
+    // Currency of the consolidated totals, if the user set it
+    pub fn base_currency(storage: &mut Storage) -> Option<String> {
+        storage.get_metadata().get("base_currency")
+    }
+
+    pub fn set_base_currency(storage: &mut Storage, currency: String) -> Result<(), Error> {
+
+        if currency.trim().is_empty() {
+            return Err(Error::Invalid("error_invalid_currency"));
+        }
+
+        Metadata::set(storage, "base_currency", Rate::normalize(&currency))
+    }
+}
+
+impl Exchange {
+
+    // Load the rates for conversions, only when the base currency is set
+    pub fn new(storage: &mut Storage) -> Result<Option<Exchange>, Error> {
+
+        let base = match Rate::base_currency(storage) {
+            Some(base) => base,
+            None => return Ok(None)
+        };
+
+        Ok(Some(Exchange {
+            base: base,
+            rates: Rate::get_rates(storage)?
+        }))
+    }
+
+    // Value of currency, on the date, converted to the target currency. Uses
+    // the newest rate until the date, of the currencies in any direction
+    pub fn convert(&self, value: Money, currency: &str, target: &str, date: NaiveDate) -> Result<Money, Error> {
+
+        let currency = Rate::normalize(currency);
+        let target = Rate::normalize(target);
+
+        if currency == target {
+            return Ok(value);
+        }
+
+        let overflow = Error::Invalid("error_money_overflow");
+
+        for rate in self.rates.iter().filter(|rate| rate.date <= date) {
+
+            if rate.currency == currency && rate.target == target {
+                return value.times(&rate.value).ok_or(overflow);
+            }
+
+            if rate.currency == target && rate.target == currency {
+                return value.divided_by(&rate.value).ok_or(overflow);
+            }
+        }
+
+        Err(Error::MissingRate(currency, target, I18n::locale().format_date(date)))
+    }
+
+    // Value of currency, on the date, converted to the base currency
+    pub fn to_base(&self, value: Money, currency: &str, date: NaiveDate) -> Result<Money, Error> {
+        self.convert(value, currency, &self.base.clone(), date)
+    }
+
+    // Return the value formatted with the base currency
+    pub fn format_value(&self, value: Money) -> String {
+        format!("{} {}", self.base, I18n::locale().format_money(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::fs;
+    use uuid::Uuid;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn money(value: &str) -> Money {
+        value.parse::<Money>().unwrap()
+    }
+
+    #[test]
+    fn import_and_store() {
+
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
+
+        let file = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string() + ".csv";
+
+        fs::write(&file, "date,currency,target,value\n2018-01-01,usd,BRL,3.2654\n2018-02-01,USD,BRL,3.1\n2018-01-01,EUR,BRL,3.9\n").unwrap();
+
+        assert_eq!(Rate::import_csv(&mut st, file.clone(), ",".to_string()), Ok(3));
+
+        let rates = Rate::get_rates(&mut st).unwrap();
+
+        assert_eq!(rates.len(), 3);
+        assert_eq!((rates[0].currency.as_str(), rates[0].target.as_str(), rates[0].date), ("USD", "BRL", date("2018-02-01")));
+
+        // The same day replaces the rate
+        Rate::store_rate(&mut st, Rate { uuid: "".to_string(), currency: "USD".to_string(), target: "BRL".to_string(), date: date("2018-02-01"), value: Rate::parse_value("3,2").unwrap() }).unwrap();
+
+        let rates = Rate::get_rates(&mut st).unwrap();
+
+        assert_eq!(rates.len(), 3);
+        assert_eq!(JsonValue::Number(rates[0].value).dump(), "3.2");
+
+        fs::write(&file, "2018-01-01,USD,BRL,-1\n").unwrap();
+
+        assert_eq!(Rate::import_csv(&mut st, file.clone(), ",".to_string()), Err(Error::Invalid("error_invalid_rate")));
+        assert_eq!(Rate::get_rates(&mut st).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn convert() {
+
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
+
+        assert!(Exchange::new(&mut st).unwrap().is_none());
+
+        Rate::set_base_currency(&mut st, "brl".to_string()).unwrap();
+
+        for (day, value) in vec![("2018-01-01", "3.25"), ("2018-02-01", "3.5")] {
+            Rate::store_rate(&mut st, Rate { uuid: "".to_string(), currency: "USD".to_string(), target: "BRL".to_string(), date: date(day), value: Rate::parse_value(value).unwrap() }).unwrap();
+        }
+
+        let exchange = Exchange::new(&mut st).unwrap().unwrap();
+
+        assert_eq!(exchange.base, "BRL");
+
+        // The rate valid on each date
+        assert_eq!(exchange.to_base(money("100"), "USD", date("2018-01-15")), Ok(money("325")));
+        assert_eq!(exchange.to_base(money("100"), "USD", date("2018-03-01")), Ok(money("350")));
+        assert_eq!(exchange.to_base(money("100"), "BRL", date("2017-01-01")), Ok(money("100")));
+
+        // The inverse direction
+        assert_eq!(exchange.convert(money("350"), "BRL", "USD", date("2018-02-01")), Ok(money("100")));
+
+        assert_eq!(exchange.to_base(money("100"), "USD", date("2017-12-31")), Err(Error::MissingRate("USD".to_string(), "BRL".to_string(), "2017-12-31".to_string())));
+        assert!(exchange.to_base(money("100"), "EUR", date("2018-01-15")).is_err());
+    }
+}
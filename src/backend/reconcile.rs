@@ -0,0 +1,229 @@
+///
+/// Blitz Money
+///
+/// Backend of module for reconcile the transactions of account with
+/// the balance of a bank statement
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use chrono::NaiveDate;
+
+use backend::storage::*;
+use backend::accounts::*;
+use backend::transactions::*;
+use backend::money::Money;
+use backend::error::Error;
+
+pub struct Reconciliation {
+    pub account: Account,
+    // Date and balance of the bank statement
+    pub date: NaiveDate,
+    pub balance: Money,
+    // Opening balance plus the transactions already reconciled
+    pub cleared: Money,
+    // Not reconciled transactions until the date, with the ticked ones
+    pub transactions: Vec<(Transaction, bool)>
+}
+
+impl Reconciliation {
+
+    // Load the transactions of account not reconciled until the statement date
+    pub fn new(storage: &mut Storage, account: Account, date: NaiveDate, balance: Money) -> Result<Reconciliation, Error> {
+
+        let (transactions, _) = Transaction::get_transactions(storage, account.clone(), NaiveDate::from_ymd(1, 1, 1), date, StatusFilter::ALL, None, false)?;
+
+        // The opening balance already has the transactions before his date
+        let transactions: Vec<Transaction> = transactions.into_iter()
+            .filter(|transaction| !transaction.is_before_opening())
+            .collect();
+
+        let cleared = account.open_balance + transactions.iter()
+            .filter(|transaction| transaction.reconciled_in.is_some())
+            .map(|transaction| transaction.value)
+            .sum::<Money>();
+
+        Ok(Reconciliation {
+            account: account,
+            date: date,
+            balance: balance,
+            cleared: cleared,
+            transactions: transactions.into_iter()
+                .filter(|transaction| transaction.reconciled_in.is_none())
+                .map(|transaction| (transaction, false))
+                .collect()
+        })
+    }
+
+    // Tick the transaction of the start of uuid or of the ofx fitid
+    pub fn tick(&mut self, id: &str) -> Result<(), Error> {
+
+        let id = id.trim();
+
+        let found: Vec<usize> = match self.transactions.iter().position(|(transaction, _)| !transaction.ofx_fitid.is_empty() && transaction.ofx_fitid == id) {
+            Some(position) => vec![position],
+            None => self.transactions.iter()
+                .enumerate()
+                .filter(|(_, (transaction, _))| !id.is_empty() && transaction.uuid.starts_with(&id.to_lowercase()))
+                .map(|(position, _)| position)
+                .collect()
+        };
+
+        match found.len() {
+            0 => Err(Error::NotFound("transactions".to_string(), id.to_string())),
+            1 => {
+                self.transactions[found[0]].1 = true;
+                Ok(())
+            },
+            _ => Err(Error::AmbiguousId("transactions".to_string(), id.to_string(), found.into_iter().map(|position| self.transactions[position].0.uuid.clone()).collect()))
+        }
+    }
+
+    // Tick, or untick, the transaction of position on the list
+    pub fn toggle(&mut self, position: usize) -> Result<(), Error> {
+        match self.transactions.get_mut(position) {
+            Some((_, ticked)) => {
+                *ticked = !*ticked;
+                Ok(())
+            },
+            None => Err(Error::NotFound("transactions".to_string(), position.to_string()))
+        }
+    }
+
+    // What is missing for the statement balance after each transaction
+    // of the list, the last one is the current difference
+    pub fn running_differences(&self) -> Vec<Money> {
+
+        let mut cleared = self.cleared;
+
+        self.transactions.iter()
+            .map(|(transaction, ticked)| {
+                if *ticked {
+                    cleared += transaction.value;
+                }
+                self.balance - cleared
+            })
+            .collect()
+    }
+
+    pub fn difference(&self) -> Money {
+        self.running_differences().last().cloned().unwrap_or(self.balance - self.cleared)
+    }
+
+    // Mark the ticked transactions as reconciled, and paid, when the balance
+    // matches the statement. Return how many was reconciled
+    pub fn finish(&self, storage: &mut Storage) -> Result<usize, Error> {
+
+        if !self.difference().is_zero() {
+            return Err(Error::Invalid("error_reconcile_difference"));
+        }
+
+        let ticked: Vec<Transaction> = self.transactions.iter()
+            .filter(|(_, ticked)| *ticked)
+            .map(|(transaction, _)| transaction.clone())
+            .collect();
+
+        for mut transaction in ticked.clone() {
+
+            transaction.reconciled_in = Some(self.date);
+
+            if transaction.paid_in.is_none() {
+                transaction.paid_in = transaction.deadline;
+            }
+
+            if transaction.transfer.is_some() {
+                Transaction::store_transfer(storage, &mut transaction.clone(), &mut transaction.transfer.unwrap())?;
+            } else {
+                Transaction::store_transaction(storage, transaction)?;
+            }
+        }
+
+        Ok(ticked.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use uuid::Uuid;
+    use backend::contacts::Contact;
+    use i18n::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn reconcile_and_lock() {
+
+        I18n::config("en_US".to_string());
+
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
+
+        let account = Account::store_account(&mut st, Account { name: "checking".to_string(), currency: "R$".to_string(), open_balance: Money::from_cents(10000), open_balance_date: Some(date("2018-01-01")), ..Default::default() }).unwrap();
+        let contact = Contact::store_contact(&mut st, Contact { uuid: "".to_string(), name: "contact".to_string(), city_location: "city".to_string() }).unwrap();
+
+        let account = Account::get_account(&mut st, account).unwrap();
+        let contact = Contact::get_contact(&mut st, contact).unwrap();
+
+        for (day, value, fitid) in vec![("2018-10-01", -3000, "F1"), ("2018-10-05", 5000, ""), ("2018-10-20", -1000, ""), ("2018-11-01", -500, "")] {
+            Transaction::store_transaction(&mut st, Transaction {
+                description: "transaction".to_string(),
+                value: Money::from_cents(value),
+                account: Some(account.clone()),
+                contact: Some(contact.clone()),
+                deadline: Some(date(day)),
+                ofx_fitid: fitid.to_string(),
+                ofx_memo: fitid.to_string(),
+                ..Default::default()
+            }).unwrap();
+        }
+
+        let mut reconciliation = Reconciliation::new(&mut st, account.clone(), date("2018-10-31"), Money::from_cents(12000)).unwrap();
+
+        // The transaction of november is not on the statement
+        assert_eq!(reconciliation.transactions.len(), 3);
+        assert_eq!(reconciliation.difference(), Money::from_cents(2000));
+
+        reconciliation.tick("F1").unwrap();
+        reconciliation.toggle(2).unwrap();
+
+        assert_eq!(reconciliation.finish(&mut st), Err(Error::Invalid("error_reconcile_difference")));
+
+        reconciliation.toggle(2).unwrap();
+        reconciliation.toggle(1).unwrap();
+
+        assert_eq!(reconciliation.running_differences(), vec![Money::from_cents(5000), Money::zero(), Money::zero()]);
+        assert_eq!(reconciliation.finish(&mut st), Ok(2));
+
+        let reconciled = reconciliation.transactions[0].0.clone();
+        let mut stored = Transaction::get_transaction(&mut st, reconciled.uuid.clone()).unwrap();
+
+        assert_eq!(stored.reconciled_in, Some(date("2018-10-31")));
+        assert_eq!(stored.paid_in, Some(date("2018-10-01")));
+
+        // Locked against changes of the value and the remove
+        stored.value = Money::from_cents(-100);
+
+        assert_eq!(Transaction::store_transaction(&mut st, stored.clone()), Err(Error::Invalid("error_transaction_reconciled")));
+        assert_eq!(Transaction::remove_transaction(&mut st, reconciled.uuid.clone()), Err(Error::Invalid("error_transaction_reconciled")));
+
+        stored.value = Money::from_cents(-3000);
+        stored.description = "other".to_string();
+
+        assert!(Transaction::store_transaction(&mut st, stored.clone()).is_ok());
+
+        // The next statement starts from the reconciled balance
+        let reconciliation = Reconciliation::new(&mut st, account.clone(), date("2018-11-30"), Money::from_cents(11500)).unwrap();
+
+        assert_eq!(reconciliation.cleared, Money::from_cents(12000));
+        assert_eq!(reconciliation.transactions.len(), 2);
+
+        // Unlocked when the reconciled date is taken out
+        stored.reconciled_in = None;
+        Transaction::store_transaction(&mut st, stored.clone()).unwrap();
+
+        assert!(Transaction::remove_transaction(&mut st, reconciled.uuid).is_ok());
+    }
+}
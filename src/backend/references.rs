@@ -0,0 +1,429 @@
+///
+/// Blitz Money
+///
+/// Backend of module for keep the references between the rows of storage
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use backend::storage::*;
+use backend::error::Error;
+use json::JsonValue;
+
+// Section, field and section of the reference
+pub const FIELDS: [(&str, &str, &str); 7] = [
+    ("transactions", "account", "accounts"),
+    ("transactions", "contact", "contacts"),
+    ("transactions", "transfer", "transactions"),
+    ("transactions", "merged_in", "transactions"),
+    ("rules", "contact", "contacts"),
+    ("forecasts", "tag", "tags"),
+    ("forecasts", "account", "accounts")
+];
+
+// Section, field and section of the lists of references. A field like
+// splits.tags is the list on each object of the splits array
+pub const LISTS: [(&str, &str, &str); 3] = [
+    ("transactions", "tags", "tags"),
+    ("transactions", "splits.tags", "tags"),
+    ("rules", "tags", "tags")
+];
+
+// What to do with the rows that point to a row being removed
+#[derive(Clone, Debug, PartialEq)]
+pub enum OnRemove {
+    // Fail listing the dependents
+    Refuse,
+    // Point them to the row of id
+    ReassignTo(String),
+    // Remove them, or just the reference when is a list
+    Cascade
+}
+
+// A row that points to other row
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dependent {
+    pub section: String,
+    pub uuid: String,
+    pub field: String,
+    pub is_list: bool
+}
+
+pub struct References {}
+
+impl References {
+
+    // All rows that point to the row of section, including the ones on
+    // the trash, that would point to nothing when restored
+    pub fn dependents(storage: &mut Storage, section: &str, uuid: &String) -> Result<Vec<Dependent>, Error> {
+
+        let mut dependents: Vec<Dependent> = vec![];
+
+        let references = FIELDS.iter()
+            .map(|reference| (reference, false))
+            .chain(LISTS.iter().map(|reference| (reference, true)));
+
+        for (&(from, field, target), is_list) in references {
+
+            if target != section {
+                continue;
+            }
+
+            for from in vec![from.to_string(), format!("{}{}", TRASH_PREFIX, from)] {
+
+                if !storage.check_section(from.clone()) {
+                    continue;
+                }
+
+                let rows = storage.get_section_data(from.clone()).all::<RawRow>()?;
+
+                for row in rows {
+
+                    let points = match is_list {
+                        true => References::list_members(&row.json, field).contains(uuid),
+                        false => !row.json[field].is_empty() && &row.json[field].to_string() == uuid
+                    };
+
+                    if points {
+                        dependents.push(Dependent {
+                            section: from.clone(),
+                            uuid: row.uuid,
+                            field: field.to_string(),
+                            is_list: is_list
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(dependents)
+    }
+
+    // Number of dependents by section, in the order they were found
+    pub fn summary(dependents: &Vec<Dependent>) -> Vec<(String, usize)> {
+
+        let mut summary: Vec<(String, usize)> = vec![];
+
+        for dependent in dependents {
+            match summary.iter_mut().find(|(section, _)| section == &dependent.section) {
+                Some((_, count)) => *count += 1,
+                None => summary.push((dependent.section.clone(), 1))
+            }
+        }
+
+        summary
+    }
+
+    // Fail if the json of a row of section points to a row that doesn't exist.
+    // The other side of transfers and the merged rows are not checked
+    pub fn check(storage: &mut Storage, section: &str, json: &JsonValue) -> Result<(), Error> {
+
+        for &(from, field, target) in FIELDS.iter() {
+
+            if from != section || target == "transactions" || json[field].is_empty() {
+                continue;
+            }
+
+            let uuid = json[field].to_string();
+
+            if storage.resolve_id(&target.to_string(), &uuid)?.is_none() {
+                return Err(Error::NotFound(target.to_string(), uuid));
+            }
+        }
+
+        for &(from, field, target) in LISTS.iter() {
+
+            if from != section {
+                continue;
+            }
+
+            for uuid in References::list_members(json, field) {
+                if storage.resolve_id(&target.to_string(), &uuid)?.is_none() {
+                    return Err(Error::NotFound(target.to_string(), uuid));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Handle the dependents of the row before it is removed
+    pub fn release(storage: &mut Storage, section: &str, uuid: &String, on_remove: OnRemove) -> Result<(), Error> {
+
+        // The row of reassign must exist, even without dependents
+        let on_remove = match on_remove {
+            OnRemove::ReassignTo(id) => match storage.resolve_id(&section.to_string(), &id)? {
+                Some(target) => OnRemove::ReassignTo(target),
+                None => return Err(Error::NotFound(section.to_string(), id))
+            },
+            on_remove => on_remove
+        };
+
+        if on_remove == OnRemove::ReassignTo(uuid.clone()) {
+            return Err(Error::Invalid("error_reassign_to_itself"));
+        }
+
+        let dependents = References::dependents(storage, section, uuid)?;
+
+        // The rows on the trash don't hold the removal, but are
+        // reassigned or removed with the others
+        let active: Vec<Dependent> = dependents.iter()
+            .filter(|dependent| !dependent.section.starts_with(TRASH_PREFIX))
+            .cloned()
+            .collect();
+
+        match on_remove {
+            OnRemove::Refuse if active.is_empty() => Ok(()),
+            OnRemove::Refuse => Err(Error::HasDependents(section.to_string(), uuid.clone(), References::summary(&active))),
+            OnRemove::ReassignTo(target) => {
+                for dependent in dependents {
+                    References::change(storage, &dependent, uuid, Some(target.clone()))?;
+                }
+
+                Ok(())
+            },
+            OnRemove::Cascade => {
+
+                for dependent in dependents {
+
+                    if dependent.is_list {
+                        References::change(storage, &dependent, uuid, None)?;
+                    } else {
+                        References::remove(storage, &dependent)?;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    // Replace, or remove when None, the reference on the row of dependent
+    fn change(storage: &mut Storage, dependent: &Dependent, uuid: &String, target: Option<String>) -> Result<(), Error> {
+
+        let mut data = storage.get_section_data(dependent.section.clone());
+
+        if !data.find_by_id(dependent.uuid.clone())? {
+            return Ok(());
+        }
+
+        let mut row = data.next::<RawRow>()?;
+        let field = dependent.field.as_str();
+
+        if dependent.is_list {
+            References::map_list(&mut row.json, field, |member| match &member == uuid {
+                true => target.clone(),
+                false => Some(member)
+            });
+        } else {
+            match target {
+                Some(target) => row.json[field] = target.into(),
+                None => { row.json.remove(field); }
+            }
+        }
+
+        data.save(row)?;
+
+        Ok(())
+    }
+
+    // The uuids of a list field of the json
+    pub fn list_members(json: &JsonValue, field: &str) -> Vec<String> {
+
+        let mut path = field.splitn(2, '.');
+        let first = path.next().unwrap_or("");
+
+        match path.next() {
+            Some(list) => json[first].members()
+                .flat_map(|item| item[list].members())
+                .map(|member| member.to_string())
+                .collect(),
+            None => json[first].members()
+                .map(|member| member.to_string())
+                .collect()
+        }
+    }
+
+    // Replace each uuid of a list field of the json by the result of map,
+    // or remove it when None. The repeated uuids are removed too
+    pub fn map_list<F>(json: &mut JsonValue, field: &str, map: F) where F: Fn(String) -> Option<String> {
+
+        let change = |list: &mut JsonValue| {
+
+            let mut members: Vec<String> = vec![];
+
+            for member in list.members().map(|member| member.to_string()) {
+                if let Some(member) = map(member) {
+                    if !members.contains(&member) {
+                        members.push(member);
+                    }
+                }
+            }
+
+            *list = members.into();
+        };
+
+        let mut path = field.splitn(2, '.');
+        let first = path.next().unwrap_or("");
+
+        match path.next() {
+            Some(list) => {
+                for item in json[first].members_mut() {
+                    if !item[list].is_null() {
+                        change(&mut item[list]);
+                    }
+                }
+            },
+            None => change(&mut json[first])
+        }
+    }
+
+    // Remove the row of dependent, with the other side when is a transfer
+    fn remove(storage: &mut Storage, dependent: &Dependent) -> Result<(), Error> {
+
+        let mut data = storage.get_section_data(dependent.section.clone());
+
+        // Already removed, like the other side of a transfer
+        if !data.find_by_id(dependent.uuid.clone())? {
+            return Ok(());
+        }
+
+        let row = data.next::<RawRow>()?;
+
+        if dependent.section.trim_start_matches(TRASH_PREFIX) == "transactions" && !row.json["transfer"].is_empty() {
+            data.remove_by_id(row.json["transfer"].to_string())?;
+        }
+
+        data.remove_by_id(dependent.uuid.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use json::parse;
+    use uuid::Uuid;
+    use backend::tags::Tag;
+    use backend::rules::Rule;
+
+    fn populate() -> (Storage, Vec<String>, String) {
+
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
+
+        let tags: Vec<String> = (1..4)
+            .map(|i| Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: format!("tag {}", i) }).unwrap())
+            .collect();
+
+        let rule_tags = vec![
+            Tag::get_tag(&mut st, tags[0].clone()).unwrap(),
+            Tag::get_tag(&mut st, tags[1].clone()).unwrap()
+        ];
+
+        let rule = Rule::store_rule(&mut st, Rule {
+            uuid: "".to_string(),
+            term: "market".to_string(),
+            expected_value: None,
+            description: "Market".to_string(),
+            contact: None,
+            tags: rule_tags
+        }).unwrap();
+
+        (st, tags, rule)
+    }
+
+    fn rule_tags(st: &mut Storage, rule: &String) -> Vec<String> {
+        Rule::get_rule(st, rule.clone()).unwrap()
+            .tags
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect()
+    }
+
+    #[test]
+    fn dependents() {
+
+        let (mut st, tags, rule) = populate();
+
+        let dependents = References::dependents(&mut st, "tags", &tags[0]).unwrap();
+
+        assert_eq!(dependents, vec![Dependent { section: "rules".to_string(), uuid: rule, field: "tags".to_string(), is_list: true }]);
+        assert_eq!(References::summary(&dependents), vec![("rules".to_string(), 1)]);
+
+        assert!(References::dependents(&mut st, "tags", &tags[2]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn release() {
+
+        let (mut st, tags, rule) = populate();
+
+        assert_eq!(References::release(&mut st, "tags", &tags[0], OnRemove::Refuse),
+            Err(Error::HasDependents("tags".to_string(), tags[0].clone(), vec![("rules".to_string(), 1)])));
+
+        assert_eq!(References::release(&mut st, "tags", &tags[0], OnRemove::ReassignTo(tags[0].clone())),
+            Err(Error::Invalid("error_reassign_to_itself")));
+
+        assert_eq!(References::release(&mut st, "tags", &tags[2], OnRemove::ReassignTo("NOOOO".to_string())),
+            Err(Error::NotFound("tags".to_string(), "NOOOO".to_string())));
+
+        // The tag 2 is already on the rule
+        References::release(&mut st, "tags", &tags[0], OnRemove::ReassignTo(tags[1].clone())).unwrap();
+
+        assert_eq!(rule_tags(&mut st, &rule), vec!["tag 2"]);
+
+        References::release(&mut st, "tags", &tags[1], OnRemove::Cascade).unwrap();
+
+        assert!(rule_tags(&mut st, &rule).is_empty());
+        assert!(Rule::get_rule(&mut st, rule).is_ok());
+    }
+
+    #[test]
+    fn trash() {
+
+        let (mut st, tags, rule) = populate();
+
+        Rule::remove_rule(&mut st, rule.clone()).unwrap();
+
+        let trash = format!("{}rules", TRASH_PREFIX);
+        let dependents = References::dependents(&mut st, "tags", &tags[0]).unwrap();
+
+        assert_eq!(dependents, vec![Dependent { section: trash.clone(), uuid: rule.clone(), field: "tags".to_string(), is_list: true }]);
+
+        // The trash doesn't hold the removal
+        assert!(References::release(&mut st, "tags", &tags[0], OnRemove::Refuse).is_ok());
+
+        // But the removed rule points to the new tag, for be restored
+        References::release(&mut st, "tags", &tags[0], OnRemove::ReassignTo(tags[2].clone())).unwrap();
+
+        let mut data = st.get_section_data(trash.clone());
+
+        assert!(data.find_by_id(rule.clone()).unwrap());
+        assert_eq!(References::list_members(&data.next::<RawRow>().unwrap().json, "tags"), vec![tags[2].clone(), tags[1].clone()]);
+
+        // Or is removed with the tag
+        References::release(&mut st, "tags", &tags[1], OnRemove::Cascade).unwrap();
+        References::release(&mut st, "tags", &tags[2], OnRemove::Cascade).unwrap();
+
+        let mut data = st.get_section_data(trash);
+
+        assert!(data.find_by_id(rule).unwrap());
+        assert!(References::list_members(&data.next::<RawRow>().unwrap().json, "tags").is_empty());
+    }
+
+    #[test]
+    fn lists_of_splits() {
+
+        let mut json = parse(r#"{"tags":["a"],"splits":[{"value":1,"tags":["b","c"]},{"value":2,"tags":["c"]},{"value":3}]}"#).unwrap();
+
+        assert_eq!(References::list_members(&json, "tags"), vec!["a"]);
+        assert_eq!(References::list_members(&json, "splits.tags"), vec!["b", "c", "c"]);
+
+        References::map_list(&mut json, "splits.tags", |uuid| match uuid.as_str() {
+            "c" => Some("b".to_string()),
+            _ => None
+        });
+
+        assert_eq!(json.dump(), r#"{"tags":["a"],"splits":[{"value":1,"tags":["b"]},{"value":2,"tags":["b"]},{"value":3}]}"#);
+    }
+}
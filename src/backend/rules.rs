@@ -10,13 +10,15 @@ use backend::storage::*;
 use backend::contacts::*;
 use backend::tags::*;
 use backend::transactions::*;
+use backend::money::Money;
+use backend::error::Error;
 use json::JsonValue;
 
 #[derive(Default, Clone, Debug)]
 pub struct Rule {
    pub uuid: String,
    pub term: String,
-   pub expected_value: Option<f32>,
+   pub expected_value: Option<Money>,
    pub description: String,
    pub contact: Option<Contact>,
    pub tags: Vec<Tag>
@@ -24,18 +26,12 @@ pub struct Rule {
 
 impl Model for Rule {
 
-    fn new(row: JsonValue, uuid: String, storage: &mut Storage, _can_recursive: bool) -> Rule {
+    fn new(row: JsonValue, uuid: String, storage: &mut Storage, _can_recursive: bool) -> Result<Rule, Error> {
 
-        if row["term"].is_null() {
-            panic!("Term not found into a row(id {}) rule", uuid);
-        }
-
-        if row["description"].is_null() {
-            panic!("Description not found into a row(id {}) rule", uuid);
-        }
+        Data::require(&row, "rules", &uuid, vec!["term", "description"])?;
 
         let mut rule = Rule {
-            uuid: uuid,
+            uuid: uuid.clone(),
             term: row["term"].to_string(),
             expected_value: None,
             description: row["description"].to_string(),
@@ -44,11 +40,14 @@ impl Model for Rule {
         };
 
         if !row["expected_value"].is_empty() {
-            rule.expected_value = Some(row["expected_value"].as_f32().unwrap());
+            let expected_value = Money::from_json(&row["expected_value"])
+                .ok_or(Error::InvalidField("rules".to_string(), uuid.clone(), "expected_value".to_string()))?;
+
+            rule.expected_value = Some(expected_value);
         }
 
         if !row["contact"].is_empty() {
-            rule.contact = Some(Contact::get_contact(storage, row["contact"].to_string()).unwrap());
+            rule.contact = Some(Contact::get_contact(storage, row["contact"].to_string())?);
         }
 
         if !row["tags"].is_empty() {
@@ -59,7 +58,7 @@ impl Model for Rule {
             }
         }
 
-        rule
+        Ok(rule)
     }
 
     fn to_save(self) -> (String, bool, JsonValue) {
@@ -92,65 +91,59 @@ impl Model for Rule {
 
 impl Rule {
 
-    // Short version of the uuid
-    pub fn id(self) -> String {
-        Data::uuid_to_id(self.uuid)
+    // Shortest unique prefix of the uuid
+    pub fn id(self, storage: &mut Storage) -> String {
+        storage.short_id("rules", &self.uuid)
     }
 
     // Return a list with all rules
-    pub fn get_rules(storage: &mut Storage) -> Vec<Rule> {
+    pub fn get_rules(storage: &mut Storage) -> Result<Vec<Rule>, Error> {
 
-        storage.start_section("rules".to_string());
+        storage.start_section("rules".to_string())?;
 
         let mut data = storage.get_section_data("rules".to_string());
 
-        let mut list: Vec<Rule> = vec![];
-
-        while let Ok(line) = data.next::<Rule>() {
-            list.push(line);
-        }
-
-        return list;
+        data.all::<Rule>()
     }
 
     // Return the rule of id
-    pub fn get_rule(storage: &mut Storage, uuid: String) -> Result<Rule, &'static str> {
+    pub fn get_rule(storage: &mut Storage, uuid: String) -> Result<Rule, Error> {
 
-        storage.start_section("rules".to_string());
+        storage.start_section("rules".to_string())?;
 
         let mut data = storage.get_section_data("rules".to_string());
 
-        if data.find_by_id(uuid) {
+        if data.find_by_id(uuid.clone())? {
             return data.next::<Rule>();
         }
 
-        Err("Rule not found")
+        Err(Error::NotFound("rules".to_string(), uuid))
     }
 
     // Save updates, or create new, rule on storage
-    pub fn store_rule(storage: &mut Storage, rule: Rule) {
+    pub fn store_rule(storage: &mut Storage, rule: Rule) -> Result<String, Error> {
 
-        storage.start_section("rules".to_string());
+        storage.start_section("rules".to_string())?;
 
         let mut data = storage.get_section_data("rules".to_string());
 
-        data.save(rule);
+        data.save(rule)
     }
 
     // Remvoe rule of storage
-    pub fn remove_rule(storage: &mut Storage, uuid: String) {
+    pub fn remove_rule(storage: &mut Storage, uuid: String) -> Result<(), Error> {
 
-        storage.start_section("rules".to_string());
+        storage.start_section("rules".to_string())?;
 
         let mut data = storage.get_section_data("rules".to_string());
 
-        data.remove_by_id(uuid);
+        data.remove_by_id(uuid)
     }
 
     // Check the description and apply the first rule with term match
-    pub fn apply_rules(storage: &mut Storage, transaction: &mut Transaction) -> bool {
+    pub fn apply_rules(storage: &mut Storage, transaction: &mut Transaction) -> Result<bool, Error> {
 
-        let rules = Rule::get_rules(storage);
+        let rules = Rule::get_rules(storage)?;
 
         for rule in rules {
             if transaction.description.to_lowercase().contains(rule.term.to_lowercase().as_str()) {
@@ -167,11 +160,11 @@ impl Rule {
 
                 transaction.tags = rule.tags.clone();
 
-                return true;
+                return Ok(true);
             }
         }
 
-        false
+        Ok(false)
     }
 }
 
@@ -181,21 +174,19 @@ mod tests {
 
     use super::*;
     use uuid::Uuid;
-    use std::collections::HashMap;
-    use std::cell::RefCell;
 
     fn populate() -> String {
 
         let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
 
-        let mut st = Storage { path_str: path.clone(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(path.clone());
 
-        Contact::store_contact(&mut st, Contact { uuid: "".to_string(), name: "contact 1".to_string(), city_location: "city A".to_string() });
-        Contact::store_contact(&mut st, Contact { uuid: "".to_string(), name: "contact 2".to_string(), city_location: "city B".to_string() });
+        Contact::store_contact(&mut st, Contact { uuid: "".to_string(), name: "contact 1".to_string(), city_location: "city A".to_string() }).unwrap();
+        Contact::store_contact(&mut st, Contact { uuid: "".to_string(), name: "contact 2".to_string(), city_location: "city B".to_string() }).unwrap();
 
-        let contacts = Contact::get_contacts(&mut st);
+        let contacts = Contact::get_contacts(&mut st).unwrap();
 
-        assert!(st.start_section("rules".to_string()));
+        assert!(st.start_section("rules".to_string()).is_ok());
 
         let mut data = st.get_section_data("rules".to_string());
 
@@ -206,7 +197,7 @@ mod tests {
             expected_value: None,
             contact: Some(contacts[0].clone()),
             tags: vec!()
-        });
+        }).unwrap();
         data.save(Rule {
             uuid: "".to_string(),
             description: "rule 2".to_string(),
@@ -214,7 +205,7 @@ mod tests {
             expected_value: None,
             contact: Some(contacts[1].clone()),
             tags: vec!()
-        });
+        }).unwrap();
         data.save(Rule {
             uuid: "".to_string(),
             description: "rule 3".to_string(),
@@ -222,7 +213,7 @@ mod tests {
             expected_value: None,
             contact: Some(contacts[1].clone()),
             tags: vec!()
-        });
+        }).unwrap();
         data.save(Rule {
             uuid: "".to_string(),
             description: "rule 4".to_string(),
@@ -230,7 +221,7 @@ mod tests {
             expected_value: None,
             contact: None,
             tags: vec!()
-        });
+        }).unwrap();
 
         path
     }
@@ -238,9 +229,9 @@ mod tests {
     #[test]
     fn get_rules() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
-        let rules = Rule::get_rules(&mut st);
+        let rules = Rule::get_rules(&mut st).unwrap();
 
         assert_eq!(rules[0].description, "rule 4".to_string());
         assert_eq!(rules[0].term, "term B".to_string());
@@ -256,24 +247,24 @@ mod tests {
     #[test]
     fn apply_rules() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
         let mut tr = Transaction {
             description: "more term b and other".to_string(),
-            value: 150.0,
+            value: Money::from_cents(15000),
             ..Default::default()
         };
 
-        assert!(Rule::apply_rules(&mut st, &mut tr));
+        assert!(Rule::apply_rules(&mut st, &mut tr).unwrap());
 
         assert_eq!(tr.description, "rule 4".to_string());
 
         let mut tr2 = Transaction {
             description: "more term not found and other".to_string(),
-            value: 10.0,
+            value: Money::from_cents(1000),
             ..Default::default()
         };
 
-        assert!(!Rule::apply_rules(&mut st, &mut tr2));
+        assert!(!Rule::apply_rules(&mut st, &mut tr2).unwrap());
     }
 }
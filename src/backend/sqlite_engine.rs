@@ -0,0 +1,482 @@
+///
+/// Blitz Money
+///
+/// Backend of module for the engine of storage on a SQLite database
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use rusqlite::{ Connection, OptionalExtension, params };
+use std::time::Duration;
+use backend::engine::{ Engine, split_line };
+use backend::crypto::Crypto;
+use backend::error::Error;
+
+// Storage on a SQLite database. The rows are kept with the same json of
+// the text format, indexed by section and uuid
+pub struct SqliteEngine {
+    path_str: String,
+    connection: Option<Connection>,
+    // Names of the sections, read once because are checked on every lookup
+    sections: Option<Vec<String>>,
+    // If the changes are grouped on a transaction
+    in_transaction: bool
+}
+
+impl SqliteEngine {
+
+    // Create the engine of the file path. The database is only opened on the first use
+    pub fn new(path_str: String) -> SqliteEngine {
+        SqliteEngine { path_str: path_str, connection: None, sections: None, in_transaction: false }
+    }
+
+    // Open the database, and create the tables, if not already opened
+    fn connection(&mut self) -> &Connection {
+
+        if self.connection.is_none() {
+
+            let connection = Connection::open(&self.path_str)
+                .unwrap_or_else(|e| panic!("Couldn't create/open the storage database at {}", e));
+
+            // The writers are serialized by the lock of storage, so only the
+            // readers can wait for a write in progress
+            connection.busy_timeout(Duration::from_secs(5))
+                .expect("Couldn't configure the storage database");
+
+            connection.execute_batch("
+                CREATE TABLE IF NOT EXISTS metadata (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+                CREATE TABLE IF NOT EXISTS sections (id INTEGER PRIMARY KEY AUTOINCREMENT, name TEXT NOT NULL UNIQUE);
+                CREATE TABLE IF NOT EXISTS rows (id INTEGER PRIMARY KEY AUTOINCREMENT, section TEXT NOT NULL, uuid TEXT NOT NULL, data TEXT NOT NULL);
+                CREATE INDEX IF NOT EXISTS rows_section_uuid ON rows (section, uuid);
+            ").unwrap_or_else(|e| panic!("Couldn't create the tables of storage database: {}", e));
+
+            self.connection = Some(connection);
+        }
+
+        self.connection.as_ref().unwrap()
+    }
+
+    // Run the statement that changes the database
+    fn execute(&mut self, sql: &str, params: &[&dyn rusqlite::ToSql]) -> usize {
+        self.connection()
+            .execute(sql, params)
+            .unwrap_or_else(|e| panic!("Couldn't write on the storage database: {}", e))
+    }
+
+    // Return the lines of query, the columns must be the uuid and the data
+    fn query_lines(&mut self, sql: &str, params: &[&dyn rusqlite::ToSql]) -> Vec<String> {
+
+        let connection = self.connection();
+
+        let mut statement = connection.prepare_cached(sql)
+            .unwrap_or_else(|e| panic!("Couldn't read the storage database: {}", e));
+
+        let lines = statement.query_map(params, |row| Ok(SqliteEngine::line(row.get(0)?, row.get(1)?)))
+            .and_then(|rows| rows.collect::<Result<Vec<String>, rusqlite::Error>>())
+            .unwrap_or_else(|e| panic!("Couldn't read the storage database: {}", e));
+
+        lines
+    }
+
+    // Return the first column of all rows of query
+    fn query_column(&mut self, sql: &str, params: &[&dyn rusqlite::ToSql]) -> Vec<String> {
+
+        let connection = self.connection();
+
+        let mut statement = connection.prepare_cached(sql)
+            .unwrap_or_else(|e| panic!("Couldn't read the storage database: {}", e));
+
+        let values = statement.query_map(params, |row| row.get(0))
+            .and_then(|rows| rows.collect::<Result<Vec<String>, rusqlite::Error>>())
+            .unwrap_or_else(|e| panic!("Couldn't read the storage database: {}", e));
+
+        values
+    }
+
+    // Id, on the table, and line of the row of section
+    fn find(&mut self, section: &str, uuid: &str) -> Option<(i64, String)> {
+        self.connection()
+            .prepare_cached("SELECT id, uuid, data FROM rows WHERE section = ?1 AND uuid = ?2 ORDER BY id DESC LIMIT 1")
+            .and_then(|mut statement| statement.query_row(params![section, uuid], |row| {
+                Ok((row.get(0)?, SqliteEngine::line(row.get(1)?, row.get(2)?)))
+            }).optional())
+            .unwrap_or_else(|e| panic!("Couldn't read the storage database: {}", e))
+    }
+
+    // Line of the text format
+    fn line(uuid: String, data: String) -> String {
+        if data.is_empty() {
+            return uuid;
+        }
+
+        format!("{} {}", uuid, data)
+    }
+}
+
+impl Engine for SqliteEngine {
+
+    fn load(&mut self) -> Result<(), Error> {
+        self.connection();
+
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.connection = None;
+        self.sections = None;
+        self.in_transaction = false;
+    }
+
+    fn crypto(&self) -> Option<&Crypto> {
+        None
+    }
+
+    // The database is never encrypted
+    fn set_passphrase(&mut self, _passphrase: String) {
+    }
+
+    fn encrypt(&mut self, _crypto: Crypto) -> Result<(), Error> {
+        Err(Error::Invalid("error_encrypt_sqlite"))
+    }
+
+    fn decrypt(&mut self) -> Result<(), Error> {
+        Err(Error::Invalid("error_not_encrypted"))
+    }
+
+    fn sections(&mut self) -> Vec<String> {
+
+        if self.sections.is_none() {
+            self.sections = Some(self.query_column("SELECT name FROM sections ORDER BY id", params![]));
+        }
+
+        self.sections.clone().unwrap()
+    }
+
+    fn start_section(&mut self, name: &str) -> Result<(), Error> {
+
+        self.execute("INSERT OR IGNORE INTO sections (name) VALUES (?1)", params![name]);
+
+        self.sections = None;
+
+        Ok(())
+    }
+
+    fn rows(&mut self, section: &str) -> Vec<String> {
+        self.query_lines("SELECT uuid, data FROM rows WHERE section = ?1 ORDER BY id DESC", params![section])
+    }
+
+    fn row(&mut self, section: &str, uuid: &str) -> Option<String> {
+        self.find(section, uuid)
+            .map(|(_, line)| line)
+    }
+
+    // All chars of the uuids are before the ~, so the range
+    // has only the uuids with the prefix and can use the index
+    fn with_prefix(&mut self, section: &str, prefix: &str) -> Vec<String> {
+        self.query_column("SELECT DISTINCT uuid FROM rows WHERE section = ?1 AND uuid >= ?2 AND uuid < ?2 || '~' ORDER BY uuid", params![section, prefix])
+    }
+
+    fn neighbours(&mut self, section: &str, uuid: &str) -> Vec<String> {
+
+        let mut neighbours = self.query_column("SELECT uuid FROM rows WHERE section = ?1 AND uuid < ?2 ORDER BY uuid DESC LIMIT 1", params![section, uuid]);
+
+        neighbours.append(&mut self.query_column("SELECT uuid FROM rows WHERE section = ?1 AND uuid > ?2 ORDER BY uuid LIMIT 1", params![section, uuid]));
+
+        neighbours
+    }
+
+    fn insert(&mut self, section: &str, line: String) -> Result<(), Error> {
+
+        // Like on the text format, the section is started by his first row
+        if !section.is_empty() {
+            self.start_section(section)?;
+        }
+
+        let (uuid, data) = split_line(&line);
+
+        self.execute("INSERT INTO rows (section, uuid, data) VALUES (?1, ?2, ?3)", params![section, uuid, data]);
+
+        Ok(())
+    }
+
+    fn update(&mut self, section: &str, uuid: &str, line: String) -> Result<Option<String>, Error> {
+
+        let (id, before) = match self.find(section, uuid) {
+            Some(found) => found,
+            None => return Ok(None)
+        };
+        let (_, data) = split_line(&line);
+
+        self.execute("UPDATE rows SET data = ?1 WHERE id = ?2", params![data, id]);
+
+        Ok(Some(before))
+    }
+
+    fn remove(&mut self, section: &str, uuid: &str) -> Result<Option<String>, Error> {
+
+        let (id, before) = match self.find(section, uuid) {
+            Some(found) => found,
+            None => return Ok(None)
+        };
+
+        self.execute("DELETE FROM rows WHERE id = ?1", params![id]);
+
+        Ok(Some(before))
+    }
+
+    fn replace_line(&mut self, expected: &str, line: Option<String>) -> Result<bool, Error> {
+
+        let (uuid, data) = split_line(expected);
+
+        let id: Option<i64> = self.connection()
+            .query_row("SELECT id FROM rows WHERE uuid = ?1 AND data = ?2 ORDER BY id DESC LIMIT 1", params![uuid, data], |row| row.get(0))
+            .optional()
+            .unwrap_or_else(|e| panic!("Couldn't read the storage database: {}", e));
+
+        let id = match id {
+            Some(id) => id,
+            None => return Ok(false)
+        };
+
+        match line {
+            Some(line) => {
+                let (uuid, data) = split_line(&line);
+                self.execute("UPDATE rows SET uuid = ?1, data = ?2 WHERE id = ?3", params![uuid, data, id]);
+            },
+            None => {
+                self.execute("DELETE FROM rows WHERE id = ?1", params![id]);
+            }
+        }
+
+        Ok(true)
+    }
+
+    fn metadata(&self, key: &str) -> Option<String> {
+
+        let connection = self.connection.as_ref()?;
+
+        connection.prepare_cached("SELECT value FROM metadata WHERE key = ?1 AND value <> ''")
+            .and_then(|mut statement| statement.query_row(params![key], |row| row.get(0)).optional())
+            .unwrap_or_else(|e| panic!("Couldn't read the storage database: {}", e))
+    }
+
+    fn set_metadata(&mut self, key: &str, value: String) -> Result<(), Error> {
+        self.execute("INSERT OR REPLACE INTO metadata (key, value) VALUES (?1, ?2)", params![key, value]);
+
+        Ok(())
+    }
+
+    fn lines(&mut self) -> Vec<String> {
+
+        let mut lines: Vec<String> = vec![];
+
+        let connection = self.connection();
+
+        let mut statement = connection.prepare("SELECT key, value FROM metadata ORDER BY key")
+            .unwrap_or_else(|e| panic!("Couldn't read the storage database: {}", e));
+
+        let metadata = statement.query_map(params![], |row| Ok(format!("::metadata::{} {}", row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .and_then(|rows| rows.collect::<Result<Vec<String>, rusqlite::Error>>())
+            .unwrap_or_else(|e| panic!("Couldn't read the storage database: {}", e));
+
+        drop(statement);
+
+        lines.extend(metadata);
+
+        // Rows of a section not started, like on the top of a text file
+        lines.extend(self.query_lines("SELECT uuid, data FROM rows WHERE section NOT IN (SELECT name FROM sections) ORDER BY id DESC", params![]));
+
+        for section in self.sections() {
+            lines.push("".to_string());
+            lines.push(format!("::section::{}", section));
+            lines.extend(self.rows(&section));
+        }
+
+        lines
+    }
+
+    fn replace_all(&mut self, lines: Vec<String>) -> Result<(), Error> {
+
+        self.begin();
+
+        self.execute("DELETE FROM metadata", params![]);
+        self.execute("DELETE FROM sections", params![]);
+        self.sections = None;
+        self.execute("DELETE FROM rows", params![]);
+
+        let mut section = "".to_string();
+        let mut rows: Vec<(String, String)> = vec![];
+
+        for line in lines {
+
+            if line.starts_with("::metadata::") {
+                let meta = &line["::metadata::".len()..];
+                let mut parts = meta.splitn(2, ' ');
+                let key = parts.next().unwrap_or("").to_string();
+                let value = parts.next().unwrap_or("").trim().to_string();
+
+                self.set_metadata(&key, value)?;
+            } else if line.starts_with("::section::") {
+                section = line["::section::".len()..].to_string();
+
+                self.start_section(&section.clone())?;
+            } else if !line.trim().is_empty() && !line.starts_with("::") {
+                rows.push((section.clone(), line));
+            }
+        }
+
+        // The newest rows are the first on text format
+        for (section, line) in rows.into_iter().rev() {
+            self.insert(&section, line)?;
+        }
+
+        self.commit()
+    }
+
+    fn begin(&mut self) {
+
+        if !self.in_transaction {
+            self.connection()
+                .execute_batch("BEGIN")
+                .unwrap_or_else(|e| panic!("Couldn't write on the storage database: {}", e));
+
+            self.in_transaction = true;
+        }
+    }
+
+    fn commit(&mut self) -> Result<(), Error> {
+
+        if self.in_transaction {
+            self.connection()
+                .execute_batch("COMMIT")
+                .unwrap_or_else(|e| panic!("Couldn't write on the storage database: {}", e));
+
+            self.in_transaction = false;
+        }
+
+        Ok(())
+    }
+
+    fn rollback(&mut self) {
+
+        if self.in_transaction {
+            self.connection()
+                .execute_batch("ROLLBACK")
+                .unwrap_or_else(|e| panic!("Couldn't write on the storage database: {}", e));
+
+            self.in_transaction = false;
+            self.sections = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::fs;
+    use uuid::Uuid;
+    use backend::storage::{ Storage, Metadata };
+    use backend::engine::EngineKind;
+    use backend::tags::Tag;
+    use backend::references::OnRemove;
+
+    fn storage() -> Storage {
+        Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string() + ".sqlite")
+    }
+
+    fn names(storage: &mut Storage) -> Vec<String> {
+        Tag::get_tags(storage).unwrap()
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect()
+    }
+
+    #[test]
+    fn rows() {
+
+        let mut st = storage();
+
+        let first = Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 1".to_string() }).unwrap();
+        Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 2".to_string() }).unwrap();
+        let third = Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 3".to_string() }).unwrap();
+
+        assert!(st.check_section("tags".to_string()));
+        assert_eq!(EngineKind::of(&st.path_str), EngineKind::Sqlite);
+
+        // The newest first, like on the text format
+        assert_eq!(names(&mut st), vec!["tag 3", "tag 2", "tag 1"]);
+
+        let short = st.short_id("tags", &first);
+
+        assert_eq!(Tag::get_tag(&mut st, short).unwrap().name, "tag 1".to_string());
+
+        Tag::store_tag(&mut st, Tag { uuid: first.clone(), name: "tag 1 updated".to_string() }).unwrap();
+        Tag::remove_tag(&mut st, third, OnRemove::Refuse).unwrap();
+
+        Metadata::set(&mut st, "key1", "my value".to_string()).unwrap();
+
+        // Read again by other storage
+        let path = st.path_str.clone();
+        drop(st);
+
+        let mut st = Storage::new(path);
+
+        assert_eq!(names(&mut st), vec!["tag 2", "tag 1 updated"]);
+        assert_eq!(st.get_metadata().get("key1"), Some("my value".to_string()));
+    }
+
+    #[test]
+    fn replace_rows() {
+
+        let mut st = storage();
+
+        let uuid = Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 1".to_string() }).unwrap();
+        let line = format!("{} {{\"name\":\"tag 1\"}}", uuid);
+
+        // Nothing is changed when one row fails
+        let failed = st.replace_rows(vec![
+            ("tags".to_string(), uuid.clone(), Some(line.clone()), Some(format!("{} {{\"name\":\"changed\"}}", uuid))),
+            ("tags".to_string(), uuid.clone(), Some(line.clone()), None)
+        ]);
+
+        assert_eq!(failed, Err(Error::Invalid("error_row_changed")));
+        assert_eq!(names(&mut st), vec!["tag 1"]);
+
+        assert!(st.replace_rows(vec![("tags".to_string(), uuid, Some(line), None)]).is_ok());
+        assert_eq!(names(&mut st), Vec::<String>::new());
+    }
+
+    #[test]
+    fn convert() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        let contents = "::metadata::schema_version 1\n\
+            \n\
+            ::section::tags\n\
+            b0000000-0000-4000-8000-000000000002 {\"name\":\"tag 2\"}\n\
+            b0000000-0000-4000-8000-000000000001 {\"name\":\"tag 1\"}\n\
+            \n\
+            ::section::contacts\n\
+            c0000000-0000-4000-8000-000000000001 {\"name\":\"contact 1\",\"city_location\":\"\"}\n";
+
+        fs::write(&path, contents).unwrap();
+
+        let mut text = Storage::new(path.clone());
+        let mut sqlite = Storage::new(path.clone() + ".sqlite");
+
+        text.copy_to(&mut sqlite).unwrap();
+
+        assert_eq!(sqlite.lines(), text.lines());
+        assert_eq!(names(&mut sqlite), vec!["tag 2", "tag 1"]);
+
+        // And back to the text format
+        let mut back = Storage::with_engine(path.clone() + ".back", EngineKind::Text);
+
+        sqlite.copy_to(&mut back).unwrap();
+        drop(back);
+
+        assert_eq!(fs::read_to_string(path + ".back").unwrap(), contents);
+    }
+}
@@ -0,0 +1,160 @@
+///
+/// Blitz Money
+///
+/// Backend of module for the statements of credit cards, with the
+/// purchases between two closing days
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use chrono::{ Local, NaiveDate };
+
+use backend::storage::*;
+use backend::accounts::*;
+use backend::transactions::*;
+use backend::money::Money;
+use backend::error::Error;
+use i18n::*;
+
+#[derive(Clone, Debug)]
+pub struct Statement {
+    pub card: Account,
+    pub from: NaiveDate,
+    pub closing: NaiveDate,
+    pub due: NaiveDate,
+    // Purchases and refunds, without the payments
+    pub transactions: Vec<Transaction>,
+    // Payment made to the card on the due date
+    pub payment: Option<Transaction>
+}
+
+impl Statement {
+
+    // Statement of the card that closes on the month, or the one that
+    // is open today
+    pub fn get_statement(storage: &mut Storage, card: Account, month: Option<NaiveDate>) -> Result<Statement, Error> {
+
+        let month = match month {
+            Some(month) => Some(month),
+            None => card.statement_month(Local::today().naive_local())
+        };
+
+        let (from, closing, due) = match month.and_then(|month| card.billing_cycle(month)) {
+            Some(cycle) => cycle,
+            None => return Err(Error::Invalid("error_not_a_credit_card"))
+        };
+
+        let (transactions, _) = Transaction::get_transactions(storage, card.clone(), from, closing, StatusFilter::ALL, None, false)?;
+
+        let (payments, _) = Transaction::get_transactions(storage, card.clone(), due, due, StatusFilter::ALL, None, false)?;
+
+        Ok(Statement {
+            card: card,
+            from: from,
+            closing: closing,
+            due: due,
+            transactions: transactions.into_iter()
+                .filter(|transaction| transaction.transfer.is_none())
+                .collect(),
+            payment: payments.into_iter()
+                .find(|transaction| transaction.transfer.is_some() && !transaction.value.is_negative())
+        })
+    }
+
+    // Still receives purchases
+    pub fn is_open(&self) -> bool {
+        Local::today().naive_local() <= self.closing
+    }
+
+    // Money owed by the purchases of statement, less the refunds
+    pub fn total(&self) -> Money {
+        -self.transactions.iter().map(|transaction| transaction.value).sum::<Money>()
+    }
+
+    // Create the payable transfer of the total, from the account to
+    // the card, on the due date
+    pub fn pay(storage: &mut Storage, statement: &Statement, account: Account) -> Result<String, Error> {
+
+        if statement.payment.is_some() {
+            return Err(Error::Invalid("error_statement_already_paid"));
+        }
+
+        if statement.total().is_negative() || statement.total().is_zero() {
+            return Err(Error::Invalid("error_statement_nothing_to_pay"));
+        }
+
+        if account.uuid == statement.card.uuid || !account.same_currency(&statement.card) {
+            return Err(Error::Invalid("error_statement_payment_account"));
+        }
+
+        let mut transaction = Transaction {
+            account: Some(account),
+            description: format!("{} {} {}", I18n::text("accounts_statement_of"), statement.card.name, I18n::locale().format_date(statement.closing)),
+            value: -statement.total(),
+            deadline: Some(statement.due),
+            ..Default::default()
+        };
+
+        Transaction::make_transaction_or_transfer(storage, &mut transaction, statement.card.uuid.clone(), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use uuid::Uuid;
+    use backend::contacts::Contact;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn statement_and_payment() {
+
+        I18n::config("en_US".to_string());
+
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
+
+        let card = Account::store_account(&mut st, Account { name: "card".to_string(), currency: "R$".to_string(), open_balance_date: Some(date("2018-01-01")), kind: AccountType::CreditCard, closing_day: Some(3), due_day: Some(10), ..Default::default() }).unwrap();
+        let checking = Account::store_account(&mut st, Account { name: "checking".to_string(), currency: "R$".to_string(), open_balance_date: Some(date("2018-01-01")), ..Default::default() }).unwrap();
+        let contact = Contact::store_contact(&mut st, Contact { uuid: "".to_string(), name: "contact".to_string(), city_location: "city".to_string() }).unwrap();
+
+        let card = Account::get_account(&mut st, card).unwrap();
+        let checking = Account::get_account(&mut st, checking).unwrap();
+        let contact = Contact::get_contact(&mut st, contact).unwrap();
+
+        // The last one is on the next statement
+        for (day, value) in vec![("2018-09-04", -10000), ("2018-10-03", -5000), ("2018-10-02", 2000), ("2018-10-04", -7000)] {
+            Transaction::store_transaction(&mut st, Transaction {
+                description: "purchase".to_string(),
+                value: Money::from_cents(value),
+                account: Some(card.clone()),
+                contact: Some(contact.clone()),
+                deadline: Some(date(day)),
+                ..Default::default()
+            }).unwrap();
+        }
+
+        let statement = Statement::get_statement(&mut st, card.clone(), Some(date("2018-10-01"))).unwrap();
+
+        assert_eq!((statement.from, statement.closing, statement.due), (date("2018-09-04"), date("2018-10-03"), date("2018-10-10")));
+        assert_eq!(statement.transactions.len(), 3);
+        assert_eq!(statement.total(), Money::from_cents(13000));
+        assert!(!statement.is_open());
+
+        assert_eq!(Statement::pay(&mut st, &statement, card.clone()), Err(Error::Invalid("error_statement_payment_account")));
+
+        Statement::pay(&mut st, &statement, checking.clone()).unwrap();
+
+        let statement = Statement::get_statement(&mut st, card.clone(), Some(date("2018-10-01"))).unwrap();
+
+        // The payment is not a purchase
+        assert_eq!(statement.total(), Money::from_cents(13000));
+        assert_eq!(statement.payment.clone().unwrap().value, Money::from_cents(13000));
+        assert_eq!(statement.payment.clone().unwrap().transfer.unwrap().account.unwrap().uuid, checking.uuid);
+
+        assert_eq!(Statement::pay(&mut st, &statement, checking), Err(Error::Invalid("error_statement_already_paid")));
+    }
+}
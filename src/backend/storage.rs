@@ -6,42 +6,74 @@
 /// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
 ///
 
-use std::error::Error;
-use std::fs::{ File, OpenOptions };
+use std::error::Error as StdError;
+use std::fs::{ self, File, OpenOptions };
 use std::io::{ Read, Write };
-use std::path::Path;
 use std::option::Option;
 use std::collections::HashMap;
+use std::any::Any;
 use std::cell::RefCell;
+use std::process;
+use std::thread;
+use std::time::Duration;
 use json::{ parse, JsonValue };
 use uuid::Uuid;
+use chrono::Local;
+use fs2::FileExt;
+use backend::journal::Journal;
+use backend::backups::Backups;
+use backend::crypto::Crypto;
+use backend::engine::{ Engine, EngineKind, split_line };
+use backend::error::Error;
+
+// Minimum size of the #id shown for the rows
+pub const SHORT_ID_MIN: usize = 7;
+
+// Start of the sections with the removed rows of other sections
+pub const TRASH_PREFIX: &str = "trash_";
 
 // Representation of storage
 pub struct Storage {
     pub path_str: String,
-    pub file: Option<File>,
-    pub lines: Vec<String>,
-    pub index: RefCell<HashMap<String, HashMap<String, String>>>
+    pub index: RefCell<HashMap<String, HashMap<String, String>>>,
+    // Exclusive lock, taken on the first write and kept until the storage is dropped
+    lock: Option<File>,
+    // Id of the changes made by this storage on the journal
+    pub journal_group: String,
+    // Where the rows are kept, the text file or the SQLite database
+    engine: Box<dyn Engine>,
+    // Rows already parsed into models, by section and uuid. Discarded
+    // when the rows change
+    models: HashMap<String, HashMap<String, Box<dyn Any>>>
 }
 
 // Representation of section data
 pub struct Data<'a> {
     section: String,
     storage: &'a mut Storage,
-    need_find_section: bool,
-    last_position: usize,
+    // Lines of the rows to read, loaded on the first #next
+    rows: Option<Vec<String>>,
+    position: usize,
     pub can_recursive: bool
 }
 
-pub trait Model {
+pub trait Model: Clone + 'static {
 
     // For set data into struct
-    fn new(row: JsonValue, uuid: String, storage: &mut Storage, can_recursive: bool) -> Self;
+    fn new(row: JsonValue, uuid: String, storage: &mut Storage, can_recursive: bool) -> Result<Self, Error> where Self: Sized;
 
     // Parse to storage data
     fn to_save(self) -> (String, bool, JsonValue);
 }
 
+// A row without the parse of references, so it can
+// be read and changed even when they are broken
+#[derive(Clone, Debug)]
+pub struct RawRow {
+    pub uuid: String,
+    pub json: JsonValue
+}
+
 // Representation of a metadata section
 #[derive(Clone)]
 pub struct Metadata<'a> {
@@ -50,103 +82,397 @@ pub struct Metadata<'a> {
 
 impl Storage {
 
-    // Open, or reopen, the file for storage. Create a file for store all data, if does not alred exists
-    fn reopen_file(&mut self) {
+    // Create the storage of the file path, with the engine of the file. The
+    // file is only opened on the first use
+    pub fn new(path_str: String) -> Storage {
 
-        let path = Path::new(&self.path_str);
+        let kind = EngineKind::of(&path_str);
 
-        // Open, or create, the file
-        if self.file.is_none() {
+        Storage::with_engine(path_str, kind)
+    }
 
-            self.file = match OpenOptions::new()
-                .read(true)
-                .write(true)
-                .create(true)
-                .append(true)
-                .open(path)
-            {
-                Ok(file) => Some(file),
-                Err(e) => panic!("Couldn't create/open the storage file at {}", e.description()),
-            };
+    // Create the storage of the file path with the engine
+    pub fn with_engine(path_str: String, kind: EngineKind) -> Storage {
+        Storage {
+            engine: kind.open(path_str.clone()),
+            path_str: path_str,
+            index: RefCell::new(HashMap::new()),
+            lock: None,
+            journal_group: Uuid::new_v4().to_string(),
+            models: HashMap::new()
+        }
+    }
 
-            // Read lines from file
-            let mut buff = String::new();
-            if self.file.as_ref().unwrap().read_to_string(&mut buff).is_err() {
-                panic!("Couldn't read lines of the storage file");
-            }
+    // Open the lock file, sibling of the storage file
+    pub fn open_lock_file(path_str: &str) -> File {
+        match OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(format!("{}.lock", path_str))
+        {
+            Ok(file) => file,
+            Err(e) => panic!("Couldn't create/open the lock file of storage at {}", e.description()),
+        }
+    }
 
-            self.lines = Vec::new();
-            for line in buff.lines() {
-                self.lines.push(line.to_string());
+    // Take the exclusive lock for the writes of this storage. Once taken, the
+    // lock is kept until the storage is dropped and the rows are read again,
+    // because other process can have changed the file after our first read
+    pub fn lock_for_write(&mut self) -> Result<(), Error> {
+
+        if self.lock.is_some() {
+            return Ok(());
+        }
+
+        let mut lock = Storage::open_lock_file(&self.path_str);
+
+        // Readers hold the shared lock only while reading the file, so
+        // we wait a little before give up
+        let mut tries = 0;
+        while lock.try_lock_exclusive().is_err() {
+
+            tries += 1;
+
+            if tries >= 50 {
+                let mut owner = String::new();
+                let _ = lock.read_to_string(&mut owner);
+
+                if owner.trim().is_empty() {
+                    return Err(Error::Locked(None));
+                }
+
+                return Err(Error::Locked(Some(owner.trim().to_string())));
             }
+
+            thread::sleep(Duration::from_millis(20));
         }
+
+        lock.set_len(0)
+            .expect("Couldn't reset the lock file of storage");
+        lock.write_fmt(format_args!("{}", process::id()))
+            .expect("Couldn't write on the lock file of storage");
+
+        self.lock = Some(lock);
+
+        // Force read again the file. A file that can't be read is not
+        // written, unless replaced, like on the restore of a backup
+        self.engine.reset();
+        let _ = self.engine.load();
+        self.models.clear();
+
+        // Copy of the file before the first change of this storage
+        Backups::snapshot(self);
+
+        Ok(())
     }
 
-    // Creates a section of data into storage, if does not alread exists
-    pub fn start_section(&mut self, name: String) -> bool {
+    // Load the rows of file, if not already loaded. Fails when the file
+    // can't be read or decrypted
+    pub fn load(&mut self) -> Result<(), Error> {
+        self.engine.load()
+    }
+
+    // All metadata, sections and rows, like the lines of the text format
+    pub fn lines(&mut self) -> Vec<String> {
+        self.engine.lines()
+    }
 
-        let mut has_write = false;
+    // Key of the storage, only when the file is encrypted
+    pub fn crypto(&self) -> Option<&Crypto> {
+        self.engine.crypto()
+    }
 
-        if !self.check_section(name.clone()) {
+    // Passphrase for read the encrypted file, instead of ask it
+    pub fn set_passphrase(&mut self, passphrase: String) {
+        self.engine.set_passphrase(passphrase);
+    }
+
+    // Check if the storage file is encrypted
+    pub fn is_encrypted(&mut self) -> bool {
+        let _ = self.engine.load();
+
+        self.engine.crypto().is_some()
+    }
 
-            self.reopen_file();
+    // Encrypt the storage file, and the journal, with the passphrase
+    pub fn encrypt(&mut self, passphrase: String) -> Result<(), Error> {
 
-            self.file.as_ref().unwrap().write_fmt(format_args!("\n::section::{}\n", name))
-                .expect("Couldn't create the section on storage file");
+        self.lock_for_write()?;
 
-            has_write = true;
+        if self.engine.crypto().is_some() {
+            return Err(Error::Invalid("error_already_encrypted"));
         }
 
-        if has_write {
-            // Force reopen the file on next read
-            self.file = None;
+        let journal = Journal::lines(self)?;
+
+        self.engine.encrypt(Crypto::new(passphrase))?;
+
+        Journal::rewrite(self, journal)?;
+
+        Backups::remove_unencrypted(self);
+
+        Ok(())
+    }
+
+    // Decrypt the storage file, and the journal
+    pub fn decrypt(&mut self) -> Result<(), Error> {
+
+        self.lock_for_write()?;
+
+        if self.engine.crypto().is_none() {
+            return Err(Error::Invalid("error_not_encrypted"));
         }
 
-        true
+        let journal = Journal::lines(self)?;
+
+        self.engine.decrypt()?;
+
+        Journal::rewrite(self, journal)?;
+
+        Ok(())
+    }
+
+    // Replace all contents of the storage file, like on the restore of a
+    // backup. Nothing is recorded on journal
+    pub fn replace_contents(&mut self, contents: Vec<u8>) -> Result<(), Error> {
+
+        self.lock_for_write()?;
+
+        let tmp_path = format!("{}.tmp", self.path_str);
+
+        fs::write(&tmp_path, &contents)
+            .map_err(|e| Error::Io("error_storage_write", e.to_string()))?;
+
+        fs::rename(&tmp_path, &self.path_str)
+            .map_err(|e| Error::Io("error_storage_write", e.to_string()))?;
+
+        self.engine.reset();
+        self.models.clear();
+
+        Ok(())
+    }
+
+    // Copy all metadata, sections and rows to the other storage, that can use
+    // other engine. Nothing is recorded on journal
+    pub fn copy_to(&mut self, target: &mut Storage) -> Result<(), Error> {
+
+        target.lock_for_write()?;
+
+        let lines = self.engine.lines();
+
+        target.engine.replace_all(lines)?;
+        target.models.clear();
+
+        Ok(())
+    }
+
+    // Creates a section of data into storage, if does not alread exists
+    pub fn start_section(&mut self, name: String) -> Result<(), Error> {
+
+        if !self.check_section(name.clone()) {
+
+            self.lock_for_write()?;
+
+            // Can be created by other process
+            self.engine.start_section(&name)?;
+        }
+
+        Ok(())
     }
 
     // Check if section exists
     pub fn check_section(&mut self, name: String) -> bool {
+        !name.is_empty() && self.engine.sections().contains(&name)
+    }
 
-        self.reopen_file();
+    // Line of the row of section, like is saved on the storage
+    pub fn row(&mut self, section: &str, uuid: &str) -> Option<String> {
+        self.engine.row(section, uuid)
+    }
+
+    // Full uuid of the row of section, by the uuid or any prefix of him. A
+    // prefix of more than one row is an error with all the candidates
+    pub fn resolve_id(&mut self, section: &String, id: &String) -> Result<Option<String>, Error> {
+
+        let prefix = id.trim().to_lowercase();
+
+        if prefix.is_empty() {
+            return Ok(None);
+        }
+
+        let candidates = self.engine.with_prefix(section, &prefix);
+
+        if candidates.contains(&prefix) {
+            return Ok(Some(prefix));
+        }
+
+        match candidates.len() {
+            0 => Ok(None),
+            1 => Ok(Some(candidates[0].clone())),
+            _ => Err(Error::AmbiguousId(section.clone(), id.clone(), candidates))
+        }
+    }
+
+    // Shortest prefix of the uuid that is unique on section, with at least
+    // SHORT_ID_MIN chars. Used as the #id of rows
+    pub fn short_id(&mut self, section: &str, uuid: &String) -> String {
 
-        for line in self.lines.clone() {
-            if line == format!("::section::{}", name) {
-                return true;
+        let mut size = SHORT_ID_MIN;
+
+        // Only the neighbours can share a longer prefix
+        for other in self.engine.neighbours(section, uuid) {
+            let common = uuid.chars()
+                .zip(other.chars())
+                .take_while(|(a, b)| a == b)
+                .count();
+
+            if common + 1 > size {
+                size = common + 1;
             }
         }
 
-        false
+        uuid.chars().take(size).collect()
+    }
+
+    // Return the model of the row, if already parsed
+    fn cached<M: Model>(&self, section: &String, uuid: &String) -> Option<M> {
+        self.models
+            .get(section)?
+            .get(uuid)?
+            .downcast_ref::<M>()
+            .cloned()
+    }
+
+    // Keep the model parsed from the row
+    fn cache<M: Model>(&mut self, section: &String, uuid: &String, model: M) {
+        self.models
+            .entry(section.clone())
+            .or_insert(HashMap::new())
+            .insert(uuid.clone(), Box::new(model));
     }
 
     // Return struct for read the data of the section
     pub fn get_section_data(&mut self, name: String) -> Data {
-        Data { section: name, storage: self, last_position: 0, need_find_section: true, can_recursive: true }
+        Data { section: name, storage: self, rows: None, position: 0, can_recursive: true }
     }
 
     // Return struct for read the metadata section
-    #[allow(dead_code)]
     pub fn get_metadata(&mut self) -> Metadata {
-        self.reopen_file();
+        let _ = self.engine.load();
 
         Metadata { storage: self }
     }
 
-    // Persist current lines on storage
-    fn persist(&mut self) {
+    // Replace the lines of rows, by uuid, only if the current line of each row is the
+    // expected one. A None line removes the row, and a None expected means that the
+    // row must not exist. Used for undo and redo the changes of journal, so
+    // nothing is recorded on journal and nothing is written if one row fails
+    pub fn replace_rows(&mut self, rows: Vec<(String, String, Option<String>, Option<String>)>) -> Result<(), Error> {
+
+        self.lock_for_write()?;
+
+        self.grouped(|storage| {
+
+            for (section, uuid, expected, line) in rows {
+
+                let current = storage.engine.row(&section, &uuid);
+
+                if current != expected {
+                    return Err(Error::Invalid("error_row_changed"));
+                }
+
+                match (current, line) {
+                    (Some(_), Some(line)) => { storage.engine.update(&section, &uuid, line)?; },
+                    (Some(_), None) => { storage.engine.remove(&section, &uuid)?; },
+                    (None, Some(line)) => {
+                        storage.engine.start_section(&section)?;
+                        storage.engine.insert(&section, line)?;
+                    },
+                    (None, None) => {}
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    // Replace the lines, by the exact content, or remove them when the new line
+    // is None. Used for repair the rows that can't be found by uuid, like the
+    // unparsable or duplicated ones, so nothing is recorded on journal
+    pub fn replace_lines(&mut self, lines: Vec<(String, Option<String>)>) -> Result<(), Error> {
+
+        self.lock_for_write()?;
+
+        self.grouped(|storage| {
+
+            for (expected, line) in lines.clone() {
+
+                if !storage.engine.replace_line(&expected, line)? {
+                    return Err(Error::Invalid("error_row_changed"));
+                }
+            }
 
-        if self.file.as_ref().unwrap().set_len(0).is_err() {
-            panic!("Couldn't reset storage file");
+            Ok(())
+        })?;
+
+        // The images of journal follow the rows replaced with the same uuid. The
+        // removed ones, or the ones with other uuid, can't be undone anymore
+        for (expected, line) in lines {
+            match line {
+                Some(line) if split_line(&line).0 == split_line(&expected).0 => {
+                    Journal::rewrite_images(self, |_, image| match image == expected {
+                        true => Some(line.clone()),
+                        false => None
+                    })?;
+                },
+                _ => Journal::barrier(self)?
+            }
         }
 
-        for line in self.lines.clone() {
-            self.file.as_ref().unwrap()
-                .write_fmt(format_args!("{}\n", line))
-                .expect("Couldn't write line on storage file");
+        Ok(())
+    }
+
+    // Run the changes of f as one, recording them on the journal before the
+    // commit. Nothing is written when the journal fails, and the records are
+    // taken out of the journal again when the storage can't be written
+    fn recorded<F>(&mut self, f: F) -> Result<(), Error>
+        where F: FnOnce(&mut Storage) -> Result<Vec<(String, String, Option<String>, Option<String>)>, Error> {
+
+        let length = Journal::length(self);
+
+        let result = self.grouped(|storage| {
+
+            for (section, uuid, before, after) in f(storage)? {
+                Journal::record(storage, section, uuid, before, after)?;
+            }
+
+            Ok(())
+        });
+
+        // The error of the change is the one reported
+        if result.is_err() {
+            let _ = Journal::truncate(self, length);
         }
 
-        // Force reopen the file on next read
-        // self.storage.file = None;
+        result
+    }
+
+    // Run the changes of f on the engine as one: all of them are written by
+    // the commit, or none when one of them, or the commit itself, fails
+    fn grouped<T, F: FnOnce(&mut Storage) -> Result<T, Error>>(&mut self, f: F) -> Result<T, Error> {
+
+        self.engine.begin();
+        self.models.clear();
+
+        let result = f(self).and_then(|value| self.engine.commit().map(|_| value));
+
+        if result.is_err() {
+            self.engine.rollback();
+            self.models.clear();
+        }
+
+        result
     }
 
     // Storage on memory the position uuid indexed
@@ -169,54 +495,29 @@ impl Storage {
     }
 }
 
-impl<'a> Data<'a> {
-
-    // Find and adjust the value of position of section
-    fn find_section(&mut self) {
+impl Drop for Storage {
 
-        if self.need_find_section {
-            // when other section use the buffer we need
-            // reset the count of rows of the section
-
-            self.need_find_section = false;
-
-            for (i, line) in self.storage.lines.iter().enumerate() {
-                if line.to_owned() == format!("::section::{}", self.section) {
-                    self.last_position = i.clone();
-                    break;
-                }
-            }
+    // Release the lock for the others writers
+    fn drop(&mut self) {
+        if let Some(lock) = self.lock.as_ref() {
+            let _ = lock.set_len(0);
+            let _ = lock.unlock();
         }
     }
+}
 
-    // Return a small version of the uuid
-    pub fn uuid_to_id(uuid: String) -> String {
-
-        let mut id = String::new();
-
-        id.push_str(&uuid.get(0..1).unwrap().to_string());
-        id.push_str(&uuid.get(10..11).unwrap().to_string());
-        id.push_str(&uuid.get(15..16).unwrap().to_string());
-        id.push_str("-");
-        id.push_str(&uuid.get(20..21).unwrap().to_string());
-        id.push_str(&uuid.get(25..26).unwrap().to_string());
-        id.push_str(&uuid.get(35..36).unwrap().to_string());
+impl Model for RawRow {
 
-        id
+    fn new(row: JsonValue, uuid: String, _storage: &mut Storage, _can_recursive: bool) -> Result<RawRow, Error> {
+        Ok(RawRow { uuid: uuid, json: row })
     }
 
-    // Check if the line is the register by uuid
-    fn line_check_uuid(mut line: String, uuid: String) -> bool {
-
-        line = line.trim().to_string();
-
-        // Is a small id
-        if uuid.chars().count() == 7 && line.chars().count() >= 36 && !line.starts_with("::") {
-            return Data::uuid_to_id(line.to_string()) == uuid;
-        }
-
-        line.chars().take(36).collect::<String>() == uuid
+    fn to_save(self) -> (String, bool, JsonValue) {
+        (self.uuid, false, self.json)
     }
+}
+
+impl<'a> Data<'a> {
 
     // Find the next row by value on index system. If a valid index is
     // founded the #find_by_id is called for you can run the #next method
@@ -228,151 +529,264 @@ impl<'a> Data<'a> {
 
         if let Some(group) = index.get(&skey) {
             if let Some(uuid) = group.get(&value) {
-                self.find_by_id(uuid.to_string());
-                return true;
+                // The index has only full uuids, never ambiguous
+                return self.find_by_id(uuid.to_string()).unwrap_or(false);
             }
         }
 
         false
     }
 
-    // Find the next row by the id. After this method is necessary
-    // use the next() method for get the row
-    pub fn find_by_id(&mut self, uuid: String) -> bool {
+    // Find the row by the id, or a prefix of him. After this
+    // method is necessary use the next() method for get the row
+    pub fn find_by_id(&mut self, uuid: String) -> Result<bool, Error> {
 
-        self.storage.reopen_file();
-        // To force postion after section start
-        self.need_find_section = true;
-        self.last_position = 0;
-        self.find_section();
+        self.rows = Some(vec![]);
+        self.position = 0;
 
-        let mut first = true;
+        let uuid = match self.storage.resolve_id(&self.section, &uuid)? {
+            Some(uuid) => uuid,
+            None => return Ok(false)
+        };
 
-        for (i, line) in self.storage.lines.iter().enumerate() {
+        match self.storage.engine.row(&self.section, &uuid) {
+            Some(line) => {
+                self.rows = Some(vec![line]);
+                Ok(true)
+            },
+            None => Ok(false)
+        }
+    }
 
-            // We start the find only after the start of section
-            if line.trim().is_empty() || self.last_position > i {
-                continue;
-            }
+    // Return the next row of values into a struct filled
+    pub fn next<M: Model>(&mut self) -> Result<M, Error> {
 
-            if !first && line.trim().chars().take(11).collect::<String>() == "::section::" {
-                // And stop on next section
-                return false;
-            }
+        if self.rows.is_none() {
+            self.rows = Some(self.storage.engine.rows(&self.section));
+        }
 
-            if Data::line_check_uuid(line.clone(), uuid.clone()) {
-                self.last_position = i.clone() - 1;
-                return true;
-            }
+        let linestr = match self.rows.as_ref().unwrap().get(self.position) {
+            Some(line) => line.clone(),
+            None => return Err(Error::EndOfSection)
+        };
 
-            first = false;
-        }
+        self.position += 1;
 
-        false
-    }
+        let (uuid, json) = split_line(&linestr);
 
-    // Return the next row of values into a struct filled
-    pub fn next<M: Model>(&mut self) -> Result<M, &'static str> {
+        // The rows of a transfer, without recursion, are incomplete
+        // and can't be shared
+        if self.can_recursive {
+            if let Some(model) = self.storage.cached::<M>(&self.section, &uuid) {
+                return Ok(model);
+            }
+        }
 
-        self.storage.reopen_file();
+        let row = match parse(&json) {
+            Ok(row) => row,
+            Err(_) => return Err(Error::InvalidRow(self.section.clone(), uuid))
+        };
 
-        self.find_section();
+        let model = M::new(row, uuid.clone(), self.storage, self.can_recursive)?;
 
-        self.last_position = (self.last_position + 1).to_owned();
+        if self.can_recursive {
+            self.storage.cache(&self.section, &uuid, model.clone());
+        }
 
-        if self.last_position.to_owned() < self.storage.lines.len() {
+        Ok(model)
+    }
 
-            let linestr = self.storage.lines[self.last_position.to_owned()].clone();
+    // Return all rows, from the current position until the end of section
+    pub fn all<M: Model>(&mut self) -> Result<Vec<M>, Error> {
 
-            if !linestr.trim().is_empty() {
+        let mut list: Vec<M> = vec![];
 
-                let uuid = linestr.chars().take(36).collect();
-                let json = linestr.chars().skip(37).collect::<String>();
+        loop {
+            match self.next::<M>() {
+                Ok(row) => list.push(row),
+                Err(Error::EndOfSection) => return Ok(list),
+                Err(e) => return Err(e)
+            }
+        }
+    }
 
-                let row = match parse(&json) {
-                    Ok(row) => row,
-                    Err(e) => panic!("Couldn't parse the row: {}", e.description())
-                };
+    // Check if the row has all the required fields
+    pub fn require(row: &JsonValue, section: &str, uuid: &String, fields: Vec<&str>) -> Result<(), Error> {
 
-                return Ok(M::new(row, uuid, self.storage, self.can_recursive));
+        for field in fields {
+            if row[field].is_null() {
+                return Err(Error::FieldNotFound(section.to_string(), uuid.clone(), field.to_string()));
             }
         }
 
-        Err("Next row not found")
+        Ok(())
     }
 
     // Insert, or update, the row into storage and return the uuid
-    pub fn save<M: Model>(&mut self, row: M) -> String {
+    pub fn save<M: Model>(&mut self, row: M) -> Result<String, Error> {
 
         let (mut uuid, is_new, data) = row.to_save();
 
-        self.storage.reopen_file();
+        self.storage.lock_for_write()?;
 
         if is_new {
             // New register
+            uuid = Uuid::new_v4().to_string();
+        } else {
+            // Register to update, always by the full uuid
+            uuid = match self.storage.resolve_id(&self.section, &uuid)? {
+                Some(full_uuid) => full_uuid,
+                None => return Err(Error::NotFound(self.section.clone(), uuid))
+            };
+        }
 
-            // To force postion after section start
-            self.need_find_section = true;
-            self.find_section();
+        let section = self.section.clone();
+        let after = format!("{} {}", uuid, data.dump());
 
-            uuid = Uuid::new_v4().to_string();
-            self.storage.lines.insert(self.last_position + 1, format!("{} {}", uuid, data.dump()));
+        let saved = self.storage.recorded(|storage| {
 
-            self.need_find_section = true;
-        } else {
-            // Register to update
-            for (i, line) in self.storage.lines.clone().iter().enumerate() {
-                if Data::line_check_uuid(line.clone(), uuid.clone()) {
-                    self.storage.lines[i] = format!("{} {}", uuid, data.dump());
-                    break;
-                }
+            if is_new {
+                storage.engine.insert(&section, after.clone())?;
+
+                return Ok(vec![(section.clone(), uuid.clone(), None, Some(after))]);
+            }
+
+            match storage.engine.update(&section, &uuid, after.clone())? {
+                Some(before) => Ok(vec![(section.clone(), uuid.clone(), Some(before), Some(after))]),
+                None => Ok(vec![])
             }
+        });
+
+        // The rows must be read again
+        self.rows = None;
+        self.position = 0;
+
+        saved?;
+
+        Ok(uuid)
+    }
+
+    // Move the row of id to the trash of section, with the
+    // time of removal on the deleted_at field
+    pub fn remove_by_id(&mut self, uuid: String) -> Result<(), Error> {
+
+        // The trash is not moved again
+        if self.section.starts_with(TRASH_PREFIX) {
+            return self.purge_by_id(uuid);
         }
 
-        self.storage.persist();
+        self.move_row(uuid, format!("{}{}", TRASH_PREFIX, self.section), |json| {
+            json["deleted_at"] = Local::now().to_rfc3339().into();
+        })?;
 
-        uuid
+        Ok(())
     }
 
-    // Remove row of storage by id
-    pub fn remove_by_id(&mut self, uuid: String) {
+    // Move the row of id, from the trash section, back to the section
+    // it was removed. Return false if the row does not exist
+    pub fn restore_by_id(&mut self, uuid: String) -> Result<bool, Error> {
+
+        let section = match self.section.starts_with(TRASH_PREFIX) {
+            true => self.section[TRASH_PREFIX.len()..].to_string(),
+            false => return Err(Error::Invalid("error_not_trash"))
+        };
+
+        self.move_row(uuid, section, |json| {
+            json.remove("deleted_at");
+        })
+    }
+
+    // Remove the row of id from storage, without keep it on the trash
+    pub fn purge_by_id(&mut self, uuid: String) -> Result<(), Error> {
+
+        self.storage.lock_for_write()?;
+
+        let full_uuid = match self.storage.resolve_id(&self.section, &uuid)? {
+            Some(full_uuid) => full_uuid,
+            None => return Ok(())
+        };
 
-        self.storage.reopen_file();
+        let section = self.section.clone();
 
-        for (i, line) in self.storage.lines.clone().iter().enumerate() {
-            if Data::line_check_uuid(line.clone(), uuid.clone()) {
-                self.storage.lines.remove(i);
-                break;
+        let purged = self.storage.recorded(|storage| {
+
+            match storage.engine.remove(&section, &full_uuid)? {
+                Some(before) => Ok(vec![(section.clone(), full_uuid.clone(), Some(before), None)]),
+                None => Ok(vec![])
             }
-        }
+        });
+
+        self.rows = None;
+        self.position = 0;
 
-        self.storage.persist();
+        purged
+    }
+
+    // Move the row of id to other section, changing the json on the way.
+    // Return false if the row does not exist
+    fn move_row<F: Fn(&mut JsonValue)>(&mut self, uuid: String, target: String, change: F) -> Result<bool, Error> {
+
+        self.storage.lock_for_write()?;
+
+        let full_uuid = match self.storage.resolve_id(&self.section, &uuid)? {
+            Some(full_uuid) => full_uuid,
+            None => return Ok(false)
+        };
+
+        let before = match self.storage.engine.row(&self.section, &full_uuid) {
+            Some(before) => before,
+            None => return Ok(false)
+        };
+
+        let (_, json) = split_line(&before);
+
+        let mut row = parse(&json)
+            .map_err(|_| Error::InvalidRow(self.section.clone(), full_uuid.clone()))?;
+
+        change(&mut row);
+
+        let after = format!("{} {}", full_uuid, row.dump());
+
+        let section = self.section.clone();
+
+        let moved = self.storage.recorded(|storage| {
+
+            storage.engine.remove(&section, &full_uuid)?;
+            storage.engine.start_section(&target)?;
+
+            // A row removed again, after the restore, replaces the old copy
+            let replaced = storage.engine.remove(&target, &full_uuid)?;
+
+            storage.engine.insert(&target, after.clone())?;
+
+            Ok(vec![
+                (section.clone(), full_uuid.clone(), Some(before), None),
+                (target.clone(), full_uuid.clone(), replaced, Some(after))
+            ])
+        });
+
+        self.rows = None;
+        self.position = 0;
+
+        moved?;
+
+        Ok(true)
     }
 }
 
 impl<'a> Metadata<'a> {
 
     // Return the value of key, if exists and is no empty, from the metadata
-    #[allow(dead_code)]
     pub fn get(self, key: &'static str) -> Option<String> {
+        self.storage.engine.metadata(key)
+    }
 
-        let meta_key = format!("::metadata::{} ", key.to_string());
-
-        for line in self.storage.lines.clone() {
+    // Create or update the value of key
+    pub fn set(storage: &mut Storage, key: &'static str, value: String) -> Result<(), Error> {
 
-            if line.starts_with(&meta_key) {
-                let value = line.get(meta_key.chars().count()..line.chars().count())
-                                .unwrap()
-                                .trim()
-                                .to_string();
+        storage.lock_for_write()?;
 
-                if !value.is_empty() {
-                    return Some(value);
-                }
-            }
-        }
-
-        None
+        storage.engine.set_metadata(key, value)
     }
 }
 
@@ -380,6 +794,7 @@ impl<'a> Metadata<'a> {
 mod tests {
 
     use super::*;
+    use std::path::Path;
     use uuid::Uuid;
 
     #[derive(Default, Clone, Debug)]
@@ -390,16 +805,14 @@ mod tests {
 
     impl Model for TestModel {
 
-        fn new(row: JsonValue, uuid: String, _storage: &mut Storage, _can_recursive: bool) -> TestModel {
+        fn new(row: JsonValue, uuid: String, _storage: &mut Storage, _can_recursive: bool) -> Result<TestModel, Error> {
 
-            if row["name"].is_null() {
-                panic!("Name not found into a row(id {})", uuid);
-            }
+            Data::require(&row, "accounts", &uuid, vec!["name"])?;
 
-            TestModel {
+            Ok(TestModel {
                 uuid: uuid,
                 name: row["name"].to_string(),
-            }
+            })
         }
 
         fn to_save(self) -> (String, bool, JsonValue) {
@@ -412,54 +825,90 @@ mod tests {
     #[test]
     fn check_section() {
 
-        let mut st = Storage { path_str: "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
         assert!(!st.check_section("accounts".to_string()));
     }
 
     #[test]
     fn start_section() {
 
-        let mut st = Storage { path_str: "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
-        assert!(st.start_section("accounts".to_string()));
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
+        assert!(st.start_section("accounts".to_string()).is_ok());
     }
 
     #[test]
     fn get_section_data() {
 
-        let mut st = Storage { path_str: "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
 
-        assert!(st.start_section("accounts".to_string()));
+        assert!(st.start_section("accounts".to_string()).is_ok());
 
         let data = st.get_section_data("accounts".to_string());
 
         assert_eq!(data.section, "accounts".to_string());
-        assert_eq!(data.last_position, 0);
-        assert_eq!(data.need_find_section, true);
+        assert_eq!(data.rows, None);
+        assert_eq!(data.position, 0);
         assert_eq!(data.can_recursive, true);
     }
 
     #[test]
     fn save() {
 
-        let mut st = Storage { path_str: "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
 
-        assert!(st.start_section("accounts".to_string()));
+        assert!(st.start_section("accounts".to_string()).is_ok());
 
         let mut data = st.get_section_data("accounts".to_string());
 
         let test = TestModel { uuid: "".to_string(), name: "TESTTT!".to_string() };
 
-        let new_uuid = data.save(test);
+        let new_uuid = data.save(test).unwrap();
 
         // Valid uuid
         assert_eq!(new_uuid.len(), 36);
 
         let test2 = TestModel { uuid: new_uuid.clone(), name: "TEST UPDATE!".to_string() };
 
-        let updated_uuid = data.save(test2);
+        let updated_uuid = data.save(test2).unwrap();
 
         // If is the same uuid
         assert_eq!(new_uuid, updated_uuid);
+
+        // Not found ids are not saved
+        let test3 = TestModel { uuid: "ffffffff".to_string(), name: "NOT SAVED!".to_string() };
+
+        assert_eq!(data.save(test3), Err(Error::NotFound("accounts".to_string(), "ffffffff".to_string())));
+    }
+
+    #[test]
+    fn save_by_prefix() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        let mut st = Storage::new(path.clone());
+
+        assert!(st.start_section("accounts".to_string()).is_ok());
+
+        let mut data = st.get_section_data("accounts".to_string());
+
+        let new_uuid = data.save(TestModel { uuid: "".to_string(), name: "TESTTT!".to_string() }).unwrap();
+
+        // The row is written with the full uuid
+        let prefix: String = new_uuid.chars().take(8).collect();
+        let updated_uuid = data.save(TestModel { uuid: prefix, name: "TEST UPDATE!".to_string() }).unwrap();
+
+        assert_eq!(updated_uuid, new_uuid);
+
+        // Load again
+        let mut st2 = Storage::new(path);
+
+        let mut data2 = st2.get_section_data("accounts".to_string());
+
+        let row = data2.next::<TestModel>().unwrap();
+
+        assert_eq!(row.uuid, new_uuid);
+        assert_eq!(row.name, "TEST UPDATE!".to_string());
+        assert_eq!(data2.next::<TestModel>().unwrap_err(), Error::EndOfSection);
     }
 
     #[test]
@@ -467,18 +916,18 @@ mod tests {
 
         let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
 
-        let mut st = Storage { path_str: path.clone(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st = Storage::new(path.clone());
 
-        assert!(st.start_section("accounts".to_string()));
+        assert!(st.start_section("accounts".to_string()).is_ok());
 
         let mut data = st.get_section_data("accounts".to_string());
 
-        data.save(TestModel { uuid: "".to_string(), name: "FIND ME!".to_string() });
+        data.save(TestModel { uuid: "".to_string(), name: "FIND ME!".to_string() }).unwrap();
 
         assert!(data.next::<TestModel>().is_ok());
 
         // Load again
-        let mut st2 = Storage { path_str: path, file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st2 = Storage::new(path);
 
         let mut data2 = st2.get_section_data("accounts".to_string());
 
@@ -490,24 +939,44 @@ mod tests {
         assert_eq!(row.unwrap().name, "FIND ME!".to_string());
     }
 
+    #[test]
+    fn next_errors() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        fs::write(&path, "::section::accounts\n\
+            bb4b7e2c-2f5c-4c4b-9d3e-6a8e5b4c3d2a {\"name\"\n\
+            bb4b7e2c-2f5c-4c4b-9d3e-6a8e5b4c3d2b {\"bank\":\"bank A\"}\n\
+            bb4b7e2c-2f5c-4c4b-9d3e-6a8e5b4c3d2c {\"name\":\"account 3\"}\n\n").unwrap();
+
+        let mut st = Storage::new(path);
+
+        let mut data = st.get_section_data("accounts".to_string());
+
+        assert_eq!(data.next::<TestModel>().unwrap_err(), Error::InvalidRow("accounts".to_string(), "bb4b7e2c-2f5c-4c4b-9d3e-6a8e5b4c3d2a".to_string()));
+        assert_eq!(data.next::<TestModel>().unwrap_err(), Error::FieldNotFound("accounts".to_string(), "bb4b7e2c-2f5c-4c4b-9d3e-6a8e5b4c3d2b".to_string(), "name".to_string()));
+        assert_eq!(data.next::<TestModel>().unwrap().name, "account 3".to_string());
+        assert_eq!(data.next::<TestModel>().unwrap_err(), Error::EndOfSection);
+    }
+
     #[test]
     fn find_by_id() {
 
-        let mut st = Storage { path_str: "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
 
-        assert!(st.start_section("accounts".to_string()));
+        assert!(st.start_section("accounts".to_string()).is_ok());
 
         let mut data = st.get_section_data("accounts".to_string());
 
-        data.save(TestModel { uuid: "".to_string(), name: "FIND ME!".to_string() });
-        let new_uuid = data.save(TestModel { uuid: "".to_string(), name: "FIND ME2!".to_string() });
-        data.save(TestModel { uuid: "".to_string(), name: "FIND ME3!".to_string() });
+        data.save(TestModel { uuid: "".to_string(), name: "FIND ME!".to_string() }).unwrap();
+        let new_uuid = data.save(TestModel { uuid: "".to_string(), name: "FIND ME2!".to_string() }).unwrap();
+        data.save(TestModel { uuid: "".to_string(), name: "FIND ME3!".to_string() }).unwrap();
 
         // Valid uuid
         assert_eq!(new_uuid.len(), 36);
 
         // If is finded
-        assert!(data.find_by_id(new_uuid.clone()));
+        assert!(data.find_by_id(new_uuid.clone()).unwrap());
 
         let row = data.next::<TestModel>();
 
@@ -517,62 +986,341 @@ mod tests {
         assert_eq!(row.unwrap().name, "FIND ME2!".to_string());
     }
 
+    #[test]
+    fn short_id() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        fs::write(&path, "::section::accounts\n\
+            b0000000-0000-4000-8000-000000000001 {\"name\":\"ONE\"}\n\
+            b0000000-1000-4000-8000-000000000002 {\"name\":\"TWO\"}\n\
+            c0000000-0000-4000-8000-000000000003 {\"name\":\"THREE\"}\n").unwrap();
+
+        let mut st = Storage::new(path);
+
+        // Only the rows with the same start need more chars
+        assert_eq!(st.short_id("accounts", &"b0000000-0000-4000-8000-000000000001".to_string()), "b0000000-0");
+        assert_eq!(st.short_id("accounts", &"b0000000-1000-4000-8000-000000000002".to_string()), "b0000000-1");
+        assert_eq!(st.short_id("accounts", &"c0000000-0000-4000-8000-000000000003".to_string()), "c000000");
+
+        let section = "accounts".to_string();
+
+        assert_eq!(st.resolve_id(&section, &"c0".to_string()), Ok(Some("c0000000-0000-4000-8000-000000000003".to_string())));
+        assert_eq!(st.resolve_id(&section, &"B0000000-1".to_string()), Ok(Some("b0000000-1000-4000-8000-000000000002".to_string())));
+        assert_eq!(st.resolve_id(&section, &" C0000000-0000-4000-8000-000000000003 ".to_string()), Ok(Some("c0000000-0000-4000-8000-000000000003".to_string())));
+        assert_eq!(st.resolve_id(&section, &"d0".to_string()), Ok(None));
+        assert_eq!(st.resolve_id(&section, &"b0".to_string()), Err(Error::AmbiguousId(section.clone(), "b0".to_string(), vec![
+            "b0000000-0000-4000-8000-000000000001".to_string(),
+            "b0000000-1000-4000-8000-000000000002".to_string()
+        ])));
+
+        let mut data = st.get_section_data(section);
+
+        assert!(data.find_by_id("b0".to_string()).is_err());
+        assert!(data.find_by_id("b0000000-1".to_string()).unwrap());
+        assert_eq!(data.next::<TestModel>().unwrap().name, "TWO".to_string());
+    }
+
     #[test]
     fn remove_by_id() {
 
-        let mut st = Storage { path_str: "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
 
-        assert!(st.start_section("accounts".to_string()));
+        assert!(st.start_section("accounts".to_string()).is_ok());
 
         let mut data = st.get_section_data("accounts".to_string());
 
-        data.save(TestModel { uuid: "".to_string(), name: "FIND ME!".to_string() });
-        let new_uuid = data.save(TestModel { uuid: "".to_string(), name: "FIND ME2!".to_string() });
-        data.save(TestModel { uuid: "".to_string(), name: "FIND ME3!".to_string() });
+        data.save(TestModel { uuid: "".to_string(), name: "FIND ME!".to_string() }).unwrap();
+        let new_uuid = data.save(TestModel { uuid: "".to_string(), name: "FIND ME2!".to_string() }).unwrap();
+        data.save(TestModel { uuid: "".to_string(), name: "FIND ME3!".to_string() }).unwrap();
 
         // Valid uuid
         assert_eq!(new_uuid.len(), 36);
 
         // If is finded
-        assert!(data.find_by_id(new_uuid.clone()));
+        assert!(data.find_by_id(new_uuid.clone()).unwrap());
 
-        data.remove_by_id(new_uuid.clone());
+        data.remove_by_id(new_uuid.clone()).unwrap();
 
         // uuid can't more finded
-        assert!(!data.find_by_id(new_uuid));
+        assert!(!data.find_by_id(new_uuid).unwrap());
     }
 
     #[test]
-    fn get_metadata() {
+    fn remove_failed() {
 
-        let mut st = Storage { path_str: "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
 
-        // Creating the metadata
+        let mut st = Storage::new(path.clone());
+
+        let uuid = st.get_section_data("accounts".to_string())
+            .save(TestModel { uuid: "".to_string(), name: "KEEP ME!".to_string() }).unwrap();
+
+        let contents = fs::read(&path).unwrap();
+
+        // The temporary file can't be created, so the move to the trash fails on the write
+        fs::create_dir(format!("{}.tmp", path)).unwrap();
+
+        assert!(st.get_section_data("accounts".to_string()).remove_by_id(uuid.clone()).is_err());
+
+        // Nothing written, and the row is still on his section, not half moved
+        assert_eq!(fs::read(&path).unwrap(), contents);
+        assert!(st.get_section_data("accounts".to_string()).find_by_id(uuid.clone()).unwrap());
+        assert!(!st.get_section_data("trash_accounts".to_string()).find_by_id(uuid.clone()).unwrap());
+        assert_eq!(Journal::get_changes(&st).unwrap().len(), 1);
+
+        // The changes are not grouped anymore, the next one is written
+        fs::remove_dir(format!("{}.tmp", path)).unwrap();
+
+        st.get_section_data("accounts".to_string()).remove_by_id(uuid.clone()).unwrap();
+
+        let mut st = Storage::new(path.clone());
+
+        assert!(!st.get_section_data("accounts".to_string()).find_by_id(uuid.clone()).unwrap());
+        assert!(st.get_section_data("trash_accounts".to_string()).find_by_id(uuid).unwrap());
+    }
+
+    #[test]
+    fn journal_failed() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        let mut st = Storage::new(path.clone());
+
+        // The journal can't be written
+        fs::create_dir(format!("{}.journal", path)).unwrap();
+
+        let saved = st.get_section_data("accounts".to_string())
+            .save(TestModel { uuid: "".to_string(), name: "NOT RECORDED!".to_string() });
+
+        assert!(saved.is_err());
+
+        // So the change is not written either
+        assert!(!fs::read_to_string(&path).unwrap().contains("NOT RECORDED!"));
+        assert_eq!(st.get_section_data("accounts".to_string()).all::<TestModel>().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn persist() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        // First command
         {
-            st.reopen_file();
+            let mut st = Storage::new(path.clone());
+
+            st.get_section_data("accounts".to_string())
+                .save(TestModel { uuid: "".to_string(), name: "FIRST!".to_string() }).unwrap();
+        }
+
+        // Second command, with many writes
+        let mut st = Storage::new(path.clone());
 
-            st.file.as_ref().unwrap()
-                .write(b"::metadata::key2 my value\n::metadata::key3 my other value")
-                .expect("Couldn't write line on storage file");
+        let mut data = st.get_section_data("accounts".to_string());
+
+        data.save(TestModel { uuid: "".to_string(), name: "SECOND!".to_string() }).unwrap();
+        data.save(TestModel { uuid: "".to_string(), name: "THIRD!".to_string() }).unwrap();
+
+        // No temporary file left behind
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
 
-            // Force reopen the file on next read
-            st.file = None;
+        let mut current = String::new();
+        File::open(&path).unwrap().read_to_string(&mut current).unwrap();
+
+        let mut previous = String::new();
+        File::open(format!("{}.bak", path)).unwrap().read_to_string(&mut previous).unwrap();
+
+        // The backup is the version before the command, not before the last write
+        assert!(current.contains("THIRD!"));
+        assert!(previous.contains("FIRST!"));
+        assert!(!previous.contains("SECOND!"));
+    }
+
+    #[test]
+    fn lock_for_write() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        let mut st = Storage::new(path.clone());
+
+        assert!(st.start_section("accounts".to_string()).is_ok());
+
+        // Readers are not blocked by the writer
+        let mut st2 = Storage::new(path);
+
+        assert!(st2.check_section("accounts".to_string()));
+
+        // But a second writer is
+        let mut data = st2.get_section_data("accounts".to_string());
+
+        let saved = data.save(TestModel { uuid: "".to_string(), name: "LOCKED!".to_string() });
+
+        assert_eq!(saved, Err(Error::Locked(Some(process::id().to_string()))));
+    }
+
+    #[test]
+    fn encrypt() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        {
+            let mut st = Storage::new(path.clone());
+
+            assert!(st.start_section("accounts".to_string()).is_ok());
+
+            assert!(st.get_section_data("accounts".to_string())
+                .save(TestModel { uuid: "".to_string(), name: "BEFORE!".to_string() }).is_ok());
+
+            assert!(st.encrypt("my secret".to_string()).is_ok());
+            assert!(st.encrypt("my secret".to_string()).is_err());
+
+            assert!(st.get_section_data("accounts".to_string())
+                .save(TestModel { uuid: "".to_string(), name: "AFTER!".to_string() }).is_ok());
+        }
+
+        let read = |path: String| {
+            let mut buff = String::new();
+            File::open(path).unwrap().read_to_string(&mut buff).unwrap();
+            buff
+        };
+
+        // Nothing in plain text, including the backup and the journal
+        for file in vec![path.clone(), format!("{}.bak", path), format!("{}.journal", path)] {
+            let contents = read(file);
+            assert!(!contents.contains("accounts"));
+            assert!(!contents.contains("BEFORE!"));
+            assert!(!contents.contains("AFTER!"));
+        }
+
+        assert!(!Path::new(&format!("{}.bak.tmp", path)).exists());
+
+        {
+            let mut st = Storage::new(path.clone());
+
+            st.set_passphrase("my secret".to_string());
+
+            assert!(st.is_encrypted());
+            assert_eq!(Journal::get_changes(&st).unwrap().len(), 2);
+
+            let mut data = st.get_section_data("accounts".to_string());
+
+            assert_eq!(data.next::<TestModel>().unwrap().name, "AFTER!".to_string());
+            assert_eq!(data.next::<TestModel>().unwrap().name, "BEFORE!".to_string());
+
+            assert!(st.decrypt().is_ok());
+        }
+
+        assert!(read(path.clone()).contains("AFTER!"));
+        assert!(read(format!("{}.journal", path)).contains("BEFORE!"));
+    }
+
+    #[test]
+    fn wrong_passphrase() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        {
+            let mut st = Storage::new(path.clone());
+
+            assert!(st.get_section_data("accounts".to_string())
+                .save(TestModel { uuid: "".to_string(), name: "SECRET!".to_string() }).is_ok());
+
+            assert!(st.encrypt("my secret".to_string()).is_ok());
+        }
+
+        let contents = fs::read(&path).unwrap();
+
+        {
+            let mut st = Storage::new(path.clone());
+
+            st.set_passphrase("wrong".to_string());
+
+            assert_eq!(st.load(), Err(Error::Invalid("error_wrong_passphrase")));
+
+            // Nothing is written over the file that couldn't be read
+            let saved = st.get_section_data("accounts".to_string())
+                .save(TestModel { uuid: "".to_string(), name: "OTHER!".to_string() });
+
+            assert_eq!(saved, Err(Error::Invalid("error_wrong_passphrase")));
+            assert_eq!(fs::read(&path).unwrap(), contents);
         }
 
+        // The journal can't be read without the key of storage
+        {
+            let st = Storage::new(path.clone());
+
+            assert_eq!(Journal::get_changes(&st).unwrap_err(), Error::Invalid("error_journal_encrypted"));
+        }
+
+        // A broken file
+        fs::write(&path, "::encrypted::v1 not-a-salt\nbroken").unwrap();
+
+        let mut st = Storage::new(path.clone());
+
+        st.set_passphrase("my secret".to_string());
+
+        assert_eq!(st.load(), Err(Error::Invalid("error_invalid_encrypted_file")));
+    }
+
+    #[test]
+    fn sections_not_started() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        fs::write(&path, "::metadata::key1 my value\n\
+            b0000000-0000-4000-8000-000000000009 {\"name\":\"outside\"}\n\
+            \n\
+            ::section::tags\n\
+            b0000000-0000-4000-8000-000000000001 {\"name\":\"tag 1\"}\n\
+            \n\
+            b0000000-0000-4000-8000-000000000002 {\"name\":\"after the blank line\"}\n").unwrap();
+
+        let mut st = Storage::new(path);
+
+        // The rows out of the sections are not found on the other ones
+        assert_eq!(st.resolve_id(&"trash_tags".to_string(), &"b0000000".to_string()), Ok(None));
+        assert_eq!(st.row("trash_tags", "b0000000-0000-4000-8000-000000000009"), None);
+
+        // The section ends on the blank line, for the lookups too
+        assert_eq!(st.resolve_id(&"tags".to_string(), &"b0000000-0000-4000-8000-000000000002".to_string()), Ok(None));
+        assert_eq!(st.get_section_data("tags".to_string()).all::<TestModel>().unwrap().len(), 1);
+
+        // The first row starts the section, after the metadata
+        assert!(st.get_section_data("contacts".to_string())
+            .save(TestModel { uuid: "".to_string(), name: "contact 1".to_string() }).is_ok());
+
+        let lines = st.lines();
+
+        assert_eq!(lines[0], "::metadata::key1 my value".to_string());
+        assert_eq!(lines[lines.len() - 2], "::section::contacts".to_string());
+        assert_eq!(st.get_section_data("contacts".to_string()).all::<TestModel>().unwrap()[0].name, "contact 1".to_string());
+        assert_eq!(st.get_metadata().get("key1"), Some("my value".to_string()));
+    }
+
+    #[test]
+    fn get_metadata() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        // Creating the metadata
+        fs::write(&path, "::metadata::key2 my value\n::metadata::key3 my other value").unwrap();
+
+        let mut st = Storage::new(path);
+
         // Checking if all operations if ok..
         {
 
             let mut data = st.get_section_data("accounts".to_string());
 
-            data.save(TestModel { uuid: "".to_string(), name: "FIND ME!".to_string() });
-            let new_uuid = data.save(TestModel { uuid: "".to_string(), name: "FIND ME2!".to_string() });
-            data.save(TestModel { uuid: "".to_string(), name: "FIND ME3!".to_string() });
+            data.save(TestModel { uuid: "".to_string(), name: "FIND ME!".to_string() }).unwrap();
+            let new_uuid = data.save(TestModel { uuid: "".to_string(), name: "FIND ME2!".to_string() }).unwrap();
+            data.save(TestModel { uuid: "".to_string(), name: "FIND ME3!".to_string() }).unwrap();
 
             // Valid uuid
             assert_eq!(new_uuid.len(), 36);
 
             // If is finded
-            assert!(data.find_by_id(new_uuid.clone()));
+            assert!(data.find_by_id(new_uuid.clone()).unwrap());
 
             let row = data.next::<TestModel>();
 
@@ -589,4 +1337,29 @@ mod tests {
         assert_eq!(metadata.clone().get("key2"), Some("my value".to_string()));
         assert_eq!(metadata.get("key3"), Some("my other value".to_string()));
     }
+
+    #[test]
+    fn set_metadata() {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        let mut st = Storage::new(path.clone());
+
+        assert!(st.start_section("accounts".to_string()).is_ok());
+
+        Metadata::set(&mut st, "key1", "my value".to_string()).unwrap();
+        Metadata::set(&mut st, "key2", "other value".to_string()).unwrap();
+        Metadata::set(&mut st, "key1", "my new value".to_string()).unwrap();
+
+        // Only one line by key and the sections still readable
+        assert_eq!(st.lines().iter().filter(|line| line.starts_with("::metadata::key1 ")).count(), 1);
+        assert!(st.check_section("accounts".to_string()));
+
+        let mut st = Storage::new(path);
+
+        let metadata = st.get_metadata();
+
+        assert_eq!(metadata.clone().get("key1"), Some("my new value".to_string()));
+        assert_eq!(metadata.get("key2"), Some("other value".to_string()));
+    }
 }
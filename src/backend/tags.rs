@@ -7,6 +7,8 @@
 ///
 
 use backend::storage::*;
+use backend::error::Error;
+use backend::references::*;
 use json::JsonValue;
 
 #[derive(Default, Clone, Debug)]
@@ -17,16 +19,14 @@ pub struct Tag {
 
 impl Model for Tag {
 
-    fn new(row: JsonValue, uuid: String, _storage: &mut Storage, _can_recursive: bool) -> Tag {
+    fn new(row: JsonValue, uuid: String, _storage: &mut Storage, _can_recursive: bool) -> Result<Tag, Error> {
 
-        if row["name"].is_null() {
-            panic!("Name not found into a row(id {}) tag", uuid);
-        }
+        Data::require(&row, "tags", &uuid, vec!["name"])?;
 
-        Tag {
+        Ok(Tag {
             uuid: uuid,
             name: row["name"].to_string(),
-        }
+        })
     }
 
     fn to_save(self) -> (String, bool, JsonValue) {
@@ -39,59 +39,82 @@ impl Model for Tag {
 
 impl Tag {
 
-    // Short version of the uuid
-    pub fn id(self) -> String {
-        Data::uuid_to_id(self.uuid)
+    // Shortest unique prefix of the uuid
+    pub fn id(self, storage: &mut Storage) -> String {
+        storage.short_id("tags", &self.uuid)
     }
 
     // Return a list with all tags
-    pub fn get_tags(storage: &mut Storage) -> Vec<Tag> {
+    pub fn get_tags(storage: &mut Storage) -> Result<Vec<Tag>, Error> {
 
-        storage.start_section("tags".to_string());
+        storage.start_section("tags".to_string())?;
 
         let mut data = storage.get_section_data("tags".to_string());
 
-        let mut list: Vec<Tag> = vec![];
-
-        while let Ok(line) = data.next::<Tag>() {
-            list.push(line);
-        }
-
-        return list;
+        data.all::<Tag>()
     }
 
     // Return the tag of id
-    pub fn get_tag(storage: &mut Storage, uuid: String) -> Result<Tag, &'static str> {
+    pub fn get_tag(storage: &mut Storage, uuid: String) -> Result<Tag, Error> {
 
-        storage.start_section("tags".to_string());
+        storage.start_section("tags".to_string())?;
 
         let mut data = storage.get_section_data("tags".to_string());
 
-        if data.find_by_id(uuid) {
+        if data.find_by_id(uuid.clone())? {
             return data.next::<Tag>();
         }
 
-        Err("Tag not found")
+        Err(Error::NotFound("tags".to_string(), uuid))
     }
 
     // Save updates, or create new, tag on storage
-    pub fn store_tag(storage: &mut Storage, tag: Tag) -> String {
+    pub fn store_tag(storage: &mut Storage, tag: Tag) -> Result<String, Error> {
 
-        storage.start_section("tags".to_string());
+        storage.start_section("tags".to_string())?;
 
         let mut data = storage.get_section_data("tags".to_string());
 
         data.save(tag)
     }
 
-    // Remvoe tag of storage
-    pub fn remove_tag(storage: &mut Storage, uuid: String) {
+    // Remvoe tag of storage, after handle the rows that point to it
+    pub fn remove_tag(storage: &mut Storage, uuid: String, on_remove: OnRemove) -> Result<(), Error> {
 
-        storage.start_section("tags".to_string());
+        let tag = Tag::get_tag(storage, uuid)?;
+
+        References::release(storage, "tags", &tag.uuid, on_remove)?;
 
         let mut data = storage.get_section_data("tags".to_string());
 
-        data.remove_by_id(uuid);
+        data.remove_by_id(tag.uuid)
+    }
+
+    // Point all rows of the dropped tags to the kept one and remove them
+    pub fn merge_tags(storage: &mut Storage, keep: String, drop: Vec<String>) -> Result<(), Error> {
+
+        let keep = Tag::get_tag(storage, keep)?;
+        let mut dropped: Vec<String> = vec![];
+
+        // All ids are checked before the first change
+        for id in drop {
+
+            let tag = Tag::get_tag(storage, id)?;
+
+            if tag.uuid == keep.uuid {
+                return Err(Error::Invalid("error_merge_into_itself"));
+            }
+
+            if !dropped.contains(&tag.uuid) {
+                dropped.push(tag.uuid);
+            }
+        }
+
+        for uuid in dropped {
+            Tag::remove_tag(storage, uuid, OnRemove::ReassignTo(keep.uuid.clone()))?;
+        }
+
+        Ok(())
     }
 }
 
@@ -100,23 +123,22 @@ mod tests {
 
     use super::*;
     use uuid::Uuid;
-    use std::collections::HashMap;
-    use std::cell::RefCell;
+    use backend::rules::Rule;
 
     fn populate() -> String {
 
         let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
 
-        let mut st = Storage { path_str: path.clone(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(path.clone());
 
-        assert!(st.start_section("tags".to_string()));
+        assert!(st.start_section("tags".to_string()).is_ok());
 
         let mut data = st.get_section_data("tags".to_string());
 
-        data.save(Tag { uuid: "".to_string(), name: "tag 1".to_string() });
-        data.save(Tag { uuid: "".to_string(), name: "tag 2".to_string() });
-        data.save(Tag { uuid: "".to_string(), name: "tag 3".to_string() });
-        data.save(Tag { uuid: "".to_string(), name: "tag 4".to_string() });
+        data.save(Tag { uuid: "".to_string(), name: "tag 1".to_string() }).unwrap();
+        data.save(Tag { uuid: "".to_string(), name: "tag 2".to_string() }).unwrap();
+        data.save(Tag { uuid: "".to_string(), name: "tag 3".to_string() }).unwrap();
+        data.save(Tag { uuid: "".to_string(), name: "tag 4".to_string() }).unwrap();
 
         path
     }
@@ -124,9 +146,9 @@ mod tests {
     #[test]
     fn get_tags() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
-        let tags = Tag::get_tags(&mut st);
+        let tags = Tag::get_tags(&mut st).unwrap();
 
         assert_eq!(tags[0].name, "tag 4".to_string());
         assert_eq!(tags[1].name, "tag 3".to_string());
@@ -137,9 +159,9 @@ mod tests {
     #[test]
     fn get_tag() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
-        let tags = Tag::get_tags(&mut st);
+        let tags = Tag::get_tags(&mut st).unwrap();
 
         let uuid = tags[0].uuid.clone();
 
@@ -157,11 +179,11 @@ mod tests {
     #[test]
     fn store_tag() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
-        Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 5".to_string() });
+        Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 5".to_string() }).unwrap();
 
-        let tags = Tag::get_tags(&mut st);
+        let tags = Tag::get_tags(&mut st).unwrap();
 
         assert_eq!(tags[0].name, "tag 5".to_string());
         assert_eq!(tags[1].name, "tag 4".to_string());
@@ -170,9 +192,9 @@ mod tests {
     #[test]
     fn remove_tag() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(populate());
 
-        let tags = Tag::get_tags(&mut st);
+        let tags = Tag::get_tags(&mut st).unwrap();
 
         let uuid = tags[0].uuid.clone();
 
@@ -180,10 +202,44 @@ mod tests {
 
         assert!(tag.is_ok());
 
-        Tag::remove_tag(&mut st, uuid.clone());
+        Tag::remove_tag(&mut st, uuid.clone(), OnRemove::Refuse).unwrap();
 
         let tage = Tag::get_tag(&mut st, uuid.clone());
 
         assert!(tage.is_err());
     }
+
+    #[test]
+    fn merge_tags() {
+
+        let mut st = Storage::new(populate());
+
+        let tags = Tag::get_tags(&mut st).unwrap();
+
+        let rule = Rule::store_rule(&mut st, Rule { term: "market".to_string(), description: "Market".to_string(), tags: vec![tags[1].clone(), tags[2].clone()], ..Default::default() }).unwrap();
+
+        assert_eq!(Tag::merge_tags(&mut st, tags[0].uuid.clone(), vec![tags[0].uuid.clone()]), Err(Error::Invalid("error_merge_into_itself")));
+        assert_eq!(Tag::merge_tags(&mut st, tags[0].uuid.clone(), vec![tags[1].uuid.clone(), "NOOOO".to_string()]), Err(Error::NotFound("tags".to_string(), "NOOOO".to_string())));
+
+        // Nothing was changed by the failures
+        assert_eq!(Tag::get_tags(&mut st).unwrap().len(), 4);
+
+        Tag::merge_tags(&mut st, tags[0].uuid.clone(), vec![tags[1].uuid.clone(), tags[2].uuid.clone()]).unwrap();
+
+        let names: Vec<String> = Tag::get_tags(&mut st).unwrap()
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+
+        assert_eq!(names, vec!["tag 4", "tag 1"]);
+
+        // Both tags of the rule became the kept one
+        let rule_tags: Vec<String> = Rule::get_rule(&mut st, rule).unwrap()
+            .tags
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect();
+
+        assert_eq!(rule_tags, vec!["tag 4"]);
+    }
 }
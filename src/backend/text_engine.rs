@@ -0,0 +1,593 @@
+///
+/// Blitz Money
+///
+/// Backend of module for the engine of the text format of storage
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use std::fs::{ self, File, OpenOptions };
+use std::io::{ Read, Write };
+use std::path::Path;
+use std::collections::HashMap;
+use fs2::FileExt;
+use backend::storage::Storage;
+use backend::engine::Engine;
+use backend::crypto::Crypto;
+use backend::error::Error;
+
+// Storage on a text file. The file has the metadata lines on top and
+// the sections after them. Each section is the header line, one row
+// by line and a blank line on the end
+pub struct TextEngine {
+    path_str: String,
+    file: Option<File>,
+    lines: Vec<String>,
+    // Key of the file, only when is encrypted
+    crypto: Option<Crypto>,
+    // Passphrase of the encrypted file, when given instead of asked
+    passphrase: Option<String>,
+    // Rows of each section, by name. Built on the first read and
+    // discarded when the lines change
+    tables: Option<HashMap<String, Table>>,
+    // Lines before the #begin, while the changes are grouped
+    original: Option<Vec<String>>,
+    // Why the file couldn't be read, nothing is written until it is
+    // read again
+    failure: Option<Error>,
+    // The .bak was already taken since the #reset, so it keeps the
+    // version of file before the command
+    backed_up: bool
+}
+
+// Rows of a section, indexed for the lookups don't need scan the lines
+struct Table {
+    // Position of the header of section on lines
+    start: usize,
+    // Position of each row on lines, by uuid
+    positions: HashMap<String, usize>,
+    // Uuids of the rows, sorted for find them by prefix
+    uuids: Vec<String>
+}
+
+impl TextEngine {
+
+    // Create the engine of the file path. The file is only opened on the first use
+    pub fn new(path_str: String) -> TextEngine {
+        TextEngine { path_str: path_str, file: None, lines: Vec::new(), crypto: None, passphrase: None, tables: None, original: None, failure: None, backed_up: false }
+    }
+
+    // Open, or reopen, the file for storage. Create a file for store all data, if does not alred exists
+    fn reopen_file(&mut self) -> Result<(), Error> {
+
+        if self.file.is_some() {
+            return Ok(());
+        }
+
+        // Not asked again, like the passphrase, until the #reset
+        if let Some(failure) = self.failure.clone() {
+            return Err(failure);
+        }
+
+        // Readers share the lock while reading, so no writer can start in the
+        // middle of the read. If a writer already holds the lock, even this
+        // process, we read anyway, the file is always replaced atomically
+        let lock = Storage::open_lock_file(&self.path_str);
+        let shared = lock.try_lock_shared().is_ok();
+
+        let read = self.read_file();
+
+        if shared {
+            let _ = lock.unlock();
+        }
+
+        self.tables = None;
+
+        match read {
+            Ok(lines) => {
+                self.lines = lines;
+                Ok(())
+            },
+            Err(e) => {
+                self.file = None;
+                self.lines = Vec::new();
+                self.failure = Some(e.clone());
+                Err(e)
+            }
+        }
+    }
+
+    // Open, or create, the file and return his lines, decrypted
+    fn read_file(&mut self) -> Result<Vec<String>, Error> {
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .append(true)
+            .open(Path::new(&self.path_str))
+            .map_err(|e| Error::Io("error_storage_open", e.to_string()))?;
+
+        // Read lines from file
+        let mut buff = String::new();
+        file.read_to_string(&mut buff)
+            .map_err(|e| Error::Io("error_storage_read", e.to_string()))?;
+
+        if Crypto::is_encrypted(&buff) {
+            buff = self.decrypt_contents(&buff)?;
+        } else {
+            self.crypto = None;
+        }
+
+        self.file = Some(file);
+
+        Ok(buff.lines()
+            .map(|line| line.to_string())
+            .collect())
+    }
+
+    // Decrypt the contents of the file. The passphrase is only asked when
+    // the key is unknown or the file was encrypted with other salt
+    fn decrypt_contents(&mut self, contents: &String) -> Result<String, Error> {
+
+        let salt = Crypto::salt_of(contents)
+            .map_err(|_| Error::Invalid("error_invalid_encrypted_file"))?;
+
+        let crypto = match self.crypto.clone() {
+            Some(ref crypto) if crypto.salt == salt => crypto.clone(),
+            _ => {
+                let passphrase = match self.passphrase.clone() {
+                    Some(passphrase) => passphrase,
+                    None => Crypto::passphrase(false)?
+                };
+
+                Crypto::with_salt(passphrase, salt)
+            }
+        };
+
+        let plain = crypto.decrypt_file(contents)
+            .map_err(|_| Error::Invalid("error_wrong_passphrase"))?;
+
+        self.crypto = Some(crypto);
+
+        Ok(plain)
+    }
+
+    // Index the rows of all sections, if not already indexed since
+    // the last change of lines
+    fn load_tables(&mut self) -> &mut HashMap<String, Table> {
+
+        // A failure is kept and returned by the #load
+        let _ = self.reopen_file();
+
+        if self.tables.is_none() {
+
+            let mut tables: HashMap<String, Table> = HashMap::new();
+
+            // Rows before the first section, of a section not started yet
+            tables.insert("".to_string(), Table { start: 0, positions: HashMap::new(), uuids: vec![] });
+            let mut current = "".to_string();
+
+            for (i, line) in self.lines.iter().enumerate() {
+
+                if line.starts_with("::section::") {
+                    let name = line["::section::".len()..].to_string();
+
+                    tables.insert(name.clone(), Table { start: i, positions: HashMap::new(), uuids: vec![] });
+                    current = name;
+
+                    continue;
+                }
+
+                // The rows of section ends on the blank line, the ones
+                // after it are out of any section
+                if line.trim().is_empty() {
+                    current = "".to_string();
+                    continue;
+                }
+
+                if line.starts_with("::") || line.trim().chars().count() < 36 {
+                    continue;
+                }
+
+                if let Some(table) = tables.get_mut(&current) {
+                    let uuid = line.trim().chars().take(36).collect::<String>();
+
+                    // Like a scan of lines, the first row wins
+                    table.positions.entry(uuid).or_insert(i);
+                }
+            }
+
+            for table in tables.values_mut() {
+                table.uuids = table.positions.keys().cloned().collect();
+                table.uuids.sort();
+            }
+
+            self.tables = Some(tables);
+        }
+
+        self.tables.as_mut().unwrap()
+    }
+
+    // Table of the section, if started
+    fn table(&mut self, section: &str) -> Option<&Table> {
+        self.load_tables().get(section)
+    }
+
+    // Position on lines of the row of section
+    fn position_of(&mut self, section: &str, uuid: &str) -> Option<usize> {
+        self.table(section)
+            .and_then(|table| table.positions.get(uuid).cloned())
+    }
+
+    // Persist current lines on storage, unless the changes are grouped.
+    // The lines are written into a temporary sibling file, synced and
+    // renamed over the original. The version of the file before the first
+    // persist of the command is kept as .bak
+    fn persist(&mut self) -> Result<(), Error> {
+
+        self.tables = None;
+
+        if self.original.is_some() {
+            return Ok(());
+        }
+
+        // The lines are not the ones of file
+        if let Some(failure) = self.failure.clone() {
+            return Err(failure);
+        }
+
+        let path = Path::new(&self.path_str);
+        let tmp_path = format!("{}.tmp", self.path_str);
+        let bak_path = format!("{}.bak", self.path_str);
+
+        let failed = |e: ::std::io::Error| Error::Io("error_storage_write", e.to_string());
+
+        {
+            let mut tmp = File::create(&tmp_path).map_err(failed)?;
+
+            let mut contents = String::new();
+            for line in self.lines.iter() {
+                contents.push_str(line);
+                contents.push_str("\n");
+            }
+
+            if let Some(crypto) = self.crypto.as_ref() {
+                contents = crypto.encrypt_file(&contents);
+            }
+
+            tmp.write_all(contents.as_bytes()).map_err(failed)?;
+            tmp.sync_all().map_err(failed)?;
+        }
+
+        if !self.backed_up && path.exists() {
+            fs::copy(path, &bak_path).map_err(failed)?;
+        }
+
+        self.backed_up = true;
+
+        fs::rename(&tmp_path, path).map_err(failed)?;
+
+        // The rename is only durable after the directory entry is synced
+        if let Some(dir) = path.parent() {
+            if let Ok(dir) = File::open(dir) {
+                let _ = dir.sync_all();
+            }
+        }
+
+        // The old handle points to the replaced file
+        self.file = Some(OpenOptions::new().read(true).open(path).map_err(failed)?);
+
+        Ok(())
+    }
+
+    // Persist the lines and replace the backup with the new version. Used
+    // when only the format changes, like on the encryption: the .bak still
+    // has the rows before the change, that are the same, but on the new
+    // format. The old file is never copied, so no plain copy of the
+    // encrypted file is written
+    fn persist_without_backup(&mut self) -> Result<(), Error> {
+
+        self.backed_up = true;
+
+        self.persist()?;
+
+        let bak_path = format!("{}.bak", self.path_str);
+        let tmp_path = format!("{}.tmp", bak_path);
+
+        let failed = |e: ::std::io::Error| Error::Io("error_storage_write", e.to_string());
+
+        fs::copy(&self.path_str, &tmp_path).map_err(failed)?;
+        fs::rename(&tmp_path, &bak_path).map_err(failed)
+    }
+}
+
+impl Engine for TextEngine {
+
+    fn load(&mut self) -> Result<(), Error> {
+        self.reopen_file()
+    }
+
+    fn reset(&mut self) {
+        self.file = None;
+        self.failure = None;
+        self.backed_up = false;
+    }
+
+    fn crypto(&self) -> Option<&Crypto> {
+        self.crypto.as_ref()
+    }
+
+    fn set_passphrase(&mut self, passphrase: String) {
+        self.passphrase = Some(passphrase);
+    }
+
+    fn encrypt(&mut self, crypto: Crypto) -> Result<(), Error> {
+
+        self.reopen_file()?;
+
+        if self.crypto.is_some() {
+            return Err(Error::Invalid("error_already_encrypted"));
+        }
+
+        self.crypto = Some(crypto);
+
+        self.persist_without_backup()
+    }
+
+    fn decrypt(&mut self) -> Result<(), Error> {
+
+        self.reopen_file()?;
+
+        if self.crypto.is_none() {
+            return Err(Error::Invalid("error_not_encrypted"));
+        }
+
+        self.crypto = None;
+
+        self.persist_without_backup()
+    }
+
+    fn sections(&mut self) -> Vec<String> {
+
+        let mut sections: Vec<(usize, String)> = self.load_tables()
+            .iter()
+            .filter(|(name, _)| !name.is_empty())
+            .map(|(name, table)| (table.start, name.clone()))
+            .collect();
+
+        sections.sort();
+
+        sections.into_iter()
+            .map(|(_, name)| name)
+            .collect()
+    }
+
+    fn start_section(&mut self, name: &str) -> Result<(), Error> {
+
+        if self.load_tables().contains_key(name) {
+            return Ok(());
+        }
+
+        self.lines.push("".to_string());
+        self.lines.push(format!("::section::{}", name));
+
+        self.persist()
+    }
+
+    fn rows(&mut self, section: &str) -> Vec<String> {
+
+        let start = match self.load_tables().get(section) {
+            Some(table) if section.is_empty() => table.start,
+            Some(table) => table.start + 1,
+            None => return vec![]
+        };
+
+        // The rows of section ends on the blank line
+        self.lines[start.min(self.lines.len())..].iter()
+            .skip_while(|line| start == 0 && line.starts_with("::metadata::"))
+            .take_while(|line| !line.trim().is_empty() && !line.starts_with("::"))
+            .cloned()
+            .collect()
+    }
+
+    fn row(&mut self, section: &str, uuid: &str) -> Option<String> {
+        self.position_of(section, uuid)
+            .map(|i| self.lines[i].clone())
+    }
+
+    fn with_prefix(&mut self, section: &str, prefix: &str) -> Vec<String> {
+
+        let table = match self.table(section) {
+            Some(table) => table,
+            None => return vec![]
+        };
+
+        let start = match table.uuids.binary_search(&prefix.to_string()) {
+            Ok(i) => i,
+            Err(i) => i
+        };
+
+        table.uuids[start..].iter()
+            .take_while(|uuid| uuid.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+
+    fn neighbours(&mut self, section: &str, uuid: &str) -> Vec<String> {
+
+        let table = match self.load_tables().get(section) {
+            Some(table) => table,
+            None => return vec![]
+        };
+
+        let mut neighbours = vec![];
+
+        if let Ok(i) = table.uuids.binary_search(&uuid.to_string()) {
+
+            if i > 0 {
+                neighbours.push(table.uuids[i - 1].clone());
+            }
+
+            if i + 1 < table.uuids.len() {
+                neighbours.push(table.uuids[i + 1].clone());
+            }
+        }
+
+        neighbours
+    }
+
+    fn insert(&mut self, section: &str, line: String) -> Result<(), Error> {
+
+        // A section not started yet is created at the end of file
+        if !section.is_empty() && !self.load_tables().contains_key(section) {
+            self.lines.push("".to_string());
+            self.lines.push(format!("::section::{}", section));
+            self.tables = None;
+        }
+
+        // New rows are the first of section
+        let position = match self.load_tables().get(section) {
+            Some(table) if !section.is_empty() => table.start + 1,
+            _ => 0
+        };
+
+        self.lines.insert(position, line);
+
+        self.persist()
+    }
+
+    fn update(&mut self, section: &str, uuid: &str, line: String) -> Result<Option<String>, Error> {
+
+        let i = match self.position_of(section, uuid) {
+            Some(i) => i,
+            None => return Ok(None)
+        };
+
+        let before = self.lines[i].clone();
+        self.lines[i] = line;
+
+        self.persist()?;
+
+        Ok(Some(before))
+    }
+
+    fn remove(&mut self, section: &str, uuid: &str) -> Result<Option<String>, Error> {
+
+        let i = match self.position_of(section, uuid) {
+            Some(i) => i,
+            None => return Ok(None)
+        };
+
+        let removed = self.lines.remove(i);
+
+        self.persist()?;
+
+        Ok(Some(removed))
+    }
+
+    fn replace_line(&mut self, expected: &str, line: Option<String>) -> Result<bool, Error> {
+
+        self.reopen_file()?;
+
+        let position = match self.lines.iter().position(|current| current == expected) {
+            Some(i) => i,
+            None => return Ok(false)
+        };
+
+        match line {
+            Some(line) => self.lines[position] = line,
+            None => { self.lines.remove(position); }
+        }
+
+        self.persist()?;
+
+        Ok(true)
+    }
+
+    fn metadata(&self, key: &str) -> Option<String> {
+
+        let meta_key = format!("::metadata::{} ", key);
+
+        for line in self.lines.iter() {
+
+            if line.starts_with(&meta_key) {
+                let value = line[meta_key.len()..].trim().to_string();
+
+                if !value.is_empty() {
+                    return Some(value);
+                }
+            }
+        }
+
+        None
+    }
+
+    // The metadata lines are kept on top of the file, before the sections
+    fn set_metadata(&mut self, key: &str, value: String) -> Result<(), Error> {
+
+        self.reopen_file()?;
+
+        let meta_key = format!("::metadata::{} ", key);
+        let line = format!("{}{}", meta_key, value);
+
+        match self.lines.iter().position(|current| current.starts_with(&meta_key)) {
+            Some(i) => self.lines[i] = line,
+            None => self.lines.insert(0, line)
+        }
+
+        self.persist()
+    }
+
+    fn lines(&mut self) -> Vec<String> {
+
+        let _ = self.reopen_file();
+
+        self.lines.clone()
+    }
+
+    fn replace_all(&mut self, lines: Vec<String>) -> Result<(), Error> {
+
+        // The file is replaced, even if it couldn't be read
+        self.failure = None;
+        self.file = None;
+
+        self.lines = lines;
+
+        self.persist()
+    }
+
+    fn begin(&mut self) {
+
+        let _ = self.reopen_file();
+
+        if self.original.is_none() {
+            self.original = Some(self.lines.clone());
+        }
+    }
+
+    fn commit(&mut self) -> Result<(), Error> {
+
+        let original = match self.original.take() {
+            Some(original) => original,
+            None => return Ok(())
+        };
+
+        let persisted = self.persist();
+
+        // Still grouped, so the #rollback puts back the lines
+        if persisted.is_err() {
+            self.original = Some(original);
+        }
+
+        persisted
+    }
+
+    fn rollback(&mut self) {
+
+        if let Some(original) = self.original.take() {
+            self.lines = original;
+            self.tables = None;
+        }
+    }
+}
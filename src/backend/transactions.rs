@@ -10,9 +10,12 @@ use backend::storage::*;
 use backend::accounts::*;
 use backend::contacts::*;
 use backend::tags::*;
+use backend::money::Money;
+use backend::error::Error;
 use i18n::*;
 use chrono::{Local, DateTime, NaiveDate};
 use json::JsonValue;
+use json::number::Number;
 
 #[derive(Clone, Debug)]
 pub struct Transaction {
@@ -20,16 +23,23 @@ pub struct Transaction {
    pub account: Option<Account>,
    pub contact: Option<Contact>,
    pub description: String,
-   pub value: f32,
+   pub value: Money,
    // When this transaction will be paid
    pub deadline: Option<NaiveDate>,
    pub paid_in: Option<NaiveDate>,
+   // Date of the bank statement that this transaction was checked against
+   pub reconciled_in: Option<NaiveDate>,
    pub created_at: Option<DateTime<Local>>,
    // Last update
    pub updated_at: Option<DateTime<Local>>,
    // When this transaction is a transfer. If has value on this field the field 'contact' will be empty
    pub transfer: Option<Box<Transaction>>,
+   // How many of the currency of other account for one of this, when the
+   // transfer is between accounts of different currencies
+   pub transfer_rate: Option<Number>,
    pub tags: Vec<Tag>,
+   // Parts of the value with his own tags, like the lines of a receipt
+   pub splits: Vec<Split>,
    pub observations: String,
    // OFX references
    pub ofx_memo: String,
@@ -48,15 +58,18 @@ impl Default for Transaction {
         Transaction {
             uuid: "".to_string(),
             description: "".to_string(),
-            value: 0.0,
+            value: Money::zero(),
             account: None,
             contact: None,
             deadline: None,
             paid_in: None,
+            reconciled_in: None,
             created_at: Some(Local::now()),
             updated_at: None,
             transfer: None,
+            transfer_rate: None,
             tags: vec!(),
+            splits: vec!(),
             observations: "".to_string(),
             ofx_memo: "".to_string(),
             ofx_fitid: "".to_string(),
@@ -66,10 +79,19 @@ impl Default for Transaction {
     }
 }
 
+// A part of the value of transaction. The values of all
+// parts must sum the value of transaction
+#[derive(Clone, Debug, Default)]
+pub struct Split {
+    pub value: Money,
+    pub tags: Vec<Tag>,
+    pub memo: String
+}
+
 #[derive(Clone, Debug)]
 pub struct Total {
     pub label: String,
-    pub value: f32,
+    pub value: Money,
 }
 
 pub const T_EXPENSES_PAYABLE: usize = 0;
@@ -92,71 +114,58 @@ pub enum StatusFilter {
 
 impl Model for Transaction {
 
-    fn new(row: JsonValue, uuid: String, storage: &mut Storage, can_recursive: bool) -> Transaction {
-
-        if row["description"].is_null() {
-            panic!("Description not found into a row(id {}) transaction", uuid);
-        }
-
-        if row["value"].is_null() {
-            panic!("Value not found into a row(id {}) transaction", uuid);
-        }
+    fn new(row: JsonValue, uuid: String, storage: &mut Storage, can_recursive: bool) -> Result<Transaction, Error> {
 
-        if row["deadline"].is_null() {
-            panic!("Deadline not found into a row(id {}) transaction", uuid);
-        }
+        Data::require(&row, "transactions", &uuid, vec!["description", "value", "deadline", "account", "created_at"])?;
 
-        if row["contact"].is_null() && row["transfer"].is_null() {
+        if row["transfer"].is_null() {
             // We dont need contact if is a transfer
-            panic!("Contact not found into a row(id {}) transaction", uuid);
+            Data::require(&row, "transactions", &uuid, vec!["contact"])?;
         }
 
-        if row["account"].is_null() {
-            panic!("Account not found into a row(id {}) transaction", uuid);
-        }
-
-        if row["created_at"].is_null() {
-            panic!("Created at not found into a row(id {}) transaction", uuid);
-        }
+        let invalid = |field: &str| Error::InvalidField("transactions".to_string(), uuid.clone(), field.to_string());
 
         let mut mov = Transaction {
             uuid: uuid.clone(),
             description: row["description"].to_string(),
-            value: row["value"].as_f32().unwrap(),
+            value: Money::from_json(&row["value"]).ok_or(invalid("value"))?,
             ..Default::default()
         };
 
-        mov.account = Some(Account::get_account(storage, row["account"].to_string()).unwrap());
+        mov.account = Some(Account::get_account(storage, row["account"].to_string())?);
 
         if !row["contact"].is_empty() {
-            mov.contact = Some(Contact::get_contact(storage, row["contact"].to_string()).unwrap());
+            mov.contact = Some(Contact::get_contact(storage, row["contact"].to_string())?);
         } else {
             mov.contact = None;
         }
 
-        mov.created_at = Some(row["created_at"].to_string().parse::<DateTime<Local>>().unwrap());
-        mov.deadline = Some(NaiveDate::parse_from_str(&row["deadline"].to_string(), "%Y-%m-%d").unwrap());
+        mov.created_at = Some(row["created_at"].to_string().parse::<DateTime<Local>>().map_err(|_| invalid("created_at"))?);
+        mov.deadline = Some(NaiveDate::parse_from_str(&row["deadline"].to_string(), "%Y-%m-%d").map_err(|_| invalid("deadline"))?);
 
         if !row["paid_in"].is_empty() {
-            mov.paid_in = Some(NaiveDate::parse_from_str(&row["paid_in"].to_string(), "%Y-%m-%d").unwrap());
+            mov.paid_in = Some(NaiveDate::parse_from_str(&row["paid_in"].to_string(), "%Y-%m-%d").map_err(|_| invalid("paid_in"))?);
+        }
+
+        if !row["reconciled_in"].is_empty() {
+            mov.reconciled_in = Some(NaiveDate::parse_from_str(&row["reconciled_in"].to_string(), "%Y-%m-%d").map_err(|_| invalid("reconciled_in"))?);
         }
 
         if !row["updated_at"].is_empty() {
-            mov.updated_at = Some(row["updated_at"].to_string().parse::<DateTime<Local>>().unwrap());
+            mov.updated_at = Some(row["updated_at"].to_string().parse::<DateTime<Local>>().map_err(|_| invalid("updated_at"))?);
         }
 
         if !row["transfer"].is_empty() && can_recursive {
 
             let mut data = storage.get_section_data("transactions".to_string());
 
-            if data.find_by_id(row["transfer"].to_string()) {
+            if data.find_by_id(row["transfer"].to_string())? {
 
                 // This instruct the new() method of the other
                 // transaction for dont't run more recursive operations
                 data.can_recursive = false;
 
-                let mut other = data.next::<Transaction>()
-                    .expect("Couldn't parse the other transfer");
+                let mut other = data.next::<Transaction>()?;
 
                 // Update the current transaction with link to
                 // transaction of other account
@@ -166,10 +175,17 @@ impl Model for Transaction {
                 // with this
                 other.transfer = Some(Box::new(mov.clone()));
             } else {
-                panic!("Couldn't find the transaction {} need by {}", row["transfer"], uuid);
+                return Err(Error::NotFound("transactions".to_string(), row["transfer"].to_string()));
             }
         }
 
+        if !row["transfer_rate"].is_null() {
+            mov.transfer_rate = match row["transfer_rate"] {
+                JsonValue::Number(rate) if rate.is_sign_positive() && !rate.is_empty() => Some(rate),
+                _ => return Err(invalid("transfer_rate"))
+            };
+        }
+
         if !row["tags"].is_empty() {
             for stag in row["tags"].members() {
                 if let Ok(tag) = Tag::get_tag(storage, stag.to_string()) {
@@ -178,6 +194,26 @@ impl Model for Transaction {
             }
         }
 
+        for split in row["splits"].members() {
+
+            let mut tags: Vec<Tag> = vec![];
+
+            for stag in split["tags"].members() {
+                if let Ok(tag) = Tag::get_tag(storage, stag.to_string()) {
+                    tags.push(tag);
+                }
+            }
+
+            mov.splits.push(Split {
+                value: Money::from_json(&split["value"]).ok_or(invalid("splits"))?,
+                tags: tags,
+                memo: match split["memo"].is_null() {
+                    true => "".to_string(),
+                    false => split["memo"].to_string()
+                }
+            });
+        }
+
         if !row["observations"].is_empty() {
             mov.observations = row["observations"].to_string();
         }
@@ -196,7 +232,7 @@ impl Model for Transaction {
             mov.previous_repetition = row["previous_repetition"].to_string();
         }
 
-        mov
+        Ok(mov)
     }
 
     fn to_save(self) -> (String, bool, JsonValue) {
@@ -213,6 +249,10 @@ impl Model for Transaction {
             ob["paid_in"] = self.paid_in.unwrap().format("%Y-%m-%d").to_string().into();
         }
 
+        if let Some(reconciled_in) = self.reconciled_in {
+            ob["reconciled_in"] = reconciled_in.format("%Y-%m-%d").to_string().into();
+        }
+
         if !self.uuid.is_empty() {
             ob["updated_at"] = Local::now().to_rfc3339().to_string().into();
         }
@@ -227,6 +267,10 @@ impl Model for Transaction {
             ob["transfer"] = self.transfer.unwrap().uuid.into();
         }
 
+        if let Some(rate) = self.transfer_rate {
+            ob["transfer_rate"] = JsonValue::Number(rate);
+        }
+
         if self.tags.len() > 0 {
             let tags: Vec<String> = self.tags
                 .iter()
@@ -236,6 +280,31 @@ impl Model for Transaction {
             ob["tags"] = tags.into();
         }
 
+        if self.splits.len() > 0 {
+            let splits: Vec<JsonValue> = self.splits
+                .iter()
+                .map(|split| {
+                    let tags: Vec<String> = split.tags
+                        .iter()
+                        .map(|tag| tag.uuid.clone())
+                        .collect();
+
+                    let mut json = object!{
+                        "value" => split.value,
+                        "tags" => tags
+                    };
+
+                    if !split.memo.is_empty() {
+                        json["memo"] = split.memo.clone().into();
+                    }
+
+                    json
+                })
+                .collect();
+
+            ob["splits"] = splits.into();
+        }
+
         if !self.observations.is_empty() {
             ob["observations"] = self.observations.into();
         }
@@ -264,67 +333,141 @@ impl Transaction {
         self.account.clone().unwrap().format_value(self.value)
     }
 
+    // Dated before the start of account, so out of the balances
+    pub fn is_before_opening(&self) -> bool {
+        match (self.deadline, self.account.as_ref()) {
+            (Some(deadline), Some(account)) => !account.is_opened_on(deadline),
+            _ => false
+        }
+    }
+
     // Return the paid in formatted
     pub fn paid_in_formmated(&self) -> String {
         if self.paid_in.is_none() {
             return "(payable)".to_string();
         }
-        self.paid_in.unwrap().to_string().clone()
+        I18n::locale().format_date(self.paid_in.unwrap())
+    }
+
+    // Value credited to each tag. The tags of transaction get all value and
+    // the tags of each split only his part, when not already on transaction
+    pub fn values_by_tag(&self) -> Vec<(Tag, Money)> {
+
+        let mut values: Vec<(Tag, Money)> = self.tags
+            .iter()
+            .map(|tag| (tag.clone(), self.value))
+            .collect();
+
+        for split in self.splits.iter() {
+            for tag in split.tags.iter() {
+
+                if self.tags.iter().any(|other| other.uuid == tag.uuid) {
+                    continue;
+                }
+
+                match values.iter_mut().find(|(other, _)| other.uuid == tag.uuid) {
+                    Some((_, value)) => *value += split.value,
+                    None => values.push((tag.clone(), split.value))
+                }
+            }
+        }
+
+        values
     }
 
-    // Short version of the uuid
-    pub fn id(self) -> String {
-        Data::uuid_to_id(self.uuid)
+    // Value credited to the tag, None when the transaction is not on tag
+    pub fn value_of_tag(&self, uuid: &str) -> Option<Money> {
+        self.values_by_tag()
+            .into_iter()
+            .find(|(tag, _)| tag.uuid == uuid)
+            .map(|(_, value)| value)
+    }
+
+    // The splits, when present, must sum the value
+    fn check_splits(&self) -> Result<(), Error> {
+
+        if !self.splits.is_empty() && self.splits.iter().map(|split| split.value).sum::<Money>() != self.value {
+            return Err(Error::Invalid("error_splits_total"));
+        }
+
+        Ok(())
+    }
+
+    // Shortest unique prefix of the uuid
+    pub fn id(self, storage: &mut Storage) -> String {
+        storage.short_id("transactions", &self.uuid)
     }
 
     // Return a list with all transactions of account
-    pub fn get_transactions_simple(storage: &mut Storage, account: Account) -> Vec<Transaction> {
+    pub fn get_transactions_simple(storage: &mut Storage, account: Account) -> Result<Vec<Transaction>, Error> {
 
-        storage.start_section("transactions".to_string());
+        storage.start_section("transactions".to_string())?;
 
         let mut data = storage.get_section_data("transactions".to_string());
         let mut list: Vec<Transaction> = vec![];
 
-        while let Ok(line) = data.next::<Transaction>() {
+        for line in data.all::<Transaction>()? {
             if account.uuid == line.account.clone().unwrap().uuid {
                 list.push(line);
             }
         }
 
-        list
+        Ok(list)
     }
 
     // Return a list with all transactions, except the mergeds, of account and totals, with more filters
-    pub fn get_transactions(storage: &mut Storage, account: Account, from: NaiveDate, to: NaiveDate, filter_status: StatusFilter, filter_tag: Option<Tag>, show_mergeds: bool) -> (Vec<Transaction>, Vec<Total>) {
+    pub fn get_transactions(storage: &mut Storage, account: Account, from: NaiveDate, to: NaiveDate, filter_status: StatusFilter, filter_tag: Option<Tag>, show_mergeds: bool) -> Result<(Vec<Transaction>, Vec<Total>), Error> {
+        Transaction::get_transactions_converted(storage, account, from, to, filter_status, filter_tag, show_mergeds, |value, _| Ok(value))
+    }
 
-        storage.start_section("transactions".to_string());
+    // Like #get_transactions, but the totals are made with the values given by convert,
+    // from the value and his date, like the conversion to other currency
+    pub fn get_transactions_converted<F>(storage: &mut Storage, account: Account, from: NaiveDate, to: NaiveDate, filter_status: StatusFilter, filter_tag: Option<Tag>, show_mergeds: bool, convert: F) -> Result<(Vec<Transaction>, Vec<Total>), Error>
+        where F: Fn(Money, NaiveDate) -> Result<Money, Error> {
+
+        storage.start_section("transactions".to_string())?;
 
         let mut data = storage.get_section_data("transactions".to_string());
         let mut list: Vec<Transaction> = vec![];
         let mut totals: Vec<Total> = vec![];
 
+        let open_balance_date = account.open_balance_date.unwrap();
+        let open_balance = convert(account.open_balance, open_balance_date)?;
+
+        // When the account starts on the opening balance date the balances
+        // of the periods before it don't have the opening balance
+        let previous_balance = match account.is_opened_on(from.pred()) {
+            true => open_balance,
+            false => Money::zero()
+        };
+
+        let balance = match account.is_opened_on(to) {
+            true => open_balance,
+            false => Money::zero()
+        };
+
         //T_EXPENSES_PAYABLE
-        totals.push(Total { label: I18n::text("transactions_expenses_payable"), value: 0.0 });
+        totals.push(Total { label: I18n::text("transactions_expenses_payable"), value: Money::zero() });
         //T_EXPENSES
-        totals.push(Total { label: I18n::text("transactions_expenses"), value: 0.0 });
+        totals.push(Total { label: I18n::text("transactions_expenses"), value: Money::zero() });
         //T_TRANSFERS_OUT
-        totals.push(Total { label: I18n::text("transactions_transfers_out"), value: 0.0 });
+        totals.push(Total { label: I18n::text("transactions_transfers_out"), value: Money::zero() });
         //T_INCOMES_TORECEIVE
-        totals.push(Total { label: I18n::text("transactions_incomes_toreceive"), value: 0.0 });
+        totals.push(Total { label: I18n::text("transactions_incomes_toreceive"), value: Money::zero() });
         //T_INCOMES
-        totals.push(Total { label: I18n::text("transactions_incomes"), value: 0.0 });
+        totals.push(Total { label: I18n::text("transactions_incomes"), value: Money::zero() });
         //T_TRANSFERS_IN
-        totals.push(Total { label: I18n::text("transactions_transfers_in"), value: 0.0 });
+        totals.push(Total { label: I18n::text("transactions_transfers_in"), value: Money::zero() });
         //T_PREVIOUS_BALANCE
-        totals.push(Total { label: I18n::text("transactions_previous_balance"), value: account.open_balance });
+        totals.push(Total { label: I18n::text("transactions_previous_balance"), value: previous_balance });
         //T_PREVIOUS_EXPECTED_BALANCE
-        totals.push(Total { label: I18n::text("transactions_previous_expected_balance"), value: account.open_balance });
+        totals.push(Total { label: I18n::text("transactions_previous_expected_balance"), value: previous_balance });
         //T_EXPECTED_BALANCE
-        totals.push(Total { label: I18n::text("transactions_expeected_balance"), value: account.open_balance });
+        totals.push(Total { label: I18n::text("transactions_expeected_balance"), value: balance });
         //T_CURRENT_BALANCE
-        totals.push(Total { label: I18n::text("transactions_current_balance"), value: account.open_balance });
+        totals.push(Total { label: I18n::text("transactions_current_balance"), value: balance });
 
-        while let Ok(line) = data.next::<Transaction>() {
+        for line in data.all::<Transaction>()? {
             if account.uuid == line.account.clone().unwrap().uuid && line.merged_in.is_empty() {
 
                 // Filter by status
@@ -334,13 +477,26 @@ impl Transaction {
                     continue;
                 }
 
-                // Filter by tag
-                if let Some(ftag) = filter_tag.clone() {
-                    if !(line.tags.iter().any(|tag| ftag.uuid == tag.uuid)) {
-                        continue;
+                // Filter by tag, with only the value of tag on the totals
+                let value = match filter_tag.as_ref() {
+                    Some(ftag) => match line.value_of_tag(&ftag.uuid) {
+                        Some(value) => value,
+                        None => continue
+                    },
+                    None => line.value
+                };
+
+                // The opening balance already has them, so they are
+                // only listed
+                if line.is_before_opening() {
+                    if line.deadline.unwrap() >= from && line.deadline.unwrap() <= to {
+                        list.push(line);
                     }
+                    continue;
                 }
 
+                let value = convert(value, line.deadline.unwrap())?;
+
                 // Totals: Previous + Total balance
                 if line.paid_in.is_some() {
 
@@ -348,24 +504,24 @@ impl Transaction {
                         // We cant sum the future transactions because the user can
                         // make scheduled transactions for nexts years or filter by
                         // previous months
-                        totals[T_CURRENT_BALANCE].value += line.value;
+                        totals[T_CURRENT_BALANCE].value += value;
                     }
 
                     if line.deadline.unwrap() < from {
-                        totals[T_PREVIOUS_BALANCE].value += line.value;
+                        totals[T_PREVIOUS_BALANCE].value += value;
                     }
                 }
 
                 // Totals: Previous expected balance
                 if line.deadline.unwrap() < from {
-                    totals[T_PREVIOUS_EXPECTED_BALANCE].value += line.value;
+                    totals[T_PREVIOUS_EXPECTED_BALANCE].value += value;
                 }
 
                 // Totals: Expected balance
                 if line.deadline.unwrap() <= to {
                     // We cant sum the future transactions because the user can
                     // make scheduled transactions for nexts years
-                    totals[T_EXPECTED_BALANCE].value += line.value;
+                    totals[T_EXPECTED_BALANCE].value += value;
                 }
 
                 // Period filter
@@ -377,26 +533,26 @@ impl Transaction {
 
                     if line.transfer.is_some() {
                         // Totals: Transfer in + Transfers out
-                        if line.value >= 0.0 {
-                            totals[T_TRANSFERS_IN].value += line.value;
+                        if !value.is_negative() {
+                            totals[T_TRANSFERS_IN].value += value;
                         } else {
-                            totals[T_TRANSFERS_OUT].value += line.value;
+                            totals[T_TRANSFERS_OUT].value += value;
                         }
                     } else {
                         // Totals: Expenses + Incomes paids
-                        if line.value >= 0.0 {
-                            totals[T_INCOMES].value += line.value;
+                        if !value.is_negative() {
+                            totals[T_INCOMES].value += value;
                         } else {
-                            totals[T_EXPENSES].value += line.value;
+                            totals[T_EXPENSES].value += value;
                         }
                     }
                 } else {
 
                     // Totals: Expenses + Incomes payables
-                    if line.value >= 0.0 {
-                        totals[T_INCOMES_TORECEIVE].value += line.value;
+                    if !value.is_negative() {
+                        totals[T_INCOMES_TORECEIVE].value += value;
                     } else {
-                        totals[T_EXPENSES_PAYABLE].value += line.value;
+                        totals[T_EXPENSES_PAYABLE].value += value;
                     }
                 }
 
@@ -409,46 +565,144 @@ impl Transaction {
 
         list.sort_by( | a, b | a.deadline.unwrap().cmp(&b.deadline.unwrap()) );
 
-        return (list, totals);
+        Ok((list, totals))
+    }
+
+    // The totals with the wording of the account type. The balances of the
+    // credit cards and loans are shown as the money owed, and the savings
+    // get the interest of the period
+    pub fn totals_of_type(account: &Account, mut totals: Vec<Total>, from: NaiveDate, to: NaiveDate) -> Vec<Total> {
+
+        let labels: Vec<(usize, &'static str)> = match account.kind {
+            AccountType::CreditCard => vec![
+                (T_EXPENSES_PAYABLE, "transactions_purchases_pending"),
+                (T_EXPENSES, "transactions_purchases"),
+                (T_TRANSFERS_IN, "transactions_payments"),
+                (T_PREVIOUS_BALANCE, "transactions_previous_owed"),
+                (T_PREVIOUS_EXPECTED_BALANCE, "transactions_previous_expected_owed"),
+                (T_EXPECTED_BALANCE, "transactions_expected_owed"),
+                (T_CURRENT_BALANCE, "transactions_owed")
+            ],
+            AccountType::Loan => vec![
+                (T_EXPENSES, "transactions_interest_and_fees"),
+                (T_TRANSFERS_IN, "transactions_payments"),
+                (T_PREVIOUS_BALANCE, "transactions_previous_principal"),
+                (T_PREVIOUS_EXPECTED_BALANCE, "transactions_previous_expected_principal"),
+                (T_EXPECTED_BALANCE, "transactions_expected_principal"),
+                (T_CURRENT_BALANCE, "transactions_principal")
+            ],
+            AccountType::Asset => vec![
+                (T_PREVIOUS_BALANCE, "transactions_previous_value"),
+                (T_PREVIOUS_EXPECTED_BALANCE, "transactions_previous_expected_value"),
+                (T_EXPECTED_BALANCE, "transactions_expected_value"),
+                (T_CURRENT_BALANCE, "transactions_current_value")
+            ],
+            _ => vec![]
+        };
+
+        for (index, label) in labels {
+            totals[index].label = I18n::text(label);
+
+            if account.kind.is_liability() && index >= T_PREVIOUS_BALANCE {
+                totals[index].value = -totals[index].value;
+            }
+        }
+
+        if let Some(interest) = account.interest(totals[T_CURRENT_BALANCE].value, from, to) {
+            totals.push(Total { label: I18n::text("transactions_interest_estimated"), value: interest });
+        }
+
+        totals
     }
 
     // Return the transaction of id
-    pub fn get_transaction(storage: &mut Storage, uuid: String) -> Result<Transaction, &'static str> {
+    pub fn get_transaction(storage: &mut Storage, uuid: String) -> Result<Transaction, Error> {
 
-        storage.start_section("transactions".to_string());
+        storage.start_section("transactions".to_string())?;
 
         let mut data = storage.get_section_data("transactions".to_string());
 
-        if data.find_by_id(uuid) {
+        if data.find_by_id(uuid.clone())? {
             return data.next::<Transaction>();
         }
 
-        Err("Transaction not found")
+        Err(Error::NotFound("transactions".to_string(), uuid))
     }
 
     // Save updates, or create new, transaction on storage
-    pub fn store_transaction(storage: &mut Storage, transaction: Transaction) -> String {
+    pub fn store_transaction(storage: &mut Storage, transaction: Transaction) -> Result<String, Error> {
 
         if transaction.transfer.is_some() {
-            panic!("You must be use the Transaction#store_transfer for transfers!");
+            // You must be use the Transaction#store_transfer for transfers!
+            return Err(Error::Invalid("error_store_transfer"));
         }
 
-        storage.start_section("transactions".to_string());
+        transaction.check_splits()?;
+
+        if transaction.uuid.is_empty() {
+            transaction.account.as_ref().unwrap().check_open(transaction.deadline)?;
+        }
+
+        storage.start_section("transactions".to_string())?;
 
         let mut data = storage.get_section_data("transactions".to_string());
 
+        Transaction::check_reconciled(&mut data, &transaction)?;
+
         data.save(transaction)
     }
 
+    // The reconciled transactions keep the value, dates and account checked against
+    // the bank statement. Only the reconciled date can be taken out, for unlock it
+    fn check_reconciled(data: &mut Data, transaction: &Transaction) -> Result<(), Error> {
+
+        if transaction.uuid.is_empty() || !data.find_by_id(transaction.uuid.clone())? {
+            return Ok(());
+        }
+
+        let stored = data.next::<RawRow>()?.json;
+
+        if stored["reconciled_in"].is_null() {
+            return Ok(());
+        }
+
+        let date = |date: Option<NaiveDate>| date.map(|date| date.format("%Y-%m-%d").to_string());
+
+        let changed = Money::from_json(&stored["value"]) != Some(transaction.value)
+            || stored["deadline"].as_str().map(|date| date.to_string()) != date(transaction.deadline)
+            || stored["paid_in"].as_str().map(|date| date.to_string()) != date(transaction.paid_in)
+            || stored["account"].as_str() != transaction.account.as_ref().map(|account| account.uuid.as_str());
+
+        match changed {
+            true => Err(Error::Invalid("error_transaction_reconciled")),
+            false => Ok(())
+        }
+    }
+
     // Save updates, or create new, transfers transactions
-    pub fn store_transfer(storage: &mut Storage, transaction: &mut Transaction, other: &mut Transaction) -> String {
+    pub fn store_transfer(storage: &mut Storage, transaction: &mut Transaction, other: &mut Transaction) -> Result<String, Error> {
 
-        storage.start_section("transactions".to_string());
+        storage.start_section("transactions".to_string())?;
 
         let mut data = storage.get_section_data("transactions".to_string());
 
-        // The absolute value must be the same, duh
-        if transaction.value.abs() != other.value.abs() {
+        // The value of a side can change here, so his parts would not sum it
+        if !transaction.splits.is_empty() || !other.splits.is_empty() {
+            return Err(Error::Invalid("error_splits_transfer"));
+        }
+
+        // The new transfers can't go to or from a closed account
+        for side in [&transaction, &other].iter() {
+            if side.uuid.is_empty() {
+                side.account.as_ref().unwrap().check_open(transaction.deadline)?;
+            }
+        }
+
+        let same_currency = transaction.account.as_ref().unwrap().same_currency(other.account.as_ref().unwrap());
+
+        // The absolute value must be the same, duh. Only the transfers
+        // between different currencies keep the value of each account
+        if same_currency && transaction.value.abs() != other.value.abs() {
             other.value = transaction.value;
         }
 
@@ -462,67 +716,82 @@ impl Transaction {
             other.paid_in = transaction.paid_in;
         }
 
-        // If value is the same we must invert the
-        // value of second transaction
-        if transaction.value == other.value {
-            if transaction.value >= 0.0 {
-                other.value = 0.0 - transaction.value;
-            } else {
-                other.value = transaction.value.abs();
-            }
+        // The money goes out of one account and in the other,
+        // so the second transaction has the inverse sign
+        other.value = match transaction.value.is_negative() {
+            true => other.value.abs(),
+            false => -other.value.abs()
+        };
+
+        if same_currency {
+            transaction.transfer_rate = None;
+            other.transfer_rate = None;
+        } else {
+            transaction.transfer_rate = transaction.value.ratio_to(other.value);
+            other.transfer_rate = other.value.ratio_to(transaction.value);
         }
 
+        Transaction::check_reconciled(&mut data, transaction)?;
+        Transaction::check_reconciled(&mut data, other)?;
+
         // If is a insert
         if transaction.uuid.is_empty() || other.uuid.is_empty() {
 
             // We save the first transaction to get his uuid
             // and put on the second
-            transaction.uuid = data.save(transaction.to_owned());
+            transaction.uuid = data.save(transaction.to_owned())?;
             other.transfer = Some(Box::new(transaction.clone()));
 
             // Now, we save de second transaction to get his
             // uuid and put on the first. On this point the second
             // has the first uuid
-            other.uuid = data.save(other.to_owned());
+            other.uuid = data.save(other.to_owned())?;
             transaction.transfer = Some(Box::new(other.clone()));
 
             // And finaly, we store the first for save the
             // uuid of second
-            data.save(transaction.to_owned());
+            data.save(transaction.to_owned())?;
         } else {
             // Update
 
             other.transfer = Some(Box::new(transaction.clone()));
             transaction.transfer = Some(Box::new(other.clone()));
 
-            data.save(transaction.to_owned());
-            data.save(other.to_owned());
+            data.save(transaction.to_owned())?;
+            data.save(other.to_owned())?;
         }
 
-        transaction.uuid.clone()
+        Ok(transaction.uuid.clone())
     }
 
     // Remvoe transaction of storage
-    pub fn remove_transaction(storage: &mut Storage, uuid: String) {
+    pub fn remove_transaction(storage: &mut Storage, uuid: String) -> Result<(), Error> {
 
-        storage.start_section("transactions".to_string());
+        storage.start_section("transactions".to_string())?;
 
         let mut data = storage.get_section_data("transactions".to_string());
 
-        if data.find_by_id(uuid.clone()) {
-            let mov = data.next::<Transaction>()
-                .expect("Clound't parse the transaction");
+        if data.find_by_id(uuid.clone())? {
+            let mov = data.next::<Transaction>()?;
+
+            let reconciled = mov.reconciled_in.is_some()
+                || mov.transfer.as_ref().map_or(false, |other| other.reconciled_in.is_some());
+
+            if reconciled {
+                return Err(Error::Invalid("error_transaction_reconciled"));
+            }
 
             if mov.transfer.is_some() {
-                data.remove_by_id(mov.transfer.unwrap().uuid);
+                data.remove_by_id(mov.transfer.unwrap().uuid)?;
             }
         }
 
-        data.remove_by_id(uuid);
+        data.remove_by_id(uuid)
     }
 
-    // Store the trasaction with validation if is a tranfer
-    pub fn make_transaction_or_transfer(storage: &mut Storage, transaction: &mut Transaction, contact_uuid: String) -> String {
+    // Store the trasaction with validation if is a tranfer. The transfer_value is the
+    // value on the destination account, when his currency is not the same
+    pub fn make_transaction_or_transfer(storage: &mut Storage, transaction: &mut Transaction, contact_uuid: String, transfer_value: Option<Money>) -> Result<String, Error> {
 
         //Transfer
         if transaction.contact.is_none() {
@@ -530,7 +799,18 @@ impl Transaction {
             let mut transfer = transaction.clone();
 
             // Destination account
-            transfer.account = Some(Account::get_account(storage, contact_uuid).unwrap());
+            transfer.account = Some(Account::get_account(storage, contact_uuid)?);
+
+            if let Some(value) = transfer_value {
+
+                let same_currency = transaction.account.as_ref().unwrap().same_currency(transfer.account.as_ref().unwrap());
+
+                if same_currency && value.abs() != transaction.value.abs() {
+                    return Err(Error::Invalid("error_transfer_value_same_currency"));
+                }
+
+                transfer.value = value;
+            }
 
             // Update the current transaction with link to
             // transaction of other account
@@ -551,9 +831,9 @@ impl Transaction {
 mod tests {
 
     use super::*;
+    use std::fs;
+    use std::time::{ Duration, Instant };
     use uuid::Uuid;
-    use std::collections::HashMap;
-    use std::cell::RefCell;
 
     fn populate() -> String {
 
@@ -561,54 +841,54 @@ mod tests {
 
         let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
 
-        let mut st = Storage { path_str: path.clone(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st = Storage::new(path.clone());
 
-        Account::store_account(&mut st, Account { uuid: "".to_string(), name: "account AA".to_string(), bank: "bank A".to_string(), currency: "R$".to_string(), open_balance: 0.0, open_balance_date: Some(Local::today().naive_local()) });
-        Account::store_account(&mut st, Account { uuid: "".to_string(), name: "account BB".to_string(), bank: "bank B".to_string(), currency: "R$".to_string(), open_balance: 35.0, open_balance_date: Some(Local::today().naive_local()) });
+        Account::store_account(&mut st, Account { uuid: "".to_string(), name: "account AA".to_string(), bank: "bank A".to_string(), currency: "R$".to_string(), open_balance: Money::zero(), open_balance_date: Some(NaiveDate::from_ymd(2018, 1, 1)), kind: AccountType::Checking, interest_rate: None, closing_day: None, due_day: None, closed_in: None, starts_on_opening: true }).unwrap();
+        Account::store_account(&mut st, Account { uuid: "".to_string(), name: "account BB".to_string(), bank: "bank B".to_string(), currency: "R$".to_string(), open_balance: Money::from_cents(3500), open_balance_date: Some(NaiveDate::from_ymd(2018, 1, 1)), kind: AccountType::Checking, interest_rate: None, closing_day: None, due_day: None, closed_in: None, starts_on_opening: true }).unwrap();
 
-        let accounts = Account::get_accounts(&mut st);
+        let accounts = Account::get_accounts(&mut st).unwrap();
 
-        Contact::store_contact(&mut st, Contact { uuid: "".to_string(), name: "contact 1".to_string(), city_location: "city A".to_string() });
-        Contact::store_contact(&mut st, Contact { uuid: "".to_string(), name: "contact 2".to_string(), city_location: "city B".to_string() });
+        Contact::store_contact(&mut st, Contact { uuid: "".to_string(), name: "contact 1".to_string(), city_location: "city A".to_string() }).unwrap();
+        Contact::store_contact(&mut st, Contact { uuid: "".to_string(), name: "contact 2".to_string(), city_location: "city B".to_string() }).unwrap();
 
-        let contacts = Contact::get_contacts(&mut st);
+        let contacts = Contact::get_contacts(&mut st).unwrap();
 
-        assert!(st.start_section("transactions".to_string()));
+        assert!(st.start_section("transactions".to_string()).is_ok());
 
         let mut data = st.get_section_data("transactions".to_string());
 
         data.save(Transaction {
             description: "transaction 1".to_string(),
-            value: 10.00,
+            value: Money::from_cents(1000),
             account: Some(accounts[0].clone()),
             contact: Some(contacts[0].clone()),
             deadline: Some(NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap()),
             ..Default::default()
-        });
+        }).unwrap();
         data.save(Transaction {
             description: "transaction 2".to_string(),
-            value: -125.53,
+            value: Money::from_cents(-12553),
             account: Some(accounts[0].clone()),
             contact: Some(contacts[1].clone()),
             deadline: Some(NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap()),
             ..Default::default()
-        });
+        }).unwrap();
         data.save(Transaction {
             description: "transaction 3".to_string(),
-            value: 25.58,
+            value: Money::from_cents(2558),
             account: Some(accounts[1].clone()),
             contact: Some(contacts[1].clone()),
             deadline: Some(NaiveDate::parse_from_str("2018-10-15", "%Y-%m-%d").unwrap()),
             ..Default::default()
-        });
+        }).unwrap();
         data.save(Transaction {
             description: "transaction 4".to_string(),
-            value: 159.02,
+            value: Money::from_cents(15902),
             account: Some(accounts[0].clone()),
             contact: Some(contacts[0].clone()),
             deadline: Some(NaiveDate::parse_from_str("2018-08-23", "%Y-%m-%d").unwrap()),
             ..Default::default()
-        });
+        }).unwrap();
 
         path
     }
@@ -616,13 +896,13 @@ mod tests {
     #[test]
     fn get_transactions() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st = Storage::new(populate());
 
-        let accounts = Account::get_accounts(&mut st);
+        let accounts = Account::get_accounts(&mut st).unwrap();
 
         assert_eq!(accounts[0].name, "account BB".to_string());
 
-        let (transactions, _total) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false);
+        let (transactions, _total) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false).unwrap();
 
         assert_eq!(transactions.len(), 2);
         assert_eq!(transactions[0].description, "transaction 2".to_string());
@@ -632,13 +912,13 @@ mod tests {
     #[test]
     fn get_transactions_simple() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st = Storage::new(populate());
 
-        let accounts = Account::get_accounts(&mut st);
+        let accounts = Account::get_accounts(&mut st).unwrap();
 
         assert_eq!(accounts[0].name, "account BB".to_string());
 
-        let transactions = Transaction::get_transactions_simple(&mut st, accounts[0].clone());
+        let transactions = Transaction::get_transactions_simple(&mut st, accounts[0].clone()).unwrap();
 
         assert_eq!(transactions.len(), 3);
         assert_eq!(transactions[0].description, "transaction 4".to_string());
@@ -649,95 +929,128 @@ mod tests {
     #[test]
     fn get_transactions_totals() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st = Storage::new(populate());
 
-        let accounts = Account::get_accounts(&mut st);
+        let accounts = Account::get_accounts(&mut st).unwrap();
 
         assert_eq!(accounts[0].name, "account BB".to_string());
         assert_eq!(accounts[1].name, "account AA".to_string());
 
-        let (transactions_a, totals_a) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false);
+        let (transactions_a, totals_a) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false).unwrap();
 
         assert_eq!(transactions_a.len(), 2);
 
         assert_eq!(totals_a.len(), 10);
         assert_eq!(totals_a[T_EXPENSES_PAYABLE].label, "Expenses(payable)".to_string());
-        assert_eq!(totals_a[T_EXPENSES_PAYABLE].value, -125.53);
+        assert_eq!(totals_a[T_EXPENSES_PAYABLE].value, Money::from_cents(-12553));
         assert_eq!(totals_a[T_EXPENSES].label, "Expenses".to_string());
-        assert_eq!(totals_a[T_EXPENSES].value, 0.0);
+        assert_eq!(totals_a[T_EXPENSES].value, Money::zero());
         assert_eq!(totals_a[T_INCOMES_TORECEIVE].label, "Incomes(to receive)".to_string());
-        assert_eq!(totals_a[T_INCOMES_TORECEIVE].value, 10.0);
+        assert_eq!(totals_a[T_INCOMES_TORECEIVE].value, Money::from_cents(1000));
         assert_eq!(totals_a[T_INCOMES].label, "Incomes".to_string());
-        assert_eq!(totals_a[T_INCOMES].value, 0.0);
+        assert_eq!(totals_a[T_INCOMES].value, Money::zero());
         assert_eq!(totals_a[T_PREVIOUS_BALANCE].label, "Previous balance".to_string());
-        assert_eq!(totals_a[T_PREVIOUS_BALANCE].value, 35.0);
+        assert_eq!(totals_a[T_PREVIOUS_BALANCE].value, Money::from_cents(3500));
         assert_eq!(totals_a[T_EXPECTED_BALANCE].label, "Expected balance".to_string());
-        assert_eq!(totals_a[T_EXPECTED_BALANCE].value, 78.490005);
+        assert_eq!(totals_a[T_EXPECTED_BALANCE].value, Money::from_cents(7849));
         assert_eq!(totals_a[T_CURRENT_BALANCE].label, "Total balance".to_string());
-        assert_eq!(totals_a[T_CURRENT_BALANCE].value, 35.0);
+        assert_eq!(totals_a[T_CURRENT_BALANCE].value, Money::from_cents(3500));
 
         let mut paid = transactions_a[0].clone();
         paid.paid_in = Some(NaiveDate::parse_from_str("2018-10-25", "%Y-%m-%d").unwrap());
-        Transaction::store_transaction(&mut st, paid);
+        Transaction::store_transaction(&mut st, paid).unwrap();
 
-        let (transactions_b, totals_b) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false);
+        let (transactions_b, totals_b) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false).unwrap();
 
         assert_eq!(transactions_b.len(), 2);
 
         assert_eq!(totals_b.len(), 10);
         assert_eq!(totals_b[T_EXPENSES_PAYABLE].label, "Expenses(payable)".to_string());
-        assert_eq!(totals_b[T_EXPENSES_PAYABLE].value, 0.0);
+        assert_eq!(totals_b[T_EXPENSES_PAYABLE].value, Money::zero());
         assert_eq!(totals_b[T_EXPENSES].label, "Expenses".to_string());
-        assert_eq!(totals_b[T_EXPENSES].value, -125.53);
+        assert_eq!(totals_b[T_EXPENSES].value, Money::from_cents(-12553));
         assert_eq!(totals_b[T_INCOMES_TORECEIVE].label, "Incomes(to receive)".to_string());
-        assert_eq!(totals_b[T_INCOMES_TORECEIVE].value, 10.0);
+        assert_eq!(totals_b[T_INCOMES_TORECEIVE].value, Money::from_cents(1000));
         assert_eq!(totals_b[T_INCOMES].label, "Incomes".to_string());
-        assert_eq!(totals_b[5].value, 0.0);
+        assert_eq!(totals_b[5].value, Money::zero());
         assert_eq!(totals_b[T_PREVIOUS_BALANCE].label, "Previous balance".to_string());
-        assert_eq!(totals_b[T_PREVIOUS_BALANCE].value, 35.0);
+        assert_eq!(totals_b[T_PREVIOUS_BALANCE].value, Money::from_cents(3500));
         assert_eq!(totals_b[T_EXPECTED_BALANCE].label, "Expected balance".to_string());
-        assert_eq!(totals_b[T_EXPECTED_BALANCE].value, 78.490005);
+        assert_eq!(totals_b[T_EXPECTED_BALANCE].value, Money::from_cents(7849));
         assert_eq!(totals_b[T_CURRENT_BALANCE].label, "Total balance".to_string());
-        assert_eq!(totals_b[T_CURRENT_BALANCE].value, -90.53);
+        assert_eq!(totals_b[T_CURRENT_BALANCE].value, Money::from_cents(-9053));
 
-        let (transactions_c, totals_c) = Transaction::get_transactions(&mut st, accounts[1].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false);
+        let (transactions_c, totals_c) = Transaction::get_transactions(&mut st, accounts[1].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false).unwrap();
 
         assert_eq!(transactions_c.len(), 1);
 
         assert_eq!(totals_c.len(), 10);
         assert_eq!(totals_c[T_EXPENSES_PAYABLE].label, "Expenses(payable)".to_string());
-        assert_eq!(totals_c[T_EXPENSES_PAYABLE].value, 0.0);
+        assert_eq!(totals_c[T_EXPENSES_PAYABLE].value, Money::zero());
         assert_eq!(totals_c[T_EXPENSES].label, "Expenses".to_string());
-        assert_eq!(totals_c[T_EXPENSES].value, 0.0);
+        assert_eq!(totals_c[T_EXPENSES].value, Money::zero());
         assert_eq!(totals_c[T_INCOMES_TORECEIVE].label, "Incomes(to receive)".to_string());
-        assert_eq!(totals_c[T_INCOMES_TORECEIVE].value, 25.580002);
+        assert_eq!(totals_c[T_INCOMES_TORECEIVE].value, Money::from_cents(2558));
         assert_eq!(totals_c[T_INCOMES].label, "Incomes".to_string());
-        assert_eq!(totals_c[5].value, 0.0);
+        assert_eq!(totals_c[5].value, Money::zero());
         assert_eq!(totals_c[T_PREVIOUS_BALANCE].label, "Previous balance".to_string());
-        assert_eq!(totals_c[T_PREVIOUS_BALANCE].value, 0.0);
+        assert_eq!(totals_c[T_PREVIOUS_BALANCE].value, Money::zero());
         assert_eq!(totals_c[T_EXPECTED_BALANCE].label, "Expected balance".to_string());
-        assert_eq!(totals_c[T_EXPECTED_BALANCE].value, 25.580002);
+        assert_eq!(totals_c[T_EXPECTED_BALANCE].value, Money::from_cents(2558));
         assert_eq!(totals_c[T_CURRENT_BALANCE].label, "Total balance".to_string());
-        assert_eq!(totals_c[T_CURRENT_BALANCE].value, 0.0);
+        assert_eq!(totals_c[T_CURRENT_BALANCE].value, Money::zero());
+    }
+
+    #[test]
+    fn get_transactions_converted() {
+
+        let mut st = Storage::new(populate());
+
+        let accounts = Account::get_accounts(&mut st).unwrap();
+
+        // A rate of 2 until september and 3 after it
+        let september = NaiveDate::parse_from_str("2018-09-01", "%Y-%m-%d").unwrap();
+
+        let (transactions, totals) = Transaction::get_transactions_converted(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false, |value, date| {
+            match date < september {
+                true => Ok(Money::from_cents(value.cents() * 2)),
+                false => Ok(Money::from_cents(value.cents() * 3))
+            }
+        }).unwrap();
+
+        // Only the totals are converted
+        assert_eq!(transactions[0].value, Money::from_cents(-12553));
+
+        assert_eq!(totals[T_EXPENSES_PAYABLE].value, Money::from_cents(-37659));
+        assert_eq!(totals[T_INCOMES_TORECEIVE].value, Money::from_cents(3000));
+        // The opening balance by the rate of his date
+        assert_eq!(totals[T_PREVIOUS_BALANCE].value, Money::from_cents(7000));
+        assert_eq!(totals[T_EXPECTED_BALANCE].value, Money::from_cents(4145));
+
+        let failed = Transaction::get_transactions_converted(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false, |_, date| {
+            Err(Error::MissingRate("USD".to_string(), "BRL".to_string(), date.format("%Y-%m-%d").to_string()))
+        });
+
+        assert!(failed.is_err());
     }
 
     #[test]
     fn transfers() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st = Storage::new(populate());
 
-        let accounts = Account::get_accounts(&mut st);
+        let accounts = Account::get_accounts(&mut st).unwrap();
 
         assert_eq!(accounts[0].name, "account BB".to_string());
         assert_eq!(accounts[1].name, "account AA".to_string());
 
-        let contacts = Contact::get_contacts(&mut st);
+        let contacts = Contact::get_contacts(&mut st).unwrap();
 
         assert_eq!(contacts[0].name, "contact 2".to_string());
 
         let mut from = Transaction {
             description: "transaction from".to_string(),
-            value: 20.00,
+            value: Money::from_cents(2000),
             account: Some(accounts[0].clone()),
             contact: Some(contacts[0].clone()),
             deadline: Some(NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap()),
@@ -748,139 +1061,275 @@ mod tests {
         to.account = Some(accounts[1].clone());
         to.description = "transaction to".to_string();
 
-        Transaction::store_transfer(&mut st, &mut from, &mut to);
+        Transaction::store_transfer(&mut st, &mut from, &mut to).unwrap();
 
-        let (transactions_a, totals_a) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false);
+        let (transactions_a, totals_a) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false).unwrap();
 
         assert_eq!(transactions_a.len(), 3);
 
         assert_eq!(totals_a.len(), 10);
         assert_eq!(totals_a[T_INCOMES_TORECEIVE].label, "Incomes(to receive)".to_string());
-        assert_eq!(totals_a[T_INCOMES_TORECEIVE].value, 30.0);
+        assert_eq!(totals_a[T_INCOMES_TORECEIVE].value, Money::from_cents(3000));
 
-        let (transactions_b, totals_b) = Transaction::get_transactions(&mut st, accounts[1].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false);
+        let (transactions_b, totals_b) = Transaction::get_transactions(&mut st, accounts[1].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false).unwrap();
 
         assert_eq!(transactions_b.len(), 2);
 
         assert_eq!(totals_b.len(), 10);
         assert_eq!(totals_b[T_EXPENSES_PAYABLE].label, "Expenses(payable)".to_string());
-        assert_eq!(totals_b[T_EXPENSES_PAYABLE].value, -20.0);
+        assert_eq!(totals_b[T_EXPENSES_PAYABLE].value, Money::from_cents(-2000));
 
         // Paying the transfer
         let mut paid = transactions_a[0].clone();
         assert_eq!(paid.description, "transaction from".to_string());
         paid.paid_in = Some(NaiveDate::parse_from_str("2018-10-19", "%Y-%m-%d").unwrap());
-        Transaction::store_transfer(&mut st, &mut paid.clone(), &mut paid.transfer.unwrap());
+        Transaction::store_transfer(&mut st, &mut paid.clone(), &mut paid.transfer.unwrap()).unwrap();
 
-        let (transactions_c, totals_c) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false);
+        let (transactions_c, totals_c) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false).unwrap();
 
         assert_eq!(transactions_c.len(), 3);
 
         assert_eq!(totals_c.len(), 10);
         assert_eq!(totals_c[T_INCOMES_TORECEIVE].label, "Incomes(to receive)".to_string());
-        assert_eq!(totals_c[T_INCOMES_TORECEIVE].value, 10.0);
+        assert_eq!(totals_c[T_INCOMES_TORECEIVE].value, Money::from_cents(1000));
         assert_eq!(totals_c[T_TRANSFERS_IN].label, "Transfers in".to_string());
-        assert_eq!(totals_c[5].value, 20.0);
+        assert_eq!(totals_c[5].value, Money::from_cents(2000));
 
-        let (transactions_d, totals_d) = Transaction::get_transactions(&mut st, accounts[1].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false);
+        let (transactions_d, totals_d) = Transaction::get_transactions(&mut st, accounts[1].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false).unwrap();
 
         assert_eq!(transactions_d.len(), 2);
 
         assert_eq!(totals_d.len(), 10);
         assert_eq!(totals_d[T_EXPENSES_PAYABLE].label, "Expenses(payable)".to_string());
-        assert_eq!(totals_d[T_EXPENSES_PAYABLE].value, 0.0);
+        assert_eq!(totals_d[T_EXPENSES_PAYABLE].value, Money::zero());
         assert_eq!(totals_d[2].label, "Transfers out".to_string());
-        assert_eq!(totals_d[2].value, -20.0);
+        assert_eq!(totals_d[2].value, Money::from_cents(-2000));
+    }
+
+    #[test]
+    fn splits() {
+
+        let mut st = Storage::new(populate());
+
+        let accounts = Account::get_accounts(&mut st).unwrap();
+        let contacts = Contact::get_contacts(&mut st).unwrap();
+
+        let tags: Vec<Tag> = vec!["groceries", "pharmacy", "market"].into_iter()
+            .map(|name| {
+                let uuid = Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: name.to_string() }).unwrap();
+                Tag::get_tag(&mut st, uuid).unwrap()
+            })
+            .collect();
+
+        let mut receipt = Transaction {
+            description: "receipt".to_string(),
+            value: Money::from_cents(-10000),
+            account: Some(accounts[0].clone()),
+            contact: Some(contacts[0].clone()),
+            deadline: Some(NaiveDate::parse_from_str("2018-10-05", "%Y-%m-%d").unwrap()),
+            tags: vec![tags[2].clone()],
+            splits: vec![
+                Split { value: Money::from_cents(-8000), tags: vec![tags[0].clone()], memo: "food".to_string() },
+                Split { value: Money::from_cents(-1000), tags: vec![tags[1].clone(), tags[2].clone()], memo: "".to_string() }
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(Transaction::store_transaction(&mut st, receipt.clone()), Err(Error::Invalid("error_splits_total")));
+
+        receipt.splits[1].value = Money::from_cents(-2000);
+
+        let uuid = Transaction::store_transaction(&mut st, receipt).unwrap();
+        let receipt = Transaction::get_transaction(&mut st, uuid).unwrap();
+
+        assert_eq!(receipt.splits.len(), 2);
+        assert_eq!(receipt.splits[0].memo, "food".to_string());
+
+        // The tag of transaction has all value
+        let values: Vec<(String, Money)> = receipt.values_by_tag().into_iter().map(|(tag, value)| (tag.name, value)).collect();
+
+        assert_eq!(values, vec![
+            ("market".to_string(), Money::from_cents(-10000)),
+            ("groceries".to_string(), Money::from_cents(-8000)),
+            ("pharmacy".to_string(), Money::from_cents(-2000))
+        ]);
+
+        let (transactions, totals) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, Some(tags[1].clone()), false).unwrap();
+
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(totals[T_EXPENSES_PAYABLE].value, Money::from_cents(-2000));
+    }
+
+    #[test]
+    fn transfers_between_currencies() {
+
+        let mut st = Storage::new(populate());
+
+        Account::store_account(&mut st, Account { uuid: "".to_string(), name: "account US".to_string(), bank: "bank US".to_string(), currency: "USD".to_string(), open_balance: Money::zero(), open_balance_date: Some(NaiveDate::from_ymd(2018, 1, 1)), kind: AccountType::Checking, interest_rate: None, closing_day: None, due_day: None, closed_in: None, starts_on_opening: true }).unwrap();
+
+        let accounts = Account::get_accounts(&mut st).unwrap();
+        let us = accounts.iter().find(|account| account.currency == "USD").unwrap().clone();
+        let br = accounts.iter().find(|account| account.name == "account AA").unwrap().clone();
+
+        let mut from = Transaction {
+            description: "exchange".to_string(),
+            value: Money::from_cents(-10000),
+            account: Some(us.clone()),
+            deadline: Some(NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap()),
+            ..Default::default()
+        };
+
+        // The same currency must have the same value
+        assert_eq!(Transaction::make_transaction_or_transfer(&mut st, &mut from.clone(), accounts[0].uuid.clone(), Some(Money::from_cents(32500))), Err(Error::Invalid("error_transfer_value_same_currency")));
+
+        let uuid = Transaction::make_transaction_or_transfer(&mut st, &mut from, br.uuid.clone(), Some(Money::from_cents(32500))).unwrap();
+
+        let mut from = Transaction::get_transaction(&mut st, uuid).unwrap();
+        let mut to = *from.transfer.clone().unwrap();
+
+        assert_eq!(to.value, Money::from_cents(32500));
+        assert_eq!(JsonValue::Number(from.transfer_rate.unwrap()).dump(), "3.25");
+        assert_eq!(JsonValue::Number(to.transfer_rate.unwrap()).dump(), "0.307692");
+
+        // The update of one side keeps the value of the other
+        from.value = Money::from_cents(-20000);
+        Transaction::store_transfer(&mut st, &mut from, &mut to).unwrap();
+
+        let to = Transaction::get_transaction(&mut st, to.uuid).unwrap();
+
+        assert_eq!(to.value, Money::from_cents(32500));
+        assert_eq!(JsonValue::Number(to.transfer_rate.unwrap()).dump(), "0.615385");
+        assert_eq!(to.transfer.unwrap().value, Money::from_cents(-20000));
+    }
+
+    #[test]
+    fn totals_of_type() {
+
+        let mut st = Storage::new(populate());
+
+        let from = NaiveDate::parse_from_str("2018-01-01", "%Y-%m-%d").unwrap();
+        let to = NaiveDate::parse_from_str("2018-12-31", "%Y-%m-%d").unwrap();
+
+        let mut account = Account::get_accounts(&mut st).unwrap()[0].clone();
+        account.open_balance = Money::from_cents(-50000);
+        account.open_balance_date = Some(from);
+
+        let (_, totals) = Transaction::get_transactions(&mut st, account.clone(), from, to, StatusFilter::ALL, None, false).unwrap();
+
+        // Checking accounts keep the totals
+        let checking = Transaction::totals_of_type(&account, totals.clone(), from, to);
+
+        assert_eq!(checking.len(), totals.len());
+        assert_eq!(checking[T_CURRENT_BALANCE].label, "Total balance".to_string());
+
+        account.kind = AccountType::CreditCard;
+
+        let card = Transaction::totals_of_type(&account, totals.clone(), from, to);
+
+        assert_eq!(card[T_CURRENT_BALANCE].label, "Amount owed".to_string());
+        assert_eq!(card[T_CURRENT_BALANCE].value, -totals[T_CURRENT_BALANCE].value);
+        assert_eq!(card[T_EXPENSES].value, totals[T_EXPENSES].value);
+
+        account.kind = AccountType::Savings;
+        account.open_balance = Money::from_cents(100000);
+        account.interest_rate = Account::parse_interest_rate("10").unwrap();
+
+        let (_, totals) = Transaction::get_transactions(&mut st, account.clone(), from, to, StatusFilter::ALL, None, false).unwrap();
+        let savings = Transaction::totals_of_type(&account, totals.clone(), from, to);
+
+        // One year of 10% over the balance
+        assert_eq!(savings.len(), totals.len() + 1);
+        assert_eq!(savings[totals.len()].value, totals[T_CURRENT_BALANCE].value.times(&Account::parse_interest_rate("0.1").unwrap().unwrap()).unwrap());
     }
 
     #[test]
     fn get_transactions_status() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st = Storage::new(populate());
 
-        let accounts = Account::get_accounts(&mut st);
+        let accounts = Account::get_accounts(&mut st).unwrap();
 
         assert_eq!(accounts[0].name, "account BB".to_string());
 
-        let (transactions_tmp, _totals) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false);
+        let (transactions_tmp, _totals) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false).unwrap();
 
         assert_eq!(transactions_tmp.len(), 2);
 
         let mut paid = transactions_tmp[0].clone();
         paid.paid_in = Some(NaiveDate::parse_from_str("2018-10-25", "%Y-%m-%d").unwrap());
-        Transaction::store_transaction(&mut st, paid);
+        Transaction::store_transaction(&mut st, paid).unwrap();
 
-        let (transactions_a, totals_a) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false);
+        let (transactions_a, totals_a) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false).unwrap();
 
         assert_eq!(transactions_a.len(), 2);
 
         assert_eq!(totals_a.len(), 10);
         assert_eq!(totals_a[T_EXPENSES_PAYABLE].label, "Expenses(payable)".to_string());
-        assert_eq!(totals_a[T_EXPENSES_PAYABLE].value, 0.0);
+        assert_eq!(totals_a[T_EXPENSES_PAYABLE].value, Money::zero());
         assert_eq!(totals_a[T_EXPENSES].label, "Expenses".to_string());
-        assert_eq!(totals_a[T_EXPENSES].value, -125.53);
+        assert_eq!(totals_a[T_EXPENSES].value, Money::from_cents(-12553));
         assert_eq!(totals_a[T_INCOMES_TORECEIVE].label, "Incomes(to receive)".to_string());
-        assert_eq!(totals_a[T_INCOMES_TORECEIVE].value, 10.0);
+        assert_eq!(totals_a[T_INCOMES_TORECEIVE].value, Money::from_cents(1000));
         assert_eq!(totals_a[T_INCOMES].label, "Incomes".to_string());
-        assert_eq!(totals_a[T_INCOMES].value, 0.0);
+        assert_eq!(totals_a[T_INCOMES].value, Money::zero());
         assert_eq!(totals_a[T_PREVIOUS_BALANCE].label, "Previous balance".to_string());
-        assert_eq!(totals_a[T_PREVIOUS_BALANCE].value, 35.0);
+        assert_eq!(totals_a[T_PREVIOUS_BALANCE].value, Money::from_cents(3500));
         assert_eq!(totals_a[T_EXPECTED_BALANCE].label, "Expected balance".to_string());
-        assert_eq!(totals_a[T_EXPECTED_BALANCE].value, 78.490005);
+        assert_eq!(totals_a[T_EXPECTED_BALANCE].value, Money::from_cents(7849));
         assert_eq!(totals_a[T_CURRENT_BALANCE].label, "Total balance".to_string());
-        assert_eq!(totals_a[T_CURRENT_BALANCE].value, -90.53);
+        assert_eq!(totals_a[T_CURRENT_BALANCE].value, Money::from_cents(-9053));
 
-        let (transactions_b, totals_b) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::PAID, None, false);
+        let (transactions_b, totals_b) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::PAID, None, false).unwrap();
 
         assert_eq!(transactions_b.len(), 1);
 
         assert_eq!(totals_b.len(), 10);
         assert_eq!(totals_b[T_EXPENSES_PAYABLE].label, "Expenses(payable)".to_string());
-        assert_eq!(totals_b[T_EXPENSES_PAYABLE].value, 0.0);
+        assert_eq!(totals_b[T_EXPENSES_PAYABLE].value, Money::zero());
         assert_eq!(totals_b[T_EXPENSES].label, "Expenses".to_string());
-        assert_eq!(totals_b[T_EXPENSES].value, -125.53);
+        assert_eq!(totals_b[T_EXPENSES].value, Money::from_cents(-12553));
         assert_eq!(totals_b[T_INCOMES_TORECEIVE].label, "Incomes(to receive)".to_string());
-        assert_eq!(totals_b[T_INCOMES_TORECEIVE].value, 0.0);
+        assert_eq!(totals_b[T_INCOMES_TORECEIVE].value, Money::zero());
         assert_eq!(totals_b[T_INCOMES].label, "Incomes".to_string());
-        assert_eq!(totals_b[T_INCOMES].value, 0.0);
+        assert_eq!(totals_b[T_INCOMES].value, Money::zero());
         assert_eq!(totals_b[T_PREVIOUS_BALANCE].label, "Previous balance".to_string());
-        assert_eq!(totals_b[T_PREVIOUS_BALANCE].value, 35.0);
+        assert_eq!(totals_b[T_PREVIOUS_BALANCE].value, Money::from_cents(3500));
         assert_eq!(totals_b[T_EXPECTED_BALANCE].label, "Expected balance".to_string());
-        assert_eq!(totals_b[T_EXPECTED_BALANCE].value, -90.53);
+        assert_eq!(totals_b[T_EXPECTED_BALANCE].value, Money::from_cents(-9053));
         assert_eq!(totals_b[T_CURRENT_BALANCE].label, "Total balance".to_string());
-        assert_eq!(totals_b[T_CURRENT_BALANCE].value, -90.53);
+        assert_eq!(totals_b[T_CURRENT_BALANCE].value, Money::from_cents(-9053));
 
-        let (transactions_c, totals_c) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::FORPAY, None, false);
+        let (transactions_c, totals_c) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::FORPAY, None, false).unwrap();
 
         assert_eq!(transactions_c.len(), 1);
 
         assert_eq!(totals_c.len(), 10);
         assert_eq!(totals_c[T_EXPENSES_PAYABLE].label, "Expenses(payable)".to_string());
-        assert_eq!(totals_c[T_EXPENSES_PAYABLE].value, 0.0);
+        assert_eq!(totals_c[T_EXPENSES_PAYABLE].value, Money::zero());
         assert_eq!(totals_c[T_EXPENSES].label, "Expenses".to_string());
-        assert_eq!(totals_c[T_EXPENSES].value, 0.0);
+        assert_eq!(totals_c[T_EXPENSES].value, Money::zero());
         assert_eq!(totals_c[T_INCOMES_TORECEIVE].label, "Incomes(to receive)".to_string());
-        assert_eq!(totals_c[T_INCOMES_TORECEIVE].value, 10.0);
+        assert_eq!(totals_c[T_INCOMES_TORECEIVE].value, Money::from_cents(1000));
         assert_eq!(totals_c[T_INCOMES].label, "Incomes".to_string());
-        assert_eq!(totals_c[T_INCOMES].value, 0.0);
+        assert_eq!(totals_c[T_INCOMES].value, Money::zero());
         assert_eq!(totals_c[T_PREVIOUS_BALANCE].label, "Previous balance".to_string());
-        assert_eq!(totals_c[T_PREVIOUS_BALANCE].value, 35.0);
+        assert_eq!(totals_c[T_PREVIOUS_BALANCE].value, Money::from_cents(3500));
         assert_eq!(totals_c[T_EXPECTED_BALANCE].label, "Expected balance".to_string());
-        assert_eq!(totals_c[T_EXPECTED_BALANCE].value, 204.02);
+        assert_eq!(totals_c[T_EXPECTED_BALANCE].value, Money::from_cents(20402));
         assert_eq!(totals_c[T_CURRENT_BALANCE].label, "Total balance".to_string());
-        assert_eq!(totals_c[T_CURRENT_BALANCE].value, 35.0);
+        assert_eq!(totals_c[T_CURRENT_BALANCE].value, Money::from_cents(3500));
     }
 
     #[test]
     fn get_transaction() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st = Storage::new(populate());
 
-        let accounts = Account::get_accounts(&mut st);
+        let accounts = Account::get_accounts(&mut st).unwrap();
 
         assert_eq!(accounts[0].name, "account BB".to_string());
 
-        let (transactions, _total) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false);
+        let (transactions, _total) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false).unwrap();
 
         let uuid = transactions[0].uuid.clone();
 
@@ -897,26 +1346,26 @@ mod tests {
     #[test]
     fn store_transaction() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st = Storage::new(populate());
 
-        let accounts = Account::get_accounts(&mut st);
+        let accounts = Account::get_accounts(&mut st).unwrap();
 
         assert_eq!(accounts[0].name, "account BB".to_string());
 
-        let contacts = Contact::get_contacts(&mut st);
+        let contacts = Contact::get_contacts(&mut st).unwrap();
 
         assert_eq!(contacts[0].name, "contact 2".to_string());
 
         Transaction::store_transaction(&mut st, Transaction {
             description: "transaction 5".to_string(),
-            value: 20.00,
+            value: Money::from_cents(2000),
             account: Some(accounts[0].clone()),
             contact: Some(contacts[0].clone()),
             deadline: Some(NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap()),
             ..Default::default()
-        });
+        }).unwrap();
 
-        let (transactions, _total) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false);
+        let (transactions, _total) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false).unwrap();
 
         assert_eq!(transactions[0].description, "transaction 5".to_string());
         assert_eq!(transactions[1].description, "transaction 2".to_string());
@@ -926,13 +1375,13 @@ mod tests {
     #[test]
     fn remove_transaction() {
 
-        let mut st = Storage { path_str: populate(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new())  };
+        let mut st = Storage::new(populate());
 
-        let accounts = Account::get_accounts(&mut st);
+        let accounts = Account::get_accounts(&mut st).unwrap();
 
         assert_eq!(accounts[0].name, "account BB".to_string());
 
-        let (transactions, _total) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false);
+        let (transactions, _total) = Transaction::get_transactions(&mut st, accounts[0].clone(), NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap(), NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap(), StatusFilter::ALL, None, false).unwrap();
 
         let uuid = transactions[0].uuid.clone();
 
@@ -940,10 +1389,160 @@ mod tests {
 
         assert!(transaction.is_ok());
 
-        Transaction::remove_transaction(&mut st, uuid.clone());
+        Transaction::remove_transaction(&mut st, uuid.clone()).unwrap();
 
         let transactione = Transaction::get_transaction(&mut st, uuid.clone());
 
         assert!(transactione.is_err());
     }
+
+    // Storage with the count of transactions, over two years
+    fn populate_many(count: usize) -> String {
+
+        let path = "/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string();
+
+        let accounts: Vec<String> = (0..2).map(|_| Uuid::new_v4().to_string()).collect();
+        let contacts: Vec<String> = (0..20).map(|_| Uuid::new_v4().to_string()).collect();
+        let tags: Vec<String> = (0..5).map(|_| Uuid::new_v4().to_string()).collect();
+
+        let mut lines: Vec<String> = vec!["::section::accounts".to_string()];
+
+        for (i, uuid) in accounts.iter().enumerate() {
+            lines.push(format!("{} {}", uuid, object!{ "name" => format!("account {}", i), "bank" => "bank A", "open_balance" => 0.0, "open_balance_date" => "2017-01-01", "currency" => "R$" }.dump()));
+        }
+
+        lines.push("".to_string());
+        lines.push("::section::contacts".to_string());
+
+        for (i, uuid) in contacts.iter().enumerate() {
+            lines.push(format!("{} {}", uuid, object!{ "name" => format!("contact {}", i), "city_location" => "city A" }.dump()));
+        }
+
+        lines.push("".to_string());
+        lines.push("::section::tags".to_string());
+
+        for (i, uuid) in tags.iter().enumerate() {
+            lines.push(format!("{} {}", uuid, object!{ "name" => format!("tag {}", i) }.dump()));
+        }
+
+        lines.push("".to_string());
+        lines.push("::section::transactions".to_string());
+
+        let mut i = 0;
+
+        while i < count {
+
+            let mut row = object!{
+                "description" => format!("transaction {}", i),
+                "value" => (i % 1000) as f32 - 500.0,
+                "deadline" => format!("{}-{:02}-{:02}", 2017 + 2 * i / count, 1 + (i / 7) % 12, 1 + i % 28),
+                "paid_in" => format!("{}-{:02}-{:02}", 2017 + 2 * i / count, 1 + (i / 7) % 12, 1 + i % 28),
+                "created_at" => "2017-01-01T10:00:00-02:00",
+                "tags" => array![tags[i % tags.len()].clone()]
+            };
+
+            if i % 10 == 0 {
+                // One of ten is a transfer between the accounts
+                let uuid = Uuid::new_v4().to_string();
+                let other_uuid = Uuid::new_v4().to_string();
+                let mut other = row.clone();
+
+                row["account"] = accounts[0].clone().into();
+                row["transfer"] = other_uuid.clone().into();
+                other["account"] = accounts[1].clone().into();
+                other["transfer"] = uuid.clone().into();
+                other["value"] = (500.0 - (i % 1000) as f32).into();
+
+                lines.push(format!("{} {}", uuid, row.dump()));
+                lines.push(format!("{} {}", other_uuid, other.dump()));
+
+                i += 2;
+            } else {
+                row["account"] = accounts[i % accounts.len()].clone().into();
+                row["contact"] = contacts[i % contacts.len()].clone().into();
+
+                lines.push(format!("{} {}", Uuid::new_v4().to_string(), row.dump()));
+
+                i += 1;
+            }
+        }
+
+        lines.push("".to_string());
+
+        fs::write(&path, lines.join("\n")).unwrap();
+
+        path
+    }
+
+    // Time of read the transactions of all accounts, like the 'accounts status'.
+    // Each call reads the file again, like each command
+    fn time_status(path: &String) -> Duration {
+
+        let from = NaiveDate::parse_from_str("2018-10-01", "%Y-%m-%d").unwrap();
+        let to = NaiveDate::parse_from_str("2018-10-31", "%Y-%m-%d").unwrap();
+
+        let started = Instant::now();
+
+        let mut st = Storage::new(path.clone());
+
+        for account in Account::get_accounts(&mut st).unwrap() {
+            let (transactions, _) = Transaction::get_transactions(&mut st, account, from, to, StatusFilter::ALL, None, false).unwrap();
+
+            assert!(transactions.len() > 0);
+        }
+
+        started.elapsed()
+    }
+
+    // Timing assertion, out of the normal run. Run with:
+    // cargo test --release lookups_scale_linearly -- --ignored
+    #[test]
+    #[ignore]
+    fn lookups_scale_linearly() {
+
+        I18n::config("en_US".to_string());
+
+        let small = populate_many(2000);
+        let large = populate_many(8000);
+
+        // The best of some runs, for the noise of other tests
+        let best = |path: &String| (0..3).map(|_| time_status(path)).min().unwrap();
+
+        let small = best(&small);
+        let large = best(&large);
+
+        // Four times the rows. With the scans of lines for each relation,
+        // the time grew with the square of rows, more than ten times
+        assert!(large < small * 8, "2000 rows: {:?}, 8000 rows: {:?}", small, large);
+    }
+
+    // Run with: cargo test --release benchmark -- --ignored --nocapture
+    //
+    // The implementation before the indexes of rows, measured with this same
+    // time_status on release, on the same machine of the numbers below:
+    //    5000 rows:  23.88s
+    //   10000 rows: 113.74s
+    // 50k rows was out of reach, the growth was quadratic. With the indexes:
+    //    5000 rows:   0.10s
+    //   50000 rows:   1.20s
+    #[test]
+    #[ignore]
+    fn benchmark_50k() {
+
+        I18n::config("en_US".to_string());
+
+        // Recorded time of the old implementation with 5000 rows
+        let old = Duration::from_millis(23881);
+
+        let baseline = time_status(&populate_many(5000));
+        let status = time_status(&populate_many(50000));
+
+        println!("5k: {:?} (old implementation: {:?}), 50k: {:?}", baseline, old, status);
+
+        // Ten times faster than the old implementation at least
+        assert!(baseline * 10 < old);
+
+        // Ten times the rows, the quadratic scans took a hundred times more
+        assert!(status < baseline * 20);
+    }
 }
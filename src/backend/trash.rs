@@ -0,0 +1,254 @@
+///
+/// Blitz Money
+///
+/// Backend of module for manange the removed rows, kept on the trash
+/// sections until they are restored or purged
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use chrono::{ DateTime, Local, Duration };
+use backend::storage::*;
+use backend::references::References;
+use backend::error::Error;
+use json::JsonValue;
+
+// Sections whose removed rows are kept on the trash
+pub const SECTIONS: [&str; 7] = ["accounts", "contacts", "tags", "rules", "forecasts", "transactions", "rates"];
+
+// Representation of a removed row
+#[derive(Clone, Debug)]
+pub struct Trashed {
+    // Section where the row was removed
+    pub section: String,
+    pub uuid: String,
+    // Name, or description, of the row
+    pub label: String,
+    pub deleted_at: DateTime<Local>,
+    row: JsonValue
+}
+
+pub struct Trash {}
+
+impl Model for Trashed {
+
+    fn new(row: JsonValue, uuid: String, _storage: &mut Storage, _can_recursive: bool) -> Result<Trashed, Error> {
+
+        Data::require(&row, "trash", &uuid, vec!["deleted_at"])?;
+
+        let deleted_at = row["deleted_at"].to_string().parse::<DateTime<Local>>()
+            .map_err(|_| Error::InvalidField("trash".to_string(), uuid.clone(), "deleted_at".to_string()))?;
+
+        let label = ["name", "description", "currency", "value"].iter()
+            .find(|field| !row[**field].is_null())
+            .map(|field| row[*field].to_string())
+            .unwrap_or_default();
+
+        Ok(Trashed {
+            section: "".to_string(),
+            uuid: uuid,
+            label: label,
+            deleted_at: deleted_at,
+            row: row
+        })
+    }
+
+    fn to_save(self) -> (String, bool, JsonValue) {
+        (self.uuid, false, self.row)
+    }
+}
+
+impl Trashed {
+
+    // Shortest unique prefix of the uuid, on the trash of section
+    pub fn id(self, storage: &mut Storage) -> String {
+        storage.short_id(&Trash::section(&self.section), &self.uuid)
+    }
+}
+
+impl Trash {
+
+    // Name of the trash section of section
+    pub fn section(section: &str) -> String {
+        format!("{}{}", TRASH_PREFIX, section)
+    }
+
+    // All removed rows, the last removed first
+    pub fn list(storage: &mut Storage) -> Result<Vec<Trashed>, Error> {
+
+        let mut list: Vec<Trashed> = vec![];
+
+        for section in SECTIONS.iter() {
+
+            if !storage.check_section(Trash::section(section)) {
+                continue;
+            }
+
+            let mut data = storage.get_section_data(Trash::section(section));
+
+            for mut trashed in data.all::<Trashed>()? {
+                trashed.section = section.to_string();
+                list.push(trashed);
+            }
+        }
+
+        list.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+
+        Ok(list)
+    }
+
+    // Return the removed row of id, from any section
+    pub fn get(storage: &mut Storage, id: String) -> Result<Trashed, Error> {
+
+        let mut found: Vec<(String, String)> = vec![];
+
+        for section in SECTIONS.iter() {
+            if let Some(uuid) = storage.resolve_id(&Trash::section(section), &id)? {
+                found.push((section.to_string(), uuid));
+            }
+        }
+
+        if found.len() > 1 {
+            return Err(Error::AmbiguousId("trash".to_string(), id, found.into_iter().map(|(_, uuid)| uuid).collect()));
+        }
+
+        let (section, uuid) = match found.pop() {
+            Some(found) => found,
+            None => return Err(Error::NotFound("trash".to_string(), id))
+        };
+
+        let mut data = storage.get_section_data(Trash::section(&section));
+
+        data.find_by_id(uuid)?;
+
+        let mut trashed = data.next::<Trashed>()?;
+        trashed.section = section;
+
+        Ok(trashed)
+    }
+
+    // Put back the removed row of id, with the other side when is a
+    // transfer. Return the restored rows
+    pub fn restore(storage: &mut Storage, id: String) -> Result<Vec<Trashed>, Error> {
+
+        let trashed = Trash::get(storage, id)?;
+        let mut restore = vec![trashed.clone()];
+
+        if trashed.section == "transactions" && !trashed.row["transfer"].is_empty() {
+            if let Ok(other) = Trash::get(storage, trashed.row["transfer"].to_string()) {
+                restore.push(other);
+            }
+        }
+
+        // The rows must not point to a removed row
+        for trashed in restore.iter() {
+            References::check(storage, &trashed.section, &trashed.row)?;
+        }
+
+        for trashed in restore.iter() {
+
+            let mut data = storage.get_section_data(Trash::section(&trashed.section));
+
+            data.restore_by_id(trashed.uuid.clone())?;
+        }
+
+        Ok(restore)
+    }
+
+    // Remove for good the rows removed before the age, or all
+    // rows when no age is given. Return the number of purged rows
+    pub fn purge(storage: &mut Storage, older_than: Option<Duration>) -> Result<usize, Error> {
+
+        let mut purged = 0;
+
+        for trashed in Trash::list(storage)? {
+
+            if let Some(age) = older_than {
+                if trashed.deleted_at > Local::now() - age {
+                    continue;
+                }
+            }
+
+            let mut data = storage.get_section_data(Trash::section(&trashed.section));
+
+            data.purge_by_id(trashed.uuid)?;
+
+            purged += 1;
+        }
+
+        Ok(purged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use uuid::Uuid;
+    use backend::tags::Tag;
+    use backend::rules::Rule;
+    use backend::references::OnRemove;
+
+    #[test]
+    fn remove_and_restore() {
+
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
+
+        let tag = Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 1".to_string() }).unwrap();
+        let rule_tags = vec![Tag::get_tag(&mut st, tag.clone()).unwrap()];
+        let rule = Rule::store_rule(&mut st, Rule { term: "market".to_string(), description: "Market".to_string(), tags: rule_tags, ..Default::default() }).unwrap();
+
+        Rule::remove_rule(&mut st, rule.clone()).unwrap();
+        Tag::remove_tag(&mut st, tag.clone(), OnRemove::Refuse).unwrap();
+
+        // Hidden from the lists
+        assert_eq!(Tag::get_tags(&mut st).unwrap().len(), 0);
+        assert_eq!(Rule::get_rules(&mut st).unwrap().len(), 0);
+
+        let list = Trash::list(&mut st).unwrap();
+
+        assert_eq!(list.len(), 2);
+        assert_eq!((list[0].section.as_str(), list[0].label.as_str()), ("tags", "tag 1"));
+        assert_eq!((list[1].section.as_str(), list[1].label.as_str()), ("rules", "Market"));
+
+        // The rule points to the removed tag
+        assert_eq!(Trash::restore(&mut st, rule.clone()).unwrap_err(), Error::NotFound("tags".to_string(), tag.clone()));
+
+        Trash::restore(&mut st, tag.clone()).unwrap();
+        Trash::restore(&mut st, rule.clone()).unwrap();
+
+        assert_eq!(Rule::get_rule(&mut st, rule).unwrap().tags[0].name, "tag 1");
+        assert_eq!(Trash::list(&mut st).unwrap().len(), 0);
+
+        assert_eq!(Trash::restore(&mut st, "NOOOO".to_string()).unwrap_err(), Error::NotFound("trash".to_string(), "NOOOO".to_string()));
+    }
+
+    #[test]
+    fn purge() {
+
+        let mut st = Storage::new("/tmp/bmoney-".to_owned() + &Uuid::new_v4().to_string());
+
+        let old = Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 1".to_string() }).unwrap();
+        let new = Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 2".to_string() }).unwrap();
+
+        Tag::remove_tag(&mut st, old.clone(), OnRemove::Refuse).unwrap();
+        Tag::remove_tag(&mut st, new.clone(), OnRemove::Refuse).unwrap();
+
+        // Make the first one removed 100 days ago
+        let mut data = st.get_section_data(Trash::section("tags"));
+        data.find_by_id(old.clone()).unwrap();
+        let mut trashed = data.next::<Trashed>().unwrap();
+        trashed.row["deleted_at"] = (Local::now() - Duration::days(100)).to_rfc3339().into();
+        data.save(trashed).unwrap();
+
+        assert_eq!(Trash::purge(&mut st, Some(Duration::days(90))).unwrap(), 1);
+
+        let list = Trash::list(&mut st).unwrap();
+
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].uuid, new);
+
+        assert_eq!(Trash::purge(&mut st, None).unwrap(), 1);
+        assert_eq!(Trash::list(&mut st).unwrap().len(), 0);
+    }
+}
@@ -8,13 +8,49 @@
 
 use json_gettext::*;
 use std::sync::Mutex;
+use chrono::NaiveDate;
+use json::JsonValue;
+use json::number::Number;
+use backend::money::Money;
+use backend::error::Error;
 
 pub struct I18n {
 }
 
+// How the money values and the dates are written on the lang
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Locale {
+    pub decimal: char,
+    pub thousands: Option<char>,
+    pub date_format: &'static str,
+    pub month_format: &'static str,
+    // Formats shown to the user on the prompts and errors
+    pub date_pattern: &'static str,
+    pub money_pattern: &'static str
+}
+
+const EN_US: Locale = Locale {
+    decimal: '.',
+    thousands: None,
+    date_format: "%Y-%m-%d",
+    month_format: "%Y-%m",
+    date_pattern: "YYYY-MM-DD",
+    money_pattern: "00000.00"
+};
+
+const PT_BR: Locale = Locale {
+    decimal: ',',
+    thousands: Some('.'),
+    date_format: "%d/%m/%Y",
+    month_format: "%m/%Y",
+    date_pattern: "DD/MM/YYYY",
+    money_pattern: "0.000,00"
+};
+
 lazy_static! {
     // yes, this is not a best solution :)
     static ref ctx_i18n: Mutex<Vec<JSONGetText<'static>>> = Mutex::new(vec!());
+    static ref ctx_locale: Mutex<Locale> = Mutex::new(EN_US);
 }
 
 impl I18n {
@@ -30,9 +66,20 @@ impl I18n {
         }
     }
 
+    // Return the formats of numbers and dates of the language in use
+    pub fn locale() -> Locale {
+        *ctx_locale.lock().unwrap()
+    }
+
     // Set the default lang for the application
     pub fn config(mut lang: String) {
 
+        // Values like pt_BR.UTF-8 or pt_BR:pt
+        lang = lang.split(|c| c == '.' || c == ':').next().unwrap_or("").to_string();
+
+        // The formats don't need the translated texts
+        *ctx_locale.lock().unwrap() = Locale::from_lang(&lang);
+
         let langs_avaliable = ["en_US"];//, "pt_BR"];
 
         if !langs_avaliable.contains(&lang.as_str()) {
@@ -46,3 +93,172 @@ impl I18n {
         ).unwrap());
     }
 }
+
+impl Locale {
+
+    // The formats of lang, en_US when unknown
+    pub fn from_lang(lang: &str) -> Locale {
+        match lang {
+            "pt_BR" => PT_BR,
+            _ => EN_US
+        }
+    }
+
+    // Money value like 1234.56 or 1.234,56
+    pub fn format_money(&self, value: Money) -> String {
+
+        let canonical = value.abs().to_string();
+        let mut parts = canonical.splitn(2, '.');
+        let units = parts.next().unwrap_or("");
+        let fraction = parts.next().unwrap_or("");
+
+        let mut grouped = String::new();
+
+        for (i, digit) in units.chars().enumerate() {
+            if let Some(thousands) = self.thousands {
+                if i > 0 && (units.len() - i) % 3 == 0 {
+                    grouped.push(thousands);
+                }
+            }
+            grouped.push(digit);
+        }
+
+        let sign = match value.is_negative() {
+            true => "-",
+            false => ""
+        };
+
+        format!("{}{}{}{}", sign, grouped, self.decimal, fraction)
+    }
+
+    // Parse the money value written like the format_money. The
+    // thousands separator is optional, but must split groups of 3
+    pub fn parse_money(&self, value: &str) -> Result<Money, Error> {
+
+        let invalid = Error::Invalid("error_invalid_money");
+        let value = value.trim();
+
+        // A dot that is not a mark of the lang, like 10.50 on a lang of comma
+        if self.decimal != '.' && self.thousands != Some('.') && value.contains('.') {
+            return Err(invalid);
+        }
+
+        let mut parts = value.splitn(2, self.decimal);
+        let mut units = parts.next().unwrap_or("").to_string();
+        let fraction = parts.next();
+
+        if let Some(thousands) = self.thousands {
+            if units.contains(thousands) {
+
+                let digits = units.trim_start_matches(|c| c == '-' || c == '+');
+                let groups: Vec<&str> = digits.split(thousands).collect();
+
+                let valid = groups[0].len() >= 1 && groups[0].len() <= 3
+                    && groups[1..].iter().all(|group| group.len() == 3);
+
+                if !valid {
+                    return Err(invalid);
+                }
+
+                units = units.replace(thousands, "");
+            }
+        }
+
+        let canonical = match fraction {
+            Some(fraction) => format!("{}.{}", units, fraction),
+            None => units
+        };
+
+        canonical.parse::<Money>()
+    }
+
+    // Date like 2018-10-01 or 01/10/2018
+    pub fn format_date(&self, date: NaiveDate) -> String {
+        date.format(self.date_format).to_string()
+    }
+
+    // Format of date and time, like the changes and backups
+    pub fn datetime_format(&self) -> String {
+        format!("{} %H:%M:%S", self.date_format)
+    }
+
+    // Parse the date on the format of lang, or the YYYY-MM-DD used
+    // by the scripts and files
+    pub fn parse_date(&self, value: &str) -> Option<NaiveDate> {
+
+        let value = value.trim();
+
+        NaiveDate::parse_from_str(value, self.date_format)
+            .or_else(|_| NaiveDate::parse_from_str(value, "%Y-%m-%d"))
+            .ok()
+    }
+
+    // First day of the month, like 2018-10 or 10/2018
+    pub fn parse_month(&self, value: &str) -> Option<NaiveDate> {
+
+        let value = value.trim();
+
+        NaiveDate::parse_from_str(&format!("1 {}", value), &format!("%d {}", self.month_format))
+            .or_else(|_| NaiveDate::parse_from_str(&format!("1 {}", value), "%d %Y-%m"))
+            .ok()
+    }
+
+    // Decimal number, like the exchange rates, with the decimal mark of lang
+    pub fn format_decimal(&self, value: Number) -> String {
+        JsonValue::Number(value).dump().replace('.', &self.decimal.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn money(value: &str) -> Money {
+        value.parse::<Money>().unwrap()
+    }
+
+    #[test]
+    fn format_and_parse_money() {
+
+        let en = Locale::from_lang("en_US");
+        let br = Locale::from_lang("pt_BR");
+
+        assert_eq!(en.format_money(money("1234567.8")), "1234567.80");
+        assert_eq!(br.format_money(money("1234567.8")), "1.234.567,80");
+        assert_eq!(br.format_money(money("-123.45")), "-123,45");
+        assert_eq!(br.format_money(money("-1234")), "-1.234,00");
+
+        assert_eq!(en.parse_money("1234.56"), Ok(money("1234.56")));
+        assert!(en.parse_money("1,234.56").is_err());
+
+        assert_eq!(br.parse_money("1.234,56"), Ok(money("1234.56")));
+        assert_eq!(br.parse_money("-1234,5"), Ok(money("-1234.5")));
+        assert_eq!(br.parse_money("1.234"), Ok(money("1234")));
+        assert_eq!(br.parse_money("12"), Ok(money("12")));
+
+        // The dot is not the decimal mark
+        assert!(br.parse_money("10.50").is_err());
+        assert!(br.parse_money("1.23.456,00").is_err());
+        assert!(br.parse_money("1,2,3").is_err());
+    }
+
+    #[test]
+    fn format_and_parse_dates() {
+
+        let en = Locale::from_lang("en_US");
+        let br = Locale::from_lang("pt_BR");
+        let date = NaiveDate::from_ymd(2018, 10, 1);
+
+        assert_eq!(en.format_date(date), "2018-10-01");
+        assert_eq!(br.format_date(date), "01/10/2018");
+
+        assert_eq!(br.parse_date("01/10/2018"), Some(date));
+        assert_eq!(br.parse_date("2018-10-01"), Some(date));
+        assert_eq!(en.parse_date("01/10/2018"), None);
+
+        assert_eq!(br.parse_month("10/2018"), Some(date));
+        assert_eq!(br.parse_month("2018-10"), Some(date));
+        assert_eq!(en.parse_month("2018-10"), Some(date));
+    }
+}
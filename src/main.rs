@@ -22,14 +22,20 @@ extern crate lazy_static;
 #[macro_use]
 extern crate lazy_static_include;
 extern crate icalendar;
+extern crate fs2;
+extern crate chacha20poly1305;
+extern crate argon2;
+extern crate hex;
+extern crate rpassword;
+extern crate rusqlite;
 
 mod ui;
 mod backend;
 mod i18n;
 
 use std::env;
-use std::collections::HashMap;
-use std::cell::RefCell;
+use std::panic;
+use std::process;
 use backend::import::Import;
 use ui::tags::Tags;
 use ui::accounts::Accounts;
@@ -37,8 +43,15 @@ use ui::contacts::Contacts;
 use ui::transactions::Transactions;
 use ui::rules::Rules;
 use ui::forecasts::Forecasts;
+use ui::storage::Storages;
+use ui::trash::Trashes;
+use ui::history::Histories;
+use ui::rates::Rates;
 use ui::ui::*;
 use backend::storage::Storage;
+use backend::engine::EngineKind;
+use backend::migrations::Migrations;
+use backend::error::Error;
 use i18n::*;
 
 fn main() {
@@ -50,6 +63,16 @@ fn main() {
 
     I18n::config(lang);
 
+    // Failures that can't be handled, like I/O errors, are shown
+    // without the backtrace
+    panic::set_hook(Box::new(|info| {
+        if let Some(message) = info.payload().downcast_ref::<String>() {
+            eprintln!("{}", message);
+        } else if let Some(message) = info.payload().downcast_ref::<&str>() {
+            eprintln!("{}", message);
+        }
+    }));
+
     let mut args: Vec<String> = env::args().collect();
 
     let path_str: String;
@@ -67,9 +90,51 @@ fn main() {
         path_str = home_dir_str.to_owned() + &"/.bmoney.bms".to_string();
     }
 
-    let mut storage = Storage { path_str: path_str, file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+    // The engine is found by the file, but can be forced for a new one
+    let mut storage = match Input::extract_named_param(&mut args, "--storage-engine=".to_string()) {
+        Some(name) => match EngineKind::from_name(&name) {
+            Ok(kind) => Storage::with_engine(path_str, kind),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(e.exit_code());
+            }
+        },
+        None => Storage::new(path_str)
+    };
+
+    // A file that can't be read, or decrypted, can only be replaced by a backup
+    let is_restore = args.len() > 2 && args[1] == "storage" && args[2] == "backups";
+
+    if !is_restore {
+        if let Err(e) = storage.load() {
+            drop(storage);
+            eprintln!("{}", e);
+            process::exit(e.exit_code());
+        }
+    }
+
+    // The storage commands must work even when the rows are broken, or
+    // the file can't be upgraded, because they are used for recover it
+    let is_storage_command = args.len() > 1 && (args[1] == "storage" || args[1] == "undo" || args[1] == "redo");
+
+    if !is_storage_command {
 
-    Import::index(&mut storage);
+        match Migrations::run(&mut storage) {
+            Ok(Some(backup)) => eprintln!("{}: {}", I18n::text("storage_upgraded"), backup),
+            Ok(None) => {},
+            Err(e) => {
+                drop(storage);
+                eprintln!("{}: {}", I18n::text("storage_couldnt_upgrade"), e);
+                process::exit(e.exit_code());
+            }
+        }
+
+        if let Err(e) = Import::index(&mut storage) {
+            drop(storage);
+            eprintln!("{}", e);
+            process::exit(e.exit_code());
+        }
+    }
 
     if args.len() == 1 {
         // Without module and action
@@ -81,90 +146,177 @@ fn main() {
 
     let is_csv = Input::extract_param(&mut args, "--use-csv".to_string());
 
-    if args[1] == "accounts" {
+    let result: Result<(), Error> = if args[1] == "accounts" {
         if args[2] == "list" {
-            Accounts::list(storage, args[3..].to_vec(), is_csv);
+            Accounts::list(storage, args[3..].to_vec(), is_csv)
         } else if args[2] == "status" {
-            Accounts::status(storage, args[3..].to_vec(), is_csv);
+            Accounts::status(storage, args[3..].to_vec(), is_csv)
         } else if args[2] == "add" {
-            Accounts::add(storage, args[3..].to_vec());
+            Accounts::add(storage, args[3..].to_vec())
         } else if args[2] == "update" {
-            Accounts::update(storage, args[3..].to_vec());
+            Accounts::update(storage, args[3..].to_vec())
         } else if args[2] == "rm" {
-            Accounts::rm(storage, args[3..].to_vec());
+            Accounts::rm(storage, args[3..].to_vec())
+        } else if args[2] == "statement" {
+            Accounts::statement(storage, args[3..].to_vec(), is_csv)
+        } else if args[2] == "pay" {
+            Accounts::pay(storage, args[3..].to_vec())
+        } else if args[2] == "reconcile" {
+            Accounts::reconcile(storage, args[3..].to_vec(), is_csv)
+        } else if args[2] == "close" {
+            Accounts::close(storage, args[3..].to_vec())
+        } else if args[2] == "reopen" {
+            Accounts::reopen(storage, args[3..].to_vec())
+        } else if args[2] == "rebalance" {
+            Accounts::rebalance(storage, args[3..].to_vec())
+        } else if args[2] == "history" {
+            Histories::history(storage, "accounts", args[3..].to_vec(), is_csv)
         } else {
-            println!("{}: bmoney accounts [list|add|update|rm|status]", I18n::text("how_to_use"));
+            println!("{}: bmoney accounts [list|add|update|rm|status|statement|pay|reconcile|close|reopen|rebalance|history]", I18n::text("how_to_use"));
+            Ok(())
         }
     } else if args[1] == "contacts" {
         if args[2] == "list" {
-            Contacts::list(storage, args[3..].to_vec(), is_csv);
+            Contacts::list(storage, args[3..].to_vec(), is_csv)
         } else if args[2] == "add" {
-            Contacts::add(storage, args[3..].to_vec());
+            Contacts::add(storage, args[3..].to_vec())
         } else if args[2] == "update" {
-            Contacts::update(storage, args[3..].to_vec());
+            Contacts::update(storage, args[3..].to_vec())
         } else if args[2] == "rm" {
-            Contacts::rm(storage, args[3..].to_vec());
+            Contacts::rm(storage, args[3..].to_vec())
+        } else if args[2] == "merge" {
+            Contacts::merge(storage, args[3..].to_vec(), is_csv)
+        } else if args[2] == "history" {
+            Histories::history(storage, "contacts", args[3..].to_vec(), is_csv)
         } else {
-            println!("{}: bmoney contacts [list|add|update|rm]", I18n::text("how_to_use"));
+            println!("{}: bmoney contacts [list|add|update|rm|merge|history]", I18n::text("how_to_use"));
+            Ok(())
         }
     } else if args[1] == "transactions" {
         if args[2] == "list" {
-            Transactions::list(storage, args[3..].to_vec(), is_csv);
+            Transactions::list(storage, args[3..].to_vec(), is_csv)
         } else if args[2] == "add" {
-            Transactions::add(storage, args[3..].to_vec());
+            Transactions::add(storage, args[3..].to_vec())
         } else if args[2] == "update" {
-            Transactions::update(storage, args[3..].to_vec());
+            Transactions::update(storage, args[3..].to_vec())
+        } else if args[2] == "split" {
+            Transactions::split(storage, args[3..].to_vec())
         } else if args[2] == "rm" {
-            Transactions::rm(storage, args[3..].to_vec());
+            Transactions::rm(storage, args[3..].to_vec())
         } else if args[2] == "ofx" {
-            Transactions::ofx(storage, args[3..].to_vec());
+            Transactions::ofx(storage, args[3..].to_vec())
         } else if args[2] == "csv" {
-            Transactions::csv(storage, args[3..].to_vec());
+            Transactions::csv(storage, args[3..].to_vec())
         } else if args[2] == "merge" {
-            Transactions::merge(storage, args[3..].to_vec());
+            Transactions::merge(storage, args[3..].to_vec())
         } else if args[2] == "calendar" {
-            Transactions::calendar(storage, args[3..].to_vec());
+            Transactions::calendar(storage, args[3..].to_vec())
+        } else if args[2] == "history" {
+            Histories::history(storage, "transactions", args[3..].to_vec(), is_csv)
         } else {
-            println!("{}: bmoney transactions [list|add|update|rm|ofx|merge|calendar]", I18n::text("how_to_use"));
+            println!("{}: bmoney transactions [list|add|update|split|rm|ofx|csv|merge|calendar|history]", I18n::text("how_to_use"));
+            Ok(())
         }
     } else if args[1] == "tags" {
         if args[2] == "list" {
-            Tags::list(storage, args[3..].to_vec(), is_csv);
+            Tags::list(storage, args[3..].to_vec(), is_csv)
         } else if args[2] == "add" {
-            Tags::add(storage, args[3..].to_vec());
+            Tags::add(storage, args[3..].to_vec())
         } else if args[2] == "update" {
-            Tags::update(storage, args[3..].to_vec());
+            Tags::update(storage, args[3..].to_vec())
         } else if args[2] == "rm" {
-            Tags::rm(storage, args[3..].to_vec());
+            Tags::rm(storage, args[3..].to_vec())
+        } else if args[2] == "merge" {
+            Tags::merge(storage, args[3..].to_vec(), is_csv)
+        } else if args[2] == "history" {
+            Histories::history(storage, "tags", args[3..].to_vec(), is_csv)
         } else {
-            println!("{}: bmoney tags [list|add|update|rm]", I18n::text("how_to_use"));
+            println!("{}: bmoney tags [list|add|update|rm|merge|history]", I18n::text("how_to_use"));
+            Ok(())
         }
     } else if args[1] == "rules" {
         if args[2] == "list" {
-            Rules::list(storage, args[3..].to_vec(), is_csv);
+            Rules::list(storage, args[3..].to_vec(), is_csv)
         } else if args[2] == "add" {
-            Rules::add(storage, args[3..].to_vec());
+            Rules::add(storage, args[3..].to_vec())
         } else if args[2] == "update" {
-            Rules::update(storage, args[3..].to_vec());
+            Rules::update(storage, args[3..].to_vec())
         } else if args[2] == "rm" {
-            Rules::rm(storage, args[3..].to_vec());
+            Rules::rm(storage, args[3..].to_vec())
+        } else if args[2] == "history" {
+            Histories::history(storage, "rules", args[3..].to_vec(), is_csv)
         } else {
-            println!("{}: bmoney rules [list|add|update|rm]", I18n::text("how_to_use"));
+            println!("{}: bmoney rules [list|add|update|rm|history]", I18n::text("how_to_use"));
+            Ok(())
         }
     } else if args[1] == "forecasts" {
         if args[2] == "list" {
-            Forecasts::list(storage, args[3..].to_vec(), is_csv);
+            Forecasts::list(storage, args[3..].to_vec(), is_csv)
         } else if args[2] == "add" {
-            Forecasts::add(storage, args[3..].to_vec());
+            Forecasts::add(storage, args[3..].to_vec())
         } else if args[2] == "update" {
-            Forecasts::update(storage, args[3..].to_vec());
+            Forecasts::update(storage, args[3..].to_vec())
         } else if args[2] == "rm" {
-            Forecasts::rm(storage, args[3..].to_vec());
+            Forecasts::rm(storage, args[3..].to_vec())
+        } else if args[2] == "history" {
+            Histories::history(storage, "forecasts", args[3..].to_vec(), is_csv)
+        } else {
+            println!("{}: bmoney forecasts [list|add|update|rm|history]", I18n::text("how_to_use"));
+            Ok(())
+        }
+    } else if args[1] == "rates" {
+        if args[2] == "list" {
+            Rates::list(storage, args[3..].to_vec(), is_csv)
+        } else if args[2] == "add" {
+            Rates::add(storage, args[3..].to_vec())
+        } else if args[2] == "rm" {
+            Rates::rm(storage, args[3..].to_vec())
+        } else if args[2] == "import" {
+            Rates::import(storage, args[3..].to_vec())
+        } else if args[2] == "base" {
+            Rates::base(storage, args[3..].to_vec())
+        } else {
+            println!("{}: bmoney rates [list|add|rm|import|base]", I18n::text("how_to_use"));
+            Ok(())
+        }
+    } else if args[1] == "storage" {
+        if args[2] == "encrypt" {
+            Storages::encrypt(storage, args[3..].to_vec())
+        } else if args[2] == "decrypt" {
+            Storages::decrypt(storage, args[3..].to_vec())
+        } else if args[2] == "check" {
+            Storages::check(storage, args[3..].to_vec())
+        } else if args[2] == "backups" {
+            Storages::backups(storage, args[3..].to_vec(), is_csv)
+        } else if args[2] == "convert" {
+            Storages::convert(storage, args[3..].to_vec())
+        } else {
+            println!("{}: bmoney storage [encrypt|decrypt|check|backups|convert]", I18n::text("how_to_use"));
+            Ok(())
+        }
+    } else if args[1] == "trash" {
+        if args[2] == "list" {
+            Trashes::list(storage, args[3..].to_vec(), is_csv)
+        } else if args[2] == "restore" {
+            Trashes::restore(storage, args[3..].to_vec())
+        } else if args[2] == "purge" {
+            Trashes::purge(storage, args[3..].to_vec())
         } else {
-            println!("{}: bmoney forecasts [list|add|update|rm]", I18n::text("how_to_use"));
+            println!("{}: bmoney trash [list|restore|purge]", I18n::text("how_to_use"));
+            Ok(())
         }
+    } else if args[1] == "undo" {
+        Storages::undo(storage, args[2..].to_vec())
+    } else if args[1] == "redo" {
+        Storages::redo(storage, args[2..].to_vec())
     } else {
-     println!("{}: bmoney [accounts|contacts|transactions|tags|rules|forecasts] [action]", I18n::text("how_to_use"));
-    }
+        println!("{}: bmoney [accounts|contacts|transactions|tags|rules|forecasts|rates|trash|storage|undo|redo] [action]", I18n::text("how_to_use"));
+        Ok(())
+    };
 
+    // The storage was released by the command, so we can exit without run his drop
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(e.exit_code());
+    }
 }
@@ -6,90 +6,156 @@
 /// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
 ///
 
-use backend::accounts::Account;
+use backend::accounts::{Account, AccountType};
 use backend::storage::Storage;
+use backend::error::Error;
+use backend::references::*;
 use ui::ui::*;
 use prettytable::{Row, Cell, Attr, color};
 use backend::transactions::Transaction;
 use backend::transactions::StatusFilter;
+use backend::rates::Exchange;
+use backend::statements::Statement;
+use backend::reconcile::Reconciliation;
+use backend::money::Money;
 use i18n::*;
+use chrono::{ Local, NaiveDate };
 
 pub struct Accounts {}
 
 impl Accounts {
 
     // List of user accounts
-    pub fn list(mut storage: Storage, _params: Vec<String>, is_csv: bool) {
+    pub fn list(mut storage: Storage, mut params: Vec<String>, is_csv: bool) -> Result<(), Error> {
 
-        let accounts = Account::get_accounts(&mut storage);
+        let include_archived = Input::extract_param(&mut params, "--include-archived".to_string());
+
+        let accounts = Account::get_active_accounts(&mut storage, include_archived)?;
         let mut table = Output::new_table();
 
-        table.set_titles(row![b->I18n::text("accounts_name"), b->I18n::text("accounts_bank"), b->I18n::text("accounts_ob"), b->I18n::text("accounts_obd"), b->"#id"]);
+        let mut header = row![b->I18n::text("accounts_name"), b->I18n::text("accounts_bank"), b->I18n::text("accounts_type"), b->I18n::text("accounts_ob"), b->I18n::text("accounts_obd"), b->"#id"];
+
+        if include_archived {
+            header.add_cell(cell!(b->I18n::text("accounts_closed_in")));
+        }
+
+        table.set_titles(header);
 
         for account in accounts {
 
             let mut row = table.add_row(row![
                 account.name,
                 account.bank,
+                account.kind_formmated(),
                 Fg->account.open_balance_formmated(),
-                account.open_balance_date.unwrap(),
-                account.clone().id()
+                I18n::locale().format_date(account.open_balance_date.unwrap()),
+                account.clone().id(&mut storage)
             ]);
 
-            if account.open_balance < 0.0 {
-                row.set_cell(cell!(Fr->account.open_balance_formmated()), 2)
+            if include_archived {
+                match account.closed_in {
+                    Some(date) => row.add_cell(cell!(I18n::locale().format_date(date))),
+                    None => row.add_cell(cell!(""))
+                }
+            }
+
+            if account.open_balance.is_negative() {
+                row.set_cell(cell!(Fr->account.open_balance_formmated()), 3)
                     .expect(&I18n::text("accounts_unable_to_set_opening_balance_of_account"));
             }
         }
 
         Output::print_table(table, is_csv);
+
+        Ok(())
     }
 
     // Show status of all accounts
-    pub fn status(mut storage: Storage, params: Vec<String>, is_csv: bool) {
+    pub fn status(mut storage: Storage, mut params: Vec<String>, is_csv: bool) -> Result<(), Error> {
+
+        let include_archived = Input::extract_param(&mut params, "--include-archived".to_string());
 
-        let (from, to) = Input::param_date_period(params, 0, 1);
+        let (from, to) = Input::param_date_period(params, 0, 1)?;
 
-        let accounts = Account::get_accounts(&mut storage);
+        let mut accounts = Account::get_active_accounts(&mut storage, include_archived)?;
+        let exchange = Exchange::new(&mut storage)?;
         let mut table = Output::new_table();
 
-        let mut first = true;
+        // Grouped by type, keeping the order of the list on each one
+        accounts.sort_by_key(|account| account.kind);
+
+        let mut header: Vec<String> = vec![];
+        let mut group: Option<AccountType> = None;
+
+        // Sum of the totals of all accounts on the base currency, each
+        // value converted by the rate of his date
+        let mut consolidated: Result<Vec<Money>, Error> = Ok(vec![]);
 
         for account in accounts {
 
-            let (_, totals) = Transaction::get_transactions(&mut storage, account.clone(), from, to, StatusFilter::ALL, None, false);
+            let (_, totals) = Transaction::get_transactions(&mut storage, account.clone(), from, to, StatusFilter::ALL, None, false)?;
 
-            let mut cols: Vec<Cell>;
+            // The header has the labels of checking accounts, plus the
+            // interest of savings when some account has it
+            let typed = Transaction::totals_of_type(&account, totals.clone(), from, to);
 
-            if first {
-                // On first account we set the header
-                // adding the news columns
+            if header.is_empty() {
+                header = totals.iter()
+                    .map(|total| total.label.clone())
+                    .collect();
+            }
 
-                cols = vec![
-                    Cell::new(&I18n::text("accounts_name"))
-                        .with_style(Attr::Bold)
-                ];
+            if typed.len() > header.len() {
+                header.extend(typed[header.len()..].iter().map(|total| total.label.clone()));
+            }
 
-                for total in totals.clone() {
-                    cols.push(
-                        Cell::new(&total.label)
-                            .with_style(Attr::Bold)
-                    );
+            if let (Some(exchange), Ok(sums)) = (exchange.as_ref(), consolidated.as_mut()) {
+
+                let converted = Transaction::get_transactions_converted(&mut storage, account.clone(), from, to, StatusFilter::ALL, None, false, |value, date| {
+                    exchange.to_base(value, &account.currency, date)
+                });
+
+                match converted {
+                    Ok((_, converted)) => {
+                        sums.resize(converted.len(), Money::zero());
+
+                        for (sum, total) in sums.iter_mut().zip(converted) {
+                            *sum += total.value;
+                        }
+                    },
+                    Err(e) => consolidated = Err(e)
                 }
+            }
 
-                table.set_titles(Row::new(cols));
+            // Each type has his own labels for the totals, like the owed
+            // of credit cards and the principal of loans
+            if group != Some(account.kind) {
+
+                let mut cols = vec![Cell::new(&account.kind.label()).with_style(Attr::Bold)];
+
+                for total in typed.iter() {
+                    cols.push(Cell::new(&total.label).with_style(Attr::Bold));
+                }
 
-                first = false;
+                table.add_row(Row::new(cols));
+
+                group = Some(account.kind);
             }
 
-            cols = vec![
-                Cell::new(&account.name)
+            let mut cols = vec![
+                Cell::new(&format!("  {}", account.name))
             ];
 
-            for total in totals {
+            for (index, total) in typed.into_iter().enumerate() {
                 let mut cell = Cell::new(&account.format_value(total.value));
 
-                if total.value >= 0.0 {
+                // Colored by the signal of balance, so the owed is red
+                let negative = match totals.get(index) {
+                    Some(raw) => raw.value.is_negative(),
+                    None => total.value.is_negative()
+                };
+
+                if !negative {
                     cell = cell.with_style(Attr::ForegroundColor(color::GREEN));
                 } else {
                     cell = cell.with_style(Attr::ForegroundColor(color::RED));
@@ -101,20 +167,68 @@ impl Accounts {
             table.add_row(Row::new(cols));
         }
 
+        if !header.is_empty() {
+
+            let mut cols = vec![
+                Cell::new(&I18n::text("accounts_name"))
+                    .with_style(Attr::Bold)
+            ];
+
+            for label in header {
+                cols.push(Cell::new(&label).with_style(Attr::Bold));
+            }
+
+            table.set_titles(Row::new(cols));
+        }
+
+        if let Some(exchange) = exchange {
+            match consolidated {
+                Ok(sums) => {
+
+                    let mut cols = vec![
+                        Cell::new(&format!("{} ({})", I18n::text("accounts_consolidated"), exchange.base))
+                            .with_style(Attr::Bold)
+                    ];
+
+                    for sum in sums {
+                        let color = match sum.is_negative() {
+                            true => color::RED,
+                            false => color::GREEN
+                        };
+
+                        cols.push(Cell::new(&exchange.format_value(sum)).with_style(Attr::ForegroundColor(color)));
+                    }
+
+                    table.add_row(Row::new(cols));
+                },
+                // The accounts are shown even without the rates
+                Err(e) => eprintln!("{}", e)
+            }
+        }
+
         Output::print_table(table, is_csv);
+
+        Ok(())
     }
 
     // Create new account
-    pub fn add(mut storage: Storage, params: Vec<String>) {
+    pub fn add(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
 
-        if params.len() == 5 {
+        if params.len() >= 5 && params.len() <= 7 {
             // Shell mode
 
             let name = Input::param(I18n::text("accounts_name"), true, params.clone(), 0);
             let bank = Input::param(I18n::text("accounts_bank"), true, params.clone(), 1);
-            let obd = Input::param_date(I18n::text("accounts_obd"), true, params.clone(), 2);
+            let obd = Input::param_date(I18n::text("accounts_obd"), true, params.clone(), 2)?;
             let currency = Input::param(I18n::text("accounts_currency"), true, params.clone(), 4);
-            let ob = Input::param_money(I18n::text("accounts_ob"), true, params.clone(), 3);
+            let ob = Input::param_money(I18n::text("accounts_ob"), true, params.clone(), 3)?;
+
+            let kind = match params.get(5) {
+                Some(kind) => kind.parse::<AccountType>()?,
+                None => AccountType::Checking
+            };
+
+            let interest_rate = Account::parse_interest_rate(params.get(6).map(|rate| rate.as_str()).unwrap_or(""))?;
 
             Account::store_account(&mut storage, Account {
                 uuid: "".to_string(),
@@ -122,89 +236,551 @@ impl Accounts {
                 name: name,
                 open_balance: ob,
                 open_balance_date: obd,
-                currency: currency
-            });
+                currency: currency,
+                kind: kind,
+                interest_rate: interest_rate,
+                closing_day: None,
+                due_day: None,
+                closed_in: None,
+                starts_on_opening: true
+            })?;
         } else if params.len() > 0 && params[0] == "-i" {
             // Interactive mode
 
             let name = Input::read(I18n::text("accounts_name"), true, None);
             let bank = Input::read(I18n::text("accounts_bank"), true, None);
-            let obd = Input::read_date(I18n::text("accounts_obd"), true, None);
+            let obd = Input::read_date(I18n::text("accounts_obd"), true, None)?;
             let currency = Input::read(I18n::text("accounts_currency"), true, None);
-            let ob = Input::read_money(I18n::text("accounts_ob"), true, None, currency.clone());
+            let ob = Input::read_money(I18n::text("accounts_ob"), true, None, currency.clone())?;
+            let kind = Accounts::read_kind(AccountType::Checking)?;
 
-            Account::store_account(&mut storage, Account {
+            let mut interest_rate = None;
+
+            if kind == AccountType::Savings {
+                interest_rate = Account::parse_interest_rate(&Input::read(I18n::text("accounts_interest_rate"), false, None))?;
+            }
+
+            let mut account = Account {
                 uuid: "".to_string(),
                 bank: bank,
                 name: name,
                 open_balance: ob,
                 open_balance_date: obd,
-                currency: currency
-            });
+                currency: currency,
+                kind: kind,
+                interest_rate: interest_rate,
+                closing_day: None,
+                due_day: None,
+                closed_in: None,
+                starts_on_opening: true
+            };
+
+            if kind == AccountType::CreditCard {
+                Accounts::read_billing_days(&mut account)?;
+            }
+
+            Account::store_account(&mut storage, account)?;
         } else {
             // Help mode
             println!("{}", I18n::text("accounts_how_to_use_add"));
         }
+
+        Ok(())
     }
 
     // Update a existing account
-    pub fn update(mut storage: Storage, params: Vec<String>) {
+    pub fn update(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
 
         if params.len() == 3 {
             // Shell mode
 
-            let mut account = Account::get_account(&mut storage, params[0].trim().to_string())
-                .expect(&I18n::text("accounts_not_found"));
+            let mut account = Account::get_account(&mut storage, params[0].trim().to_string())?;
 
             if params[1] == "name" {
                 account.name = Input::param(I18n::text("accounts_name"), true, params.clone(), 2);
             } else if params[1] == "bank" {
                 account.bank = Input::param(I18n::text("accounts_bank"), true, params.clone(), 2);
             } else if params[1] == "obd" {
-                account.open_balance_date = Input::param_date(I18n::text("accounts_obd"), true, params.clone(), 2);
+                account.open_balance_date = Input::param_date(I18n::text("accounts_obd"), true, params.clone(), 2)?;
             } else if params[1] == "ob" {
-                account.open_balance = Input::param_money(I18n::text("accounts_ob"), true, params.clone(), 2);
+                account.open_balance = Input::param_money(I18n::text("accounts_ob"), true, params.clone(), 2)?;
             } else if params[1] == "currency" {
                 account.currency = Input::param(I18n::text("accounts_currency"), true, params.clone(), 2);
+            } else if params[1] == "type" {
+                account.kind = params[2].parse::<AccountType>()?;
+            } else if params[1] == "interest" {
+                account.interest_rate = Account::parse_interest_rate(&params[2])?;
+            } else if params[1] == "closing_day" {
+                account.closing_day = Some(Account::parse_day(&params[2])?);
+                account.due_day = account.due_day.or(account.closing_day);
+            } else if params[1] == "due_day" {
+                account.due_day = Some(Account::parse_day(&params[2])?);
+                account.closing_day = account.closing_day.or(account.due_day);
+            } else if params[1] == "starts_on_opening" {
+                account.starts_on_opening = params[2] == "y";
             } else {
-                panic!(I18n::text("field_not_found"));
+                return Err(Error::Invalid("field_not_found"));
             }
 
-            Account::store_account(&mut storage, account);
+            Account::store_account(&mut storage, account)?;
 
         } else if params.len() > 0 && params[0] == "-i" {
             // Interactive mode
 
             let id = Input::read("#id".to_string(), true, None);
 
-            let mut account = Account::get_account(&mut storage, id)
-                .expect(&I18n::text("accounts_not_found"));
+            let mut account = Account::get_account(&mut storage, id)?;
 
             account.name = Input::read(I18n::text("accounts_name"), true, Some(account.name));
             account.bank = Input::read(I18n::text("accounts_bank"), true, Some(account.bank));
-            account.open_balance_date = Input::read_date(I18n::text("accounts_obd"), true, account.open_balance_date);
+            account.open_balance_date = Input::read_date(I18n::text("accounts_obd"), true, account.open_balance_date)?;
             account.currency = Input::read(I18n::text("accounts_currency"), true, Some(account.currency));
-            account.open_balance = Input::read_money(I18n::text("accounts_ob"), true, Some(account.open_balance), account.currency.clone());
+            account.open_balance = Input::read_money(I18n::text("accounts_ob"), true, Some(account.open_balance), account.currency.clone())?;
+
+            let starts_on_opening = match account.starts_on_opening {
+                true => "y".to_string(),
+                false => "n".to_string()
+            };
+
+            account.starts_on_opening = Input::read(I18n::text("accounts_starts_on_opening"), false, Some(starts_on_opening)) == "y";
+            account.kind = Accounts::read_kind(account.kind)?;
+
+            if account.kind == AccountType::Savings {
+                let current = account.interest_rate.map(|rate| I18n::locale().format_decimal(rate));
+
+                account.interest_rate = Account::parse_interest_rate(&Input::read(I18n::text("accounts_interest_rate"), false, current))?;
+            }
 
-            Account::store_account(&mut storage, account);
+            if account.kind == AccountType::CreditCard {
+                Accounts::read_billing_days(&mut account)?;
+            }
+
+            Account::store_account(&mut storage, account)?;
 
         } else {
             // Help mode
             println!("{}", I18n::text("accounts_how_to_use_update"));
         }
+
+        Ok(())
     }
 
     // Remove a existing account
-    pub fn rm(mut storage: Storage, params: Vec<String>) {
+    pub fn rm(mut storage: Storage, mut params: Vec<String>) -> Result<(), Error> {
 
-        if params.len() == 1 {
+        let on_remove = Input::extract_on_remove(&mut params);
+
+        if params.len() == 1 && params[0] != "-i" {
             // Shell mode
 
-            Account::remove_account(&mut storage, params[0].trim().to_string());
+            Account::remove_account(&mut storage, params[0].trim().to_string(), on_remove)?;
+        } else if params.len() > 0 && params[0] == "-i" {
+            // Interactive mode
+
+            let id = Input::read("#id".to_string(), true, None);
+
+            let account = Account::get_account(&mut storage, id)?;
+
+            let dependents = References::dependents(&mut storage, "accounts", &account.uuid)?;
+            let mut on_remove = OnRemove::Refuse;
+
+            if dependents.len() > 0 {
+
+                let mut accounts: Vec<(String, String)> = vec![];
+                for other in Account::get_active_accounts(&mut storage, false)? {
+                    if other.uuid != account.uuid {
+                        accounts.push((other.uuid, other.name));
+                    }
+                }
 
+                on_remove = Input::read_on_remove(References::summary(&dependents), accounts);
+            }
+
+            Account::remove_account(&mut storage, account.uuid, on_remove)?;
         } else {
             // Help mode
             println!("{}", I18n::text("accounts_how_to_use_rm"));
         }
+
+        Ok(())
+    }
+
+    // Ask the type of account
+    fn read_kind(current: AccountType) -> Result<AccountType, Error> {
+
+        let options = AccountType::all().into_iter()
+            .map(|kind| (kind.as_str().to_string(), kind.label()))
+            .collect();
+
+        Input::read_option(I18n::text("accounts_type"), true, Some(current.as_str().to_string()), options)
+            .parse::<AccountType>()
+    }
+
+    // Ask the closing and due days of credit card, both or none
+    fn read_billing_days(account: &mut Account) -> Result<(), Error> {
+
+        let closing_day = Input::read(I18n::text("accounts_closing_day"), false, account.closing_day.map(|day| day.to_string()));
+
+        if closing_day.is_empty() {
+            account.closing_day = None;
+            account.due_day = None;
+
+            return Ok(());
+        }
+
+        account.closing_day = Some(Account::parse_day(&closing_day)?);
+        account.due_day = Some(Account::parse_day(&Input::read(I18n::text("accounts_due_day"), true, account.due_day.map(|day| day.to_string())))?);
+
+        Ok(())
+    }
+
+    // Show the purchases of the statement of credit card
+    pub fn statement(mut storage: Storage, params: Vec<String>, is_csv: bool) -> Result<(), Error> {
+
+        if params.len() == 1 || params.len() == 2 {
+
+            let card = Account::get_account(&mut storage, params[0].trim().to_string())?;
+            let statement = Statement::get_statement(&mut storage, card.clone(), Accounts::param_month(&params, 1)?)?;
+            let locale = I18n::locale();
+            let mut table = Output::new_table();
+
+            table.set_titles(row![b->I18n::text("transactions_deadline"), b->I18n::text("transactions_description"), b->I18n::text("transactions_value"), b->I18n::text("transactions_paidin"), b->"#id"]);
+
+            for transaction in statement.transactions.clone() {
+
+                let color = match transaction.value.is_negative() {
+                    true => color::RED,
+                    false => color::GREEN
+                };
+
+                table.add_row(Row::new(vec![
+                    Cell::new(&locale.format_date(transaction.deadline.unwrap())),
+                    Cell::new(&transaction.description),
+                    Cell::new(&card.format_value(transaction.value)).with_style(Attr::ForegroundColor(color)),
+                    Cell::new(&transaction.paid_in_formmated()),
+                    Cell::new(&transaction.clone().id(&mut storage))
+                ]));
+            }
+
+            table.add_row(Row::empty());
+
+            let status = match (statement.is_open(), statement.payment.is_some()) {
+                (true, _) => I18n::text("accounts_statement_open"),
+                (false, true) => I18n::text("accounts_statement_paid"),
+                (false, false) => I18n::text("accounts_statement_closed")
+            };
+
+            let summary = vec![
+                (I18n::text("accounts_statement_period"), format!("{} - {}", locale.format_date(statement.from), locale.format_date(statement.closing))),
+                (I18n::text("accounts_statement_due"), locale.format_date(statement.due)),
+                (I18n::text("accounts_statement_status"), status),
+                (I18n::text("transactions_owed"), card.format_value(statement.total()))
+            ];
+
+            for (label, value) in summary {
+                table.add_row(row!["", b->label, value]);
+            }
+
+            Output::print_table(table, is_csv);
+        } else {
+            // Help mode
+            println!("{}", I18n::text("accounts_how_to_use_statement"));
+        }
+
+        Ok(())
+    }
+
+    // Create the transfer that pays the statement of credit card
+    pub fn pay(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
+
+        if params.len() == 2 || params.len() == 3 {
+
+            let card = Account::get_account(&mut storage, params[0].trim().to_string())?;
+            let account = Account::get_account(&mut storage, params[1].trim().to_string())?;
+            let statement = Statement::get_statement(&mut storage, card, Accounts::param_month(&params, 2)?)?;
+
+            Statement::pay(&mut storage, &statement, account)?;
+        } else {
+            // Help mode
+            println!("{}", I18n::text("accounts_how_to_use_pay"));
+        }
+
+        Ok(())
+    }
+
+    // Month of statement, like 2018-10 or 10/2018
+    fn param_month(params: &Vec<String>, position: usize) -> Result<Option<NaiveDate>, Error> {
+        match params.get(position) {
+            Some(month) => match I18n::locale().parse_month(month) {
+                Some(month) => Ok(Some(month)),
+                None => Err(Error::Invalid("error_invalid_month"))
+            },
+            None => Ok(None)
+        }
+    }
+
+    // Check the transactions of account against the balance of a bank statement
+    pub fn reconcile(mut storage: Storage, mut params: Vec<String>, is_csv: bool) -> Result<(), Error> {
+
+        let interactive = Input::extract_param(&mut params, "-i".to_string());
+
+        if params.len() >= 3 {
+
+            let account = Account::get_account(&mut storage, params[0].trim().to_string())?;
+            let date = Input::param_date(I18n::text("accounts_reconcile_date"), true, params.clone(), 1)?;
+            let balance = Input::param_money(I18n::text("accounts_reconcile_balance"), true, params.clone(), 2)?;
+
+            let mut reconciliation = Reconciliation::new(&mut storage, account, date.unwrap(), balance)?;
+
+            // Shell mode, by the ids or ofx fitids
+            for id in params[3..].iter() {
+                reconciliation.tick(id)?;
+            }
+
+            if interactive {
+
+                loop {
+                    Accounts::print_reconciliation(&mut storage, &reconciliation, is_csv);
+
+                    let positions = Input::read(I18n::text("accounts_reconcile_tick"), false, None);
+
+                    if positions == "q" {
+                        return Ok(());
+                    }
+
+                    if positions.is_empty() {
+                        match reconciliation.finish(&mut storage) {
+                            Ok(reconciled) => {
+                                println!("{}: {}", I18n::text("accounts_reconciled"), reconciled);
+                                return Ok(());
+                            },
+                            Err(e) => eprintln!("{}", e)
+                        }
+                        continue;
+                    }
+
+                    for position in positions.split(",") {
+                        match position.trim().parse::<usize>() {
+                            Ok(position) => if let Err(e) = reconciliation.toggle(position) {
+                                eprintln!("{}", e);
+                            },
+                            Err(_) => eprintln!("{}: {}", I18n::text("accounts_reconcile_invalid_position"), position)
+                        }
+                    }
+                }
+            }
+
+            Accounts::print_reconciliation(&mut storage, &reconciliation, is_csv);
+
+            // Without ids the list is only shown
+            if params.len() > 3 {
+                let reconciled = reconciliation.finish(&mut storage)?;
+
+                println!("{}: {}", I18n::text("accounts_reconciled"), reconciled);
+            }
+        } else {
+            // Help mode
+            println!("{}", I18n::text("accounts_how_to_use_reconcile"));
+        }
+
+        Ok(())
+    }
+
+    // Table of not reconciled transactions, with the difference after each one
+    fn print_reconciliation(storage: &mut Storage, reconciliation: &Reconciliation, is_csv: bool) {
+
+        let account = &reconciliation.account;
+        let locale = I18n::locale();
+        let mut table = Output::new_table();
+
+        table.set_titles(row![b->"#", b->I18n::text("transactions_deadline"), b->I18n::text("transactions_description"), b->I18n::text("transactions_value"), b->I18n::text("transactions_paidin"), b->I18n::text("accounts_reconcile_ticked"), b->I18n::text("accounts_reconcile_difference"), b->"#id", b->I18n::text("transactions_ofx_fitid")]);
+
+        let differences = reconciliation.running_differences();
+
+        for (position, ((transaction, ticked), difference)) in reconciliation.transactions.iter().zip(differences).enumerate() {
+
+            let ticked = match ticked {
+                true => "x",
+                false => ""
+            };
+
+            table.add_row(row![
+                position,
+                locale.format_date(transaction.deadline.unwrap()),
+                transaction.description,
+                account.format_value(transaction.value),
+                transaction.paid_in_formmated(),
+                ticked,
+                account.format_value(difference),
+                transaction.clone().id(storage),
+                transaction.ofx_fitid
+            ]);
+        }
+
+        table.add_row(Row::empty());
+
+        let summary = vec![
+            (I18n::text("accounts_reconcile_balance"), reconciliation.balance),
+            (I18n::text("accounts_reconcile_cleared"), reconciliation.cleared),
+            (I18n::text("accounts_reconcile_difference"), reconciliation.difference())
+        ];
+
+        for (label, value) in summary {
+            table.add_row(row!["", "", b->label, account.format_value(value)]);
+        }
+
+        Output::print_table(table, is_csv);
+    }
+
+    // Close the account, after it the transactions are not accepted
+    pub fn close(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
+
+        if params.len() == 1 || params.len() == 2 {
+
+            let date = match params.len() {
+                2 => Input::param_date(I18n::text("accounts_closed_in"), true, params.clone(), 1)?,
+                _ => Some(Local::today().naive_local())
+            };
+
+            let balance = Account::close_account(&mut storage, params[0].trim().to_string(), date)?;
+
+            if !balance.is_zero() {
+                let account = Account::get_account(&mut storage, params[0].trim().to_string())?;
+
+                eprintln!("{}: {}", I18n::text("accounts_closed_with_balance"), account.format_value(balance));
+            }
+        } else {
+            // Help mode
+            println!("{}", I18n::text("accounts_how_to_use_close"));
+        }
+
+        Ok(())
+    }
+
+    // Recompute the opening balance from the balance of a bank statement
+    pub fn rebalance(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
+
+        if params.len() == 3 {
+
+            let date = Input::param_date(I18n::text("accounts_rebalance_date"), true, params.clone(), 1)?;
+            let balance = Input::param_money(I18n::text("accounts_rebalance_balance"), true, params.clone(), 2)?;
+
+            let open_balance = Account::rebalance(&mut storage, params[0].trim().to_string(), date.unwrap(), balance)?;
+            let account = Account::get_account(&mut storage, params[0].trim().to_string())?;
+
+            println!("{}: {}", I18n::text("accounts_ob"), account.format_value(open_balance));
+        } else {
+            // Help mode
+            println!("{}", I18n::text("accounts_how_to_use_rebalance"));
+        }
+
+        Ok(())
+    }
+
+    // Open again a closed account
+    pub fn reopen(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
+
+        if params.len() == 1 {
+            Account::close_account(&mut storage, params[0].trim().to_string(), None)?;
+        } else {
+            // Help mode
+            println!("{}", I18n::text("accounts_how_to_use_reopen"));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use uuid::Uuid;
+    use backend::contacts::Contact;
+    use std::process::Command;
+    use assert_cmd::prelude::*;
+
+    fn date(value: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d").unwrap()
+    }
+
+    fn populate() -> String {
+
+        let path = "/tmp/bmoney-ui-".to_owned() + &Uuid::new_v4().to_string();
+
+        let mut st = Storage::new(path.clone());
+
+        let card = Account::store_account(&mut st, Account { name: "card".to_string(), currency: "R$".to_string(), open_balance_date: Some(date("2018-01-01")), kind: AccountType::CreditCard, closing_day: Some(3), due_day: Some(10), ..Default::default() }).unwrap();
+        Account::store_account(&mut st, Account { name: "savings".to_string(), currency: "R$".to_string(), open_balance: Money::from_cents(100000), open_balance_date: Some(date("2018-01-01")), kind: AccountType::Savings, interest_rate: Account::parse_interest_rate("12").unwrap(), ..Default::default() }).unwrap();
+        let contact = Contact::store_contact(&mut st, Contact { uuid: "".to_string(), name: "contact".to_string(), city_location: "city".to_string() }).unwrap();
+
+        let card = Account::get_account(&mut st, card).unwrap();
+        let contact = Contact::get_contact(&mut st, contact).unwrap();
+
+        Transaction::store_transaction(&mut st, Transaction {
+            description: "purchase".to_string(),
+            value: Money::from_cents(-10000),
+            account: Some(card),
+            contact: Some(contact),
+            deadline: Some(date("2018-10-05")),
+            paid_in: Some(date("2018-10-05")),
+            ..Default::default()
+        }).unwrap();
+
+        path
+    }
+
+    #[test]
+    fn accounts_status() {
+
+        let path_str = populate();
+
+        let mut main = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+        main.arg("accounts")
+            .arg("status")
+            .arg("--storage-file=".to_owned() + &path_str)
+            .arg("--use-csv")
+            .arg("2018-10-01")
+            .arg("2018-10-31");
+
+        let output = main.output().unwrap();
+        let stdout = String::from_utf8(output.stdout).unwrap();
+
+        assert!(output.status.success());
+
+        let lines: Vec<&str> = stdout.lines().collect();
+
+        assert_eq!(lines.len(), 5);
+        assert!(lines[0].ends_with("\"Total balance\",\"Interest of period(estimated)\""));
+
+        // The card shows the amount owed, positive
+        assert!(lines[1].starts_with("\"Credit card\",\"Purchases(pending)\",\"Purchases\""));
+        assert!(lines[1].ends_with("\"Expected amount owed\",\"Amount owed\""));
+        assert!(lines[2].starts_with("\"  card\",\"R$ 0.00\",\"R$ -100.00\""));
+        assert!(lines[2].ends_with("\"R$ 100.00\",\"R$ 100.00\""));
+
+        // And the savings the interest of period
+        assert!(lines[3].ends_with("\"Total balance\",\"Interest of period(estimated)\""));
+        assert!(lines[4].ends_with("\"R$ 1000.00\",\"R$ 10.19\""));
+    }
+
+    #[test]
+    fn accounts_status_invalid_date() {
+
+        let path_str = populate();
+
+        let mut main = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+        main.arg("accounts")
+            .arg("status")
+            .arg("--storage-file=".to_owned() + &path_str)
+            .arg("2018-13-01")
+            .arg("2018-10-31");
+
+        let output = main.output().unwrap();
+
+        assert_eq!(output.status.code(), Some(5));
+        assert!(String::from_utf8_lossy(&output.stderr).ends_with("Couldn't parse the string to date. The format is YYYY-MM-DD\n"));
     }
 }
@@ -8,6 +8,9 @@
 
 use backend::contacts::Contact;
 use backend::storage::Storage;
+use backend::error::Error;
+use backend::references::*;
+use backend::duplicates::Duplicates;
 use ui::ui::*;
 use i18n::*;
 
@@ -16,9 +19,9 @@ pub struct Contacts {}
 impl Contacts {
 
     // List of user contacts
-    pub fn list(mut storage: Storage, _params: Vec<String>, is_csv: bool) {
+    pub fn list(mut storage: Storage, _params: Vec<String>, is_csv: bool) -> Result<(), Error> {
 
-        let contacts = Contact::get_contacts(&mut storage);
+        let contacts = Contact::get_contacts(&mut storage)?;
         let mut table = Output::new_table();
 
         table.set_titles(row![b->I18n::text("contacts_name"), b->I18n::text("contacts_city_location"), b->"#id"]);
@@ -28,15 +31,17 @@ impl Contacts {
             table.add_row(row![
                 contact.name,
                 contact.city_location,
-                contact.clone().id()
+                contact.clone().id(&mut storage)
             ]);
         }
 
         Output::print_table(table, is_csv);
+
+        Ok(())
     }
 
     // Create new contact
-    pub fn add(mut storage: Storage, params: Vec<String>) {
+    pub fn add(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
 
         if params.len() == 2 {
             // Shell mode
@@ -48,7 +53,7 @@ impl Contacts {
                 uuid: "".to_string(),
                 city_location: city,
                 name: name,
-            });
+            })?;
         } else if params.len() > 0 && params[0] == "-i" {
             // Interactive mode
 
@@ -59,60 +64,138 @@ impl Contacts {
                 uuid: "".to_string(),
                 city_location: city,
                 name: name,
-            });
+            })?;
         } else {
             // Help mode
             println!("{}", I18n::text("contacts_how_to_use_add"));
         }
+
+        Ok(())
     }
 
     // Update a existing contact
-    pub fn update(mut storage: Storage, params: Vec<String>) {
+    pub fn update(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
 
         if params.len() == 3 {
             // Shell mode
 
-            let mut contact = Contact::get_contact(&mut storage, params[0].trim().to_string())
-                .expect(&I18n::text("contacts_not_found"));
+            let mut contact = Contact::get_contact(&mut storage, params[0].trim().to_string())?;
 
             if params[1] == "name" {
                 contact.name = Input::param(I18n::text("contacts_name"), true, params.clone(), 2);
             } else if params[1] == "city" {
                 contact.city_location = Input::param(I18n::text("contacts_city_location"), true, params.clone(), 2);
             } else {
-                panic!(I18n::text("field_not_found"));
+                return Err(Error::Invalid("field_not_found"));
             }
 
-            Contact::store_contact(&mut storage, contact);
+            Contact::store_contact(&mut storage, contact)?;
 
         } else if params.len() > 0 && params[0] == "-i" {
             // Interactive mode
 
             let id = Input::read("#id".to_string(), true, None);
 
-            let mut contact = Contact::get_contact(&mut storage, id)
-                .expect(&I18n::text("contacts_not_found"));
+            let mut contact = Contact::get_contact(&mut storage, id)?;
 
             contact.name = Input::read(I18n::text("contacts_name"), true, Some(contact.name));
             contact.city_location = Input::read(I18n::text("contacts_city_location"), true, Some(contact.city_location));
 
-            Contact::store_contact(&mut storage, contact);
+            Contact::store_contact(&mut storage, contact)?;
         } else {
             // Help mode
             println!("{}", I18n::text("contacts_how_to_use_update"));
         }
+
+        Ok(())
     }
 
     // Remove a existing contact
-    pub fn rm(mut storage: Storage, params: Vec<String>) {
+    pub fn rm(mut storage: Storage, mut params: Vec<String>) -> Result<(), Error> {
 
-        if params.len() == 1 {
+        let on_remove = Input::extract_on_remove(&mut params);
+
+        if params.len() == 1 && params[0] != "-i" {
             // Shell mode
 
-            Contact::remove_contact(&mut storage, params[0].trim().to_string());
+            Contact::remove_contact(&mut storage, params[0].trim().to_string(), on_remove)?;
+        } else if params.len() > 0 && params[0] == "-i" {
+            // Interactive mode
+
+            let id = Input::read("#id".to_string(), true, None);
+
+            let contact = Contact::get_contact(&mut storage, id)?;
+
+            let dependents = References::dependents(&mut storage, "contacts", &contact.uuid)?;
+            let mut on_remove = OnRemove::Refuse;
+
+            if dependents.len() > 0 {
+
+                let mut contacts: Vec<(String, String)> = vec![];
+                for other in Contact::get_contacts(&mut storage)? {
+                    if other.uuid != contact.uuid {
+                        contacts.push((other.uuid, other.name));
+                    }
+                }
+
+                on_remove = Input::read_on_remove(References::summary(&dependents), contacts);
+            }
+
+            Contact::remove_contact(&mut storage, contact.uuid, on_remove)?;
         } else {
             // Help mode
             println!("{}", I18n::text("contacts_how_to_use_rm"));
         }
+
+        Ok(())
+    }
+
+    // Merge duplicated contacts into one
+    pub fn merge(mut storage: Storage, mut params: Vec<String>, is_csv: bool) -> Result<(), Error> {
+
+        if Input::extract_param(&mut params, "--suggest".to_string()) {
+            // Suggest mode
+
+            let mut contacts: Vec<(String, String)> = vec![];
+            for contact in Contact::get_contacts(&mut storage)? {
+                contacts.push((contact.uuid, contact.name));
+            }
+
+            let suggestions = Duplicates::suggest(contacts);
+
+            if suggestions.is_empty() {
+                println!("{}", I18n::text("merge_no_suggestions"));
+                return Ok(());
+            }
+
+            let mut table = Output::new_table();
+
+            table.set_titles(row![b->I18n::text("merge_group"), b->I18n::text("contacts_name"), b->"#id"]);
+
+            for (i, suggestion) in suggestions.into_iter().enumerate() {
+                for (uuid, name) in suggestion {
+                    table.add_row(row![
+                        i + 1,
+                        name,
+                        storage.short_id("contacts", &uuid)
+                    ]);
+                }
+            }
+
+            Output::print_table(table, is_csv);
+        } else if params.len() > 1 {
+            // Shell mode
+
+            let drop: Vec<String> = params[1..].iter()
+                .map(|id| id.trim().to_string())
+                .collect();
+
+            Contact::merge_contacts(&mut storage, params[0].trim().to_string(), drop)?;
+        } else {
+            // Help mode
+            println!("{}", I18n::text("contacts_how_to_use_merge"));
+        }
+
+        Ok(())
     }
 }
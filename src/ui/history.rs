@@ -0,0 +1,90 @@
+///
+/// Blitz Money
+///
+/// Frontend/Ui of module for see the versions of the rows and revert them
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use backend::history::History;
+use backend::storage::Storage;
+use backend::error::Error;
+use ui::ui::*;
+use i18n::*;
+
+pub struct Histories {}
+
+impl Histories {
+
+    // List the versions of a row of section, or revert it to one of them
+    pub fn history(mut storage: Storage, section: &str, mut params: Vec<String>, is_csv: bool) -> Result<(), Error> {
+
+        let revert = Input::extract_named_param(&mut params, "--revert=".to_string());
+
+        if params.len() != 1 {
+            // Help mode
+            println!("{}", I18n::text("history_how_to_use").replace("[module]", section));
+            return Ok(());
+        }
+
+        let id = params[0].trim().to_string();
+
+        if let Some(number) = revert {
+
+            let number = number.trim().parse::<usize>()
+                .map_err(|_| Error::NotFound("revisions".to_string(), number.clone()))?;
+
+            let revision = History::revert(&mut storage, section, id, number)?;
+
+            println!("{}: {}", I18n::text("history_reverted"), revision.number);
+
+            return Ok(());
+        }
+
+        let mut table = Output::new_table();
+
+        table.set_titles(row![b->I18n::text("history_revision"), b->I18n::text("history_changed_at"), b->I18n::text("history_command"), b->I18n::text("history_user"), b->I18n::text("history_field"), b->I18n::text("history_before"), b->I18n::text("history_after")]);
+
+        for revision in History::revisions(&mut storage, section, id)? {
+
+            let changed_at = match revision.changed_at {
+                Some(changed_at) => changed_at.format(&I18n::locale().datetime_format()).to_string(),
+                None => "-".to_string()
+            };
+
+            if revision.row.is_none() {
+                table.add_row(row![revision.number, changed_at, revision.command, revision.user, I18n::text("history_removed"), "", ""]);
+                continue;
+            }
+
+            // A version without changes, like a save of the same values
+            if revision.changes.is_empty() {
+                table.add_row(row![revision.number, changed_at, revision.command, revision.user, "", "", ""]);
+                continue;
+            }
+
+            for (i, change) in revision.changes.iter().enumerate() {
+
+                // The revision is shown only on the first field
+                let (number, changed_at, command, user) = match i {
+                    0 => (revision.number.to_string(), changed_at.clone(), revision.command.clone(), revision.user.clone()),
+                    _ => ("".to_string(), "".to_string(), "".to_string(), "".to_string())
+                };
+
+                table.add_row(row![
+                    number,
+                    changed_at,
+                    command,
+                    user,
+                    change.field,
+                    change.before.clone().unwrap_or_default(),
+                    change.after.clone().unwrap_or_default()
+                ]);
+            }
+        }
+
+        Output::print_table(table, is_csv);
+
+        Ok(())
+    }
+}
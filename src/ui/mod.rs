@@ -5,3 +5,7 @@ pub mod tags;
 pub mod transactions;
 pub mod rules;
 pub mod forecasts;
+pub mod storage;
+pub mod trash;
+pub mod history;
+pub mod rates;
@@ -0,0 +1,142 @@
+///
+/// Blitz Money
+///
+/// Frontend/Ui of module for manange the exchange rates
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use backend::rates::Rate;
+use backend::storage::Storage;
+use backend::error::Error;
+use ui::ui::*;
+use i18n::*;
+
+pub struct Rates {}
+
+impl Rates {
+
+    // List of exchange rates, the newest first
+    pub fn list(mut storage: Storage, _params: Vec<String>, is_csv: bool) -> Result<(), Error> {
+
+        let rates = Rate::get_rates(&mut storage)?;
+        let locale = I18n::locale();
+        let mut table = Output::new_table();
+
+        table.set_titles(row![b->I18n::text("rates_date"), b->I18n::text("rates_currency"), b->I18n::text("rates_target"), b->I18n::text("rates_value"), b->"#id"]);
+
+        for rate in rates {
+
+            table.add_row(row![
+                locale.format_date(rate.date),
+                rate.currency,
+                rate.target,
+                locale.format_decimal(rate.value),
+                rate.clone().id(&mut storage)
+            ]);
+        }
+
+        Output::print_table(table, is_csv);
+
+        Ok(())
+    }
+
+    // Create new rate, or replace the rate of same currencies and date
+    pub fn add(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
+
+        if params.len() == 4 {
+            // Shell mode
+
+            let currency = Input::param(I18n::text("rates_currency"), true, params.clone(), 0);
+            let target = Input::param(I18n::text("rates_target"), true, params.clone(), 1);
+            let date = Input::param_date(I18n::text("rates_date"), true, params.clone(), 2)?;
+            let value = Rate::parse_value(&Input::param(I18n::text("rates_value"), true, params.clone(), 3))?;
+
+            Rate::store_rate(&mut storage, Rate {
+                uuid: "".to_string(),
+                currency: currency,
+                target: target,
+                date: date.unwrap(),
+                value: value
+            })?;
+        } else if params.len() > 0 && params[0] == "-i" {
+            // Interactive mode
+
+            let currency = Input::read(I18n::text("rates_currency"), true, None);
+            let target = Input::read(I18n::text("rates_target"), true, Rate::base_currency(&mut storage));
+            let date = Input::read_date(I18n::text("rates_date"), true, None)?;
+            let value = Rate::parse_value(&Input::read(I18n::text("rates_value"), true, None))?;
+
+            Rate::store_rate(&mut storage, Rate {
+                uuid: "".to_string(),
+                currency: currency,
+                target: target,
+                date: date.unwrap(),
+                value: value
+            })?;
+        } else {
+            // Help mode
+            println!("{}", I18n::text("rates_how_to_use_add"));
+        }
+
+        Ok(())
+    }
+
+    // Remove a existing rate
+    pub fn rm(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
+
+        if params.len() == 1 {
+            // Shell mode
+
+            Rate::remove_rate(&mut storage, params[0].trim().to_string())?;
+        } else {
+            // Help mode
+            println!("{}", I18n::text("rates_how_to_use_rm"));
+        }
+
+        Ok(())
+    }
+
+    // Store the rates of a CSV file
+    pub fn import(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
+
+        if params.len() == 1 || params.len() == 2 {
+            // Shell mode
+
+            let delimiter = match params.get(1) {
+                Some(delimiter) if !delimiter.is_empty() => delimiter.clone(),
+                _ => ",".to_string()
+            };
+
+            let imported = Rate::import_csv(&mut storage, params[0].clone(), delimiter)?;
+
+            println!("{}: {}", I18n::text("rates_imported"), imported);
+        } else {
+            // Help mode
+            println!("{}", I18n::text("rates_how_to_use_import"));
+        }
+
+        Ok(())
+    }
+
+    // Show, or change, the currency of the consolidated totals
+    pub fn base(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
+
+        if params.len() == 1 {
+            // Shell mode
+
+            Rate::set_base_currency(&mut storage, params[0].clone())?;
+        } else if params.is_empty() {
+
+            match Rate::base_currency(&mut storage) {
+                Some(base) => println!("{}: {}", I18n::text("rates_base"), base),
+                None => println!("{}", I18n::text("rates_how_to_use_base"))
+            }
+        } else {
+            // Help mode
+            println!("{}", I18n::text("rates_how_to_use_base"));
+        }
+
+        Ok(())
+    }
+}
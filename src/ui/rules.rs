@@ -9,7 +9,9 @@
 use backend::rules::Rule;
 use backend::contacts::Contact;
 use backend::tags::Tag;
+use backend::money::Money;
 use backend::storage::Storage;
+use backend::error::Error;
 use ui::ui::*;
 use i18n::*;
 
@@ -18,9 +20,9 @@ pub struct Rules {}
 impl Rules {
 
     // List of user rules
-    pub fn list(mut storage: Storage, _params: Vec<String>, is_csv: bool) {
+    pub fn list(mut storage: Storage, _params: Vec<String>, is_csv: bool) -> Result<(), Error> {
 
-        let rules = Rule::get_rules(&mut storage);
+        let rules = Rule::get_rules(&mut storage)?;
         let mut table = Output::new_table();
 
         table.set_titles(row![b->I18n::text("rules_term"), b->I18n::text("rules_expected_value"), b->I18n::text("rules_description"), b->I18n::text("rules_contact"), b->I18n::text("rules_tags"), b->"#id"]);
@@ -38,11 +40,11 @@ impl Rules {
                 rule.description,
                 "",
                 tags.join(", "),
-                rule.clone().id()
+                rule.clone().id(&mut storage)
             ]);
 
             if rule.expected_value.is_some() {
-                row.set_cell(cell!(rule.expected_value.unwrap()), 1)
+                row.set_cell(cell!(I18n::locale().format_money(rule.expected_value.unwrap())), 1)
                     .expect(&I18n::text("rules_unable_to_set_expected_value"));
             }
 
@@ -53,19 +55,21 @@ impl Rules {
         }
 
         Output::print_table(table, is_csv);
+
+        Ok(())
     }
 
     // Create new rule
-    pub fn add(mut storage: Storage, params: Vec<String>) {
+    pub fn add(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
 
         if params.len() == 5 {
             // Shell mode
 
             let term = Input::param(I18n::text("rules_term"), true, params.clone(), 0);
 
-            let mut expected_value: Option<f32> = None;
-            let get_expected = Input::param_money(I18n::text("rules_expected_value"), false, params.clone(), 1);
-            if get_expected != 0.0 {
+            let mut expected_value: Option<Money> = None;
+            let get_expected = Input::param_money(I18n::text("rules_expected_value"), false, params.clone(), 1)?;
+            if !get_expected.is_zero() {
                 expected_value = Some(get_expected);
             }
 
@@ -85,8 +89,7 @@ impl Rules {
             if !tags_str.is_empty() {
                 for tag in tags_str.split(",") {
                     tags.push(
-                        Tag::get_tag(&mut storage, tag.to_string())
-                            .expect(&I18n::text("tags_not_found"))
+                        Tag::get_tag(&mut storage, tag.to_string())?
                     );
                 }
             }
@@ -98,22 +101,22 @@ impl Rules {
                 term: term,
                 contact: contact.clone(),
                 tags: tags
-            });
+            })?;
         } else if params.len() > 0 && params[0] == "-i" {
             // Interactive mode
 
             let term = Input::read(I18n::text("rules_term"), true, None);
 
-            let mut expected_value: Option<f32> = None;
-            let get_expected = Input::read_money(I18n::text("rules_expected_value"), false, None, "".to_string());
-            if get_expected != 0.0 {
+            let mut expected_value: Option<Money> = None;
+            let get_expected = Input::read_money(I18n::text("rules_expected_value"), false, None, "".to_string())?;
+            if !get_expected.is_zero() {
                 expected_value = Some(get_expected);
             }
 
             let description = Input::read(I18n::text("rules_description"), true, None);
 
             let mut contacts: Vec<(String, String)> = vec![];
-            for co in Contact::get_contacts(&mut storage) {
+            for co in Contact::get_contacts(&mut storage)? {
                 contacts.push((co.uuid, co.name));
             }
 
@@ -125,7 +128,7 @@ impl Rules {
             };
 
             let mut tags_ops: Vec<(String, String)> = vec![];
-            for tag in Tag::get_tags(&mut storage) {
+            for tag in Tag::get_tags(&mut storage)? {
                 tags_ops.push((tag.uuid, tag.name));
             }
 
@@ -133,9 +136,8 @@ impl Rules {
                 .iter()
                 .map(
                     |tag| Tag::get_tag(&mut storage, tag.to_string())
-                                .expect(&I18n::text("tags_not_found"))
                 )
-                .collect();
+                .collect::<Result<Vec<Tag>, Error>>()?;
 
             Rule::store_rule(&mut storage, Rule {
                 uuid: "".to_string(),
@@ -144,37 +146,38 @@ impl Rules {
                 term: term,
                 contact: contact.clone(),
                 tags: tags
-            });
+            })?;
         } else {
             // Help mode
             println!("{}", I18n::text("rules_how_to_use_add"));
         }
+
+        Ok(())
     }
 
 
     // Update a existing rule
-    pub fn update(mut storage: Storage, params: Vec<String>) {
+    pub fn update(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
 
         if params.len() == 3 {
             // Shell mode
 
-            let mut rule = Rule::get_rule(&mut storage, params[0].trim().to_string())
-                .expect(&I18n::text("rules_not_found"));
+            let mut rule = Rule::get_rule(&mut storage, params[0].trim().to_string())?;
 
             if params[1] == "term" {
                 rule.term = Input::param(I18n::text("rules_term"), true, params.clone(), 2);
             } else if params[1] == "description" {
                 rule.description = Input::param(I18n::text("rules_description"), true, params.clone(), 2);
             } else if params[1] == "expected" {
-                let get_expected = Input::param_money(I18n::text("rules_expected_value"), false, params.clone(), 2);
-                if get_expected != 0.0 {
+                let get_expected = Input::param_money(I18n::text("rules_expected_value"), false, params.clone(), 2)?;
+                if !get_expected.is_zero() {
                     rule.expected_value = Some(get_expected);
                 } else {
                     rule.expected_value = None;
                 }
             } else if params[1] == "contact" {
                 let contact_uuid = Input::param(I18n::text("rules_contact"), true, params.clone(), 2);
-                rule.contact = Some(Contact::get_contact(&mut storage, contact_uuid).unwrap());
+                rule.contact = Some(Contact::get_contact(&mut storage, contact_uuid)?);
             } else if params[1] == "tags" {
                 let tags_str = Input::param(I18n::text("rules_tags"), false, params.clone(), 2);
                 rule.tags = vec![];
@@ -182,30 +185,28 @@ impl Rules {
                 if !tags_str.is_empty() {
                     for tag in tags_str.split(",") {
                         rule.tags.push(
-                            Tag::get_tag(&mut storage, tag.to_string())
-                                .expect(&I18n::text("tags_not_found"))
+                            Tag::get_tag(&mut storage, tag.to_string())?
                         );
                     }
                 }
 
             } else {
-                panic!(I18n::text("field_not_found"));
+                return Err(Error::Invalid("field_not_found"));
             }
 
-            Rule::store_rule(&mut storage, rule);
+            Rule::store_rule(&mut storage, rule)?;
 
         } else if params.len() > 0 && params[0] == "-i" {
             // Interactive mode
 
             let id = Input::read("#id".to_string(), true, None);
 
-            let mut rule = Rule::get_rule(&mut storage, id)
-                .expect(&I18n::text("rules_not_found"));
+            let mut rule = Rule::get_rule(&mut storage, id)?;
 
             rule.term = Input::read(I18n::text("rules_term"), true, Some(rule.term));
 
-            let get_expected = Input::read_money(I18n::text("rules_expected_value"), false, rule.expected_value, "".to_string());
-            if get_expected != 0.0 {
+            let get_expected = Input::read_money(I18n::text("rules_expected_value"), false, rule.expected_value, "".to_string())?;
+            if !get_expected.is_zero() {
                 rule.expected_value = Some(get_expected);
             } else {
                 rule.expected_value = None;
@@ -214,7 +215,7 @@ impl Rules {
             rule.description = Input::read(I18n::text("rules_description"), true, Some(rule.description));
 
             let mut contacts: Vec<(String, String)> = vec![];
-            for co in Contact::get_contacts(&mut storage) {
+            for co in Contact::get_contacts(&mut storage)? {
                 contacts.push((co.uuid, co.name));
             }
 
@@ -225,7 +226,7 @@ impl Rules {
             };
 
             let mut tags_ops: Vec<(String, String)> = vec![];
-            for tag in Tag::get_tags(&mut storage) {
+            for tag in Tag::get_tags(&mut storage)? {
                 tags_ops.push((tag.uuid, tag.name));
             }
 
@@ -238,29 +239,32 @@ impl Rules {
                 .iter()
                 .map(
                     |tag| Tag::get_tag(&mut storage, tag.to_string())
-                                .expect(&I18n::text("tags_not_found"))
                 )
-                .collect();
+                .collect::<Result<Vec<Tag>, Error>>()?;
 
-            Rule::store_rule(&mut storage, rule);
+            Rule::store_rule(&mut storage, rule)?;
 
         } else {
             // Help mode
             println!("{}", I18n::text("rules_how_to_use_update"));
         }
+
+        Ok(())
     }
 
     // Remove a existing rule
-    pub fn rm(mut storage: Storage, params: Vec<String>) {
+    pub fn rm(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
 
         if params.len() == 1 {
             // Shell mode
 
-            Rule::remove_rule(&mut storage, params[0].trim().to_string());
+            Rule::remove_rule(&mut storage, params[0].trim().to_string())?;
 
         } else {
             // Help mode
             println!("{}", I18n::text("rules_how_to_use_rm"));
         }
+
+        Ok(())
     }
 }
@@ -0,0 +1,306 @@
+///
+/// Blitz Money
+///
+/// Frontend/Ui of module for manange the storage file
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use std::path::Path;
+use backend::storage::Storage;
+use backend::journal::Journal;
+use backend::crypto::Crypto;
+use backend::check::{ Check, Issue, IssueKind, Repair };
+use backend::backups::Backups;
+use backend::engine::EngineKind;
+use backend::error::Error;
+use ui::ui::*;
+use i18n::*;
+
+pub struct Storages {}
+
+impl Storages {
+
+    // Revert the changes of the last command
+    pub fn undo(mut storage: Storage, _params: Vec<String>) -> Result<(), Error> {
+
+        match Journal::undo(&mut storage)? {
+            Some(command) => println!("{}: bmoney {}", I18n::text("storage_undone"), command),
+            None => println!("{}", I18n::text("storage_nothing_to_undo"))
+        }
+
+        Ok(())
+    }
+
+    // Apply again the changes of the last undone command
+    pub fn redo(mut storage: Storage, _params: Vec<String>) -> Result<(), Error> {
+
+        match Journal::redo(&mut storage)? {
+            Some(command) => println!("{}: bmoney {}", I18n::text("storage_redone"), command),
+            None => println!("{}", I18n::text("storage_nothing_to_redo"))
+        }
+
+        Ok(())
+    }
+
+    // Encrypt the storage file with a passphrase
+    pub fn encrypt(mut storage: Storage, _params: Vec<String>) -> Result<(), Error> {
+
+        if storage.is_encrypted() {
+            return Err(Error::Invalid("error_already_encrypted"));
+        }
+
+        let passphrase = Crypto::passphrase(true)?;
+
+        storage.encrypt(passphrase)?;
+
+        println!("{}", I18n::text("storage_encrypted"));
+
+        Ok(())
+    }
+
+    // Decrypt the storage file, the passphrase is asked when the file is read
+    pub fn decrypt(mut storage: Storage, _params: Vec<String>) -> Result<(), Error> {
+
+        storage.decrypt()?;
+
+        println!("{}", I18n::text("storage_decrypted"));
+
+        Ok(())
+    }
+
+    // Label of the kind of issue
+    fn issue_kind(issue: &Issue) -> String {
+        match issue.kind {
+            IssueKind::InvalidRow => I18n::text("storage_check_invalid_row"),
+            IssueKind::DuplicatedUuid => I18n::text("storage_check_duplicated_uuid"),
+            IssueKind::BrokenReference => I18n::text("storage_check_broken_reference"),
+            IssueKind::AsymmetricTransfer => I18n::text("storage_check_asymmetric_transfer")
+        }
+    }
+
+    // Label of the repair of issue
+    fn issue_repair(issue: &Issue) -> String {
+        match issue.repair {
+            Some(Repair::RemoveRow) => I18n::text("storage_check_remove_row"),
+            Some(Repair::ReplaceRow(_)) => I18n::text("storage_check_replace_row"),
+            None => I18n::text("storage_check_no_repair")
+        }
+    }
+
+    // Print the table of issues
+    fn print_issues(issues: Vec<Issue>) {
+
+        let mut table = Output::new_table();
+
+        table.set_titles(row![b->I18n::text("storage_check_problem"), b->I18n::text("storage_check_section"), b->"#uuid", b->I18n::text("storage_check_detail"), b->I18n::text("storage_check_repair")]);
+
+        for issue in issues {
+
+            table.add_row(row![
+                Storages::issue_kind(&issue),
+                issue.section,
+                issue.line.chars().take(36).collect::<String>(),
+                issue.detail,
+                Storages::issue_repair(&issue)
+            ]);
+        }
+
+        Output::print_table(table, false);
+    }
+
+    // Check the integrity of storage and repair the problems
+    pub fn check(mut storage: Storage, mut params: Vec<String>) -> Result<(), Error> {
+
+        let auto = Input::extract_param(&mut params, "--auto".to_string());
+
+        let issues = Check::run(&mut storage);
+
+        if issues.is_empty() {
+            println!("{}", I18n::text("storage_check_ok"));
+            return Ok(());
+        }
+
+        Storages::print_issues(issues);
+
+        if auto {
+            let repaired = Check::repair_all(&mut storage);
+
+            println!("{}: {}", I18n::text("storage_check_repaired"), repaired);
+        } else {
+
+            let mut skipped: Vec<(String, String)> = vec![];
+
+            // A repair can change other issues, so the storage is checked again after each one
+            loop {
+
+                let next = Check::run(&mut storage)
+                    .into_iter()
+                    .find(|issue| issue.repair.is_some() && !skipped.contains(&(issue.line.clone(), issue.detail.clone())));
+
+                let issue = match next {
+                    Some(issue) => issue,
+                    None => break
+                };
+
+                println!("{} {}, {} => {}", Storages::issue_kind(&issue), issue.line.chars().take(36).collect::<String>(), issue.detail, Storages::issue_repair(&issue));
+
+                if Input::read(I18n::text("storage_check_apply"), false, None) != "y" {
+                    skipped.push((issue.line.clone(), issue.detail.clone()));
+                    continue;
+                }
+
+                Check::repair(&mut storage, &issue)?;
+            }
+        }
+
+        let left = Check::run(&mut storage);
+
+        if left.is_empty() {
+            println!("{}", I18n::text("storage_check_ok"));
+        } else {
+            println!("{}:", I18n::text("storage_check_left"));
+            Storages::print_issues(left);
+        }
+
+        Ok(())
+    }
+
+    // List and restore the backups of storage file
+    pub fn backups(mut storage: Storage, params: Vec<String>, is_csv: bool) -> Result<(), Error> {
+
+        if params.len() == 1 && params[0] == "list" {
+
+            let mut table = Output::new_table();
+
+            table.set_titles(row![b->I18n::text("storage_backups_name"), b->I18n::text("storage_backups_created_at"), b->I18n::text("storage_backups_size")]);
+
+            for backup in Backups::list(&storage) {
+                table.add_row(row![
+                    backup.name,
+                    backup.created_at.format(&I18n::locale().datetime_format()),
+                    r->backup.size
+                ]);
+            }
+
+            Output::print_table(table, is_csv);
+        } else if params.len() == 2 && params[0] == "restore" {
+
+            let backup = Backups::restore(&mut storage, params[1].clone())?;
+
+            println!("{}: {}", I18n::text("storage_backups_restored"), backup.name);
+        } else {
+            // Help mode
+            println!("{}", I18n::text("storage_how_to_use_backups"));
+        }
+
+        Ok(())
+    }
+
+    // Copy the storage to a new file, that can use other engine
+    pub fn convert(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
+
+        if params.len() < 1 || params.len() > 2 {
+            // Help mode
+            println!("{}", I18n::text("storage_how_to_use_convert"));
+            return Ok(());
+        }
+
+        let path = params[0].trim().to_string();
+
+        let kind = match params.get(1) {
+            Some(name) => EngineKind::from_name(name)?,
+            None => EngineKind::of(&path)
+        };
+
+        if Path::new(&path).exists() {
+            return Err(Error::Invalid("error_convert_target_exists"));
+        }
+
+        // The other engine can't keep the file encrypted
+        if storage.is_encrypted() {
+            return Err(Error::Invalid("error_convert_encrypted"));
+        }
+
+        let mut target = Storage::with_engine(path.clone(), kind);
+
+        storage.copy_to(&mut target)?;
+
+        Journal::copy_to(&storage, &target)?;
+
+        println!("{} {}: {}", I18n::text("storage_converted"), kind.name(), path);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use uuid::Uuid;
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+    use assert_cmd::prelude::*;
+
+    #[test]
+    fn encrypt_leaves_no_plain_copy() {
+
+        let path_str = "/tmp/bmoney-ui-".to_owned() + &Uuid::new_v4().to_string();
+
+        let run = |args: Vec<&str>| {
+            Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap()
+                .args(args)
+                .arg("--storage-file=".to_owned() + &path_str)
+                .env("BMONEY_PASSPHRASE", "my secret")
+                .assert()
+                .success();
+        };
+
+        // Some changes, for the .bak, the backups and the journal
+        run(vec!["tags", "add", "PLAIN!"]);
+        run(vec!["tags", "add", "OTHER PLAIN!"]);
+        run(vec!["storage", "encrypt"]);
+
+        let dir = Path::new(&path_str).parent().unwrap();
+        let name = Path::new(&path_str).file_name().unwrap().to_str().unwrap().to_string();
+
+        let mut files: Vec<_> = fs::read_dir(dir).unwrap()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.file_name().unwrap().to_str().unwrap().starts_with(&name))
+            .collect();
+
+        files.extend(fs::read_dir(format!("{}.backups", path_str)).unwrap()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path()));
+
+        assert!(files.iter().any(|path| path.to_str().unwrap().ends_with(".bak")));
+        assert!(!files.iter().any(|path| path.to_str().unwrap().ends_with(".tmp")));
+
+        for path in files.iter().filter(|path| path.is_file()) {
+            let contents = String::from_utf8_lossy(&fs::read(path).unwrap()).to_string();
+
+            assert!(!contents.contains("PLAIN!"), "plain copy at {:?}", path);
+        }
+    }
+
+    #[test]
+    fn encrypt_empty_passphrase() {
+
+        let path_str = "/tmp/bmoney-ui-".to_owned() + &Uuid::new_v4().to_string();
+
+        let mut main = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+        main.arg("storage")
+            .arg("encrypt")
+            .arg("--storage-file=".to_owned() + &path_str)
+            .env("BMONEY_PASSPHRASE", "");
+
+        let output = main.output().unwrap();
+
+        assert_eq!(output.status.code(), Some(5));
+        assert!(String::from_utf8_lossy(&output.stderr).ends_with("The passphrase can't be empty\n"));
+        assert!(!fs::read_to_string(&path_str).unwrap().starts_with("::encrypted::"));
+    }
+}
@@ -8,6 +8,9 @@
 
 use backend::tags::Tag;
 use backend::storage::Storage;
+use backend::error::Error;
+use backend::references::*;
+use backend::duplicates::Duplicates;
 use ui::ui::*;
 use i18n::*;
 
@@ -16,9 +19,9 @@ pub struct Tags {}
 impl Tags {
 
     // List of user tags
-    pub fn list(mut storage: Storage, _params: Vec<String>, is_csv: bool) {
+    pub fn list(mut storage: Storage, _params: Vec<String>, is_csv: bool) -> Result<(), Error> {
 
-        let tags = Tag::get_tags(&mut storage);
+        let tags = Tag::get_tags(&mut storage)?;
         let mut table = Output::new_table();
 
         table.set_titles(row![b->I18n::text("tags_name"), b->"#id"]);
@@ -27,15 +30,17 @@ impl Tags {
 
             table.add_row(row![
                 tag.name,
-                tag.clone().id()
+                tag.clone().id(&mut storage)
             ]);
         }
 
         Output::print_table(table, is_csv);
+
+        Ok(())
     }
 
     // Create new tag
-    pub fn add(mut storage: Storage, params: Vec<String>) {
+    pub fn add(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
 
         if params.len() == 1 && params[0] != "-i"{
             // Shell mode
@@ -45,7 +50,7 @@ impl Tags {
             Tag::store_tag(&mut storage, Tag {
                 uuid: "".to_string(),
                 name: name,
-            });
+            })?;
         } else if params.len() > 0 && params[0] == "-i" {
             // Interactive mode
 
@@ -54,58 +59,136 @@ impl Tags {
             Tag::store_tag(&mut storage, Tag {
                 uuid: "".to_string(),
                 name: name,
-            });
+            })?;
         } else {
             // Help mode
             println!("{}", I18n::text("tags_how_to_use_add"));
         }
+
+        Ok(())
     }
 
     // Update a existing tag
-    pub fn update(mut storage: Storage, params: Vec<String>) {
+    pub fn update(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
 
         if params.len() == 3 {
             // Shell mode
 
-            let mut tag = Tag::get_tag(&mut storage, params[0].trim().to_string())
-                .expect(&I18n::text("tags_not_found"));
+            let mut tag = Tag::get_tag(&mut storage, params[0].trim().to_string())?;
 
             if params[1] == "name" {
                 tag.name = Input::param(I18n::text("tags_name"), true, params.clone(), 2);
             } else {
-                panic!(I18n::text("field_not_found"));
+                return Err(Error::Invalid("field_not_found"));
             }
 
-            Tag::store_tag(&mut storage, tag);
+            Tag::store_tag(&mut storage, tag)?;
 
         } else if params.len() > 0 && params[0] == "-i" {
             // Interactive mode
 
             let id = Input::read("#id".to_string(), true, None);
 
-            let mut tag = Tag::get_tag(&mut storage, id)
-                .expect(&I18n::text("tags_not_found"));
+            let mut tag = Tag::get_tag(&mut storage, id)?;
 
             tag.name = Input::read(I18n::text("tags_name"), true, Some(tag.name));
 
-            Tag::store_tag(&mut storage, tag);
+            Tag::store_tag(&mut storage, tag)?;
         } else {
             // Help mode
             println!("{}", I18n::text("tags_how_to_use_update"));
         }
+
+        Ok(())
     }
 
     // Remove a existing tag
-    pub fn rm(mut storage: Storage, params: Vec<String>) {
+    pub fn rm(mut storage: Storage, mut params: Vec<String>) -> Result<(), Error> {
 
-        if params.len() == 1 {
+        let on_remove = Input::extract_on_remove(&mut params);
+
+        if params.len() == 1 && params[0] != "-i" {
             // Shell mode
 
-            Tag::remove_tag(&mut storage, params[0].trim().to_string());
+            Tag::remove_tag(&mut storage, params[0].trim().to_string(), on_remove)?;
+        } else if params.len() > 0 && params[0] == "-i" {
+            // Interactive mode
+
+            let id = Input::read("#id".to_string(), true, None);
+
+            let tag = Tag::get_tag(&mut storage, id)?;
+
+            let dependents = References::dependents(&mut storage, "tags", &tag.uuid)?;
+            let mut on_remove = OnRemove::Refuse;
+
+            if dependents.len() > 0 {
+
+                let mut tags: Vec<(String, String)> = vec![];
+                for other in Tag::get_tags(&mut storage)? {
+                    if other.uuid != tag.uuid {
+                        tags.push((other.uuid, other.name));
+                    }
+                }
+
+                on_remove = Input::read_on_remove(References::summary(&dependents), tags);
+            }
+
+            Tag::remove_tag(&mut storage, tag.uuid, on_remove)?;
         } else {
             // Help mode
             println!("{}", I18n::text("tags_how_to_use_rm"));
         }
+
+        Ok(())
+    }
+
+    // Merge duplicated tags into one
+    pub fn merge(mut storage: Storage, mut params: Vec<String>, is_csv: bool) -> Result<(), Error> {
+
+        if Input::extract_param(&mut params, "--suggest".to_string()) {
+            // Suggest mode
+
+            let mut tags: Vec<(String, String)> = vec![];
+            for tag in Tag::get_tags(&mut storage)? {
+                tags.push((tag.uuid, tag.name));
+            }
+
+            let suggestions = Duplicates::suggest(tags);
+
+            if suggestions.is_empty() {
+                println!("{}", I18n::text("merge_no_suggestions"));
+                return Ok(());
+            }
+
+            let mut table = Output::new_table();
+
+            table.set_titles(row![b->I18n::text("merge_group"), b->I18n::text("tags_name"), b->"#id"]);
+
+            for (i, suggestion) in suggestions.into_iter().enumerate() {
+                for (uuid, name) in suggestion {
+                    table.add_row(row![
+                        i + 1,
+                        name,
+                        storage.short_id("tags", &uuid)
+                    ]);
+                }
+            }
+
+            Output::print_table(table, is_csv);
+        } else if params.len() > 1 {
+            // Shell mode
+
+            let drop: Vec<String> = params[1..].iter()
+                .map(|id| id.trim().to_string())
+                .collect();
+
+            Tag::merge_tags(&mut storage, params[0].trim().to_string(), drop)?;
+        } else {
+            // Help mode
+            println!("{}", I18n::text("tags_how_to_use_merge"));
+        }
+
+        Ok(())
     }
 }
 
@@ -114,24 +197,22 @@ mod tests {
 
     use super::*;
     use uuid::Uuid;
+    use backend::rules::Rule;
     use std::process::Command;
-    use std::collections::HashMap;
-    use std::cell::RefCell;
     use assert_cmd::prelude::*;
-    use backend::storage::Data;
 
     fn populate() -> (String, Vec<String>) {
 
         let path = "/tmp/bmoney-ui-".to_owned() + &Uuid::new_v4().to_string();
 
-        let mut st = Storage { path_str: path.clone(), file: None, lines: Vec::new(), index: RefCell::new(HashMap::new()) };
+        let mut st = Storage::new(path.clone());
 
         let mut uuids: Vec<String> = vec![];
 
-        uuids.push(Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 1".to_string() }));
-        uuids.push(Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 2".to_string() }));
-        uuids.push(Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 3".to_string() }));
-        uuids.push(Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 4".to_string() }));
+        uuids.push(Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 1".to_string() }).unwrap());
+        uuids.push(Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 2".to_string() }).unwrap());
+        uuids.push(Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 3".to_string() }).unwrap());
+        uuids.push(Tag::store_tag(&mut st, Tag { uuid: "".to_string(), name: "tag 4".to_string() }).unwrap());
 
         (path, uuids)
     }
@@ -157,6 +238,14 @@ mod tests {
 
         let (path_str, uuids) = populate();
 
+        let ids: Vec<String> = {
+            let mut st = Storage::new(path_str.clone());
+
+            uuids.iter()
+                .map(|uuid| st.short_id("tags", uuid))
+                .collect()
+        };
+
         let mut main = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
 
         main.arg("tags")
@@ -168,10 +257,10 @@ mod tests {
         stdout.push_str("| Tag name | #id     |\n");
         stdout.push_str("+----------+---------+\n");
 
-        stdout.push_str(&format!("| tag 4    | {} |\n", Data::uuid_to_id(uuids[3].clone())));
-        stdout.push_str(&format!("| tag 3    | {} |\n", Data::uuid_to_id(uuids[2].clone())));
-        stdout.push_str(&format!("| tag 2    | {} |\n", Data::uuid_to_id(uuids[1].clone())));
-        stdout.push_str(&format!("| tag 1    | {} |\n", Data::uuid_to_id(uuids[0].clone())));
+        stdout.push_str(&format!("| tag 4    | {} |\n", ids[3]));
+        stdout.push_str(&format!("| tag 3    | {} |\n", ids[2]));
+        stdout.push_str(&format!("| tag 2    | {} |\n", ids[1]));
+        stdout.push_str(&format!("| tag 1    | {} |\n", ids[0]));
         stdout.push_str("+----------+---------+\n");
 
         main.assert()
@@ -179,4 +268,70 @@ mod tests {
             .stdout(stdout.to_owned());
     }
 
+    #[test]
+    fn tags_update_not_found() {
+
+        let (path_str, _uuids) = populate();
+
+        let mut main = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+        main.arg("tags")
+            .arg("update")
+            .arg("--storage-file=".to_owned() + &path_str)
+            .arg("nope")
+            .arg("name")
+            .arg("new name");
+
+        let output = main.output().unwrap();
+
+        assert_eq!(output.status.code(), Some(2));
+        assert!(String::from_utf8_lossy(&output.stderr).ends_with("Not found: tags nope\n"));
+    }
+
+    #[test]
+    fn tags_rm_dependents() {
+
+        let (path_str, uuids) = populate();
+
+        {
+            let mut st = Storage::new(path_str.clone());
+
+            let tag = Tag::get_tag(&mut st, uuids[0].clone()).unwrap();
+
+            Rule::store_rule(&mut st, Rule { term: "market".to_string(), description: "Market".to_string(), tags: vec![tag], ..Default::default() }).unwrap();
+        }
+
+        let rm = |options: Vec<&str>| {
+
+            let mut main = Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+
+            main.arg("tags")
+                .arg("rm")
+                .arg("--storage-file=".to_owned() + &path_str)
+                .arg(uuids[0].clone())
+                .args(options);
+
+            main.output().unwrap()
+        };
+
+        let output = rm(vec![]);
+
+        assert_eq!(output.status.code(), Some(5));
+        assert!(String::from_utf8_lossy(&output.stderr).ends_with("rules: 1\n"));
+
+        let output = rm(vec!["--reassign-to=nope"]);
+
+        assert_eq!(output.status.code(), Some(2));
+
+        let output = rm(vec!["--reassign-to=".to_owned() + &uuids[1]].iter().map(|option| option.as_str()).collect());
+
+        assert!(output.status.success());
+
+        let mut st = Storage::new(path_str.clone());
+
+        let rule = Rule::get_rules(&mut st).unwrap().remove(0);
+
+        assert_eq!(rule.tags[0].uuid, uuids[1]);
+        assert!(Tag::get_tag(&mut st, uuids[0].clone()).is_err());
+    }
 }
@@ -8,17 +8,19 @@
 
 use chrono::{Local, prelude::Datelike, NaiveDate, Duration};
 
-use backend::transactions::{Transaction, StatusFilter, T_PREVIOUS_BALANCE, T_PREVIOUS_EXPECTED_BALANCE, T_EXPECTED_BALANCE, T_EXPENSES_PAYABLE, T_INCOMES_TORECEIVE};
+use backend::transactions::{Transaction, Split, StatusFilter, T_PREVIOUS_BALANCE, T_PREVIOUS_EXPECTED_BALANCE, T_EXPECTED_BALANCE, T_EXPENSES_PAYABLE, T_INCOMES_TORECEIVE};
 use backend::accounts::Account;
 use backend::contacts::Contact;
 use backend::tags::Tag;
-use backend::storage::{Storage, Data};
+use backend::storage::Storage;
+use backend::error::Error;
 use backend::import;
 use backend::import_ofx::ImportOfx;
 use backend::import_csv::ImportCsv;
 use backend::calendar::Calendar;
 use backend::rules::Rule;
 use backend::forecasts::Forecast;
+use backend::money::Money;
 use ui::ui::*;
 use i18n::*;
 
@@ -27,11 +29,11 @@ pub struct Transactions {}
 impl Transactions {
 
     // List of user transactions
-    pub fn list(mut storage: Storage, mut params: Vec<String>, is_csv: bool) {
+    pub fn list(mut storage: Storage, mut params: Vec<String>, is_csv: bool) -> Result<(), Error> {
 
         if params.len() >= 1 {
 
-            let account = Account::get_account(&mut storage, params[0].trim().to_string()).unwrap();
+            let account = Account::get_account(&mut storage, params[0].trim().to_string())?;
 
             let show_all = Input::extract_param(&mut params, "--show-all".to_string());
 
@@ -53,20 +55,19 @@ impl Transactions {
             let mut tag: Option<Tag> = None;
 
             if tag_param.is_some() {
-                let filter_tag = Tag::get_tag(&mut storage, tag_param.unwrap())
-                                        .expect(&I18n::text("tags_not_found"));
+                let filter_tag = Tag::get_tag(&mut storage, tag_param.unwrap())?;
                 tag = Some(filter_tag);
             }
 
-            let (from, to) = Input::param_date_period(params, 1, 2);
+            let (from, to) = Input::param_date_period(params, 1, 2)?;
 
-            let (mut transactions, mut totals) = Transaction::get_transactions(&mut storage, account.clone(), from, to, status, tag, show_mergeds);
+            let (mut transactions, mut totals) = Transaction::get_transactions(&mut storage, account.clone(), from, to, status, tag.clone(), show_mergeds)?;
 
             if show_forecasts {
-                for tr in Forecast::remaining_transactions(&mut storage, transactions.clone(), to) {
+                for tr in Forecast::remaining_transactions(&mut storage, transactions.clone(), to)? {
                     transactions.push(tr.clone());
                     totals[T_EXPECTED_BALANCE].value += tr.value;
-                    if tr.value >= 0.0 {
+                    if !tr.value.is_negative() {
                         totals[T_INCOMES_TORECEIVE].value += tr.value;
                     } else {
                         totals[T_EXPENSES_PAYABLE].value += tr.value;
@@ -77,6 +78,13 @@ impl Transactions {
             let mut balance = totals[T_PREVIOUS_BALANCE].value.clone(); // Previous Balance
             let mut expected_balance = totals[T_PREVIOUS_EXPECTED_BALANCE].value.clone(); // Previous Expected Balance
 
+            // When the account starts on the opening balance date inside of
+            // the period, the opening balance is a row of the list on his date
+            let open_balance_date = account.open_balance_date.unwrap();
+            let mut opened = account.is_opened_on(from.pred());
+
+            let locale = I18n::locale();
+
             let mut table = Output::new_table();
 
             let mut header = row![b->I18n::text("transactions_deadline"), b->I18n::text("transactions_description"), b->I18n::text("transactions_type"), b->I18n::text("transactions_value"), b->I18n::text("transactions_excpected_balance"), b->I18n::text("transactions_paidin"), b->I18n::text("transactions_balance"), b->I18n::text("transactions_contact"), b->I18n::text("transactions_tags"), b->"#id", b->I18n::text("transactions_byofx")];
@@ -89,6 +97,7 @@ impl Transactions {
                 header.add_cell(cell!(b->I18n::text("transactions_repetitions_previous")));
                 header.add_cell(cell!(b->I18n::text("transactions_ofx_fitid")));
                 header.add_cell(cell!(b->I18n::text("transactions_ofx_memo")));
+                header.add_cell(cell!(b->I18n::text("transactions_reconciled_in")));
             }
 
             table.set_titles(header);
@@ -102,11 +111,27 @@ impl Transactions {
                     by_ofx = "Yes".to_string();
                 }
 
-                if transaction.merged_in.is_empty() {
+                // With the tag filter only the value of tag is on the balances
+                let value = match tag.as_ref() {
+                    Some(tag) => transaction.value_of_tag(&tag.uuid).unwrap_or(transaction.value),
+                    None => transaction.value
+                };
+
+                if !opened && transaction.deadline.unwrap() >= open_balance_date {
+                    balance += account.open_balance;
+                    expected_balance += account.open_balance;
+                    opened = true;
+
+                    Transactions::add_opening_row(&mut table, &account, balance, expected_balance);
+                }
+
+                let before_opening = transaction.is_before_opening();
+
+                if transaction.merged_in.is_empty() && !before_opening {
                     if transaction.paid_in.is_some() {
-                        balance += transaction.value;
+                        balance += value;
                     }
-                    expected_balance += transaction.value;
+                    expected_balance += value;
                 }
 
                 if !show_all && transaction.description.chars().count() > 50 {
@@ -122,7 +147,7 @@ impl Transactions {
                 }
 
                 let mut row = table.add_row(row![
-                    transaction.deadline.unwrap(),
+                    locale.format_date(transaction.deadline.unwrap()),
                     transaction.description,
                     "C",
                     Fg->transaction.value_formmated(),
@@ -131,33 +156,50 @@ impl Transactions {
                     Fg->account.format_value(balance),
                     "",
                     tags.join(", "),
-                    transaction.clone().id(),
+                    transaction.clone().id(&mut storage),
                     by_ofx
                 ]);
 
-                if transaction.value < 0.0 {
+                if transaction.value.is_negative() {
                     row.set_cell(cell!("D"), 2)
                         .expect(&I18n::text("transactions_unable_to_set_d"));
                     row.set_cell(cell!(Fr->transaction.value_formmated()), 3)
                         .expect(&I18n::text("transactions_unable_to_set_value"));
                 }
 
-                if expected_balance < 0.0 {
+                if expected_balance.is_negative() {
                     row.set_cell(cell!(Fr->account.format_value(expected_balance)), 4)
                         .expect(&I18n::text("transactions_unable_to_set_expected_balance"));
                 }
 
-                if balance < 0.0 {
+                if balance.is_negative() {
                     row.set_cell(cell!(Fr->account.format_value(balance)), 6)
                         .expect(&I18n::text("transactions_unable_to_set_balance"));
                 }
 
+                // Already on the opening balance, so out of the balances
+                if before_opening {
+                    row.set_cell(cell!(I18n::text("transactions_before_opening")), 4)
+                        .expect(&I18n::text("transactions_unable_to_set_expected_balance"));
+                    row.set_cell(cell!(I18n::text("transactions_before_opening")), 6)
+                        .expect(&I18n::text("transactions_unable_to_set_balance"));
+                }
+
                 if transaction.transfer.is_some() {
                     row.set_cell(cell!("T"), 2)
                         .expect(&I18n::text("transactions_unable_to_set_t"));
 
-                    // In transcations we show the destination account on place of contact
-                    row.set_cell(cell!(transaction.transfer.unwrap().account.clone().unwrap().name + &I18n::text("transactions_caccount")), 7)
+                    let destination = transaction.transfer.clone().unwrap().account.unwrap();
+
+                    // In transcations we show the destination account on place of contact,
+                    // with the rate of transfers between currencies
+                    let mut name = destination.name.clone() + &I18n::text("transactions_caccount");
+
+                    if let Some(rate) = transaction.transfer_rate {
+                        name = format!("{} 1 {} = {} {}", name, account.currency, locale.format_decimal(rate), destination.currency);
+                    }
+
+                    row.set_cell(cell!(name), 7)
                         .expect(&I18n::text("transactions_unable_to_set_account"));
                 } else {
                     let mut contact = transaction.contact.clone().unwrap().name;
@@ -179,25 +221,64 @@ impl Transactions {
                     }
 
                     if !transaction.merged_in.is_empty() {
-                        row.add_cell(cell!(Data::uuid_to_id(transaction.merged_in)));
+                        row.add_cell(cell!(storage.short_id("transactions", &transaction.merged_in)));
                     } else {
                         row.add_cell(cell!(""));
                     }
 
                     if !transaction.previous_repetition.is_empty() {
-                        row.add_cell(cell!(Data::uuid_to_id(transaction.previous_repetition)));
+                        row.add_cell(cell!(storage.short_id("transactions", &transaction.previous_repetition)));
                     } else {
                         row.add_cell(cell!(""));
                     }
 
                     row.add_cell(cell!(transaction.ofx_fitid));
                     row.add_cell(cell!(transaction.ofx_memo));
+
+                    match transaction.reconciled_in {
+                        Some(date) => row.add_cell(cell!(locale.format_date(date))),
+                        None => row.add_cell(cell!(""))
+                    }
+                }
+
+                // The parts of value, below the transaction
+                for split in transaction.splits {
+
+                    let tags: Vec<String> = split.tags
+                        .iter()
+                        .map(|tag| tag.name.clone())
+                        .collect();
+
+                    let value = match split.value.is_negative() {
+                        true => cell!(Fr->account.format_value(split.value)),
+                        false => cell!(Fg->account.format_value(split.value))
+                    };
+
+                    table.add_row(prettytable::Row::new(vec![
+                        cell!(""),
+                        cell!(format!("  {}", split.memo)),
+                        cell!(""),
+                        value,
+                        cell!(""),
+                        cell!(""),
+                        cell!(""),
+                        cell!(""),
+                        cell!(tags.join(", "))
+                    ]));
                 }
             }
 
+            // Without transactions after the opening balance date
+            if !opened && account.is_opened_on(to) {
+                balance += account.open_balance;
+                expected_balance += account.open_balance;
+
+                Transactions::add_opening_row(&mut table, &account, balance, expected_balance);
+            }
+
             table.add_row(prettytable::Row::empty());
 
-            for total in totals {
+            for total in Transaction::totals_of_type(&account, totals, from, to) {
 
                 let mut row = table.add_row(row![
                     "",
@@ -206,7 +287,7 @@ impl Transactions {
                     Fg->account.format_value(total.value),
                 ]);
 
-                if total.value < 0.0 {
+                if total.value.is_negative() {
                     row.set_cell(cell!(Fr->account.format_value(total.value)), 3)
                         .expect(&I18n::text("transactions_unable_to_set_total"));
                 }
@@ -218,16 +299,53 @@ impl Transactions {
             // Help mode
             println!("{}", I18n::text("transactions_how_to_use_list"));
         }
+
+        Ok(())
+    }
+
+    // Row of the opening balance, with the balances after it
+    fn add_opening_row(table: &mut prettytable::Table, account: &Account, balance: Money, expected_balance: Money) {
+
+        let mut row = table.add_row(row![
+            I18n::locale().format_date(account.open_balance_date.unwrap()),
+            I18n::text("accounts_ob"),
+            "",
+            Fg->account.open_balance_formmated(),
+            Fg->account.format_value(expected_balance),
+            "",
+            Fg->account.format_value(balance)
+        ]);
+
+        if account.open_balance.is_negative() {
+            row.set_cell(cell!(Fr->account.open_balance_formmated()), 3)
+                .expect(&I18n::text("transactions_unable_to_set_value"));
+        }
+
+        if expected_balance.is_negative() {
+            row.set_cell(cell!(Fr->account.format_value(expected_balance)), 4)
+                .expect(&I18n::text("transactions_unable_to_set_expected_balance"));
+        }
+
+        if balance.is_negative() {
+            row.set_cell(cell!(Fr->account.format_value(balance)), 6)
+                .expect(&I18n::text("transactions_unable_to_set_balance"));
+        }
     }
 
     // Create new transaction
-    pub fn add(mut storage: Storage, params: Vec<String>) {
+    pub fn add(mut storage: Storage, mut params: Vec<String>) -> Result<(), Error> {
+
+        // Value on the destination account of a transfer between currencies
+        let mut transfer_value = match Input::extract_named_param(&mut params, "--transfer-value=".to_string()) {
+            Some(value) => Some(I18n::locale().parse_money(&value)?),
+            None => None
+        };
 
         if params.len() >= 5 || (params.len() == 1 && params[0] == "-i") {
 
             let description: String;
             let account: Option<Account>;
-            let value: f32;
+            let value: Money;
             let contact_uuid;
             let deadline: Option<NaiveDate>;
             let paid_in: Option<NaiveDate>;
@@ -243,31 +361,30 @@ impl Transactions {
                 description = Input::param(I18n::text("transactions_description"), true, params.clone(), 0);
 
                 let account_uuid = Input::param(I18n::text("transactions_account"), true, params.clone(), 2);
-                account = Some(Account::get_account(&mut storage, account_uuid).unwrap());
+                account = Some(Account::get_account(&mut storage, account_uuid)?);
 
-                value = Input::param_money(I18n::text("transactions_lvalue"), true, params.clone(), 1);
+                value = Input::param_money(I18n::text("transactions_lvalue"), true, params.clone(), 1)?;
 
                 contact_uuid = Input::param(I18n::text("transactions_contact"), true, params.clone(), 3);
 
-                deadline = Input::param_date(I18n::text("transactions_deadline"), true, params.clone(), 4);
-                paid_in = Input::param_date(I18n::text("transactions_paidin"), false, params.clone(), 5);
+                deadline = Input::param_date(I18n::text("transactions_deadline"), true, params.clone(), 4)?;
+                paid_in = Input::param_date(I18n::text("transactions_paidin"), false, params.clone(), 5)?;
 
                 let tags_str = Input::param(I18n::text("transactions_tags"), false, params.clone(), 6);
 
                 if !tags_str.is_empty() {
                     for tag in tags_str.split(",") {
                         tags.push(
-                            Tag::get_tag(&mut storage, tag.to_string())
-                                .expect(&I18n::text("tags_not_found"))
+                            Tag::get_tag(&mut storage, tag.to_string())?
                         );
                     }
                 }
 
                 observations = Input::param(I18n::text("transactions_observations"), false, params.clone(), 7);
 
-                repetitions = Input::param_int(I18n::text("transactions_repetitions"), false, params.clone(), 8);
+                repetitions = Input::param_int(I18n::text("transactions_repetitions"), false, params.clone(), 8)?;
                 repetitions_specific_day = Input::param(I18n::text("transactions_repetitions_specific_day"), false, params.clone(), 9) == "y";
-                repetitions_interval = Input::param_int(I18n::text("transactions_repetitions_interval"), false, params.clone(), 10);
+                repetitions_interval = Input::param_int(I18n::text("transactions_repetitions_interval"), false, params.clone(), 10)?;
 
             } else {
                 // Interactive mode
@@ -275,17 +392,17 @@ impl Transactions {
                 description = Input::read(I18n::text("transactions_description"), true, None);
 
                 let mut accounts: Vec<(String, String)> = vec![];
-                for ac in Account::get_accounts(&mut storage) {
+                for ac in Account::get_active_accounts(&mut storage, false)? {
                     accounts.push((ac.uuid, ac.name));
                 }
 
                 let account_uuid = Input::read_option(I18n::text("transactions_account"), true, None, accounts.clone());
-                account = Some(Account::get_account(&mut storage, account_uuid).unwrap());
+                account = Some(Account::get_account(&mut storage, account_uuid)?);
 
-                value = Input::read_money(I18n::text("transactions_lvalue"), true, None, account.clone().unwrap().currency);
+                value = Input::read_money(I18n::text("transactions_lvalue"), true, None, account.clone().unwrap().currency)?;
 
                 let mut contacts: Vec<(String, String)> = vec![];
-                for co in Contact::get_contacts(&mut storage) {
+                for co in Contact::get_contacts(&mut storage)? {
                     contacts.push((co.uuid, co.name));
                 }
                 // For transfers
@@ -295,11 +412,17 @@ impl Transactions {
 
                 contact_uuid = Input::read_option(I18n::text("transactions_contact_or_other_account"), true, None, contacts);
 
-                deadline = Input::read_date(I18n::text("transactions_deadline"), true, None);
-                paid_in = Input::read_date(I18n::text("transactions_paidin"), false, None);
+                if let Ok(destination) = Account::get_account(&mut storage, contact_uuid.clone()) {
+                    if !destination.same_currency(account.as_ref().unwrap()) {
+                        transfer_value = Some(Input::read_money(I18n::text("transactions_transfer_value"), true, None, destination.currency)?);
+                    }
+                }
+
+                deadline = Input::read_date(I18n::text("transactions_deadline"), true, None)?;
+                paid_in = Input::read_date(I18n::text("transactions_paidin"), false, None)?;
 
                 let mut tags_ops: Vec<(String, String)> = vec![];
-                for tag in Tag::get_tags(&mut storage) {
+                for tag in Tag::get_tags(&mut storage)? {
                     tags_ops.push((tag.uuid, tag.name));
                 }
 
@@ -307,19 +430,18 @@ impl Transactions {
                     .iter()
                     .map(
                         |tag| Tag::get_tag(&mut storage, tag.to_string())
-                                    .expect(&I18n::text("tags_not_found"))
                     )
-                    .collect();
+                    .collect::<Result<Vec<Tag>, Error>>()?;
 
                 observations = Input::read(I18n::text("transactions_observations"), false, None);
 
-                repetitions = Input::read_int(I18n::text("transactions_repetitions"), false, None);
+                repetitions = Input::read_int(I18n::text("transactions_repetitions"), false, None)?;
                 if repetitions > 0 {
                     // We only ask the interval if the user input the repetitions...
                     repetitions_specific_day = Input::read(I18n::text("transactions_repetitions_specific_day"), false, None) == "y";
                     if !repetitions_specific_day {
                         // We only ask the interval if the user choice for repetitions
-                        repetitions_interval = Input::read_int(I18n::text("transactions_repetitions_interval"), false, None);
+                        repetitions_interval = Input::read_int(I18n::text("transactions_repetitions_interval"), false, None)?;
                     }
                 } else {
                     repetitions_interval = 0;
@@ -359,7 +481,7 @@ impl Transactions {
                     mov.description.push_str(&format!(" [{}/{}]", rep + 1, repetitions));
                 }
 
-                let tr_uuid = Transaction::make_transaction_or_transfer(&mut storage, &mut mov, contact_uuid.clone());
+                let tr_uuid = Transaction::make_transaction_or_transfer(&mut storage, &mut mov, contact_uuid.clone(), transfer_value)?;
 
                 if repetitions_specific_day {
                     // Go to first day of this month and jump 32 days to get the next
@@ -390,16 +512,17 @@ impl Transactions {
             // Help mode
             println!("{}", I18n::text("transactions_how_to_use_add"));
         }
+
+        Ok(())
     }
 
     // Update a existing transaction
-    pub fn update(mut storage: Storage, params: Vec<String>) {
+    pub fn update(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
 
         if params.len() >= 2 && params[1] == "pay" {
             // Pay mode
 
-            let mut transaction = Transaction::get_transaction(&mut storage, params[0].trim().to_string())
-                .expect(&I18n::text("transactions_not_found"));
+            let mut transaction = Transaction::get_transaction(&mut storage, params[0].trim().to_string())?;
 
             // Update the paid date
             if params.len() >= 3 && params[2] != "today" {
@@ -407,7 +530,7 @@ impl Transactions {
                     transaction.paid_in = None;
                 } else {
                     // Is a date value
-                    transaction.paid_in = Input::param_date(I18n::text("transactions_paidin"), false, params.clone(), 2);
+                    transaction.paid_in = Input::param_date(I18n::text("transactions_paidin"), false, params.clone(), 2)?;
                 }
             } else {
                 // No date, no empty or the 'today' value we set this transaction as paid today
@@ -416,37 +539,51 @@ impl Transactions {
 
             // Update the value
             if params.len() >= 4 {
-                transaction.value = Input::param_money(I18n::text("transactions_lvalue"), true, params.clone(), 3);
+                transaction.value = Input::param_money(I18n::text("transactions_lvalue"), true, params.clone(), 3)?;
             }
 
             if transaction.transfer.is_some() {
-                Transaction::store_transfer(&mut storage, &mut transaction.clone(), &mut transaction.transfer.unwrap());
+                Transaction::store_transfer(&mut storage, &mut transaction.clone(), &mut transaction.transfer.unwrap())?;
             } else {
-                Transaction::store_transaction(&mut storage, transaction);
+                Transaction::store_transaction(&mut storage, transaction)?;
             }
 
         } else if params.len() == 3 {
             // Shell mode
 
-            let mut transaction = Transaction::get_transaction(&mut storage, params[0].trim().to_string())
-                .expect(&I18n::text("transactions_not_found"));
+            let mut transaction = Transaction::get_transaction(&mut storage, params[0].trim().to_string())?;
 
             if params[1] == "description" {
                 transaction.description = Input::param(I18n::text("transactions_description"), true, params.clone(), 2);
             } else if params[1] == "value" {
-                transaction.value = Input::param_money(I18n::text("transactions_lvalue"), true, params.clone(), 2);
+                transaction.value = Input::param_money(I18n::text("transactions_lvalue"), true, params.clone(), 2)?;
+            } else if params[1] == "transfer_value" && transaction.transfer.is_some() {
+                // The other side of transfer is the one changed, so his value is kept
+                let mut other = transaction.clone().transfer.unwrap();
+
+                let value = Input::param_money(I18n::text("transactions_transfer_value"), true, params.clone(), 2)?.abs();
+
+                // Only the value changes, not the direction of the transfer
+                other.value = match other.value.is_negative() {
+                    true => -value,
+                    false => value
+                };
+
+                Transaction::store_transfer(&mut storage, &mut other, &mut transaction)?;
+
+                return Ok(());
             } else if params[1] == "account" {
                 let account_uuid = Input::param(I18n::text("transactions_account"), true, params.clone(), 2);
-                transaction.account = Some(Account::get_account(&mut storage, account_uuid).unwrap());
+                transaction.account = Some(Account::get_account(&mut storage, account_uuid)?);
             } else if params[1] == "contact" {
                 if transaction.transfer.is_none() {
                     let contact_uuid = Input::param(I18n::text("transactions_contact"), true, params.clone(), 2);
-                    transaction.contact = Some(Contact::get_contact(&mut storage, contact_uuid).unwrap());
+                    transaction.contact = Some(Contact::get_contact(&mut storage, contact_uuid)?);
                 }
             } else if params[1] == "deadline" {
-                transaction.deadline = Input::param_date(I18n::text("transactions_deadline"), true, params.clone(), 2);
+                transaction.deadline = Input::param_date(I18n::text("transactions_deadline"), true, params.clone(), 2)?;
             } else if params[1] == "paid_in" {
-                transaction.paid_in = Input::param_date(I18n::text("transactions_paidin"), false, params.clone(), 2);
+                transaction.paid_in = Input::param_date(I18n::text("transactions_paidin"), false, params.clone(), 2)?;
             } else if params[1] == "tags" {
                 let tags_str = Input::param(I18n::text("transactions_tags"), false, params.clone(), 2);
                 transaction.tags = vec![];
@@ -454,21 +591,23 @@ impl Transactions {
                 if !tags_str.is_empty() {
                     for tag in tags_str.split(",") {
                         transaction.tags.push(
-                            Tag::get_tag(&mut storage, tag.to_string())
-                                .expect(&I18n::text("tags_not_found"))
+                            Tag::get_tag(&mut storage, tag.to_string())?
                         );
                     }
                 }
             } else if params[1] == "observations" {
                 transaction.observations = Input::param(I18n::text("transactions_observations"), false, params.clone(), 2);
+            } else if params[1] == "reconciled" && params[2] == "no" {
+                // Unlock the transaction for changes
+                transaction.reconciled_in = None;
             } else {
-                panic!(I18n::text("field_not_found"));
+                return Err(Error::Invalid("field_not_found"));
             }
 
             if transaction.transfer.is_some() {
-                Transaction::store_transfer(&mut storage, &mut transaction.clone(), &mut transaction.transfer.unwrap());
+                Transaction::store_transfer(&mut storage, &mut transaction.clone(), &mut transaction.transfer.unwrap())?;
             } else {
-                Transaction::store_transaction(&mut storage, transaction);
+                Transaction::store_transaction(&mut storage, transaction)?;
             }
 
         } else if params.len() > 0 && params[0] == "-i" {
@@ -476,40 +615,39 @@ impl Transactions {
 
             let id = Input::read("#id".to_string(), true, None);
 
-            let mut transaction = Transaction::get_transaction(&mut storage, id)
-                .expect(&I18n::text("transactions_not_found"));
+            let mut transaction = Transaction::get_transaction(&mut storage, id)?;
 
             transaction.description = Input::read(I18n::text("transactions_description"), true, Some(transaction.description));
 
             let mut accounts: Vec<(String, String)> = vec![];
-            for ac in Account::get_accounts(&mut storage) {
+            for ac in Account::get_active_accounts(&mut storage, false)? {
                 accounts.push((ac.uuid, ac.name));
             }
 
             let account_uuid = Input::read_option(I18n::text("transactions_account"), true, Some(transaction.account.clone().unwrap().uuid), accounts.clone());
-            transaction.account = Some(Account::get_account(&mut storage, account_uuid).unwrap());
+            transaction.account = Some(Account::get_account(&mut storage, account_uuid)?);
 
-            transaction.value = Input::read_money(I18n::text("transactions_lvalue"), true, Some(transaction.value), transaction.account.clone().unwrap().currency);
+            transaction.value = Input::read_money(I18n::text("transactions_lvalue"), true, Some(transaction.value), transaction.account.clone().unwrap().currency)?;
 
             let contact_uuid: String;
 
             if transaction.transfer.is_some() {
-                contact_uuid = Input::read_option(I18n::text("transactions_destination_account"),true, Some(transaction.transfer.clone().unwrap().uuid), accounts);
+                contact_uuid = Input::read_option(I18n::text("transactions_destination_account"),true, Some(transaction.transfer.clone().unwrap().account.unwrap().uuid), accounts);
             } else {
                 let mut contacts: Vec<(String, String)> = vec![];
-                for co in Contact::get_contacts(&mut storage) {
+                for co in Contact::get_contacts(&mut storage)? {
                     contacts.push((co.uuid, co.name));
                 }
 
                 contact_uuid = Input::read_option(I18n::text("transactions_contact"), true, Some(transaction.contact.clone().unwrap().uuid), contacts);
-                transaction.contact = Some(Contact::get_contact(&mut storage, contact_uuid.clone()).unwrap());
+                transaction.contact = Some(Contact::get_contact(&mut storage, contact_uuid.clone())?);
             }
 
-            transaction.deadline = Input::read_date(I18n::text("transactions_deadline"), true, transaction.deadline);
-            transaction.paid_in = Input::read_date(I18n::text("transactions_paidin"), false, transaction.paid_in);
+            transaction.deadline = Input::read_date(I18n::text("transactions_deadline"), true, transaction.deadline)?;
+            transaction.paid_in = Input::read_date(I18n::text("transactions_paidin"), false, transaction.paid_in)?;
 
             let mut tags_ops: Vec<(String, String)> = vec![];
-            for tag in Tag::get_tags(&mut storage) {
+            for tag in Tag::get_tags(&mut storage)? {
                 tags_ops.push((tag.uuid, tag.name));
             }
 
@@ -522,9 +660,8 @@ impl Transactions {
                 .iter()
                 .map(
                     |tag| Tag::get_tag(&mut storage, tag.to_string())
-                                .expect(&I18n::text("tags_not_found"))
                 )
-                .collect();
+                .collect::<Result<Vec<Tag>, Error>>()?;
 
             transaction.observations = Input::read(I18n::text("transactions_observations"), false, Some(transaction.observations));
 
@@ -532,38 +669,147 @@ impl Transactions {
                 let mut transfer = transaction.clone().transfer.unwrap();
 
                 // Destination account
-                transfer.account = Some(Account::get_account(&mut storage, contact_uuid).unwrap());
+                transfer.account = Some(Account::get_account(&mut storage, contact_uuid)?);
+
+                if !transfer.account.as_ref().unwrap().same_currency(transaction.account.as_ref().unwrap()) {
+                    transfer.value = Input::read_money(I18n::text("transactions_transfer_value"), true, Some(transfer.value.abs()), transfer.account.clone().unwrap().currency)?;
+                }
 
-                Transaction::store_transfer(&mut storage, &mut transaction, &mut transfer);
+                Transaction::store_transfer(&mut storage, &mut transaction, &mut transfer)?;
             } else {
-                Transaction::store_transaction(&mut storage, transaction);
+                Transaction::store_transaction(&mut storage, transaction)?;
             }
         } else {
             // Help mode
             println!("{}", I18n::text("transactions_how_to_use_update"));
         }
+
+        Ok(())
+    }
+
+    // Divide the value of a transaction on parts with his own tags
+    pub fn split(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
+
+        if params.len() == 2 && params[1] == "--clear" {
+            // Back to one value
+
+            let mut transaction = Transaction::get_transaction(&mut storage, params[0].trim().to_string())?;
+
+            transaction.splits = vec![];
+
+            if transaction.transfer.is_some() {
+                Transaction::store_transfer(&mut storage, &mut transaction.clone(), &mut transaction.transfer.unwrap())?;
+            } else {
+                Transaction::store_transaction(&mut storage, transaction)?;
+            }
+        } else if params.len() >= 2 {
+            // Shell mode
+
+            let mut transaction = Transaction::get_transaction(&mut storage, params[0].trim().to_string())?;
+
+            if transaction.transfer.is_some() {
+                return Err(Error::Invalid("error_splits_transfer"));
+            }
+
+            transaction.splits = vec![];
+
+            // Each part like [value]:[tags]:[memo]
+            for param in params[1..].iter() {
+
+                let mut parts = param.splitn(3, ':');
+
+                let value = I18n::locale().parse_money(parts.next().unwrap_or(""))?;
+                let mut tags: Vec<Tag> = vec![];
+
+                for tag in parts.next().unwrap_or("").split(",").filter(|tag| !tag.trim().is_empty()) {
+                    tags.push(Tag::get_tag(&mut storage, tag.trim().to_string())?);
+                }
+
+                transaction.splits.push(Split {
+                    value: value,
+                    tags: tags,
+                    memo: parts.next().unwrap_or("").trim().to_string()
+                });
+            }
+
+            Transaction::store_transaction(&mut storage, transaction)?;
+        } else if params.len() == 1 && params[0] == "-i" {
+            // Interactive mode
+
+            let id = Input::read("#id".to_string(), true, None);
+
+            let mut transaction = Transaction::get_transaction(&mut storage, id)?;
+
+            if transaction.transfer.is_some() {
+                return Err(Error::Invalid("error_splits_transfer"));
+            }
+
+            let mut tags_ops: Vec<(String, String)> = vec![];
+            for tag in Tag::get_tags(&mut storage)? {
+                tags_ops.push((tag.uuid, tag.name));
+            }
+
+            let currency = transaction.account.clone().unwrap().currency;
+            let mut remaining = transaction.value;
+
+            transaction.splits = vec![];
+
+            // Until the parts sum the value of transaction
+            while !remaining.is_zero() {
+
+                println!("{}: {}", I18n::text("transactions_split_remaining"), transaction.account.clone().unwrap().format_value(remaining));
+
+                let value = Input::read_money(I18n::text("transactions_split_value"), true, Some(remaining), currency.clone())?;
+
+                let tags = Input::read_options(I18n::text("transactions_tags"), false, vec![], tags_ops.clone())
+                    .iter()
+                    .map(
+                        |tag| Tag::get_tag(&mut storage, tag.to_string())
+                    )
+                    .collect::<Result<Vec<Tag>, Error>>()?;
+
+                let memo = Input::read(I18n::text("transactions_split_memo"), false, None);
+
+                transaction.splits.push(Split {
+                    value: value,
+                    tags: tags,
+                    memo: memo
+                });
+
+                remaining -= value;
+            }
+
+            Transaction::store_transaction(&mut storage, transaction)?;
+        } else {
+            // Help mode
+            println!("{}", I18n::text("transactions_how_to_use_split"));
+        }
+
+        Ok(())
     }
 
     // Remove a existing transaction
-    pub fn rm(mut storage: Storage, params: Vec<String>) {
+    pub fn rm(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
 
         if params.len() == 1 {
             // Shell mode
 
-            Transaction::remove_transaction(&mut storage, params[0].trim().to_string());
+            Transaction::remove_transaction(&mut storage, params[0].trim().to_string())?;
         } else {
             // Help mode
             println!("{}", I18n::text("transactions_how_to_use_rm"));
         }
+
+        Ok(())
     }
 
     // Interface to import csv
-    pub fn csv(mut storage: Storage, mut params: Vec<String>) {
+    pub fn csv(mut storage: Storage, mut params: Vec<String>) -> Result<(), Error> {
 
         if params.len() >= 6 {
             // Shell mode
 
-            let account = Account::get_account(&mut storage, params[0].to_owned()).unwrap();
+            let account = Account::get_account(&mut storage, params[0].to_owned())?;
 
             // Invert +/- of values
             let invert_values = Input::extract_param(&mut params, "--invert-values".to_string());
@@ -580,20 +826,22 @@ impl Transactions {
 
             let transactions = csv.get_transactions(pos_posted, pos_ammount, pos_memo, invert_values);
 
-            Transactions::import_interface(storage, params, account, transactions);
+            Transactions::import_interface(storage, params, account, transactions)?;
         } else {
             // Help mode
             println!("{}", I18n::text("transactions_how_to_use_csv"));
         }
+
+        Ok(())
     }
 
     // Interface to import ofx
-    pub fn ofx(mut storage: Storage, mut params: Vec<String>) {
+    pub fn ofx(mut storage: Storage, mut params: Vec<String>) -> Result<(), Error> {
 
         if params.len() >= 2 {
             // Shell mode
 
-            let account = Account::get_account(&mut storage, params[0].to_owned()).unwrap();
+            let account = Account::get_account(&mut storage, params[0].to_owned())?;
 
             // Invert +/- of values
             let invert_values = Input::extract_param(&mut params, "--invert-values".to_string());
@@ -603,15 +851,17 @@ impl Transactions {
 
             let transactions = ofx.get_transactions(invert_values);
 
-            Transactions::import_interface(storage, params, account, transactions);
+            Transactions::import_interface(storage, params, account, transactions)?;
         } else {
             // Help mode
             println!("{}", I18n::text("transactions_how_to_use_ofx"));
         }
+
+        Ok(())
     }
 
     // Interface to import ofx/csv files
-    fn import_interface(mut storage: Storage, mut params: Vec<String>, account: Account, transactions: Vec<import::Transaction>) {
+    fn import_interface(mut storage: Storage, mut params: Vec<String>, account: Account, transactions: Vec<import::Transaction>) -> Result<(), Error> {
 
         // Skip all already added transactions
         let auto_skip = Input::extract_param(&mut params, "--auto-skip".to_string());
@@ -630,16 +880,16 @@ impl Transactions {
 
 
         let mut contacts: Vec<(String, String)> = vec![];
-        for co in Contact::get_contacts(&mut storage) {
+        for co in Contact::get_contacts(&mut storage)? {
             contacts.push((co.uuid, co.name));
         }
         // For transfers
-        for account in Account::get_accounts(&mut storage) {
+        for account in Account::get_active_accounts(&mut storage, false)? {
             contacts.push((account.uuid, account.name + &I18n::text("transactions_caccount")));
         }
 
         let mut tags_ops: Vec<(String, String)> = vec![];
-        for tag in Tag::get_tags(&mut storage) {
+        for tag in Tag::get_tags(&mut storage)? {
             tags_ops.push((tag.uuid, tag.name));
         }
 
@@ -650,22 +900,22 @@ impl Transactions {
         // +1 month
         let to = ((Local::now().with_day(1).unwrap() + Duration::days(64)).with_day(1).unwrap() - Duration::days(1)).date().naive_local();
 
-        let (current_transfers, _) = Transaction::get_transactions(&mut storage, account.clone(), from, to, StatusFilter::PAID, None, false);
+        let (current_transfers, _) = Transaction::get_transactions(&mut storage, account.clone(), from, to, StatusFilter::PAID, None, false)?;
 
         if enable_merge {
 
-            let (trs, _) = Transaction::get_transactions(&mut storage, account.clone(), from, to, StatusFilter::FORPAY, None, false);
+            let (trs, _) = Transaction::get_transactions(&mut storage, account.clone(), from, to, StatusFilter::FORPAY, None, false)?;
 
             for tr in trs {
-                transactions_for_merge.push((tr.clone().uuid, format!("{} {} - {}", tr.deadline.unwrap(), tr.value_formmated(), tr.description)));
+                transactions_for_merge.push((tr.clone().uuid, format!("{} {} - {}", I18n::locale().format_date(tr.deadline.unwrap()), tr.value_formmated(), tr.description)));
             }
         }
 
         for (i, ofx_tr) in transactions.iter().enumerate() {
             println!("{} {}/{}", I18n::text("transactions_ofx_index"), i + 1, transactions.len());
-            println!("{} {} {}, memo: {}", account.format_value(ofx_tr.amount), I18n::text("transactions_ofx_on"), ofx_tr.posted_at.unwrap(), ofx_tr.memo);
+            println!("{} {} {}, memo: {}", account.format_value(ofx_tr.amount), I18n::text("transactions_ofx_on"), I18n::locale().format_date(ofx_tr.posted_at.unwrap()), ofx_tr.memo);
 
-            let mut tr = ofx_tr.clone().build_transaction(&mut storage, account.clone());
+            let mut tr = ofx_tr.clone().build_transaction(&mut storage, account.clone())?;
 
             let mut question = I18n::text("transactions_ofx_add_skip");
 
@@ -688,14 +938,14 @@ impl Transactions {
             tr.account = Some(account.clone());
 
             if tr.uuid.is_empty() {
-                if Rule::apply_rules(&mut storage, &mut tr) {
+                if Rule::apply_rules(&mut storage, &mut tr)? {
                     println!("{}", I18n::text("transactions_ofx_rule_matches"));
 
                     if auto_confirm_rules && tr.contact.is_some() {
                         println!("{}", I18n::text("transactions_ofx_auto_confirm"));
                         let contact_uuid = tr.clone().contact.unwrap().uuid;
 
-                        Transaction::make_transaction_or_transfer(&mut storage, &mut tr, contact_uuid);
+                        Transaction::make_transaction_or_transfer(&mut storage, &mut tr, contact_uuid, None)?;
                         continue;
                     }
                 } else if auto_skip_nomatches {
@@ -710,8 +960,7 @@ impl Transactions {
 
                     let principal_uuid = Input::read_option(I18n::text("transactions_ofx_mergethis"), true, None, transactions_for_merge.clone());
 
-                    let mut principal = Transaction::get_transaction(&mut storage, principal_uuid)
-                        .expect(&I18n::text("transactions_merge_not_found_principal"));
+                    let mut principal = Transaction::get_transaction(&mut storage, principal_uuid)?;
 
                     let mut contact_uuid = String::new();
 
@@ -724,16 +973,16 @@ impl Transactions {
 
                     tr.merged_in = principal.clone().uuid;
 
-                    Transaction::make_transaction_or_transfer(&mut storage, &mut tr, contact_uuid);
+                    Transaction::make_transaction_or_transfer(&mut storage, &mut tr, contact_uuid, None)?;
 
                     // Update the original transaction to avoid problems
                     principal.paid_in = Some(Local::today().naive_local());
                     principal.value = tr.value;
 
                     if tr.transfer.is_some() {
-                        Transaction::store_transfer(&mut storage, &mut tr.clone(), &mut tr.transfer.unwrap());
+                        Transaction::store_transfer(&mut storage, &mut tr.clone(), &mut tr.transfer.unwrap())?;
                     } else {
-                        Transaction::store_transaction(&mut storage, principal);
+                        Transaction::store_transaction(&mut storage, principal)?;
                     }
 
                     continue;
@@ -765,9 +1014,8 @@ impl Transactions {
                 .iter()
                 .map(
                     |tag| Tag::get_tag(&mut storage, tag.to_string())
-                                .expect(&I18n::text("tags_not_found"))
                 )
-                .collect();
+                .collect::<Result<Vec<Tag>, Error>>()?;
 
 
             tr.observations = Input::read(I18n::text("transactions_observations"), false, Some(tr.observations));
@@ -787,56 +1035,61 @@ impl Transactions {
                 }
             }
 
-            Transaction::make_transaction_or_transfer(&mut storage, &mut tr, other_account_uuid);
+            Transaction::make_transaction_or_transfer(&mut storage, &mut tr, other_account_uuid, None)?;
 
         }
+
+        Ok(())
     }
 
     // Interface to merge two transactions
-    pub fn merge(mut storage: Storage, params: Vec<String>) {
+    pub fn merge(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
 
         if params.len() == 2 {
             // Shell mode
 
-            let principal = Transaction::get_transaction(&mut storage, params[0].to_string())
-                .expect(&I18n::text("transactions_merge_not_found_principal"));
+            let principal = Transaction::get_transaction(&mut storage, params[0].to_string())?;
 
-            let mut secondary = Transaction::get_transaction(&mut storage, params[1].to_string())
-                .expect(&I18n::text("transactions_merge_not_found_secondary"));
+            let mut secondary = Transaction::get_transaction(&mut storage, params[1].to_string())?;
 
             secondary.merged_in = principal.uuid;
 
             if secondary.transfer.is_some() {
-                Transaction::store_transfer(&mut storage, &mut secondary.clone(), &mut secondary.transfer.unwrap());
+                Transaction::store_transfer(&mut storage, &mut secondary.clone(), &mut secondary.transfer.unwrap())?;
             } else {
-                Transaction::store_transaction(&mut storage, secondary);
+                Transaction::store_transaction(&mut storage, secondary)?;
             }
 
         } else {
             // Help mode
             println!("{}", I18n::text("transactions_how_to_use_merge"));
         }
+
+        Ok(())
     }
 
     // Interface to export and import transactions to the stdout
-    pub fn calendar(mut storage: Storage, params: Vec<String>) {
+    pub fn calendar(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
 
         if params.len() >= 2 && params[0] == "export" {
 
             // Shell mode
-            let account = Account::get_account(&mut storage, params[1].trim().to_string()).unwrap();
+            let account = Account::get_account(&mut storage, params[1].trim().to_string())?;
 
             let mut from = Local::now().with_day(1).unwrap().date().naive_local();
             let mut to = (Local::now() + Duration::weeks(104)).date().naive_local();
 
             if params.len() == 4 {
-                from = NaiveDate::parse_from_str(&params[2].trim().to_string(), "%Y-%m-%d").unwrap();
-                to = NaiveDate::parse_from_str(&params[3].trim().to_string(), "%Y-%m-%d").unwrap();
+                let locale = I18n::locale();
+                let invalid = format!("{} {}", I18n::text("couldnt_parse_the_string_to_date"), locale.date_pattern);
+
+                from = locale.parse_date(&params[2]).expect(&invalid);
+                to = locale.parse_date(&params[3]).expect(&invalid);
             }
 
-            let (transactions, _totals) = Transaction::get_transactions(&mut storage, account.clone(), from, to, StatusFilter::FORPAY, None, false);
+            let (transactions, _totals) = Transaction::get_transactions(&mut storage, account.clone(), from, to, StatusFilter::FORPAY, None, false)?;
 
-            let calendar = Calendar::export(transactions);
+            let calendar = Calendar::export(&mut storage, transactions);
 
             calendar.print()
                 .expect(&I18n::text("transactions_calendar_fail_print"));
@@ -845,5 +1098,7 @@ impl Transactions {
             // Help mode
             println!("{}", I18n::text("transactions_how_to_use_calendar"));
         }
+
+        Ok(())
     }
 }
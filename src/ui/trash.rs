@@ -0,0 +1,83 @@
+///
+/// Blitz Money
+///
+/// Frontend/Ui of module for manange the removed rows
+///
+/// Copyright 2018 Luis Fernando Batels <luisfbatels@gmail.com>
+///
+
+use chrono::Duration;
+use backend::trash::Trash;
+use backend::storage::Storage;
+use backend::error::Error;
+use ui::ui::*;
+use i18n::*;
+
+pub struct Trashes {}
+
+impl Trashes {
+
+    // List of removed rows
+    pub fn list(mut storage: Storage, _params: Vec<String>, is_csv: bool) -> Result<(), Error> {
+
+        let list = Trash::list(&mut storage)?;
+        let mut table = Output::new_table();
+
+        table.set_titles(row![b->I18n::text("trash_section"), b->I18n::text("trash_label"), b->I18n::text("trash_deleted_at"), b->"#id"]);
+
+        for trashed in list {
+            table.add_row(row![
+                trashed.section,
+                trashed.label,
+                trashed.deleted_at.format(&I18n::locale().datetime_format()),
+                trashed.clone().id(&mut storage)
+            ]);
+        }
+
+        Output::print_table(table, is_csv);
+
+        Ok(())
+    }
+
+    // Put back a removed row
+    pub fn restore(mut storage: Storage, params: Vec<String>) -> Result<(), Error> {
+
+        if params.len() == 1 {
+            // Shell mode
+
+            for trashed in Trash::restore(&mut storage, params[0].trim().to_string())? {
+                println!("{}: {} {}", I18n::text("trash_restored"), trashed.section, trashed.label);
+            }
+        } else {
+            // Help mode
+            println!("{}", I18n::text("trash_how_to_use_restore"));
+        }
+
+        Ok(())
+    }
+
+    // Remove for good the rows of trash
+    pub fn purge(mut storage: Storage, mut params: Vec<String>) -> Result<(), Error> {
+
+        // Age in days, like 90d
+        let older_than = match Input::extract_named_param(&mut params, "--older-than=".to_string()) {
+            Some(age) => match (age.trim().ends_with("d"), age.trim().trim_end_matches('d').parse::<i64>()) {
+                (true, Ok(days)) if days >= 0 => Some(Duration::days(days)),
+                _ => return Err(Error::Invalid("error_invalid_older_than"))
+            },
+            None => None
+        };
+
+        if params.len() > 0 {
+            // Help mode
+            println!("{}", I18n::text("trash_how_to_use_purge"));
+            return Ok(());
+        }
+
+        let purged = Trash::purge(&mut storage, older_than)?;
+
+        println!("{}: {}", I18n::text("trash_purged"), purged);
+
+        Ok(())
+    }
+}
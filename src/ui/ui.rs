@@ -7,10 +7,13 @@
 ///
 
 use prettytable::*;
-use csv::*;
+use csv::{ WriterBuilder, QuoteStyle };
 use std::io;
 use chrono::{Local, prelude::Datelike, NaiveDate, Duration};
 use i18n::*;
+use backend::references::OnRemove;
+use backend::money::Money;
+use backend::error::Error;
 
 pub struct Output {
 }
@@ -77,33 +80,35 @@ impl Input {
     }
 
     // Read a date value from stdin
-    pub fn read_date(mut label: String, is_required: bool, current_value: Option<NaiveDate>) -> Option<NaiveDate> {
+    pub fn read_date(mut label: String, is_required: bool, current_value: Option<NaiveDate>) -> Result<Option<NaiveDate>, Error> {
 
-        label.push_str(&format!("({})", I18n::text("format_date")));
+        let locale = I18n::locale();
+
+        label.push_str(&format!("({} {})", I18n::text("format_date"), locale.date_pattern));
 
         let mut current:Option<String> = None;
         if current_value.is_some() {
-            current = Some(current_value.unwrap().format("%Y-%m-%d").to_string());
+            current = Some(locale.format_date(current_value.unwrap()));
         }
 
         let value = Input::read(label, is_required, current);
 
         if value.is_empty() && !is_required {
-            return None;
+            return Ok(None);
         }
 
         if value == "today".to_string() {
-            return Some(Local::now().date().naive_local());
+            return Ok(Some(Local::now().date().naive_local()));
         }
 
-        let date = NaiveDate::parse_from_str(&value, "%Y-%m-%d")
-            .expect(&I18n::text("couldnt_parse_the_string_to_date"));
+        let date = locale.parse_date(&value)
+            .ok_or(Error::InvalidInput("couldnt_parse_the_string_to_date", locale.date_pattern.to_string()))?;
 
-        Some(date)
+        Ok(Some(date))
     }
 
     // Read a money value from stdin
-    pub fn read_money(mut label: String, is_required: bool, current_value: Option<f32>, currency: String) -> f32 {
+    pub fn read_money(mut label: String, is_required: bool, current_value: Option<Money>, currency: String) -> Result<Money, Error> {
 
         if !currency.is_empty() {
             label.push_str("(");
@@ -111,25 +116,25 @@ impl Input {
             label.push_str(")");
         }
 
+        let locale = I18n::locale();
+
         let mut current:Option<String> = None;
         if current_value.is_some() {
-            current = Some(current_value.unwrap().to_string());
+            current = Some(locale.format_money(current_value.unwrap()));
         }
 
         let value = Input::read(label, is_required, current);
 
         if value.is_empty() && !is_required {
-            return 0.0;
+            return Ok(Money::zero());
         }
 
-        let money = value.parse::<f32>()
-            .expect(&I18n::text("couldnt_parse_the_string_to_money"));
-
-        money
+        locale.parse_money(&value)
+            .map_err(|_| Error::InvalidInput("couldnt_parse_the_string_to_money", locale.money_pattern.to_string()))
     }
 
     // Read a integer value from stdin
-    pub fn read_int(label: String, is_required: bool, current_value: Option<i32>) -> i32 {
+    pub fn read_int(label: String, is_required: bool, current_value: Option<i32>) -> Result<i32, Error> {
 
         let mut current:Option<String> = None;
         if current_value.is_some() {
@@ -139,13 +144,11 @@ impl Input {
         let value = Input::read(label, is_required, current);
 
         if value.is_empty() && !is_required {
-            return 0;
+            return Ok(0);
         }
 
-        let int = value.parse::<i32>()
-            .expect(&I18n::text("couldnt_parse_the_string_to_integer"));
-
-        int
+        value.parse::<i32>()
+            .map_err(|_| Error::InvalidInput("couldnt_parse_the_string_to_integer", "".to_string()))
     }
 
     // Build the str for display options
@@ -293,48 +296,48 @@ impl Input {
     }
 
     // Parse the param to a date
-    pub fn param_date(label: String, is_required: bool, params: Vec<String>, position: usize) -> Option<NaiveDate> {
+    pub fn param_date(label: String, is_required: bool, params: Vec<String>, position: usize) -> Result<Option<NaiveDate>, Error> {
 
         let value = Input::param(label, is_required, params, position);
 
         if value.is_empty() && !is_required {
-            return None;
+            return Ok(None);
         }
 
-        let date = NaiveDate::parse_from_str(&value, "%Y-%m-%d")
-            .expect(&I18n::text("couldnt_parse_the_string_to_date"));
+        let locale = I18n::locale();
+
+        let date = locale.parse_date(&value)
+            .ok_or(Error::InvalidInput("couldnt_parse_the_string_to_date", locale.date_pattern.to_string()))?;
 
-        Some(date)
+        Ok(Some(date))
     }
 
     // Parse the param to a money value
-    pub fn param_money(label: String, is_required: bool, params: Vec<String>, position: usize) -> f32 {
+    pub fn param_money(label: String, is_required: bool, params: Vec<String>, position: usize) -> Result<Money, Error> {
 
         let value = Input::param(label, is_required, params, position);
 
         if value.is_empty() && !is_required {
-            return 0.0;
+            return Ok(Money::zero());
         }
 
-        let money = value.parse::<f32>()
-            .expect(&I18n::text("couldnt_parse_the_string_to_money"));
+        let locale = I18n::locale();
 
-        money
+        locale.parse_money(&value)
+            .map_err(|_| Error::InvalidInput("couldnt_parse_the_string_to_money", locale.money_pattern.to_string()))
     }
 
     // Parse the param to a integer value
-    pub fn param_int(label: String, is_required: bool, params: Vec<String>, position: usize) -> i32 {
+    pub fn param_int(label: String, is_required: bool, params: Vec<String>, position: usize) -> Result<i32, Error> {
 
         let value = Input::param(label, is_required, params, position);
 
         if value.is_empty() && !is_required {
-            return 0;
+            return Ok(0);
         }
 
-        let int = value.parse::<i32>()
-            .expect(&I18n::text("couldnt_parse_the_string_to_integer"));
-
-        int
+        value.parse::<i32>()
+            .map_err(|_| Error::InvalidInput("couldnt_parse_the_string_to_integer", "".to_string()))
     }
 
     // Return if the param exists on params vec
@@ -366,27 +369,69 @@ impl Input {
         None
     }
 
+    // Return what to do with the dependents of a removed row, by
+    // the --reassign-to=id and --cascade params, and remove them
+    pub fn extract_on_remove(params: &mut Vec<String>) -> OnRemove {
+
+        if let Some(id) = Input::extract_named_param(params, "--reassign-to=".to_string()) {
+            return OnRemove::ReassignTo(id);
+        }
+
+        if Input::extract_param(params, "--cascade".to_string()) {
+            return OnRemove::Cascade;
+        }
+
+        OnRemove::Refuse
+    }
+
+    // Read from stdin what to do with the dependents of a removed row. The
+    // summary is the number of dependents by section and the options are
+    // the rows that can receive them
+    pub fn read_on_remove(summary: Vec<(String, usize)>, options: Vec<(String, String)>) -> OnRemove {
+
+        println!("{}:", I18n::text("remove_dependents"));
+
+        for (section, count) in summary {
+            println!("{}: {}", section, count);
+        }
+
+        let action = Input::read_option(I18n::text("remove_action"), true, None, vec![
+            ("refuse".to_string(), I18n::text("remove_refuse")),
+            ("reassign".to_string(), I18n::text("remove_reassign")),
+            ("cascade".to_string(), I18n::text("remove_cascade"))
+        ]);
+
+        match action.as_str() {
+            "reassign" => OnRemove::ReassignTo(Input::read_option(I18n::text("remove_reassign_to"), true, None, options)),
+            "cascade" => OnRemove::Cascade,
+            _ => OnRemove::Refuse
+        }
+    }
+
     // Parse and return a period date from params
-    pub fn param_date_period(params: Vec<String>, from_position: usize, to_position: usize) -> (NaiveDate, NaiveDate) {
+    pub fn param_date_period(params: Vec<String>, from_position: usize, to_position: usize) -> Result<(NaiveDate, NaiveDate), Error> {
 
         // Default: Current month
         let mut from = Local::now().with_day(1).unwrap().date().naive_local();
         let mut to = ((Local::now().with_day(1).unwrap() + Duration::days(32)).with_day(1).unwrap() - Duration::days(1)).date().naive_local();
 
+        let locale = I18n::locale();
+        let invalid = || Error::InvalidInput("couldnt_parse_the_string_to_date", locale.date_pattern.to_string());
+
         if (params.len() - 1) == to_position {
             // When user input full from and to dates
 
-            from = NaiveDate::parse_from_str(&params[from_position].trim().to_string(), "%Y-%m-%d").unwrap();
-            to = NaiveDate::parse_from_str(&params[to_position].trim().to_string(), "%Y-%m-%d").unwrap();
+            from = locale.parse_date(&params[from_position]).ok_or_else(invalid)?;
+            to = locale.parse_date(&params[to_position]).ok_or_else(invalid)?;
 
         } else if (params.len() - 1) == from_position && params[from_position].len() == 7 {
             // When user only input the year+month
 
-            from = NaiveDate::parse_from_str(&format!("{}-01", params[from_position]), "%Y-%m-%d").unwrap();
+            from = locale.parse_month(&params[from_position]).ok_or_else(invalid)?;
             to = (from + Duration::days(32)).with_day(1).unwrap() - Duration::days(1);
 
         }
 
-        (from, to)
+        Ok((from, to))
     }
 }